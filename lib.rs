@@ -0,0 +1,7218 @@
+//! Accounts, their ledgers and the bank that holds them, shared by the interactive menu in
+//! `main.rs` and anything else that needs to drive a bank programmatically. Start from
+//! [`bank::Bank`]; accounts and ledger entries are grouped under [`account`] and
+//! [`transaction`], and [`storage`] keeps a bank between runs.
+
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Deref, DerefMut, Mul, Neg, Range, Sub, SubAssign};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum AccountType {
+    Checking,
+    Savings,
+    Credit,
+}
+
+impl AccountType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AccountType::Checking => "Checking",
+            AccountType::Savings => "Savings",
+            AccountType::Credit => "Credit",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<AccountType> {
+        match name {
+            "Checking" => Some(AccountType::Checking),
+            "Savings" => Some(AccountType::Savings),
+            "Credit" => Some(AccountType::Credit),
+            _ => None,
+        }
+    }
+}
+
+// Open accounts can be frozen and unfrozen at will. Closing is final: a Closed account keeps its
+// history but refuses every operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccountStatus {
+    Open,
+    Frozen,
+    Closed,
+}
+
+impl AccountStatus {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AccountStatus::Open => "Open",
+            AccountStatus::Frozen => "Frozen",
+            AccountStatus::Closed => "Closed",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<AccountStatus> {
+        match name {
+            "Open" => Some(AccountStatus::Open),
+            "Frozen" => Some(AccountStatus::Frozen),
+            "Closed" => Some(AccountStatus::Closed),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Account {
+    balance: Money,
+    account_type: AccountType,
+    transactions: Vec<Transaction>,
+    status: AccountStatus,
+    fee_schedule: Option<FeeSchedule>,
+    interest_rate: f64,
+    // Promotional rate earned on the part of the balance up to the cap; the rest earns
+    // `interest_rate`. Without a cap the account earns `interest_rate` throughout.
+    bonus_rate: f64,
+    bonus_rate_cap: Option<Money>,
+    statement_delivery: DeliveryMethod,
+    currency: Currency,
+    credit_limit: Money,
+    // Checking only: how far below zero withdrawals may take the balance.
+    overdraft_limit: Money,
+    // Savings only: withdrawals may not take the balance below this floor.
+    minimum_balance: Money,
+    temporary_credit: Option<TemporaryCredit>,
+    foreign_balances: BTreeMap<Currency, Money>,
+    created_at: SystemTime,
+    withdrawal_hold_until: Option<SystemTime>,
+    // Term-deposit Savings: withdrawing before this date forfeits part of the interest.
+    maturity: Option<SystemTime>,
+    interest_basis: InterestBasis,
+    compound_frequency: CompoundFrequency,
+    pin_hash: Option<String>,
+    pin_salt: String,
+    failed_pin_attempts: u32,
+    tags: BTreeSet<String>,
+    owner: Option<String>,
+    // Customers other than the owner who may use the account, by id.
+    holders: BTreeMap<String, auth::Role>,
+    // External ids of the transactions imported from other banks' files, to skip them next time.
+    imported_ids: BTreeSet<String>,
+    // Outstanding authorization holds by id; they reduce the available balance.
+    auth_holds: BTreeMap<u64, AuthHold>,
+    statements: VecDeque<Statement>,
+    next_transaction_id: u64,
+    velocity_limits: VelocityLimits,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompoundFrequency {
+    Daily,
+    Monthly,
+    Annually,
+}
+
+impl CompoundFrequency {
+    pub fn periods_per_year(&self) -> f64 {
+        match self {
+            CompoundFrequency::Daily => 365.0,
+            CompoundFrequency::Monthly => 12.0,
+            CompoundFrequency::Annually => 1.0,
+        }
+    }
+
+    // Fractional growth of a balance at the nominal annual `rate` over `years`.
+    pub fn growth(&self, rate: f64, years: f64) -> f64 {
+        let periods = self.periods_per_year();
+        (1.0 + rate / periods).powf(periods * years) - 1.0
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CompoundFrequency::Daily => "Daily",
+            CompoundFrequency::Monthly => "Monthly",
+            CompoundFrequency::Annually => "Annually",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<CompoundFrequency> {
+        match name {
+            "Daily" => Some(CompoundFrequency::Daily),
+            "Monthly" => Some(CompoundFrequency::Monthly),
+            "Annually" => Some(CompoundFrequency::Annually),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InterestBasis {
+    EndingBalance,
+    AverageDailyBalance,
+}
+
+impl InterestBasis {
+    pub fn name(&self) -> &'static str {
+        match self {
+            InterestBasis::EndingBalance => "EndingBalance",
+            InterestBasis::AverageDailyBalance => "AverageDailyBalance",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<InterestBasis> {
+        match name {
+            "EndingBalance" => Some(InterestBasis::EndingBalance),
+            "AverageDailyBalance" => Some(InterestBasis::AverageDailyBalance),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AuthHold {
+    pub amount: Money,
+    pub expires: SystemTime,
+}
+
+#[derive(Debug, Clone)]
+pub struct TemporaryCredit {
+    pub extra: Money,
+    pub until: SystemTime,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeliveryMethod {
+    Paper,
+    Email,
+    None,
+}
+
+impl DeliveryMethod {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DeliveryMethod::Paper => "Paper",
+            DeliveryMethod::Email => "Email",
+            DeliveryMethod::None => "None",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<DeliveryMethod> {
+        match name {
+            "Paper" => Some(DeliveryMethod::Paper),
+            "Email" => Some(DeliveryMethod::Email),
+            "None" => Some(DeliveryMethod::None),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AgeBucket {
+    UnderOneMonth,
+    OneToSixMonths,
+    SixToTwelveMonths,
+    OverOneYear,
+}
+
+impl AgeBucket {
+    pub const ALL: [AgeBucket; 4] = [
+        AgeBucket::UnderOneMonth,
+        AgeBucket::OneToSixMonths,
+        AgeBucket::SixToTwelveMonths,
+        AgeBucket::OverOneYear,
+    ];
+
+    // Months are approximated as 30 days and a year as 365.
+    pub fn for_age(age: Duration) -> AgeBucket {
+        let days = age.as_secs() / 86_400;
+        match days {
+            0..=29 => AgeBucket::UnderOneMonth,
+            30..=179 => AgeBucket::OneToSixMonths,
+            180..=364 => AgeBucket::SixToTwelveMonths,
+            _ => AgeBucket::OverOneYear,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FeeSchedule {
+    pub free_withdrawals: u32,
+    pub fee: Money,
+    pub monthly_maintenance: Money,
+    // Maintenance is waived for the month when the average balance is at least this much.
+    pub maintenance_waiver_balance: Option<Money>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct UsageProfile {
+    pub deposits: u32,
+    pub withdrawals: u32,
+    pub average_balance: Money,
+}
+
+impl Account {
+    pub fn new(account_type: AccountType) -> Account {
+        Account {
+            balance: Money::ZERO,
+            account_type,
+            transactions: Vec::new(),
+            status: AccountStatus::Open,
+            fee_schedule: None,
+            interest_rate: 0.0,
+            bonus_rate: 0.0,
+            bonus_rate_cap: None,
+            statement_delivery: DeliveryMethod::Paper,
+            currency: Currency::default(),
+            credit_limit: Money::ZERO,
+            overdraft_limit: Money::ZERO,
+            minimum_balance: Money::ZERO,
+            temporary_credit: None,
+            foreign_balances: BTreeMap::new(),
+            created_at: SystemTime::now(),
+            withdrawal_hold_until: None,
+            maturity: None,
+            interest_basis: InterestBasis::EndingBalance,
+            compound_frequency: CompoundFrequency::Monthly,
+            pin_hash: None,
+            pin_salt: String::new(),
+            failed_pin_attempts: 0,
+            tags: BTreeSet::new(),
+            owner: None,
+            holders: BTreeMap::new(),
+            imported_ids: BTreeSet::new(),
+            auth_holds: BTreeMap::new(),
+            statements: VecDeque::new(),
+            next_transaction_id: 1,
+            velocity_limits: VelocityLimits::default(),
+        }
+    }
+
+    pub fn record(&mut self, kind: TransactionKind) {
+        let currency = self.currency.clone();
+        self.record_in(kind, currency);
+    }
+
+    pub fn record_in(&mut self, kind: TransactionKind, currency: Currency) {
+        self.record_with_memo(kind, currency, None);
+    }
+
+    pub fn record_with_memo(&mut self, kind: TransactionKind, currency: Currency, memo: Option<String>) {
+        self.record_at(kind, currency, memo, SystemTime::now());
+    }
+
+    // An entry dated earlier than now, such as the fee for a month that has already ended, still
+    // goes at the end of the ledger.
+    fn record_at(&mut self, kind: TransactionKind, currency: Currency, memo: Option<String>, timestamp: SystemTime) {
+        let previous = self.transactions.last().map_or("", |t| t.chain_hash.as_str());
+        let mut transaction = Transaction {
+            id: self.next_transaction_id,
+            kind,
+            timestamp,
+            balance_after: self.balance_in(&currency),
+            currency,
+            memo,
+            reversed_by: None,
+            chain_hash: String::new(),
+        };
+        transaction.chain_hash = transaction.compute_hash(previous);
+        self.transactions.push(transaction);
+        self.next_transaction_id += 1;
+    }
+
+    // Rewrites each entry's resulting balance by walking back from the current balances, for
+    // after entries were removed or reordered.
+    pub fn restate_running_balances(&mut self) {
+        let mut after: BTreeMap<Currency, Money> = self.foreign_balances.clone();
+        after.insert(self.currency.clone(), self.balance);
+        for transaction in self.transactions.iter_mut().rev() {
+            let balance = after.entry(transaction.currency.clone()).or_default();
+            transaction.balance_after = *balance;
+            *balance -= transaction.kind.balance_effect();
+        }
+    }
+
+    pub fn rechain(&mut self) {
+        let mut previous = String::new();
+        for transaction in &mut self.transactions {
+            transaction.chain_hash = transaction.compute_hash(&previous);
+            previous = transaction.chain_hash.clone();
+        }
+    }
+
+    pub fn verify_chain(&self) -> bool {
+        let mut previous = "";
+        for transaction in &self.transactions {
+            if transaction.chain_hash != transaction.compute_hash(previous) {
+                return false;
+            }
+            previous = &transaction.chain_hash;
+        }
+        true
+    }
+
+    // Withdrawals and outgoing transfers, which share the fee schedule's free allowance.
+    pub fn withdrawals_in_month(&self, now: SystemTime) -> u32 {
+        let month = calendar::year_month(now);
+        self.transactions
+            .iter()
+            .filter(|t| matches!(t.kind, TransactionKind::Withdrawal(_) | TransactionKind::TransferOut(..)))
+            .filter(|t| calendar::year_month(t.timestamp) == month)
+            .count() as u32
+    }
+
+    pub fn withdrawal_fee(&self, now: SystemTime) -> Money {
+        match &self.fee_schedule {
+            Some(schedule) if self.withdrawals_in_month(now) >= schedule.free_withdrawals => schedule.fee,
+            _ => Money::ZERO,
+        }
+    }
+
+    pub fn effective_credit_limit(&self, now: SystemTime) -> Money {
+        if !matches!(self.account_type, AccountType::Credit) {
+            return Money::ZERO;
+        }
+        match &self.temporary_credit {
+            // A combined limit past what Money holds is as good as unlimited.
+            Some(temporary) if now < temporary.until => Money::from_cents(self.credit_limit.0.saturating_add(temporary.extra.0)),
+            _ => self.credit_limit,
+        }
+    }
+
+    pub fn check_withdrawal_hold(&self, now: SystemTime) -> Result<(), BankError> {
+        match self.withdrawal_hold_until {
+            Some(until) if now < until => Err(BankError::AccountOnHold),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn deposit(&mut self, amount: Money) -> Result<(), BankError> {
+        if amount <= Money::ZERO {
+            return Err(BankError::InvalidAmount);
+        }
+        self.check_open()?;
+        self.balance = self.balance.checked_add(amount)?;
+        self.record(TransactionKind::Deposit(amount));
+        Ok(())
+    }
+
+    // Checking accounts may not go below their overdraft allowance (zero unless one is set),
+    // Savings accounts below their minimum balance or zero, and Credit accounts beyond their
+    // (possibly temporarily raised) credit limit.
+    pub fn check_debit(&self, debit: Money, now: SystemTime) -> Result<(), BankError> {
+        self.check_open()?;
+        self.check_withdrawal_hold(now)?;
+        self.check_balance_floor(self.checked_available_balance()?.checked_sub(debit)?, now)
+    }
+
+    pub fn check_balance_floor(&self, remaining: Money, now: SystemTime) -> Result<(), BankError> {
+        match self.account_type {
+            AccountType::Credit if remaining < -self.effective_credit_limit(now) => Err(BankError::CreditLimitExceeded),
+            AccountType::Savings if remaining < self.minimum_balance && self.minimum_balance > Money::ZERO => {
+                Err(BankError::BelowMinimumBalance)
+            }
+            AccountType::Checking if remaining < -self.overdraft_limit => Err(BankError::InsufficientFunds),
+            AccountType::Savings if remaining < Money::ZERO => Err(BankError::InsufficientFunds),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn withdraw(&mut self, amount: Money) -> Result<(), BankError> {
+        self.withdraw_with_memo(amount, None)
+    }
+
+    pub fn withdraw_with_memo(&mut self, amount: Money, memo: Option<String>) -> Result<(), BankError> {
+        let now = SystemTime::now();
+        self.check_withdrawal_velocity(amount, now)?;
+        self.debit(TransactionKind::Withdrawal(amount), memo, now)
+    }
+
+    // Writes `entry`, a Withdrawal or a TransferOut, and the fee schedule's fee once the free
+    // allowance is used up. Transfers have their own velocity checks, so none are made here.
+    fn debit(&mut self, entry: TransactionKind, memo: Option<String>, now: SystemTime) -> Result<(), BankError> {
+        let amount = entry.amount();
+        if amount <= Money::ZERO {
+            return Err(BankError::InvalidAmount);
+        }
+        let fee_type = match entry {
+            TransactionKind::TransferOut(..) => FeeType::Transfer,
+            _ => FeeType::Transaction,
+        };
+        let fee = self.withdrawal_fee(now);
+        self.check_debit(amount.checked_add(fee)?, now)?;
+        let after_debit = self.balance.checked_sub(amount)?;
+        let after_fee = after_debit.checked_sub(fee)?;
+
+        self.balance = after_debit;
+        let currency = self.currency.clone();
+        self.record_at(entry, currency.clone(), memo, now);
+        if fee > Money::ZERO {
+            self.balance = after_fee;
+            self.record_at(TransactionKind::Fee(fee, fee_type), currency, None, now);
+        }
+        Ok(())
+    }
+
+    // The incoming leg of a transfer, dated with the outgoing one so the two can be paired.
+    fn receive_transfer(&mut self, amount: Money, from: String, sent_at: SystemTime) -> Result<(), BankError> {
+        if amount <= Money::ZERO {
+            return Err(BankError::InvalidAmount);
+        }
+        self.check_open()?;
+        self.balance = self.balance.checked_add(amount)?;
+        let currency = self.currency.clone();
+        self.record_at(TransactionKind::TransferIn(amount, from), currency, None, sent_at);
+        Ok(())
+    }
+
+    pub fn balance(&self) -> Money {
+        self.balance
+    }
+
+    // Cash withdrawn today in the account's currency. Transfers write TransferOut legs rather
+    // than Withdrawals, so they do not count.
+    fn withdrawn_on_day(&self, now: SystemTime) -> Money {
+        let today = calendar::day_number(now);
+        self.transactions
+            .iter()
+            .filter(|t| t.currency == self.currency && calendar::day_number(t.timestamp) == today)
+            .map(|t| match t.kind {
+                TransactionKind::Withdrawal(amount) => amount,
+                _ => Money::ZERO,
+            })
+            .sum()
+    }
+
+    fn transfers_on_day(&self, now: SystemTime) -> u32 {
+        let today = calendar::day_number(now);
+        self.transactions
+            .iter()
+            .filter(|t| matches!(t.kind, TransactionKind::TransferOut(..)) && calendar::day_number(t.timestamp) == today)
+            .count() as u32
+    }
+
+    pub fn remaining_daily_allowance(&self, now: SystemTime) -> DailyAllowance {
+        let limits = &self.velocity_limits;
+        DailyAllowance {
+            withdrawal: limits.daily_withdrawal_total.map(|total| (total - self.withdrawn_on_day(now)).max(Money::ZERO)),
+            transfers: limits.daily_transfer_count.map(|count| count.saturating_sub(self.transfers_on_day(now))),
+        }
+    }
+
+    pub fn check_withdrawal_velocity(&self, amount: Money, now: SystemTime) -> Result<(), BankError> {
+        if self.velocity_limits.max_withdrawal.is_some_and(|max| amount > max) {
+            return Err(BankError::VelocityLimitExceeded(VelocityLimit::MaxWithdrawal));
+        }
+        match self.remaining_daily_allowance(now).withdrawal {
+            Some(remaining) if amount > remaining => Err(BankError::VelocityLimitExceeded(VelocityLimit::DailyWithdrawalTotal)),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn check_transfer_velocity(&self, now: SystemTime) -> Result<(), BankError> {
+        match self.remaining_daily_allowance(now).transfers {
+            Some(0) => Err(BankError::VelocityLimitExceeded(VelocityLimit::DailyTransferCount)),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn holds(&self) -> &BTreeMap<u64, AuthHold> {
+        &self.auth_holds
+    }
+
+    // The balance less any outstanding authorization holds.
+    pub fn available_balance(&self) -> Money {
+        self.balance - self.auth_holds.values().map(|hold| hold.amount).sum()
+    }
+
+    fn checked_available_balance(&self) -> Result<Money, BankError> {
+        let held = self.auth_holds.values().try_fold(Money::ZERO, |held, hold| held.checked_add(hold.amount))?;
+        self.balance.checked_sub(held)
+    }
+
+    // Start of the entries written by the operation ending just before `end`: a deposit, a
+    // withdrawal or outgoing transfer with its fee, or an incoming transfer with its conversion.
+    pub fn operation_start(&self, end: usize) -> Option<usize> {
+        let kinds: Vec<&TransactionKind> = self.transactions[..end].iter().map(|t| &t.kind).collect();
+        let len = match kinds.as_slice() {
+            [.., TransactionKind::TransferIn(..), TransactionKind::Conversion(..)] => 2,
+            [.., TransactionKind::Withdrawal(_), TransactionKind::Fee(_, FeeType::Transaction)] => 2,
+            [.., TransactionKind::TransferOut(..), TransactionKind::Fee(_, FeeType::Transfer)] => 2,
+            [.., TransactionKind::Deposit(_) | TransactionKind::Withdrawal(_)] => 1,
+            [.., TransactionKind::TransferOut(..) | TransactionKind::TransferIn(..)] => 1,
+            _ => return None,
+        };
+        Some(end - len)
+    }
+
+    // The entries written by the operation that wrote the entry at `index`.
+    fn operation_containing(&self, index: usize) -> Option<Range<usize>> {
+        // An operation is at most two entries, so it ends within two of any of them.
+        (index + 1..=(index + 2).min(self.transactions.len()))
+            .rev()
+            .find_map(|end| self.operation_start(end).filter(|start| *start <= index).map(|start| start..end))
+    }
+
+    pub fn balance_in(&self, currency: &Currency) -> Money {
+        if *currency == self.currency {
+            self.balance
+        } else {
+            self.foreign_balances.get(currency).copied().unwrap_or_default()
+        }
+    }
+
+    pub fn deposit_currency(&mut self, amount: Money, currency: &Currency) -> Result<(), BankError> {
+        if *currency == self.currency {
+            return self.deposit(amount);
+        }
+        if amount <= Money::ZERO {
+            return Err(BankError::InvalidAmount);
+        }
+        self.check_open()?;
+        let balance = self.foreign_balances.entry(currency.clone()).or_default();
+        *balance = balance.checked_add(amount)?;
+        self.record_in(TransactionKind::Deposit(amount), currency.clone());
+        Ok(())
+    }
+
+    pub fn withdraw_currency(&mut self, amount: Money, currency: &Currency) -> Result<(), BankError> {
+        if *currency == self.currency {
+            return self.withdraw(amount);
+        }
+        if amount <= Money::ZERO {
+            return Err(BankError::InvalidAmount);
+        }
+        self.check_open()?;
+        self.check_withdrawal_hold(SystemTime::now())?;
+        match self.foreign_balances.get_mut(currency) {
+            Some(balance) if *balance >= amount => {
+                *balance = balance.checked_sub(amount)?;
+                self.record_in(TransactionKind::Withdrawal(amount), currency.clone());
+                Ok(())
+            }
+            _ => Err(BankError::InsufficientFunds),
+        }
+    }
+
+    // Before maturity, forfeits `penalty_rate` of the interest earned so far (net of earlier
+    // penalties) as an EarlyWithdrawal fee. Returns the penalty charged.
+    pub fn withdraw_with_penalty(&mut self, amount: Money, penalty_rate: f64, now: SystemTime) -> Result<Money, BankError> {
+        let penalty = match self.maturity {
+            Some(maturity) if now < maturity => {
+                let net_interest: Money = self
+                    .transactions
+                    .iter()
+                    .map(|t| match t.kind {
+                        TransactionKind::Interest(amount) => amount,
+                        TransactionKind::Fee(amount, FeeType::EarlyWithdrawal) => -amount,
+                        _ => Money::ZERO,
+                    })
+                    .sum();
+                net_interest.max(Money::ZERO).scale(penalty_rate)
+            }
+            _ => Money::ZERO,
+        };
+        self.check_debit(amount.checked_add(self.withdrawal_fee(now))?.checked_add(penalty)?, now)?;
+        self.withdraw(amount)?;
+        if penalty > Money::ZERO {
+            self.balance = self.balance.checked_sub(penalty)?;
+            self.record(TransactionKind::Fee(penalty, FeeType::EarlyWithdrawal));
+        }
+        Ok(penalty)
+    }
+
+    // When the balance last went negative, found by walking the history back to the entry
+    // that took it below zero. None when the balance is not negative.
+    pub fn overdrawn_since(&self) -> Option<SystemTime> {
+        if self.balance >= Money::ZERO {
+            return None;
+        }
+        let mut after = self.balance;
+        for transaction in self.transactions.iter().rev() {
+            let before = after - transaction.kind.balance_effect();
+            if before >= Money::ZERO {
+                return Some(transaction.timestamp);
+            }
+            after = before;
+        }
+        Some(self.created_at)
+    }
+
+    // Withdrawals and outgoing transfers summed by category; counterparty rules apply to the
+    // transfers.
+    pub fn spending_by_category(&self, rules: &[CategoryRule]) -> BTreeMap<Category, Money> {
+        let mut spending = BTreeMap::new();
+        for transaction in &self.transactions {
+            let amount = match &transaction.kind {
+                TransactionKind::Withdrawal(amount) | TransactionKind::TransferOut(amount, _) => *amount,
+                _ => continue,
+            };
+            *spending.entry(classify_transaction(transaction, rules)).or_default() += amount;
+        }
+        spending
+    }
+
+    pub fn currency(&self) -> &Currency {
+        &self.currency
+    }
+
+    pub fn account_type(&self) -> &AccountType {
+        &self.account_type
+    }
+
+    pub fn status(&self) -> AccountStatus {
+        self.status
+    }
+
+    // Time since the last transaction, or since opening for an account without any. None when
+    // that is after `now`.
+    pub fn idle_for(&self, now: SystemTime) -> Option<Duration> {
+        let last_activity = self.transactions.iter().map(|t| t.timestamp).max().unwrap_or(self.created_at);
+        now.duration_since(last_activity).ok()
+    }
+
+    pub fn check_open(&self) -> Result<(), BankError> {
+        match self.status {
+            AccountStatus::Open => Ok(()),
+            AccountStatus::Frozen => Err(BankError::AccountInactive),
+            AccountStatus::Closed => Err(BankError::AccountClosed),
+        }
+    }
+
+    // Moves the account along Open <-> Frozen -> Closed. Closed accounts stay closed.
+    fn change_status(&mut self, to: AccountStatus) -> Result<(), BankError> {
+        match (self.status, to) {
+            (AccountStatus::Closed, _) => Err(BankError::AccountClosed),
+            (from, to) if from == to => Err(BankError::InvalidStatusChange(from, to)),
+            (_, to) => {
+                self.status = to;
+                Ok(())
+            }
+        }
+    }
+
+    pub fn owner(&self) -> Option<&str> {
+        self.owner.as_deref()
+    }
+
+    pub fn transactions(&self) -> &Vec<Transaction> {
+        &self.transactions
+    }
+
+    pub fn tags(&self) -> &BTreeSet<String> {
+        &self.tags
+    }
+
+    // What the entries in the account's currency add up to.
+    pub fn ledger_sum(&self) -> Money {
+        self.balance_effect_where(|_| true)
+    }
+
+    pub fn verify_balance(&self) -> Result<(), (Money, Money)> {
+        let computed = self.ledger_sum();
+        if computed == self.balance {
+            Ok(())
+        } else {
+            Err((computed, self.balance))
+        }
+    }
+
+    // Incoming entries are given fresh IDs from this account's sequence.
+    pub fn merge_transactions(&mut self, incoming: Vec<Transaction>) {
+        for mut transaction in incoming {
+            transaction.id = self.next_transaction_id;
+            self.next_transaction_id += 1;
+            self.transactions.push(transaction);
+        }
+        self.transactions.sort_by_key(|t| t.timestamp);
+        self.rechain();
+        self.recompute_balances();
+        self.restate_running_balances();
+    }
+
+    pub fn recompute_balances(&mut self) {
+        self.balance = Money::ZERO;
+        self.foreign_balances.clear();
+        for transaction in &self.transactions {
+            let effect = transaction.kind.balance_effect();
+            if transaction.currency == self.currency {
+                self.balance += effect;
+            } else {
+                *self.foreign_balances.entry(transaction.currency.clone()).or_default() += effect;
+            }
+        }
+    }
+
+    pub fn last_balance_change(&self) -> Option<&Transaction> {
+        self.transactions.iter().rev().find(|t| t.kind.balance_effect() != Money::ZERO)
+    }
+
+    pub fn top_counterparties(&self, n: usize) -> Vec<(String, usize, Money)> {
+        let mut totals: BTreeMap<&str, (usize, Money)> = BTreeMap::new();
+        for transaction in &self.transactions {
+            if let TransactionKind::TransferOut(amount, other) | TransactionKind::TransferIn(amount, other) = &transaction.kind {
+                let entry = totals.entry(other).or_default();
+                entry.0 += 1;
+                entry.1 += *amount;
+            }
+        }
+        let mut ranked: Vec<(String, usize, Money)> =
+            totals.into_iter().map(|(other, (count, total))| (other.to_string(), count, total)).collect();
+        ranked.sort_by(|a, b| b.2.cmp(&a.2).then(b.1.cmp(&a.1)));
+        ranked.truncate(n);
+        ranked
+    }
+
+    // Fraction of the amount sent by transfer that went to accounts outside `known_internal`;
+    // 0 when nothing was sent.
+    pub fn external_transfer_ratio(&self, known_internal: &HashSet<String>) -> f64 {
+        let (external, total) = self
+            .transactions
+            .iter()
+            .filter_map(|t| match &t.kind {
+                TransactionKind::TransferOut(amount, to) => Some((*amount, to)),
+                _ => None,
+            })
+            .fold((Money::ZERO, Money::ZERO), |(external, total), (amount, to)| {
+                let external = if known_internal.contains(to) { external } else { external + amount };
+                (external, total + amount)
+            });
+        if total == Money::ZERO {
+            return 0.0;
+        }
+        external.cents() as f64 / total.cents() as f64
+    }
+
+    pub fn transaction(&self, id: u64) -> Option<&Transaction> {
+        self.transactions.iter().find(|t| t.id == id)
+    }
+
+    // Entries within `period` (if given) whose kind is named `kind` (if given), e.g. "Fee".
+    pub fn ledger(&self, period: Option<&Period>, kind: Option<&str>) -> Vec<&Transaction> {
+        self.transactions
+            .iter()
+            .filter(|t| period.is_none_or(|period| period.contains(t.timestamp)))
+            .filter(|t| kind.is_none_or(|kind| t.kind.name() == kind))
+            .collect()
+    }
+
+    pub fn find_transaction_where(&self, pred: impl Fn(&Transaction) -> bool) -> Option<(usize, &Transaction)> {
+        self.transactions.iter().enumerate().find(|(_, transaction)| pred(transaction))
+    }
+
+    // Nearest-rank percentiles of the sizes of entries that moved money, with each `p` a
+    // fraction (0.5 for the median). Transfer legs and other notes are not counted separately
+    // from the withdrawal or deposit that moved the funds. Zero for every `p` on an empty log.
+    pub fn transaction_size_percentiles(&self, ps: &[f64]) -> Vec<Money> {
+        let mut sizes: Vec<Money> = self
+            .transactions
+            .iter()
+            .map(|t| t.kind.balance_effect().abs())
+            .filter(|size| *size > Money::ZERO)
+            .collect();
+        if sizes.is_empty() {
+            return vec![Money::ZERO; ps.len()];
+        }
+        sizes.sort_unstable();
+        ps.iter()
+            .map(|p| {
+                let rank = (p.clamp(0.0, 1.0) * sizes.len() as f64).ceil() as usize;
+                sizes[rank.clamp(1, sizes.len()) - 1]
+            })
+            .collect()
+    }
+
+    // At the account's own rate only; Bank::effective_rate also falls back to its type's rate.
+    pub fn is_interest_bearing(&self) -> bool {
+        self.is_interest_bearing_at(self.interest_rate)
+    }
+
+    fn is_interest_bearing_at(&self, rate: f64) -> bool {
+        let bonus = self.bonus_rate_cap.is_some() && self.bonus_rate > 0.0;
+        !matches!(self.account_type, AccountType::Credit) && (rate > 0.0 || bonus) && self.balance > Money::ZERO
+    }
+
+    // Interest on `basis` over `years`, splitting it at the bonus cap when there is one.
+    pub fn interest_on(&self, basis: Money, years: f64) -> Money {
+        self.interest_at(basis, years, self.interest_rate, 0.0)
+    }
+
+    // As interest_on at `rate` below the bonus cap, with both rates moved by `rate_delta` (but
+    // never below zero).
+    fn interest_at(&self, basis: Money, years: f64, rate: f64, rate_delta: f64) -> Money {
+        let at_rate =
+            |amount: Money, rate: f64| amount.scale(self.compound_frequency.growth((rate + rate_delta).max(0.0), years));
+        match self.bonus_rate_cap {
+            Some(cap) => {
+                let bonus_part = basis.min(cap).max(Money::ZERO);
+                at_rate(bonus_part, self.bonus_rate) + at_rate(basis - bonus_part, rate)
+            }
+            None => at_rate(basis, rate),
+        }
+    }
+
+    pub fn projected_interest(&self, months: u32) -> Money {
+        self.projected_interest_at(months, self.interest_rate)
+    }
+
+    fn projected_interest_at(&self, months: u32, rate: f64) -> Money {
+        if !self.is_interest_bearing_at(rate) {
+            return Money::ZERO;
+        }
+        self.interest_at(self.balance, months as f64 / 12.0, rate, 0.0)
+    }
+
+    // What projected_interest would be if the account's rates moved by `rate_delta`.
+    pub fn projected_interest_shifted(&self, months: u32, rate_delta: f64) -> Money {
+        self.projected_interest_shifted_at(months, self.interest_rate, rate_delta)
+    }
+
+    fn projected_interest_shifted_at(&self, months: u32, rate: f64, rate_delta: f64) -> Money {
+        if matches!(self.account_type, AccountType::Credit) || self.balance <= Money::ZERO {
+            return Money::ZERO;
+        }
+        self.interest_at(self.balance, months as f64 / 12.0, rate, rate_delta)
+    }
+
+    pub fn daily_closing_balances(&self, from: SystemTime, to: SystemTime) -> Vec<Money> {
+        let first_day = calendar::day_number(from);
+        let last_day = calendar::day_number(to);
+        if last_day < first_day {
+            return Vec::new();
+        }
+
+        let mut changes: BTreeMap<i64, Money> = BTreeMap::new();
+        for transaction in self.transactions.iter().filter(|t| t.currency == self.currency) {
+            *changes.entry(calendar::day_number(transaction.timestamp)).or_default() += transaction.kind.balance_effect();
+        }
+
+        let mut balance = self.balance - changes.range(first_day..).map(|(_, change)| *change).sum::<Money>();
+        (first_day..=last_day)
+            .map(|day| {
+                balance += changes.get(&day).copied().unwrap_or_default();
+                balance
+            })
+            .collect()
+    }
+
+    // Rounded to the nearest cent.
+    pub fn average_daily_balance(&self, from: SystemTime, to: SystemTime) -> Money {
+        let balances = self.daily_closing_balances(from, to);
+        if balances.is_empty() {
+            return Money::ZERO;
+        }
+        balances.iter().copied().sum::<Money>().scale(1.0 / balances.len() as f64)
+    }
+
+    // Standard deviation of the daily closing balances, in currency units.
+    pub fn balance_volatility(&self, from: SystemTime, to: SystemTime) -> f64 {
+        let balances: Vec<f64> = self.daily_closing_balances(from, to).into_iter().map(Money::to_f64).collect();
+        if balances.is_empty() {
+            return 0.0;
+        }
+        let mean = balances.iter().sum::<f64>() / balances.len() as f64;
+        let variance = balances.iter().map(|balance| (balance - mean).powi(2)).sum::<f64>() / balances.len() as f64;
+        variance.sqrt()
+    }
+
+    pub fn interest_for_period(&self, from: SystemTime, to: SystemTime) -> Money {
+        self.interest_on(self.interest_basis_for(from, to), calendar::years_between(from, to))
+    }
+
+    // The balance interest for the period is worked out on, per the account's interest basis.
+    pub fn interest_basis_for(&self, from: SystemTime, to: SystemTime) -> Money {
+        match self.interest_basis {
+            InterestBasis::EndingBalance => self.balance - self.balance_effect_where(|t| t.timestamp > to),
+            InterestBasis::AverageDailyBalance => self.average_daily_balance(from, to),
+        }
+    }
+
+    // Whether the account carries a rate of its own rather than its type's default.
+    pub fn has_own_rate(&self) -> bool {
+        self.interest_rate > 0.0 || (self.bonus_rate_cap.is_some() && self.bonus_rate > 0.0)
+    }
+
+    // Days until the balance reaches zero at the average daily net outflow over the last
+    // BURN_RATE_WINDOW_DAYS. None when the balance held steady or grew over that window.
+    pub fn days_to_depletion(&self, now: SystemTime) -> Option<u32> {
+        let window_start = now - Duration::from_secs(BURN_RATE_WINDOW_DAYS * 86_400);
+        let start_balance = self.balance - self.balance_effect_where(|t| t.timestamp > window_start);
+        let balance = self.balance - self.balance_effect_where(|t| t.timestamp > now);
+        let daily_outflow = (start_balance - balance).to_f64() / BURN_RATE_WINDOW_DAYS as f64;
+        if daily_outflow <= 0.0 {
+            return None;
+        }
+        if balance <= Money::ZERO {
+            return Some(0);
+        }
+        Some((balance.to_f64() / daily_outflow).ceil() as u32)
+    }
+
+    pub fn days_overdrawn(&self, now: SystemTime) -> u32 {
+        let mut history: Vec<&Transaction> = self.transactions.iter().filter(|t| t.currency == self.currency).collect();
+        history.sort_by_key(|t| t.timestamp);
+
+        let mut balance = Money::ZERO;
+        let mut overdrawn_since: Option<i64> = None;
+        let mut last_counted = i64::MIN;
+        let mut days = 0;
+        let mut count_days = |from: i64, to: i64| {
+            let from = from.max(last_counted.saturating_add(1));
+            if to >= from {
+                days += (to - from + 1) as u32;
+                last_counted = to;
+            }
+        };
+        for transaction in history {
+            let day = calendar::day_number(transaction.timestamp);
+            balance += transaction.kind.balance_effect();
+            match overdrawn_since {
+                None if balance < Money::ZERO => overdrawn_since = Some(day),
+                Some(since) if balance >= Money::ZERO => {
+                    count_days(since, day);
+                    overdrawn_since = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(since) = overdrawn_since {
+            count_days(since, calendar::day_number(now));
+        }
+        days
+    }
+
+    pub fn real_return(&self, nominal_rate: f64, inflation_rate: f64) -> f64 {
+        (1.0 + nominal_rate) / (1.0 + inflation_rate) - 1.0
+    }
+
+    pub fn months_to_goal(&self, goal: Money, monthly_contribution: Money, rate: f64) -> Option<u32> {
+        const MAX_MONTHS: u32 = 12 * 200;
+        let (goal, monthly_contribution) = (goal.to_f64(), monthly_contribution.to_f64());
+        let mut balance = self.balance.to_f64();
+        let mut months = 0;
+        while balance < goal {
+            if months == MAX_MONTHS {
+                return None;
+            }
+            let next = balance * (1.0 + rate / 12.0) + monthly_contribution;
+            if next <= balance {
+                return None;
+            }
+            balance = next;
+            months += 1;
+        }
+        Some(months)
+    }
+
+    // Smallest monthly transaction count at which a flat monthly fee beats paying
+    // `per_tx_fee` for every transaction beyond `free_count`; u32::MAX if it never does.
+    pub fn fee_comparison(&self, per_tx_fee: Money, free_count: u32, flat_fee: Money) -> u32 {
+        if per_tx_fee <= Money::ZERO {
+            return u32::MAX;
+        }
+        if flat_fee < Money::ZERO {
+            return 0;
+        }
+        let paid_at_break_even = u32::try_from(flat_fee.cents() / per_tx_fee.cents()).unwrap_or(u32::MAX);
+        free_count.saturating_add(paid_at_break_even).saturating_add(1)
+    }
+
+    // Deposits are always free; only withdrawals beyond the free allowance are charged.
+    pub fn expected_monthly_fees(&self, usage: UsageProfile) -> Money {
+        let schedule = match &self.fee_schedule {
+            Some(schedule) => schedule,
+            None => return Money::ZERO,
+        };
+        let maintenance = match schedule.maintenance_waiver_balance {
+            Some(threshold) if usage.average_balance >= threshold => Money::ZERO,
+            _ => schedule.monthly_maintenance,
+        };
+        let overage = usage.withdrawals.saturating_sub(schedule.free_withdrawals);
+        maintenance + schedule.fee * overage as i64
+    }
+
+    // Each payment is rounded to the cent; the final payment absorbs the rounding.
+    pub fn amortization_schedule(&self, principal: Money, rate: f64, months: u32) -> Vec<Payment> {
+        if !matches!(self.account_type, AccountType::Credit) || months == 0 {
+            return Vec::new();
+        }
+
+        let monthly_rate = rate / 12.0;
+        let payment = if monthly_rate == 0.0 {
+            principal.scale(1.0 / months as f64)
+        } else {
+            principal.scale(monthly_rate / (1.0 - (1.0 + monthly_rate).powi(-(months as i32))))
+        };
+
+        let mut remaining = principal;
+        let mut schedule = Vec::with_capacity(months as usize);
+        for period in 1..=months {
+            let interest = remaining.scale(monthly_rate);
+            let principal_portion = if period == months { remaining } else { payment - interest };
+            remaining -= principal_portion;
+            schedule.push(Payment {
+                period,
+                amount: principal_portion + interest,
+                principal: principal_portion,
+                interest,
+                remaining,
+            });
+        }
+        schedule
+    }
+
+    pub fn to_json(&self) -> json::Value {
+        let fee_schedule = match &self.fee_schedule {
+            Some(schedule) => json::object(vec![
+                ("free_withdrawals", json::Value::Number(schedule.free_withdrawals as f64)),
+                ("fee", schedule.fee.to_json()),
+                ("monthly_maintenance", schedule.monthly_maintenance.to_json()),
+                (
+                    "maintenance_waiver_balance",
+                    schedule.maintenance_waiver_balance.map_or(json::Value::Null, Money::to_json),
+                ),
+            ]),
+            None => json::Value::Null,
+        };
+        let temporary_credit = match &self.temporary_credit {
+            Some(temporary) => json::object(vec![
+                ("extra", temporary.extra.to_json()),
+                ("until", json::Value::Number(calendar::to_millis(temporary.until) as f64)),
+            ]),
+            None => json::Value::Null,
+        };
+        json::object(vec![
+            ("account_type", json::string(self.account_type.name())),
+            ("created_at", json::Value::Number(calendar::to_millis(self.created_at) as f64)),
+            ("balance", self.balance.to_json()),
+            ("status", json::string(self.status.name())),
+            ("interest_rate", json::Value::Number(self.interest_rate)),
+            ("bonus_rate", json::Value::Number(self.bonus_rate)),
+            ("bonus_rate_cap", self.bonus_rate_cap.map_or(json::Value::Null, Money::to_json)),
+            ("fee_schedule", fee_schedule),
+            ("interest_basis", json::string(self.interest_basis.name())),
+            ("compound_frequency", json::string(self.compound_frequency.name())),
+            (
+                "pin_hash",
+                match &self.pin_hash {
+                    Some(hash) => json::string(hash),
+                    None => json::Value::Null,
+                },
+            ),
+            ("pin_salt", json::string(&self.pin_salt)),
+            ("failed_pin_attempts", json::Value::Number(self.failed_pin_attempts as f64)),
+            ("tags", json::Value::Array(self.tags.iter().map(|tag| json::string(tag)).collect())),
+            ("owner", self.owner.as_deref().map_or(json::Value::Null, json::string)),
+            (
+                "holders",
+                json::Value::Object(self.holders.iter().map(|(id, role)| (id.clone(), json::string(role.name()))).collect()),
+            ),
+            ("imported_ids", json::Value::Array(self.imported_ids.iter().map(|id| json::string(id)).collect())),
+            (
+                "auth_holds",
+                json::Value::Array(
+                    self.auth_holds
+                        .iter()
+                        .map(|(id, hold)| {
+                            json::object(vec![
+                                ("id", json::Value::Number(*id as f64)),
+                                ("amount", hold.amount.to_json()),
+                                ("expires", json::Value::Number(calendar::to_millis(hold.expires) as f64)),
+                            ])
+                        })
+                        .collect(),
+                ),
+            ),
+            ("statement_delivery", json::string(self.statement_delivery.name())),
+            ("currency", json::string(self.currency.code())),
+            ("credit_limit", self.credit_limit.to_json()),
+            ("overdraft_limit", self.overdraft_limit.to_json()),
+            ("minimum_balance", self.minimum_balance.to_json()),
+            ("temporary_credit", temporary_credit),
+            (
+                "withdrawal_hold_until",
+                match self.withdrawal_hold_until {
+                    Some(until) => json::Value::Number(calendar::to_millis(until) as f64),
+                    None => json::Value::Null,
+                },
+            ),
+            (
+                "maturity",
+                match self.maturity {
+                    Some(maturity) => json::Value::Number(calendar::to_millis(maturity) as f64),
+                    None => json::Value::Null,
+                },
+            ),
+            (
+                "foreign_balances",
+                json::Value::Object(
+                    self.foreign_balances
+                        .iter()
+                        .map(|(currency, balance)| (currency.code().to_string(), balance.to_json()))
+                        .collect(),
+                ),
+            ),
+            ("transactions", json::Value::Array(self.transactions.iter().map(Transaction::to_json).collect())),
+        ])
+    }
+
+    pub fn from_json(value: &json::Value) -> Result<Account, String> {
+        let account_type = value
+            .get("account_type")
+            .and_then(json::Value::as_str)
+            .and_then(AccountType::from_name)
+            .ok_or("missing or unknown account_type")?;
+        let mut account = Account::new(account_type);
+        if let Some(created_at) = value.get("created_at").and_then(json::Value::as_f64) {
+            account.created_at = calendar::from_millis(created_at as i64);
+        }
+        account.balance = value.get("balance").and_then(Money::from_json).ok_or("missing balance")?;
+        // Files from before account statuses only say whether the account was active.
+        account.status = match value.get("status").and_then(json::Value::as_str) {
+            Some(name) => AccountStatus::from_name(name).ok_or(format!("unknown account status {}", name))?,
+            None if value.get("is_active").and_then(json::Value::as_bool) == Some(false) => AccountStatus::Frozen,
+            None => AccountStatus::Open,
+        };
+        account.interest_rate = value.get("interest_rate").and_then(json::Value::as_f64).unwrap_or(0.0);
+        account.bonus_rate = value.get("bonus_rate").and_then(json::Value::as_f64).unwrap_or(0.0);
+        account.bonus_rate_cap = value.get("bonus_rate_cap").and_then(Money::from_json);
+        account.fee_schedule = match value.get("fee_schedule") {
+            Some(schedule @ json::Value::Object(_)) => Some(FeeSchedule {
+                free_withdrawals: schedule.get("free_withdrawals").and_then(json::Value::as_f64).ok_or("invalid fee_schedule")?
+                    as u32,
+                fee: schedule.get("fee").and_then(Money::from_json).ok_or("invalid fee_schedule")?,
+                monthly_maintenance: schedule.get("monthly_maintenance").and_then(Money::from_json).unwrap_or_default(),
+                maintenance_waiver_balance: schedule.get("maintenance_waiver_balance").and_then(Money::from_json),
+            }),
+            _ => None,
+        };
+        account.pin_hash = value.get("pin_hash").and_then(json::Value::as_str).map(str::to_string);
+        account.pin_salt = value.get("pin_salt").and_then(json::Value::as_str).unwrap_or("").to_string();
+        account.failed_pin_attempts = value.get("failed_pin_attempts").and_then(json::Value::as_f64).unwrap_or(0.0) as u32;
+        if let Some(tags) = value.get("tags").and_then(json::Value::as_array) {
+            account.tags = tags.iter().filter_map(json::Value::as_str).map(str::to_string).collect();
+        }
+        account.owner = value.get("owner").and_then(json::Value::as_str).map(str::to_string);
+        if let Some(json::Value::Object(holders)) = value.get("holders") {
+            for (id, role) in holders {
+                let role = role.as_str().and_then(auth::Role::from_name).ok_or(format!("unknown role for holder {}", id))?;
+                account.holders.insert(id.clone(), role);
+            }
+        }
+        if let Some(ids) = value.get("imported_ids").and_then(json::Value::as_array) {
+            account.imported_ids = ids.iter().filter_map(json::Value::as_str).map(str::to_string).collect();
+        }
+        if let Some(holds) = value.get("auth_holds").and_then(json::Value::as_array) {
+            for hold in holds {
+                let id = hold.get("id").and_then(json::Value::as_f64).ok_or("invalid auth_holds")? as u64;
+                let amount = hold.get("amount").and_then(Money::from_json).ok_or("invalid auth_holds")?;
+                let expires = hold.get("expires").and_then(json::Value::as_f64).ok_or("invalid auth_holds")?;
+                account.auth_holds.insert(id, AuthHold { amount, expires: calendar::from_millis(expires as i64) });
+            }
+        }
+        account.compound_frequency = value
+            .get("compound_frequency")
+            .and_then(json::Value::as_str)
+            .and_then(CompoundFrequency::from_name)
+            .unwrap_or(CompoundFrequency::Monthly);
+        account.interest_basis = value
+            .get("interest_basis")
+            .and_then(json::Value::as_str)
+            .and_then(InterestBasis::from_name)
+            .unwrap_or(InterestBasis::EndingBalance);
+        account.statement_delivery = value
+            .get("statement_delivery")
+            .and_then(json::Value::as_str)
+            .and_then(DeliveryMethod::from_name)
+            .unwrap_or(DeliveryMethod::Paper);
+        account.currency = value
+            .get("currency")
+            .and_then(json::Value::as_str)
+            .map(Currency::new)
+            .unwrap_or_default();
+        account.credit_limit = value.get("credit_limit").and_then(Money::from_json).unwrap_or_default();
+        account.overdraft_limit = value.get("overdraft_limit").and_then(Money::from_json).unwrap_or_default();
+        account.minimum_balance = value.get("minimum_balance").and_then(Money::from_json).unwrap_or_default();
+        account.temporary_credit = match value.get("temporary_credit") {
+            Some(temporary @ json::Value::Object(_)) => Some(TemporaryCredit {
+                extra: temporary.get("extra").and_then(Money::from_json).ok_or("invalid temporary_credit")?,
+                until: calendar::from_millis(
+                    temporary.get("until").and_then(json::Value::as_f64).ok_or("invalid temporary_credit")? as i64,
+                ),
+            }),
+            _ => None,
+        };
+        account.withdrawal_hold_until = value
+            .get("withdrawal_hold_until")
+            .and_then(json::Value::as_f64)
+            .map(|until| calendar::from_millis(until as i64));
+        account.maturity = value
+            .get("maturity")
+            .and_then(json::Value::as_f64)
+            .map(|maturity| calendar::from_millis(maturity as i64));
+        if let Some(json::Value::Object(balances)) = value.get("foreign_balances") {
+            for (code, balance) in balances {
+                let balance = Money::from_json(balance).ok_or("invalid foreign balance")?;
+                account.foreign_balances.insert(Currency::new(code), balance);
+            }
+        }
+        if let Some(transactions) = value.get("transactions").and_then(json::Value::as_array) {
+            for transaction in transactions {
+                account.transactions.push(Transaction::from_json(transaction)?);
+            }
+            // Files written before transactions had IDs are numbered in ledger order.
+            if transactions.iter().any(|t| t.get("id").is_none()) {
+                for (index, transaction) in account.transactions.iter_mut().enumerate() {
+                    transaction.id = index as u64 + 1;
+                }
+            }
+            account.next_transaction_id = account.transactions.iter().map(|t| t.id + 1).max().unwrap_or(1);
+            // Files written before transactions were chained carry no hashes to check.
+            if transactions.iter().any(|t| t.get("chain_hash").is_none()) {
+                account.rechain();
+            }
+            if transactions.iter().any(|t| t.get("balance_after").is_none()) {
+                account.restate_running_balances();
+            }
+        }
+        Ok(account)
+    }
+
+    pub fn set_statement_delivery(&mut self, method: DeliveryMethod) {
+        self.statement_delivery = method;
+    }
+
+    pub fn balance_effect_where(&self, pred: impl Fn(&Transaction) -> bool) -> Money {
+        self.transactions
+            .iter()
+            .filter(|t| t.currency == self.currency && pred(t))
+            .map(|t| t.kind.balance_effect())
+            .sum()
+    }
+
+    pub fn statement(&self, account_number: &str, period: &Period) -> Statement {
+        Statement {
+            account_number: account_number.to_string(),
+            period: period.clone(),
+            delivery: self.statement_delivery,
+            opening_balance: self.balance - self.balance_effect_where(|t| t.timestamp >= period.from),
+            closing_balance: self.balance - self.balance_effect_where(|t| t.timestamp > period.to),
+            transactions: self.transactions.iter().filter(|t| period.contains(t.timestamp)).cloned().collect(),
+        }
+    }
+
+    pub fn statements(&self) -> &VecDeque<Statement> {
+        &self.statements
+    }
+
+    pub fn retain_statement(&mut self, statement: Statement, keep_last: usize) {
+        self.statements.push_back(statement);
+        while self.statements.len() > keep_last {
+            self.statements.pop_front();
+        }
+    }
+
+    pub fn hash_pin(salt: &str, pin: &str) -> String {
+        sha256::hex_digest(format!("{}:{}", salt, pin).as_bytes())
+    }
+
+    pub fn set_pin(&mut self, pin: &str) {
+        let salt = RandomState::new().build_hasher().finish();
+        self.pin_salt = format!("{:016x}", salt);
+        self.pin_hash = Some(Account::hash_pin(&self.pin_salt, pin));
+        self.failed_pin_attempts = 0;
+    }
+
+    // Consecutive failures lock the PIN once `max_attempts` is reached; it then stays locked,
+    // even for the correct PIN, until an operator unfreezes the account. Freezing it is left to
+    // Bank::verify_pin, so the lockout is recorded like any other freeze.
+    pub fn verify_pin(&mut self, pin: &str, max_attempts: u32) -> Result<(), BankError> {
+        let expected = self.pin_hash.as_ref().ok_or(BankError::PinNotSet)?;
+        if self.failed_pin_attempts >= max_attempts {
+            return Err(BankError::AccountLocked);
+        }
+        if *expected == Account::hash_pin(&self.pin_salt, pin) {
+            self.failed_pin_attempts = 0;
+            Ok(())
+        } else {
+            self.failed_pin_attempts += 1;
+            if self.failed_pin_attempts >= max_attempts {
+                Err(BankError::AccountLocked)
+            } else {
+                Err(BankError::IncorrectPin)
+            }
+        }
+    }
+
+    // Unfreezing also clears the failed PIN attempts that may have frozen the account.
+    pub fn unfreeze(&mut self) -> Result<(), BankError> {
+        self.change_status(AccountStatus::Open)?;
+        self.failed_pin_attempts = 0;
+        Ok(())
+    }
+
+    pub fn freeze(&mut self) -> Result<(), BankError> {
+        self.change_status(AccountStatus::Frozen)
+    }
+
+    // Only an empty account can be closed.
+    pub fn close(&mut self) -> Result<(), BankError> {
+        if self.status == AccountStatus::Closed {
+            return Err(BankError::AccountClosed);
+        }
+        if self.balance != Money::ZERO {
+            return Err(BankError::BalanceRemaining(self.balance));
+        }
+        self.change_status(AccountStatus::Closed)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Payment {
+    pub period: u32,
+    pub amount: Money,
+    pub principal: Money,
+    pub interest: Money,
+    pub remaining: Money,
+}
+
+#[derive(Debug, Clone)]
+pub struct Transaction {
+    // Unique within the account's ledger; never reused, even after entries are undone.
+    pub id: u64,
+    pub kind: TransactionKind,
+    pub timestamp: SystemTime,
+    pub currency: Currency,
+    // Free text from the customer, e.g. the merchant on a card withdrawal.
+    pub memo: Option<String>,
+    // The balance in `currency` once this entry was applied.
+    pub balance_after: Money,
+    // Id of the Reversal or Chargeback entry that undid this one. Set after the fact, so not part of the hash.
+    pub reversed_by: Option<u64>,
+    // SHA-256 over the previous transaction's hash and this transaction's fields.
+    chain_hash: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum TransactionKind {
+    Deposit(Money),
+    Withdrawal(Money),
+    TransferOut(Money, String),
+    TransferIn(Money, String),
+    Fee(Money, FeeType),
+    Interest(Money),
+    // An authorization-only hold on funds; it moves no money until settled as a Withdrawal.
+    Authorization(Money),
+    // An authorization released without settling, on expiry or by release_hold.
+    AuthReversed(Money),
+    // Signed: negative on the recipient being clawed back, positive on the refunded sender.
+    Chargeback(Money, String),
+    // Original amount and currency of a converted transfer, and the rate applied.
+    Conversion(Money, Currency, f64),
+    // Only found in histories recorded while balances were kept in floating point.
+    RoundingAdjustment(Money),
+    // Signed like a Chargeback, undoing the operation that starts at the entry with this id.
+    Reversal(Money, u64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FeeType {
+    Transaction,
+    Overdraft,
+    Maintenance,
+    Transfer,
+    FinanceCharge,
+    EarlyWithdrawal,
+}
+
+impl FeeType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FeeType::Transaction => "Transaction",
+            FeeType::Overdraft => "Overdraft",
+            FeeType::Maintenance => "Maintenance",
+            FeeType::Transfer => "Transfer",
+            FeeType::FinanceCharge => "FinanceCharge",
+            FeeType::EarlyWithdrawal => "EarlyWithdrawal",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<FeeType> {
+        match name {
+            "Transaction" => Some(FeeType::Transaction),
+            "Overdraft" => Some(FeeType::Overdraft),
+            "Maintenance" => Some(FeeType::Maintenance),
+            "Transfer" => Some(FeeType::Transfer),
+            "FinanceCharge" => Some(FeeType::FinanceCharge),
+            "EarlyWithdrawal" => Some(FeeType::EarlyWithdrawal),
+            _ => None,
+        }
+    }
+}
+
+impl TransactionKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TransactionKind::Deposit(_) => "Deposit",
+            TransactionKind::Withdrawal(_) => "Withdrawal",
+            TransactionKind::TransferOut(..) => "TransferOut",
+            TransactionKind::TransferIn(..) => "TransferIn",
+            TransactionKind::Fee(..) => "Fee",
+            TransactionKind::Interest(_) => "Interest",
+            TransactionKind::Authorization(_) => "Authorization",
+            TransactionKind::AuthReversed(_) => "AuthReversed",
+            TransactionKind::Chargeback(..) => "Chargeback",
+            TransactionKind::Conversion(..) => "Conversion",
+            TransactionKind::RoundingAdjustment(_) => "RoundingAdjustment",
+            TransactionKind::Reversal(..) => "Reversal",
+        }
+    }
+
+    pub fn amount(&self) -> Money {
+        match self {
+            TransactionKind::Deposit(amount)
+            | TransactionKind::Withdrawal(amount)
+            | TransactionKind::TransferOut(amount, _)
+            | TransactionKind::TransferIn(amount, _)
+            | TransactionKind::Fee(amount, _)
+            | TransactionKind::Interest(amount)
+            | TransactionKind::Authorization(amount)
+            | TransactionKind::AuthReversed(amount)
+            | TransactionKind::Chargeback(amount, _)
+            | TransactionKind::Conversion(amount, _, _)
+            | TransactionKind::RoundingAdjustment(amount)
+            | TransactionKind::Reversal(amount, _) => *amount,
+        }
+    }
+
+    pub fn counterparty(&self) -> Option<&str> {
+        match self {
+            TransactionKind::TransferOut(_, other) | TransactionKind::TransferIn(_, other) | TransactionKind::Chargeback(_, other) => {
+                Some(other)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn balance_effect(&self) -> Money {
+        match self {
+            TransactionKind::Deposit(amount) | TransactionKind::Interest(amount) | TransactionKind::TransferIn(amount, _) => *amount,
+            TransactionKind::Withdrawal(amount) | TransactionKind::Fee(amount, _) | TransactionKind::TransferOut(amount, _) => -*amount,
+            TransactionKind::Chargeback(amount, _) | TransactionKind::RoundingAdjustment(amount) | TransactionKind::Reversal(amount, _) => {
+                *amount
+            }
+            // Records the rate applied to the TransferIn before it, which moved the money.
+            TransactionKind::Conversion(..) => Money::ZERO,
+            TransactionKind::Authorization(_) | TransactionKind::AuthReversed(_) => Money::ZERO,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Column {
+    Index,
+    Date,
+    Type,
+    Amount,
+    Currency,
+    Counterparty,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Alignment {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone)]
+pub struct FixedWidthField {
+    pub column: Column,
+    pub width: usize,
+    pub alignment: Alignment,
+    pub pad: char,
+}
+
+#[derive(Debug, Clone)]
+pub struct FixedWidthSpec {
+    pub fields: Vec<FixedWidthField>,
+}
+
+impl FixedWidthSpec {
+    pub fn line_width(&self) -> usize {
+        self.fields.iter().map(|field| field.width).sum()
+    }
+
+    // Fails with InvalidData rather than cut a value that is wider than its field.
+    pub fn format(&self, index: usize, transaction: &Transaction) -> io::Result<String> {
+        let mut line = String::with_capacity(self.line_width());
+        for field in &self.fields {
+            let value = match field.column {
+                Column::Index => index.to_string(),
+                Column::Date => calendar::format_date(transaction.timestamp),
+                Column::Type => transaction.kind.name().to_string(),
+                Column::Amount => transaction.kind.amount().to_string(),
+                Column::Currency => transaction.currency.code().to_string(),
+                Column::Counterparty => transaction.kind.counterparty().unwrap_or("").to_string(),
+            };
+            let width = value.chars().count();
+            if width > field.width {
+                let message = format!("{:?} value \"{}\" is wider than its {} characters", field.column, value, field.width);
+                return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+            }
+            let padding: String = std::iter::repeat_n(field.pad, field.width - width).collect();
+            match field.alignment {
+                Alignment::Left => {
+                    line.push_str(&value);
+                    line.push_str(&padding);
+                }
+                Alignment::Right => {
+                    line.push_str(&padding);
+                    line.push_str(&value);
+                }
+            }
+        }
+        Ok(line)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Period {
+    pub from: SystemTime,
+    pub to: SystemTime,
+}
+
+impl Period {
+    // The calendar month in UTC, up to the last instant before the next one starts.
+    pub fn month(year: i64, month: u32) -> Period {
+        let (next_year, next_month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+        Period {
+            from: calendar::month_start(year, month),
+            to: calendar::month_start(next_year, next_month) - Duration::from_nanos(1),
+        }
+    }
+
+    pub fn contains(&self, time: SystemTime) -> bool {
+        time >= self.from && time <= self.to
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Statement {
+    pub account_number: String,
+    pub period: Period,
+    pub delivery: DeliveryMethod,
+    pub opening_balance: Money,
+    pub closing_balance: Money,
+    pub transactions: Vec<Transaction>,
+}
+
+// What Bank::find_accounts looks for; criteria left as None match every account. The balance
+// bounds are inclusive and in each account's own currency.
+#[derive(Debug, Clone, Default)]
+pub struct AccountFilter {
+    pub account_type: Option<AccountType>,
+    pub status: Option<AccountStatus>,
+    pub min_balance: Option<Money>,
+    pub max_balance: Option<Money>,
+}
+
+impl AccountFilter {
+    pub fn matches(&self, account: &Account) -> bool {
+        self.account_type.as_ref().is_none_or(|account_type| account.account_type == *account_type)
+            && self.status.is_none_or(|status| account.status == status)
+            && self.min_balance.is_none_or(|min| account.balance >= min)
+            && self.max_balance.is_none_or(|max| account.balance <= max)
+    }
+}
+
+// One account's month as settled by Bank::run_month_end. Totals are in the account's
+// currency; deposits and withdrawals include the money moved by transfers.
+#[derive(Debug, Clone)]
+pub struct MonthEndSummary {
+    pub account_number: String,
+    pub period: Period,
+    pub currency: Currency,
+    pub opening_balance: Money,
+    pub closing_balance: Money,
+    pub deposits: Money,
+    pub withdrawals: Money,
+    // Includes the maintenance fee charged at month end.
+    pub fees: Money,
+    pub interest: Money,
+    // Zero when the account has no maintenance fee or it was waived.
+    pub maintenance_fee: Money,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StatementFormat {
+    Csv,
+    Json,
+}
+
+impl StatementFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            StatementFormat::Csv => "CSV",
+            StatementFormat::Json => "JSON",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<StatementFormat> {
+        match name.to_ascii_uppercase().as_str() {
+            "CSV" => Some(StatementFormat::Csv),
+            "JSON" => Some(StatementFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScenarioResult {
+    pub current_interest: Money,
+    pub projected_interest: Money,
+}
+
+impl ScenarioResult {
+    pub fn change(&self) -> Money {
+        self.projected_interest - self.current_interest
+    }
+}
+
+// Statements for an explicit list of accounts, which may belong to different owners. Totals are
+// summed as-is, so the accounts are expected to share a currency.
+#[derive(Debug, Clone)]
+pub struct HouseholdStatement {
+    pub period: Period,
+    pub sections: Vec<Statement>,
+    pub missing: Vec<String>,
+    pub opening_balance: Money,
+    pub closing_balance: Money,
+}
+
+// A statement laid out for legal use and rendered to a PDF by the caller. The content hash
+// covers every section except the generation time, so regenerating the statement for the
+// same history and period yields the same hash.
+#[derive(Debug, Clone)]
+pub struct FormalStatement {
+    pub bank_name: String,
+    pub account_number: String,
+    pub account_holder: Option<String>,
+    pub currency: Currency,
+    pub period: Period,
+    pub opening_balance: Money,
+    pub closing_balance: Money,
+    pub transactions: Vec<Transaction>,
+    pub generated_at: SystemTime,
+    pub content_hash: String,
+}
+
+impl FormalStatement {
+    fn body(&self) -> String {
+        let mut body = format!(
+            "{}\nStatement of account {}\nAccount holder: {}\nCurrency: {}\nPeriod: {} to {}\nOpening balance: {}\nTransactions:\n",
+            self.bank_name,
+            self.account_number,
+            self.account_holder.as_deref().unwrap_or("-"),
+            self.currency.code(),
+            calendar::format_date(self.period.from),
+            calendar::format_date(self.period.to),
+            self.opening_balance,
+        );
+        for transaction in &self.transactions {
+            body.push_str(&format!(
+                "  {} {} {}",
+                calendar::format_datetime(transaction.timestamp),
+                transaction.kind.name(),
+                transaction.kind.amount()
+            ));
+            if let Some(other) = transaction.kind.counterparty() {
+                body.push_str(&format!(" ({})", other));
+            }
+            body.push('\n');
+        }
+        body.push_str(&format!("Closing balance: {}\n", self.closing_balance));
+        body
+    }
+
+    pub fn to_text(&self) -> String {
+        format!(
+            "{}Generated: {} UTC\nContent hash: {}\n",
+            self.body(),
+            calendar::format_datetime(self.generated_at),
+            self.content_hash
+        )
+    }
+}
+
+impl Transaction {
+    // Timestamps are hashed at millisecond precision so the chain survives a JSON round trip.
+    // The memo is only hashed when present, so chains recorded before memos still verify.
+    pub fn compute_hash(&self, previous: &str) -> String {
+        let mut fields = format!(
+            "{}|{:?}|{}|{}",
+            previous,
+            self.kind,
+            calendar::to_millis(self.timestamp),
+            self.currency.code()
+        );
+        if let Some(memo) = &self.memo {
+            fields.push('|');
+            fields.push_str(memo);
+        }
+        sha256::hex_digest(fields.as_bytes())
+    }
+
+    pub fn to_json(&self) -> json::Value {
+        let mut fields = vec![
+            ("type", json::string(self.kind.name())),
+            ("amount", self.kind.amount().to_json()),
+            ("timestamp", json::Value::Number(calendar::to_millis(self.timestamp) as f64)),
+            ("currency", json::string(self.currency.code())),
+            ("chain_hash", json::string(&self.chain_hash)),
+            ("id", json::Value::Number(self.id as f64)),
+            ("balance_after", self.balance_after.to_json()),
+        ];
+        if let Some(counterparty) = self.kind.counterparty() {
+            fields.push(("counterparty", json::string(counterparty)));
+        }
+        if let TransactionKind::Fee(_, fee_type) = &self.kind {
+            fields.push(("fee_type", json::string(fee_type.name())));
+        }
+        if let TransactionKind::Conversion(_, currency, rate) = &self.kind {
+            fields.push(("from_currency", json::string(currency.code())));
+            fields.push(("rate", json::Value::Number(*rate)));
+        }
+        if let TransactionKind::Reversal(_, original) = &self.kind {
+            fields.push(("reverses", json::Value::Number(*original as f64)));
+        }
+        if let Some(memo) = &self.memo {
+            fields.push(("memo", json::string(memo)));
+        }
+        if let Some(reversal) = self.reversed_by {
+            fields.push(("reversed_by", json::Value::Number(reversal as f64)));
+        }
+        json::object(fields)
+    }
+
+    pub fn from_json(value: &json::Value) -> Result<Transaction, String> {
+        let amount = value.get("amount").and_then(Money::from_json).ok_or("transaction missing amount")?;
+        let counterparty = || {
+            value
+                .get("counterparty")
+                .and_then(json::Value::as_str)
+                .map(str::to_string)
+                .ok_or("transaction missing counterparty")
+        };
+        let kind = match value.get("type").and_then(json::Value::as_str) {
+            Some("Deposit") => TransactionKind::Deposit(amount),
+            Some("Withdrawal") => TransactionKind::Withdrawal(amount),
+            Some("TransferOut") => TransactionKind::TransferOut(amount, counterparty()?),
+            Some("TransferIn") => TransactionKind::TransferIn(amount, counterparty()?),
+            Some("Fee") => TransactionKind::Fee(
+                amount,
+                value
+                    .get("fee_type")
+                    .and_then(json::Value::as_str)
+                    .and_then(FeeType::from_name)
+                    .unwrap_or(FeeType::Transaction),
+            ),
+            Some("Interest") => TransactionKind::Interest(amount),
+            Some("Authorization") => TransactionKind::Authorization(amount),
+            Some("AuthReversed") => TransactionKind::AuthReversed(amount),
+            Some("Chargeback") => TransactionKind::Chargeback(amount, counterparty()?),
+            Some("RoundingAdjustment") => TransactionKind::RoundingAdjustment(amount),
+            Some("Reversal") => {
+                let original = value.get("reverses").and_then(json::Value::as_f64).ok_or("reversal missing reverses")?;
+                TransactionKind::Reversal(amount, original as u64)
+            }
+            Some("Conversion") => TransactionKind::Conversion(
+                amount,
+                value
+                    .get("from_currency")
+                    .and_then(json::Value::as_str)
+                    .map(Currency::new)
+                    .ok_or("conversion missing from_currency")?,
+                value.get("rate").and_then(json::Value::as_f64).ok_or("conversion missing rate")?,
+            ),
+            _ => return Err("missing or unknown transaction type".to_string()),
+        };
+        let timestamp = value.get("timestamp").and_then(json::Value::as_f64).ok_or("transaction missing timestamp")?;
+        Ok(Transaction {
+            id: value.get("id").and_then(json::Value::as_f64).unwrap_or_default() as u64,
+            kind,
+            timestamp: calendar::from_millis(timestamp as i64),
+            currency: value
+                .get("currency")
+                .and_then(json::Value::as_str)
+                .map(Currency::new)
+                .unwrap_or_default(),
+            memo: value.get("memo").and_then(json::Value::as_str).map(str::to_string),
+            balance_after: value.get("balance_after").and_then(Money::from_json).unwrap_or_default(),
+            reversed_by: value.get("reversed_by").and_then(json::Value::as_f64).map(|id| id as u64),
+            chain_hash: value.get("chain_hash").and_then(json::Value::as_str).unwrap_or("").to_string(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Category {
+    Food,
+    Housing,
+    Transport,
+    Utilities,
+    Entertainment,
+    Transfers,
+    Other,
+}
+
+#[derive(Debug, Clone)]
+pub enum CategoryMatch {
+    // Case-insensitive substring of the memo.
+    MemoKeyword(String),
+    Counterparty(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct CategoryRule {
+    pub matcher: CategoryMatch,
+    pub category: Category,
+}
+
+// The first matching rule wins; a transaction no rule matches is Other.
+pub fn classify_transaction(transaction: &Transaction, rules: &[CategoryRule]) -> Category {
+    let memo = transaction.memo.as_deref().unwrap_or("").to_lowercase();
+    rules
+        .iter()
+        .find(|rule| match &rule.matcher {
+            CategoryMatch::MemoKeyword(keyword) => !keyword.is_empty() && memo.contains(&keyword.to_lowercase()),
+            CategoryMatch::Counterparty(counterparty) => transaction.kind.counterparty() == Some(counterparty.as_str()),
+        })
+        .map_or(Category::Other, |rule| rule.category)
+}
+
+#[derive(Debug, Clone)]
+pub struct TransferRef {
+    pub account_number: String,
+    pub index: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BankError {
+    AccountNotFound,
+    AccountExists,
+    InsufficientFunds,
+    CreditLimitExceeded,
+    BelowMinimumBalance,
+    AccountOnHold,
+    BelowMinimumDeposit,
+    UnsupportedAccountType,
+    TransactionNotFound,
+    MandateNotFound,
+    MandateLimitExceeded,
+    ScheduleNotFound,
+    InvalidSchedule,
+    CustomerNotFound,
+    CustomerExists,
+    TransactionLimitExceeded,
+    VelocityLimitExceeded(VelocityLimit),
+    PinNotSet,
+    IncorrectPin,
+    AccountLocked,
+    AccountInactive,
+    AccountClosed,
+    // The acting customer's role on the account doesn't allow the operation.
+    NotPermitted,
+    // The account's current status and the one it was asked to move to.
+    InvalidStatusChange(AccountStatus, AccountStatus),
+    SameAccount,
+    ExchangeRateUnavailable,
+    BalanceRemaining(Money),
+    NotReversible,
+    AlreadyReversed,
+    // A repair was asked for on a transfer whose credit is in the recipient's ledger.
+    TransferComplete,
+    InvalidAmount,
+    Overflow,
+    Io(String),
+    InvalidData(String),
+}
+
+// Messages are written for the person at the terminal, saying what went wrong and, where it
+// helps, what to do about it.
+impl fmt::Display for BankError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BankError::AccountNotFound => write!(f, "Account not found"),
+            BankError::AccountExists => write!(f, "Account already exists"),
+            BankError::InsufficientFunds => write!(f, "Insufficient balance"),
+            BankError::CreditLimitExceeded => write!(f, "Credit limit exceeded"),
+            BankError::BelowMinimumBalance => write!(f, "Withdrawal would go below the minimum balance"),
+            BankError::AccountOnHold => write!(f, "Account is on hold"),
+            BankError::BelowMinimumDeposit => write!(f, "Opening deposit is below the minimum for this account type"),
+            BankError::UnsupportedAccountType => write!(f, "Not supported for this account type"),
+            BankError::TransactionNotFound => write!(f, "Transaction not found"),
+            BankError::MandateNotFound => write!(f, "Mandate not found"),
+            BankError::MandateLimitExceeded => write!(f, "Charge exceeds the mandate's limits"),
+            BankError::ScheduleNotFound => write!(f, "Scheduled payment not found"),
+            BankError::InvalidSchedule => write!(f, "Day of month must be between 1 and 31"),
+            BankError::CustomerNotFound => write!(f, "Customer not found"),
+            BankError::CustomerExists => write!(f, "Customer already exists"),
+            BankError::TransactionLimitExceeded => write!(f, "Transaction limit exceeded"),
+            BankError::VelocityLimitExceeded(VelocityLimit::MaxWithdrawal) => write!(f, "Withdrawal exceeds the maximum for this account"),
+            BankError::VelocityLimitExceeded(VelocityLimit::DailyWithdrawalTotal) => write!(f, "Daily withdrawal limit reached"),
+            BankError::VelocityLimitExceeded(VelocityLimit::DailyTransferCount) => write!(f, "Daily transfer limit reached"),
+            BankError::PinNotSet => write!(f, "No PIN has been set for this account"),
+            BankError::IncorrectPin => write!(f, "Incorrect PIN"),
+            BankError::AccountLocked => write!(f, "Account is locked; ask an operator to reactivate it"),
+            BankError::AccountInactive => write!(f, "Account is frozen; unfreeze it first"),
+            BankError::AccountClosed => write!(f, "Account is closed"),
+            BankError::NotPermitted => write!(f, "Not permitted for this customer on this account"),
+            BankError::InvalidStatusChange(from, to) if from == to => write!(f, "Account is already {}", from.name()),
+            BankError::InvalidStatusChange(from, to) => write!(f, "Account cannot go from {} to {}", from.name(), to.name()),
+            BankError::SameAccount => write!(f, "Cannot transfer to the same account"),
+            BankError::ExchangeRateUnavailable => write!(f, "No exchange rate between these currencies"),
+            BankError::BalanceRemaining(balance) => {
+                write!(f, "Account still has a balance of {}; withdraw or transfer it first", balance)
+            }
+            BankError::NotReversible => write!(f, "The operation cannot be reversed"),
+            BankError::AlreadyReversed => write!(f, "The transaction has already been reversed"),
+            BankError::TransferComplete => write!(f, "The transfer already reached its recipient"),
+            BankError::InvalidAmount => write!(f, "Invalid amount"),
+            BankError::Overflow => write!(f, "Amount is too large"),
+            BankError::Io(message) => write!(f, "I/O error: {}", message),
+            BankError::InvalidData(message) => write!(f, "Invalid data: {}", message),
+        }
+    }
+}
+
+// Amounts within this fraction below a reporting threshold count as "just under" it.
+const STRUCTURING_MARGIN: f64 = 0.1;
+
+// Deposits and withdrawals in exact multiples of this are "round" for fraud screening.
+const ROUND_AMOUNT: Money = Money(10_000);
+
+// How long a hold placed with place_hold lasts before expire_authorizations releases it.
+const HOLD_DURATION: Duration = Duration::from_secs(7 * 86_400);
+
+// Days of history the burn rate in days_to_depletion is averaged over.
+const BURN_RATE_WINDOW_DAYS: u64 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RemainderPolicy {
+    ToBank,
+    ToCustomer,
+    Discard,
+}
+
+impl RemainderPolicy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RemainderPolicy::ToBank => "ToBank",
+            RemainderPolicy::ToCustomer => "ToCustomer",
+            RemainderPolicy::Discard => "Discard",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<RemainderPolicy> {
+        match name {
+            "ToBank" => Some(RemainderPolicy::ToBank),
+            "ToCustomer" => Some(RemainderPolicy::ToCustomer),
+            "Discard" => Some(RemainderPolicy::Discard),
+            _ => None,
+        }
+    }
+}
+
+// How a computed withdrawal with sub-cent precision is brought to whole cents.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WithdrawalRounding {
+    Truncate,
+    Round,
+}
+
+impl WithdrawalRounding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WithdrawalRounding::Truncate => "Truncate",
+            WithdrawalRounding::Round => "Round",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<WithdrawalRounding> {
+        match name {
+            "Truncate" => Some(WithdrawalRounding::Truncate),
+            "Round" => Some(WithdrawalRounding::Round),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Flow {
+    Charge,
+    Payout,
+}
+
+// An amount in whole cents. Rates, ratios and other statistics stay f64; amounts of money
+// are always Money so repeated arithmetic never drifts.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Money(i64);
+
+impl Money {
+    pub const ZERO: Money = Money(0);
+
+    pub fn from_cents(cents: i64) -> Money {
+        Money(cents)
+    }
+
+    pub fn cents(self) -> i64 {
+        self.0
+    }
+
+    // Rounds to the nearest cent.
+    pub fn from_f64(amount: f64) -> Money {
+        Money((amount * 100.0).round() as i64)
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / 100.0
+    }
+
+    // Multiplies by a rate or growth factor, rounding to the nearest cent.
+    pub fn scale(self, factor: f64) -> Money {
+        Money((self.0 as f64 * factor).round() as i64)
+    }
+
+    pub fn abs(self) -> Money {
+        Money(self.0.abs())
+    }
+
+    pub fn checked_add(self, other: Money) -> Result<Money, BankError> {
+        self.0.checked_add(other.0).map(Money).ok_or(BankError::Overflow)
+    }
+
+    pub fn checked_sub(self, other: Money) -> Result<Money, BankError> {
+        self.0.checked_sub(other.0).map(Money).ok_or(BankError::Overflow)
+    }
+
+    // Two decimals with the currency's symbol in front, or its code after the amount for
+    // currencies without a well-known symbol: "$12.34", "-€5.00", "7.50 CHF".
+    pub fn format_in(self, currency: &Currency) -> String {
+        let unsigned = self.abs().to_string();
+        let sign = if self.0 < 0 { "-" } else { "" };
+        match currency.symbol() {
+            Some(symbol) => format!("{}{}{}", sign, symbol, unsigned),
+            None => format!("{}{} {}", sign, unsigned, currency.code()),
+        }
+    }
+
+    // Serialized in currency units so exported files read naturally and stay compatible.
+    pub fn to_json(self) -> json::Value {
+        json::Value::Number(self.to_f64())
+    }
+
+    pub fn from_json(value: &json::Value) -> Option<Money> {
+        value.as_f64().map(Money::from_f64)
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let cents = self.0.unsigned_abs();
+        write!(f, "{}{}.{:02}", sign, cents / 100, cents % 100)
+    }
+}
+
+// Accepts what a user would type: digits and at most two decimals, e.g. "12.34", "5" or ".5".
+// A sign is refused; the operation says which way the money moves, not the amount.
+impl FromStr for Money {
+    type Err = BankError;
+
+    fn from_str(input: &str) -> Result<Money, BankError> {
+        let input = input.trim();
+        let (whole, fraction) = input.split_once('.').unwrap_or((input, ""));
+        let digits = |text: &str| text.chars().all(|c| c.is_ascii_digit());
+        if (whole.is_empty() && fraction.is_empty()) || fraction.len() > 2 || !digits(whole) || !digits(fraction) {
+            return Err(BankError::InvalidAmount);
+        }
+        let whole: i64 = if whole.is_empty() { 0 } else { whole.parse().map_err(|_| BankError::Overflow)? };
+        let cents: i64 = format!("{:0<2}", fraction).parse().map_err(|_| BankError::InvalidAmount)?;
+        whole.checked_mul(100).and_then(|whole| whole.checked_add(cents)).map(Money).ok_or(BankError::Overflow)
+    }
+}
+
+impl fmt::Debug for Money {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl Add for Money {
+    type Output = Money;
+
+    fn add(self, other: Money) -> Money {
+        Money(self.0 + other.0)
+    }
+}
+
+impl Sub for Money {
+    type Output = Money;
+
+    fn sub(self, other: Money) -> Money {
+        Money(self.0 - other.0)
+    }
+}
+
+impl Neg for Money {
+    type Output = Money;
+
+    fn neg(self) -> Money {
+        Money(-self.0)
+    }
+}
+
+impl Mul<i64> for Money {
+    type Output = Money;
+
+    fn mul(self, times: i64) -> Money {
+        Money(self.0 * times)
+    }
+}
+
+impl AddAssign for Money {
+    fn add_assign(&mut self, other: Money) {
+        self.0 += other.0;
+    }
+}
+
+impl SubAssign for Money {
+    fn sub_assign(&mut self, other: Money) {
+        self.0 -= other.0;
+    }
+}
+
+impl Sum for Money {
+    fn sum<I: Iterator<Item = Money>>(iter: I) -> Money {
+        iter.fold(Money::ZERO, Add::add)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Currency(String);
+
+impl Currency {
+    pub fn new(code: &str) -> Currency {
+        Currency(code.trim().to_ascii_uppercase())
+    }
+
+    pub fn code(&self) -> &str {
+        &self.0
+    }
+
+    pub fn symbol(&self) -> Option<&'static str> {
+        match self.code() {
+            "USD" => Some("$"),
+            "EUR" => Some("€"),
+            "GBP" => Some("£"),
+            "JPY" => Some("¥"),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Currency {
+    fn default() -> Currency {
+        Currency::new("USD")
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ExchangeRate {
+    pub bid: f64,
+    pub ask: f64,
+}
+
+// `credited` is in the recipient's currency; every other amount is in the sender's.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransferCost {
+    pub principal: Money,
+    pub fee: Money,
+    pub fx_cost: Money,
+    pub total_debited: Money,
+    pub credited: Money,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScheduledTransfer {
+    pub from_account: String,
+    pub to_account: String,
+    pub amount: Money,
+    pub due: SystemTime,
+}
+
+impl ScheduledTransfer {
+    fn to_json(&self) -> json::Value {
+        json::object(vec![
+            ("from_account", json::string(&self.from_account)),
+            ("to_account", json::string(&self.to_account)),
+            ("amount", self.amount.to_json()),
+            ("due", json::Value::Number(calendar::to_millis(self.due) as f64)),
+        ])
+    }
+
+    fn from_json(value: &json::Value) -> Result<ScheduledTransfer, String> {
+        let missing = |field: &str| format!("scheduled transfer missing {}", field);
+        let text = |field: &str| value.get(field).and_then(json::Value::as_str).map(str::to_string).ok_or_else(|| missing(field));
+        Ok(ScheduledTransfer {
+            from_account: text("from_account")?,
+            to_account: text("to_account")?,
+            amount: value.get("amount").and_then(Money::from_json).ok_or_else(|| missing("amount"))?,
+            due: calendar::from_millis(value.get("due").and_then(json::Value::as_f64).ok_or_else(|| missing("due"))? as i64),
+        })
+    }
+}
+
+// A future-dated credit, e.g. a paycheck; it is not part of any balance until applied.
+#[derive(Debug, Clone)]
+pub struct ScheduledDeposit {
+    pub account_number: String,
+    pub amount: Money,
+    pub available_at: SystemTime,
+}
+
+impl ScheduledDeposit {
+    fn to_json(&self) -> json::Value {
+        json::object(vec![
+            ("account", json::string(&self.account_number)),
+            ("amount", self.amount.to_json()),
+            ("available_at", json::Value::Number(calendar::to_millis(self.available_at) as f64)),
+        ])
+    }
+
+    fn from_json(value: &json::Value) -> Result<ScheduledDeposit, String> {
+        let missing = |field: &str| format!("scheduled deposit missing {}", field);
+        let available_at = value.get("available_at").and_then(json::Value::as_f64).ok_or_else(|| missing("available_at"))?;
+        Ok(ScheduledDeposit {
+            account_number: value.get("account").and_then(json::Value::as_str).ok_or_else(|| missing("account"))?.to_string(),
+            amount: value.get("amount").and_then(Money::from_json).ok_or_else(|| missing("amount"))?,
+            available_at: calendar::from_millis(available_at as i64),
+        })
+    }
+}
+
+// Paid on the same day of every month, from `from_account` to `to_account`, or deposited into
+// `to_account` when there is no source. Months shorter than `day_of_month` pay on their last day.
+#[derive(Debug, Clone)]
+pub struct RecurringPayment {
+    pub from_account: Option<String>,
+    pub to_account: String,
+    pub amount: Money,
+    pub day_of_month: u32,
+    pub next_due: SystemTime,
+}
+
+impl RecurringPayment {
+    fn to_json(&self, id: u64) -> json::Value {
+        let mut fields = vec![
+            ("id", json::Value::Number(id as f64)),
+            ("to_account", json::string(&self.to_account)),
+            ("amount", self.amount.to_json()),
+            ("day_of_month", json::Value::Number(self.day_of_month as f64)),
+            ("next_due", json::Value::Number(calendar::to_millis(self.next_due) as f64)),
+        ];
+        if let Some(from_account) = &self.from_account {
+            fields.push(("from_account", json::string(from_account)));
+        }
+        json::object(fields)
+    }
+
+    fn from_json(value: &json::Value) -> Result<(u64, RecurringPayment), String> {
+        let number = |field: &str| value.get(field).and_then(json::Value::as_f64).ok_or(format!("recurring payment missing {}", field));
+        let payment = RecurringPayment {
+            from_account: value.get("from_account").and_then(json::Value::as_str).map(str::to_string),
+            to_account: value
+                .get("to_account")
+                .and_then(json::Value::as_str)
+                .ok_or("recurring payment missing to_account")?
+                .to_string(),
+            amount: value.get("amount").and_then(Money::from_json).ok_or("recurring payment missing amount")?,
+            day_of_month: number("day_of_month")? as u32,
+            next_due: calendar::from_millis(number("next_due")? as i64),
+        };
+        Ok((number("id")? as u64, payment))
+    }
+}
+
+// Someone who holds accounts with the bank. Accounts are linked to a customer through their
+// owner, which is the customer's id.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Customer {
+    pub name: String,
+    pub email: Option<String>,
+    pub phone: Option<String>,
+}
+
+impl Customer {
+    fn to_json(&self) -> json::Value {
+        let optional = |value: &Option<String>| value.as_deref().map_or(json::Value::Null, json::string);
+        json::object(vec![
+            ("name", json::string(&self.name)),
+            ("email", optional(&self.email)),
+            ("phone", optional(&self.phone)),
+        ])
+    }
+
+    fn from_json(value: &json::Value) -> Result<Customer, String> {
+        let optional = |field: &str| value.get(field).and_then(json::Value::as_str).map(str::to_string);
+        Ok(Customer {
+            name: optional("name").ok_or("customer missing name")?,
+            email: optional("email"),
+            phone: optional("phone"),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Mandate {
+    pub debtor: String,
+    pub creditor: String,
+    pub max_per_charge: Money,
+    pub total_cap: Money,
+    pub charged: Money,
+}
+
+impl Mandate {
+    fn to_json(&self, id: u64) -> json::Value {
+        json::object(vec![
+            ("id", json::Value::Number(id as f64)),
+            ("debtor", json::string(&self.debtor)),
+            ("creditor", json::string(&self.creditor)),
+            ("max_per_charge", self.max_per_charge.to_json()),
+            ("total_cap", self.total_cap.to_json()),
+            ("charged", self.charged.to_json()),
+        ])
+    }
+
+    fn from_json(value: &json::Value) -> Result<(u64, Mandate), String> {
+        let missing = |field: &str| format!("mandate missing {}", field);
+        let text = |field: &str| value.get(field).and_then(json::Value::as_str).map(str::to_string).ok_or_else(|| missing(field));
+        let money = |field: &str| value.get(field).and_then(Money::from_json).ok_or_else(|| missing(field));
+        let id = value.get("id").and_then(json::Value::as_f64).ok_or("mandate missing id")?;
+        let mandate = Mandate {
+            debtor: text("debtor")?,
+            creditor: text("creditor")?,
+            max_per_charge: money("max_per_charge")?,
+            total_cap: money("total_cap")?,
+            charged: money("charged")?,
+        };
+        Ok((id as u64, mandate))
+    }
+}
+
+// A state change, either made by this bank or delivered from an event stream. Ids are unique
+// per event, so a stream that may deliver an event more than once can be replayed without
+// applying it twice.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub id: u64,
+    pub kind: EventKind,
+}
+
+// The account operations that make up the bank's history. Configuration (rates, limits per
+// account type, fees) is not an event, so a bank rebuilt from events should be configured like
+// the original before replaying.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventKind {
+    AccountOpened(String, AccountType, Currency),
+    Deposited(String, Money),
+    // Account, amount and memo.
+    Withdrawn(String, Money, Option<String>),
+    // From, to and amount.
+    Transferred(String, String, Money),
+    Frozen(String),
+    Unfrozen(String),
+    // The account and, when its balance was swept out on closing, where it went.
+    Closed(String, Option<String>),
+    // The account's type-specific limit, as set by Bank::set_account_limit.
+    LimitSet(String, Money),
+    // A transaction read from another bank's export, by import_transactions.
+    Imported(String, import::ImportedTransaction),
+    // The account and the id of the transaction reversed there.
+    Reversed(String, u64),
+    // Deposits and withdrawals held in a currency other than the account's own.
+    DepositedIn(String, Money, Currency),
+    WithdrawnIn(String, Money, Currency),
+    InterestPaid(String, Money),
+    // Dated, since a month-end fee belongs to the month it closes rather than the day it ran.
+    FeeCharged(String, Money, FeeType, SystemTime),
+    // Account, hold id, amount and expiry.
+    Authorized(String, u64, Money, SystemTime),
+    // The sender and the position of the TransferOut entry in its ledger.
+    ChargedBack(String, usize),
+    // The sender and the id of the TransferOut entry of a transfer that was never credited.
+    TransferRepaired(String, u64),
+    AuthorizationSettled(String, u64),
+    // Released by hand or on expiry.
+    AuthorizationReleased(String, u64),
+    // An account moved in from another bank by import_account, with its state as exported.
+    AccountImported(String, String),
+    // Account, customer and the role the customer holds it in.
+    HolderAdded(String, String, auth::Role),
+    HolderRemoved(String, String),
+}
+
+impl EventKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventKind::AccountOpened(..) => "AccountOpened",
+            EventKind::Deposited(..) => "Deposited",
+            EventKind::Withdrawn(..) => "Withdrawn",
+            EventKind::Transferred(..) => "Transferred",
+            EventKind::Frozen(_) => "Frozen",
+            EventKind::Unfrozen(_) => "Unfrozen",
+            EventKind::Closed(..) => "Closed",
+            EventKind::LimitSet(..) => "LimitSet",
+            EventKind::Imported(..) => "Imported",
+            EventKind::Reversed(..) => "Reversed",
+            EventKind::DepositedIn(..) => "DepositedIn",
+            EventKind::WithdrawnIn(..) => "WithdrawnIn",
+            EventKind::InterestPaid(..) => "InterestPaid",
+            EventKind::FeeCharged(..) => "FeeCharged",
+            EventKind::Authorized(..) => "Authorized",
+            EventKind::ChargedBack(..) => "ChargedBack",
+            EventKind::TransferRepaired(..) => "TransferRepaired",
+            EventKind::AuthorizationSettled(..) => "AuthorizationSettled",
+            EventKind::AuthorizationReleased(..) => "AuthorizationReleased",
+            EventKind::AccountImported(..) => "AccountImported",
+            EventKind::HolderAdded(..) => "HolderAdded",
+            EventKind::HolderRemoved(..) => "HolderRemoved",
+        }
+    }
+
+    // The name the operation is audited under.
+    fn operation(&self) -> &'static str {
+        match self {
+            EventKind::AccountOpened(..) => "create_account",
+            EventKind::Deposited(..) => "deposit",
+            EventKind::Withdrawn(..) => "withdraw",
+            EventKind::Transferred(..) => "transfer",
+            EventKind::Frozen(_) => "freeze_account",
+            EventKind::Unfrozen(_) => "unfreeze_account",
+            EventKind::Closed(..) => "close_account",
+            EventKind::LimitSet(..) => "set_account_limit",
+            EventKind::Imported(..) => "import_transaction",
+            EventKind::Reversed(..) => "reverse_transaction",
+            EventKind::DepositedIn(..) => "deposit_currency",
+            EventKind::WithdrawnIn(..) => "withdraw_currency",
+            EventKind::InterestPaid(..) => "pay_interest",
+            EventKind::FeeCharged(..) => "charge_fee",
+            EventKind::Authorized(..) => "authorize",
+            EventKind::ChargedBack(..) => "chargeback_transfer",
+            EventKind::TransferRepaired(..) => "repair_transfer",
+            EventKind::AuthorizationSettled(..) => "settle_authorization",
+            EventKind::AuthorizationReleased(..) => "release_authorization",
+            EventKind::AccountImported(..) => "import_account",
+            EventKind::HolderAdded(..) => "add_holder",
+            EventKind::HolderRemoved(..) => "remove_holder",
+        }
+    }
+
+    fn parameters(&self) -> Vec<(&'static str, String)> {
+        match self {
+            EventKind::AccountOpened(number, account_type, currency) => vec![
+                ("account", number.clone()),
+                ("type", account_type.name().to_string()),
+                ("currency", currency.code().to_string()),
+            ],
+            EventKind::Deposited(number, amount) | EventKind::Withdrawn(number, amount, _) => {
+                vec![("account", number.clone()), ("amount", amount.to_string())]
+            }
+            EventKind::Transferred(from, to, amount) => vec![("from", from.clone()), ("to", to.clone()), ("amount", amount.to_string())],
+            EventKind::Frozen(number) | EventKind::Unfrozen(number) | EventKind::Closed(number, None) => vec![("account", number.clone())],
+            EventKind::AccountImported(number, _) => vec![("account", number.clone())],
+            EventKind::HolderAdded(number, customer_id, role) => {
+                vec![("account", number.clone()), ("customer", customer_id.clone()), ("role", role.name().to_string())]
+            }
+            EventKind::HolderRemoved(number, customer_id) => vec![("account", number.clone()), ("customer", customer_id.clone())],
+            EventKind::Closed(number, Some(sweep_to)) => vec![("account", number.clone()), ("sweep_to", sweep_to.clone())],
+            EventKind::LimitSet(number, limit) => vec![("account", number.clone()), ("limit", limit.to_string())],
+            EventKind::Imported(number, transaction) => vec![
+                ("account", number.clone()),
+                ("external_id", transaction.external_id.clone()),
+                ("amount", transaction.amount.to_string()),
+            ],
+            EventKind::Reversed(number, id) | EventKind::TransferRepaired(number, id) => {
+                vec![("account", number.clone()), ("transaction", id.to_string())]
+            }
+            EventKind::DepositedIn(number, amount, currency) | EventKind::WithdrawnIn(number, amount, currency) => {
+                vec![("account", number.clone()), ("amount", amount.to_string()), ("currency", currency.code().to_string())]
+            }
+            EventKind::InterestPaid(number, amount) => vec![("account", number.clone()), ("amount", amount.to_string())],
+            EventKind::FeeCharged(number, amount, fee_type, _) => {
+                vec![("account", number.clone()), ("amount", amount.to_string()), ("fee_type", fee_type.name().to_string())]
+            }
+            EventKind::Authorized(number, id, amount, _) => {
+                vec![("account", number.clone()), ("hold", id.to_string()), ("amount", amount.to_string())]
+            }
+            EventKind::AuthorizationSettled(number, id) | EventKind::AuthorizationReleased(number, id) => {
+                vec![("account", number.clone()), ("hold", id.to_string())]
+            }
+            EventKind::ChargedBack(number, index) => vec![("account", number.clone()), ("index", index.to_string())],
+        }
+    }
+}
+
+impl Event {
+    pub fn to_json(&self) -> json::Value {
+        let mut fields = vec![("id", json::Value::Number(self.id as f64)), ("type", json::string(self.kind.name()))];
+        match &self.kind {
+            EventKind::AccountOpened(number, account_type, currency) => {
+                fields.push(("account", json::string(number)));
+                fields.push(("account_type", json::string(account_type.name())));
+                fields.push(("currency", json::string(currency.code())));
+            }
+            EventKind::Deposited(number, amount) => {
+                fields.push(("account", json::string(number)));
+                fields.push(("amount", amount.to_json()));
+            }
+            EventKind::Withdrawn(number, amount, memo) => {
+                fields.push(("account", json::string(number)));
+                fields.push(("amount", amount.to_json()));
+                if let Some(memo) = memo {
+                    fields.push(("memo", json::string(memo)));
+                }
+            }
+            EventKind::Transferred(from, to, amount) => {
+                fields.push(("from", json::string(from)));
+                fields.push(("to", json::string(to)));
+                fields.push(("amount", amount.to_json()));
+            }
+            EventKind::Frozen(number) | EventKind::Unfrozen(number) => {
+                fields.push(("account", json::string(number)));
+            }
+            EventKind::Closed(number, sweep_to) => {
+                fields.push(("account", json::string(number)));
+                if let Some(sweep_to) = sweep_to {
+                    fields.push(("sweep_to", json::string(sweep_to)));
+                }
+            }
+            EventKind::LimitSet(number, limit) => {
+                fields.push(("account", json::string(number)));
+                fields.push(("limit", limit.to_json()));
+            }
+            EventKind::Imported(number, transaction) => {
+                fields.push(("account", json::string(number)));
+                fields.push(("external_id", json::string(&transaction.external_id)));
+                fields.push(("date", json::Value::Number(calendar::to_millis(transaction.date) as f64)));
+                fields.push(("amount", transaction.amount.to_json()));
+                fields.push(("description", json::string(&transaction.description)));
+            }
+            EventKind::Reversed(number, id) | EventKind::TransferRepaired(number, id) => {
+                fields.push(("account", json::string(number)));
+                fields.push(("transaction", json::Value::Number(*id as f64)));
+            }
+            EventKind::DepositedIn(number, amount, currency) | EventKind::WithdrawnIn(number, amount, currency) => {
+                fields.push(("account", json::string(number)));
+                fields.push(("amount", amount.to_json()));
+                fields.push(("currency", json::string(currency.code())));
+            }
+            EventKind::InterestPaid(number, amount) => {
+                fields.push(("account", json::string(number)));
+                fields.push(("amount", amount.to_json()));
+            }
+            EventKind::FeeCharged(number, amount, fee_type, charged_at) => {
+                fields.push(("account", json::string(number)));
+                fields.push(("amount", amount.to_json()));
+                fields.push(("fee_type", json::string(fee_type.name())));
+                fields.push(("date", json::Value::Number(calendar::to_millis(*charged_at) as f64)));
+            }
+            EventKind::Authorized(number, id, amount, expires) => {
+                fields.push(("account", json::string(number)));
+                fields.push(("hold", json::Value::Number(*id as f64)));
+                fields.push(("amount", amount.to_json()));
+                fields.push(("expires", json::Value::Number(calendar::to_millis(*expires) as f64)));
+            }
+            EventKind::AuthorizationSettled(number, id) | EventKind::AuthorizationReleased(number, id) => {
+                fields.push(("account", json::string(number)));
+                fields.push(("hold", json::Value::Number(*id as f64)));
+            }
+            EventKind::ChargedBack(number, index) => {
+                fields.push(("account", json::string(number)));
+                fields.push(("index", json::Value::Number(*index as f64)));
+            }
+            EventKind::AccountImported(number, state) => {
+                fields.push(("account", json::string(number)));
+                fields.push(("state", json::string(state)));
+            }
+            EventKind::HolderAdded(number, customer_id, role) => {
+                fields.push(("account", json::string(number)));
+                fields.push(("customer", json::string(customer_id)));
+                fields.push(("role", json::string(role.name())));
+            }
+            EventKind::HolderRemoved(number, customer_id) => {
+                fields.push(("account", json::string(number)));
+                fields.push(("customer", json::string(customer_id)));
+            }
+        }
+        json::object(fields)
+    }
+
+    pub fn from_json(value: &json::Value) -> Result<Event, String> {
+        let text = |field: &str| value.get(field).and_then(json::Value::as_str).map(str::to_string).ok_or(format!("event missing {}", field));
+        let money = |field: &str| value.get(field).and_then(Money::from_json).ok_or(format!("event missing {}", field));
+        let number = |field: &str| value.get(field).and_then(json::Value::as_f64).ok_or(format!("event missing {}", field));
+        let date = |field: &str| number(field).map(|millis| calendar::from_millis(millis as i64));
+        let kind = match value.get("type").and_then(json::Value::as_str) {
+            Some("AccountOpened") => {
+                let account_type = text("account_type")?;
+                EventKind::AccountOpened(
+                    text("account")?,
+                    AccountType::from_name(&account_type).ok_or(format!("unknown account type {}", account_type))?,
+                    Currency::new(&text("currency")?),
+                )
+            }
+            Some("Deposited") => EventKind::Deposited(text("account")?, money("amount")?),
+            Some("Withdrawn") => EventKind::Withdrawn(text("account")?, money("amount")?, text("memo").ok()),
+            Some("Transferred") => EventKind::Transferred(text("from")?, text("to")?, money("amount")?),
+            // Logs written before account statuses call these Deactivated and Activated.
+            Some("Frozen" | "Deactivated") => EventKind::Frozen(text("account")?),
+            Some("Unfrozen" | "Activated") => EventKind::Unfrozen(text("account")?),
+            Some("Closed") => EventKind::Closed(text("account")?, text("sweep_to").ok()),
+            Some("LimitSet") => EventKind::LimitSet(text("account")?, money("limit")?),
+            Some("Imported") => {
+                let transaction = import::ImportedTransaction {
+                    external_id: text("external_id")?,
+                    date: date("date")?,
+                    amount: money("amount")?,
+                    description: text("description")?,
+                };
+                EventKind::Imported(text("account")?, transaction)
+            }
+            Some("Reversed") => EventKind::Reversed(text("account")?, number("transaction")? as u64),
+            Some("DepositedIn") => EventKind::DepositedIn(text("account")?, money("amount")?, Currency::new(&text("currency")?)),
+            Some("WithdrawnIn") => EventKind::WithdrawnIn(text("account")?, money("amount")?, Currency::new(&text("currency")?)),
+            Some("InterestPaid") => EventKind::InterestPaid(text("account")?, money("amount")?),
+            Some("FeeCharged") => {
+                let fee_type = text("fee_type")?;
+                let fee_type = FeeType::from_name(&fee_type).ok_or(format!("unknown fee type {}", fee_type))?;
+                EventKind::FeeCharged(text("account")?, money("amount")?, fee_type, date("date")?)
+            }
+            Some("Authorized") => EventKind::Authorized(text("account")?, number("hold")? as u64, money("amount")?, date("expires")?),
+            Some("AuthorizationSettled") => EventKind::AuthorizationSettled(text("account")?, number("hold")? as u64),
+            Some("AuthorizationReleased") => EventKind::AuthorizationReleased(text("account")?, number("hold")? as u64),
+            Some("ChargedBack") => EventKind::ChargedBack(text("account")?, number("index")? as usize),
+            Some("TransferRepaired") => EventKind::TransferRepaired(text("account")?, number("transaction")? as u64),
+            Some("AccountImported") => EventKind::AccountImported(text("account")?, text("state")?),
+            Some("HolderAdded") => {
+                let role = text("role")?;
+                let role = auth::Role::from_name(&role).ok_or(format!("unknown role {}", role))?;
+                EventKind::HolderAdded(text("account")?, text("customer")?, role)
+            }
+            Some("HolderRemoved") => EventKind::HolderRemoved(text("account")?, text("customer")?),
+            Some(other) => return Err(format!("unknown event type {}", other)),
+            None => return Err("event missing type".to_string()),
+        };
+        let id = value.get("id").and_then(json::Value::as_f64).ok_or("event missing id")?;
+        Ok(Event { id: id as u64, kind })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operation {
+    Deposit,
+    Withdraw,
+    Transfer,
+}
+
+impl Operation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operation::Deposit => "Deposit",
+            Operation::Withdraw => "Withdraw",
+            Operation::Transfer => "Transfer",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Operation> {
+        match name {
+            "Deposit" => Some(Operation::Deposit),
+            "Withdraw" => Some(Operation::Withdraw),
+            "Transfer" => Some(Operation::Transfer),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MigrationReport {
+    pub total_accounts: usize,
+    pub accounts_affected: usize,
+    // Field name to the number of accounts it would be defaulted for.
+    pub defaulted_fields: BTreeMap<String, usize>,
+}
+
+// Largest single amount each operation may move, in the units of the currency the limits
+// are set for; None is unlimited.
+#[derive(Debug, Clone, Default)]
+pub struct TransactionLimits {
+    pub max_deposit: Option<Money>,
+    pub max_withdrawal: Option<Money>,
+    pub max_transfer: Option<Money>,
+}
+
+impl TransactionLimits {
+    pub fn limit_for(&self, operation: Operation) -> Option<Money> {
+        match operation {
+            Operation::Deposit => self.max_deposit,
+            Operation::Withdraw => self.max_withdrawal,
+            Operation::Transfer => self.max_transfer,
+        }
+    }
+
+    fn to_json(&self) -> json::Value {
+        let limit = |limit: Option<Money>| limit.map_or(json::Value::Null, Money::to_json);
+        json::object(vec![
+            ("max_deposit", limit(self.max_deposit)),
+            ("max_withdrawal", limit(self.max_withdrawal)),
+            ("max_transfer", limit(self.max_transfer)),
+        ])
+    }
+
+    fn from_json(value: &json::Value) -> TransactionLimits {
+        let limit = |field: &str| value.get(field).and_then(Money::from_json);
+        TransactionLimits {
+            max_deposit: limit("max_deposit"),
+            max_withdrawal: limit("max_withdrawal"),
+            max_transfer: limit("max_transfer"),
+        }
+    }
+}
+
+// Caps set per account type and enforced by the account itself. A day is a calendar day in
+// UTC; None is unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct VelocityLimits {
+    pub max_withdrawal: Option<Money>,
+    pub daily_withdrawal_total: Option<Money>,
+    pub daily_transfer_count: Option<u32>,
+}
+
+impl VelocityLimits {
+    fn to_json(self) -> json::Value {
+        let limit = |limit: Option<Money>| limit.map_or(json::Value::Null, Money::to_json);
+        json::object(vec![
+            ("max_withdrawal", limit(self.max_withdrawal)),
+            ("daily_withdrawal_total", limit(self.daily_withdrawal_total)),
+            (
+                "daily_transfer_count",
+                self.daily_transfer_count.map_or(json::Value::Null, |count| json::Value::Number(count as f64)),
+            ),
+        ])
+    }
+
+    fn from_json(value: &json::Value) -> VelocityLimits {
+        let limit = |field: &str| value.get(field).and_then(Money::from_json);
+        VelocityLimits {
+            max_withdrawal: limit("max_withdrawal"),
+            daily_withdrawal_total: limit("daily_withdrawal_total"),
+            daily_transfer_count: value.get("daily_transfer_count").and_then(json::Value::as_f64).map(|count| count as u32),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VelocityLimit {
+    MaxWithdrawal,
+    DailyWithdrawalTotal,
+    DailyTransferCount,
+}
+
+// What is left of today's velocity limits; None where there is no limit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DailyAllowance {
+    pub withdrawal: Option<Money>,
+    pub transfers: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ReviewRules {
+    pub amount_threshold: Option<Money>,
+    pub round_multiple: Option<Money>,
+}
+
+impl ReviewRules {
+    fn to_json(&self) -> json::Value {
+        let rule = |rule: Option<Money>| rule.map_or(json::Value::Null, Money::to_json);
+        json::object(vec![
+            ("amount_threshold", rule(self.amount_threshold)),
+            ("round_multiple", rule(self.round_multiple)),
+        ])
+    }
+
+    fn from_json(value: &json::Value) -> ReviewRules {
+        ReviewRules {
+            amount_threshold: value.get("amount_threshold").and_then(Money::from_json),
+            round_multiple: value.get("round_multiple").and_then(Money::from_json),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReviewItem {
+    pub id: u64,
+    pub account_number: String,
+    pub operation: Operation,
+    pub amount: Money,
+    pub reason: String,
+    pub flagged_at: SystemTime,
+}
+
+impl ReviewItem {
+    fn to_json(&self) -> json::Value {
+        json::object(vec![
+            ("id", json::Value::Number(self.id as f64)),
+            ("account", json::string(&self.account_number)),
+            ("operation", json::string(self.operation.name())),
+            ("amount", self.amount.to_json()),
+            ("reason", json::string(&self.reason)),
+            ("flagged_at", json::Value::Number(calendar::to_millis(self.flagged_at) as f64)),
+        ])
+    }
+
+    fn from_json(value: &json::Value) -> Result<ReviewItem, String> {
+        let missing = |field: &str| format!("review item missing {}", field);
+        let text = |field: &str| value.get(field).and_then(json::Value::as_str).map(str::to_string).ok_or_else(|| missing(field));
+        let number = |field: &str| value.get(field).and_then(json::Value::as_f64).ok_or_else(|| missing(field));
+        let operation = text("operation")?;
+        Ok(ReviewItem {
+            id: number("id")? as u64,
+            account_number: text("account")?,
+            operation: Operation::from_name(&operation).ok_or(format!("unknown operation {}", operation))?,
+            amount: value.get("amount").and_then(Money::from_json).ok_or_else(|| missing("amount"))?,
+            reason: text("reason")?,
+            flagged_at: calendar::from_millis(number("flagged_at")? as i64),
+        })
+    }
+}
+
+#[cfg(debug_assertions)]
+#[derive(Debug, Clone)]
+pub struct FailureMode {
+    pub operation: Operation,
+    pub error: BankError,
+}
+
+#[derive(Debug, Clone)]
+pub struct Bank {
+    name: String,
+    accounts: BTreeMap<String, Account>,
+    customers: BTreeMap<String, Customer>,
+    remainder_policy: RemainderPolicy,
+    withdrawal_rounding: WithdrawalRounding,
+    // Sub-cent remainders in currency units, so this is the one running total kept in f64.
+    retained_remainders: f64,
+    exchange_rates: HashMap<(Currency, Currency), ExchangeRate>,
+    scheduled_transfers: Vec<ScheduledTransfer>,
+    scheduled_deposits: Vec<ScheduledDeposit>,
+    recurring_payments: BTreeMap<u64, RecurringPayment>,
+    next_recurring_id: u64,
+    new_account_hold: Duration,
+    overdraft_fee: Money,
+    overdraft_grace: Duration,
+    minimum_opening_deposits: HashMap<AccountType, Money>,
+    // Annual rates for accounts that have no rate of their own.
+    interest_rates: HashMap<AccountType, f64>,
+    transaction_limits: HashMap<Currency, TransactionLimits>,
+    velocity_limits: HashMap<AccountType, VelocityLimits>,
+    mandates: BTreeMap<u64, Mandate>,
+    next_mandate_id: u64,
+    next_auth_id: u64,
+    max_pin_attempts: u32,
+    review_rules: ReviewRules,
+    review_queue: BTreeMap<u64, ReviewItem>,
+    next_review_id: u64,
+    applied_events: BTreeSet<u64>,
+    events: Vec<Event>,
+    next_event_id: u64,
+    event_log: Option<PathBuf>,
+    // How many of `events` have been written to the event log.
+    events_logged: usize,
+    // Journals not yet committed or rolled back. Their events reach the log only once the
+    // outermost one commits, so a rollback never leaves events behind in the file.
+    open_journals: usize,
+    // Append-only; records are never edited or removed once written.
+    audit_log: Vec<audit::AuditRecord>,
+    actor: String,
+    #[cfg(debug_assertions)]
+    failure_mode: Option<FailureMode>,
+}
+
+impl Bank {
+    pub fn new() -> Bank {
+        Bank {
+            name: "Bank".to_string(),
+            accounts: BTreeMap::new(),
+            customers: BTreeMap::new(),
+            remainder_policy: RemainderPolicy::ToBank,
+            withdrawal_rounding: WithdrawalRounding::Round,
+            retained_remainders: 0.0,
+            exchange_rates: HashMap::new(),
+            scheduled_transfers: Vec::new(),
+            scheduled_deposits: Vec::new(),
+            recurring_payments: BTreeMap::new(),
+            next_recurring_id: 1,
+            new_account_hold: Duration::ZERO,
+            overdraft_fee: Money::ZERO,
+            overdraft_grace: Duration::ZERO,
+            minimum_opening_deposits: HashMap::new(),
+            interest_rates: HashMap::new(),
+            transaction_limits: HashMap::new(),
+            velocity_limits: HashMap::new(),
+            mandates: BTreeMap::new(),
+            next_mandate_id: 1,
+            next_auth_id: 1,
+            max_pin_attempts: 3,
+            review_rules: ReviewRules::default(),
+            review_queue: BTreeMap::new(),
+            next_review_id: 1,
+            applied_events: BTreeSet::new(),
+            events: Vec::new(),
+            next_event_id: 1,
+            event_log: None,
+            events_logged: 0,
+            open_journals: 0,
+            audit_log: Vec::new(),
+            actor: "system".to_string(),
+            #[cfg(debug_assertions)]
+            failure_mode: None,
+        }
+    }
+
+    // Debug builds only: the next operation of the given kind fails with the given error,
+    // after which the injected failure is cleared.
+    #[cfg(debug_assertions)]
+    pub fn set_failure_mode(&mut self, mode: FailureMode) {
+        self.failure_mode = Some(mode);
+    }
+
+    #[cfg(debug_assertions)]
+    pub fn injected_failure(&mut self, operation: Operation) -> Option<BankError> {
+        match &self.failure_mode {
+            Some(mode) if mode.operation == operation => self.failure_mode.take().map(|mode| mode.error),
+            _ => None,
+        }
+    }
+
+    #[cfg(not(debug_assertions))]
+    pub fn injected_failure(&mut self, _operation: Operation) -> Option<BankError> {
+        None
+    }
+
+    // Appends an audit record for an operation that has just run, attributed to the current actor.
+    fn audit<T>(&mut self, operation: &str, parameters: Vec<(&str, String)>, result: &Result<T, BankError>) {
+        self.audit_log.push(audit::AuditRecord {
+            sequence: self.audit_log.len() as u64 + 1,
+            timestamp: SystemTime::now(),
+            actor: self.actor.clone(),
+            operation: operation.to_string(),
+            parameters: parameters.into_iter().map(|(name, value)| (name.to_string(), value)).collect(),
+            error: result.as_ref().err().map(BankError::to_string),
+        });
+    }
+
+    // Printed at the top of formal statements.
+    pub fn set_name(&mut self, name: String) {
+        self.name = name;
+    }
+
+    pub fn begin_journal(&mut self) -> Journal<'_> {
+        // The audit log is append-only, so it is kept out of what a rollback restores.
+        let audit_log = std::mem::take(&mut self.audit_log);
+        let before = Box::new(self.clone());
+        self.audit_log = audit_log;
+        self.open_journals += 1;
+        Journal { bank: self, before: Some(before) }
+    }
+
+    // Applies the events in order, skipping any whose id is already in this bank's history.
+    // Stops at the first event that fails, leaving the ones before it applied. Replayed events
+    // join the history under their own ids but are not audited. Returns how many were applied.
+    pub fn replay(&mut self, events: &[Event]) -> Result<usize, BankError> {
+        let mut applied = 0;
+        for event in events {
+            if self.applied_events.contains(&event.id) {
+                continue;
+            }
+            self.apply(&event.kind)?;
+            self.record_event(event.clone());
+            applied += 1;
+        }
+        Ok(applied)
+    }
+
+    // A bank in the state the events leave it in, starting from an empty bank.
+    pub fn from_events(events: &[Event]) -> Result<Bank, BankError> {
+        let mut bank = Bank::new();
+        bank.replay(events)?;
+        Ok(bank)
+    }
+
+    // Every event this bank has made or replayed since it was created or loaded, oldest first.
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    // From now on each event is appended to `path` as a line of JSON as soon as it happens.
+    // Events already in the file keep their ids; new ones are numbered after them.
+    pub fn set_event_log(&mut self, path: &Path) -> Result<(), BankError> {
+        if path.exists() {
+            if let Some(last) = Bank::read_event_log(path)?.iter().map(|event| event.id).max() {
+                self.next_event_id = self.next_event_id.max(last + 1);
+            }
+        }
+        self.event_log = Some(path.to_path_buf());
+        self.events_logged = self.events.len();
+        Ok(())
+    }
+
+    pub fn read_event_log(path: &Path) -> Result<Vec<Event>, BankError> {
+        let contents = fs::read_to_string(path).map_err(|e| BankError::Io(e.to_string()))?;
+        contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .enumerate()
+            .map(|(i, line)| {
+                json::parse(line)
+                    .and_then(|value| Event::from_json(&value))
+                    .map_err(|e| BankError::InvalidData(format!("event log line {}: {}", i + 1, e)))
+            })
+            .collect()
+    }
+
+    // Writes any events the log is missing. A failed write is retried with the next event, so
+    // this only needs calling to find out whether the log has caught up. Inside a journal,
+    // nothing is written until it commits.
+    pub fn flush_event_log(&mut self) -> Result<(), BankError> {
+        let Some(path) = &self.event_log else {
+            return Ok(());
+        };
+        if self.open_journals > 0 {
+            return Ok(());
+        }
+        if self.events_logged == self.events.len() {
+            return Ok(());
+        }
+        let lines: String = self.events[self.events_logged..].iter().map(|event| format!("{}\n", event.to_json())).collect();
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| io::Write::write_all(&mut file, lines.as_bytes()))
+            .map_err(|e| BankError::Io(e.to_string()))?;
+        self.events_logged = self.events.len();
+        Ok(())
+    }
+
+    // Applies one of the core operations, audits the attempt and records it as an event when
+    // it succeeds.
+    fn execute(&mut self, kind: EventKind) -> Result<(), BankError> {
+        let result = self.apply(&kind);
+        self.audit(kind.operation(), kind.parameters(), &result);
+        if result.is_ok() {
+            let id = self.next_event_id;
+            self.record_event(Event { id, kind });
+        }
+        result
+    }
+
+    fn apply(&mut self, kind: &EventKind) -> Result<(), BankError> {
+        match kind.clone() {
+            EventKind::AccountOpened(number, account_type, currency) => self.insert_new_account(number, account_type, currency),
+            EventKind::Deposited(number, amount) => self.apply_deposit(number, amount),
+            EventKind::Withdrawn(number, amount, memo) => self.apply_withdrawal(number, amount, memo),
+            EventKind::Transferred(from, to, amount) => self.apply_transfer(from, to, amount),
+            EventKind::Frozen(number) => self.accounts.get_mut(&number).ok_or(BankError::AccountNotFound)?.freeze(),
+            EventKind::Unfrozen(number) => self.accounts.get_mut(&number).ok_or(BankError::AccountNotFound)?.unfreeze(),
+            EventKind::Closed(number, sweep_to) => self.apply_close(&number, sweep_to),
+            EventKind::LimitSet(number, limit) => self.apply_account_limit(number, limit),
+            EventKind::Imported(number, transaction) => self.apply_import(number, transaction),
+            EventKind::Reversed(number, id) => self.apply_reversal(number, id),
+            EventKind::DepositedIn(number, amount, currency) => {
+                self.accounts.get_mut(&number).ok_or(BankError::AccountNotFound)?.deposit_currency(amount, &currency)
+            }
+            EventKind::WithdrawnIn(number, amount, currency) => {
+                self.accounts.get_mut(&number).ok_or(BankError::AccountNotFound)?.withdraw_currency(amount, &currency)
+            }
+            EventKind::InterestPaid(number, amount) => self.apply_interest_paid(number, amount),
+            EventKind::FeeCharged(number, amount, fee_type, charged_at) => self.apply_fee(number, amount, fee_type, charged_at),
+            EventKind::Authorized(number, id, amount, expires) => self.apply_authorization(number, id, amount, expires),
+            EventKind::AuthorizationSettled(number, id) => self.apply_settlement(number, id),
+            EventKind::AuthorizationReleased(number, id) => self.apply_release(number, id),
+            EventKind::ChargedBack(number, index) => self.apply_chargeback(number, index),
+            EventKind::TransferRepaired(number, id) => self.apply_transfer_repair(number, id),
+            EventKind::AccountImported(number, state) => self.apply_account_import(number, &state),
+            EventKind::HolderAdded(number, customer_id, role) => {
+                self.accounts.get_mut(&number).ok_or(BankError::AccountNotFound)?.holders.insert(customer_id, role);
+                Ok(())
+            }
+            EventKind::HolderRemoved(number, customer_id) => {
+                let account = self.accounts.get_mut(&number).ok_or(BankError::AccountNotFound)?;
+                account.holders.remove(&customer_id).map(|_| ()).ok_or(BankError::CustomerNotFound)
+            }
+        }
+    }
+
+    fn record_event(&mut self, event: Event) {
+        self.applied_events.insert(event.id);
+        self.next_event_id = self.next_event_id.max(event.id + 1);
+        self.events.push(event);
+        // The event has already happened, so a log that can't be written only falls behind.
+        let _ = self.flush_event_log();
+    }
+
+    pub fn set_remainder_policy(&mut self, policy: RemainderPolicy) {
+        self.remainder_policy = policy;
+    }
+
+    pub fn set_withdrawal_rounding(&mut self, rounding: WithdrawalRounding) {
+        self.withdrawal_rounding = rounding;
+    }
+
+    // Brings a computed withdrawal to whole cents. 33.333% of 100.00 is 33.333, which is
+    // 33.33 either way; 33.335% of 100.00 is 33.335, which truncates to 33.33 but rounds
+    // to 33.34.
+    pub fn round_withdrawal(&self, exact: f64) -> Money {
+        let cents = exact * 100.0;
+        match self.withdrawal_rounding {
+            WithdrawalRounding::Truncate => Money::from_cents((cents + 1e-9).floor() as i64),
+            WithdrawalRounding::Round => Money::from_cents(cents.round() as i64),
+        }
+    }
+
+    // Rounds a computed amount to whole cents, routing the sub-cent remainder according
+    // to the policy. Remainders kept by the bank (or given away) are tracked in
+    // `retained_remainders` so customer balances plus retained remainders reconcile.
+    pub fn settle_remainder(&mut self, exact: f64, flow: Flow) -> Money {
+        let (rounded, retained) = self.round_remainder(exact, flow);
+        self.retained_remainders += retained;
+        rounded
+    }
+
+    // What settle_remainder has kept so far, negative when more was given away than kept.
+    pub fn retained_remainders(&self) -> f64 {
+        self.retained_remainders
+    }
+
+    pub fn round_remainder(&self, exact: f64, flow: Flow) -> (Money, f64) {
+        let cents = exact * 100.0;
+        let floor = Money::from_cents((cents + 1e-9).floor() as i64);
+        let ceil = Money::from_cents((cents - 1e-9).ceil() as i64);
+        let rounded = match (self.remainder_policy, flow) {
+            (RemainderPolicy::ToBank, Flow::Payout) | (RemainderPolicy::ToCustomer, Flow::Charge) => floor,
+            (RemainderPolicy::ToBank, Flow::Charge) | (RemainderPolicy::ToCustomer, Flow::Payout) => ceil,
+            (RemainderPolicy::Discard, _) => floor,
+        };
+        let retained = match (self.remainder_policy, flow) {
+            (RemainderPolicy::Discard, _) => 0.0,
+            (_, Flow::Payout) => exact - rounded.to_f64(),
+            (_, Flow::Charge) => rounded.to_f64() - exact,
+        };
+        (rounded, retained)
+    }
+
+    pub fn set_exchange_rate(&mut self, base: Currency, quote: Currency, bid: f64, ask: f64) {
+        self.exchange_rates.insert((base, quote), ExchangeRate { bid, ask });
+    }
+
+    // Reads `base,quote,rate` rows, using the single rate as both bid and ask. A header
+    // row and blank lines are skipped. Nothing is loaded if any row is malformed.
+    pub fn load_exchange_rates(&mut self, path: &Path) -> Result<usize, BankError> {
+        let contents = fs::read_to_string(path).map_err(|e| BankError::Io(e.to_string()))?;
+        let mut rates = Vec::new();
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || (i == 0 && line.to_ascii_lowercase().starts_with("base,")) {
+                continue;
+            }
+            let malformed = || BankError::InvalidData(format!("line {}: expected base,quote,rate but found {:?}", i + 1, line));
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let (base, quote, rate) = match fields.as_slice() {
+                [base, quote, rate] if !base.is_empty() && !quote.is_empty() => (base, quote, rate),
+                _ => return Err(malformed()),
+            };
+            let rate: f64 = rate.parse().map_err(|_| malformed())?;
+            if !rate.is_finite() || rate <= 0.0 {
+                return Err(malformed());
+            }
+            rates.push((Currency::new(base), Currency::new(quote), rate));
+        }
+        let loaded = rates.len();
+        for (base, quote, rate) in rates {
+            self.set_exchange_rate(base, quote, rate, rate);
+        }
+        Ok(loaded)
+    }
+
+    // The bank buys the base currency at the bid and sells it at the ask, so converting
+    // base -> quote multiplies by the bid and quote -> base divides by the ask. The
+    // converted amount is exact; callers decide how to round it.
+    pub fn convert(&self, amount: Money, from: &Currency, to: &Currency) -> Option<(f64, f64)> {
+        let amount = amount.to_f64();
+        if from == to {
+            return Some((amount, 1.0));
+        }
+        if let Some(rate) = self.exchange_rates.get(&(from.clone(), to.clone())) {
+            Some((amount * rate.bid, rate.bid))
+        } else {
+            self.exchange_rates
+                .get(&(to.clone(), from.clone()))
+                .map(|rate| (amount / rate.ask, 1.0 / rate.ask))
+        }
+    }
+
+    // Midpoint between the bid and ask, in units of `to` per unit of `from`.
+    pub fn mid_rate(&self, from: &Currency, to: &Currency) -> Option<f64> {
+        if from == to {
+            return Some(1.0);
+        }
+        if let Some(rate) = self.exchange_rates.get(&(from.clone(), to.clone())) {
+            Some((rate.bid + rate.ask) / 2.0)
+        } else {
+            self.exchange_rates.get(&(to.clone(), from.clone())).map(|rate| 2.0 / (rate.bid + rate.ask))
+        }
+    }
+
+    // What a transfer would cost without executing it. The FX cost is what the spread and
+    // rounding take compared with converting at the mid rate, in the sender's currency.
+    pub fn transfer_cost_breakdown(&self, from_account: &str, to_account: &str, amount: Money) -> Result<TransferCost, BankError> {
+        let from = self.accounts.get(from_account).ok_or(BankError::AccountNotFound)?;
+        let to = self.accounts.get(to_account).ok_or(BankError::AccountNotFound)?;
+        let fee = from.withdrawal_fee(SystemTime::now());
+        let (credited, fx_cost) = if from.currency == to.currency {
+            (amount, Money::ZERO)
+        } else {
+            let (converted, _) = self.convert(amount, &from.currency, &to.currency).ok_or(BankError::ExchangeRateUnavailable)?;
+            let mid = self.mid_rate(&from.currency, &to.currency).ok_or(BankError::ExchangeRateUnavailable)?;
+            let (credited, _) = self.round_remainder(converted, Flow::Payout);
+            (credited, amount - Money::from_f64(credited.to_f64() / mid))
+        };
+        Ok(TransferCost {
+            principal: amount,
+            fee,
+            fx_cost,
+            total_debited: amount + fee,
+            credited,
+        })
+    }
+
+    pub fn set_type_interest_rate(&mut self, account_type: AccountType, rate: f64) {
+        self.interest_rates.insert(account_type, rate);
+    }
+
+    // The account's own rate, or its type's when it has none of its own.
+    pub fn effective_rate(&self, account: &Account) -> f64 {
+        if account.has_own_rate() {
+            account.interest_rate
+        } else {
+            self.interest_rates.get(&account.account_type).copied().unwrap_or(0.0)
+        }
+    }
+
+    // Posts interest for the period to every active account: deposit accounts are paid on
+    // their interest basis, and Credit accounts owing money at the end of the period are
+    // charged simple interest as a finance charge. An account's own rate takes precedence over
+    // its type's. Returns each posting, with charges negative.
+    pub fn accrue_interest(&mut self, period: &Period) -> Vec<(String, Money)> {
+        let years = calendar::years_between(period.from, period.to);
+        let mut postings = Vec::new();
+        for (number, account) in &self.accounts {
+            if account.status != AccountStatus::Open {
+                continue;
+            }
+            let rate = self.effective_rate(account);
+            if matches!(account.account_type, AccountType::Credit) {
+                let owed = account.balance_effect_where(|t| t.timestamp > period.to) - account.balance;
+                let charge = owed.scale(rate * years);
+                if charge > Money::ZERO {
+                    let kind = EventKind::FeeCharged(number.clone(), charge, FeeType::FinanceCharge, SystemTime::now());
+                    postings.push((number.clone(), -charge, kind));
+                }
+                continue;
+            }
+            let basis = account.interest_basis_for(period.from, period.to);
+            if basis <= Money::ZERO {
+                continue;
+            }
+            let interest = if account.has_own_rate() {
+                account.interest_on(basis, years)
+            } else {
+                basis.scale(account.compound_frequency.growth(rate, years))
+            };
+            if interest > Money::ZERO {
+                postings.push((number.clone(), interest, EventKind::InterestPaid(number.clone(), interest)));
+            }
+        }
+        let mut posted = Vec::new();
+        for (number, amount, kind) in postings {
+            if self.execute(kind).is_ok() {
+                posted.push((number, amount));
+            }
+        }
+        posted
+    }
+
+    fn apply_interest_paid(&mut self, account_number: String, amount: Money) -> Result<(), BankError> {
+        let account = self.accounts.get_mut(&account_number).ok_or(BankError::AccountNotFound)?;
+        if account.status == AccountStatus::Closed {
+            return Err(BankError::AccountClosed);
+        }
+        account.balance = account.balance.checked_add(amount)?;
+        account.record(TransactionKind::Interest(amount));
+        Ok(())
+    }
+
+    fn apply_fee(&mut self, account_number: String, amount: Money, fee_type: FeeType, charged_at: SystemTime) -> Result<(), BankError> {
+        let account = self.accounts.get_mut(&account_number).ok_or(BankError::AccountNotFound)?;
+        if account.status == AccountStatus::Closed {
+            return Err(BankError::AccountClosed);
+        }
+        account.balance = account.balance.checked_sub(amount)?;
+        let currency = account.currency.clone();
+        account.record_at(TransactionKind::Fee(amount, fee_type), currency, None, charged_at);
+        Ok(())
+    }
+
+    pub fn set_overdraft_fee(&mut self, fee: Money, grace: Duration) {
+        self.overdraft_fee = fee;
+        self.overdraft_grace = grace;
+    }
+
+    // Charges the overdraft fee on Checking and Savings accounts that have stayed negative for
+    // longer than the grace period, once per overdraft. Credit accounts are in debt rather than
+    // overdrawn and pay finance charges instead. Returns the accounts charged.
+    pub fn process_overdraft_fees(&mut self, now: SystemTime) -> Vec<String> {
+        if self.overdraft_fee <= Money::ZERO {
+            return Vec::new();
+        }
+        let mut due = Vec::new();
+        for (number, account) in &self.accounts {
+            if matches!(account.account_type, AccountType::Credit) {
+                continue;
+            }
+            let since = match account.overdrawn_since() {
+                Some(since) => since,
+                None => continue,
+            };
+            if now.duration_since(since).map_or(true, |overdrawn| overdrawn <= self.overdraft_grace) {
+                continue;
+            }
+            let already_charged = account
+                .transactions
+                .iter()
+                .any(|t| matches!(t.kind, TransactionKind::Fee(_, FeeType::Overdraft)) && t.timestamp >= since);
+            if already_charged {
+                continue;
+            }
+            due.push(number.clone());
+        }
+        let fee = self.overdraft_fee;
+        due.into_iter()
+            .filter(|number| self.execute(EventKind::FeeCharged(number.clone(), fee, FeeType::Overdraft, SystemTime::now())).is_ok())
+            .collect()
+    }
+
+    // The rounding remainder is only kept once the fee has actually been charged.
+    pub fn charge_percentage_fee(&mut self, account_number: String, rate: f64, fee_type: FeeType) -> Result<Money, BankError> {
+        let balance = self.accounts.get(&account_number).ok_or(BankError::AccountNotFound)?.balance;
+        let (fee, retained) = self.round_remainder(balance.max(Money::ZERO).to_f64() * rate, Flow::Charge);
+        self.execute(EventKind::FeeCharged(account_number, fee, fee_type, SystemTime::now()))?;
+        self.retained_remainders += retained;
+        Ok(fee)
+    }
+
+    pub fn create_account(&mut self, account_number: String, account_type: AccountType) -> Result<(), BankError> {
+        self.create_account_in_currency(account_number, account_type, Currency::default())
+    }
+
+    // An existing account with the same number is left untouched.
+    pub fn create_account_in_currency(&mut self, account_number: String, account_type: AccountType, currency: Currency) -> Result<(), BankError> {
+        self.execute(EventKind::AccountOpened(account_number, account_type, currency))
+    }
+
+    fn insert_new_account(&mut self, account_number: String, account_type: AccountType, currency: Currency) -> Result<(), BankError> {
+        if self.accounts.contains_key(&account_number) {
+            return Err(BankError::AccountExists);
+        }
+        let account = self.new_account(account_type, currency);
+        self.accounts.insert(account_number, account);
+        Ok(())
+    }
+
+    fn new_account(&self, account_type: AccountType, currency: Currency) -> Account {
+        let mut account = Account::new(account_type);
+        account.currency = currency;
+        account.velocity_limits = self.velocity_limits.get(&account.account_type).copied().unwrap_or_default();
+        if self.new_account_hold > Duration::ZERO {
+            account.withdrawal_hold_until = Some(account.created_at + self.new_account_hold);
+        }
+        account
+    }
+
+    // The limit is the credit limit of a Credit account, the minimum balance of a Savings
+    // account or the overdraft allowance of a Checking account.
+    pub fn create_account_with_limit(&mut self, account_number: String, account_type: AccountType, limit: Money) -> Result<(), BankError> {
+        self.create_account(account_number.clone(), account_type)?;
+        self.set_account_limit(account_number, limit)
+    }
+
+    // Sets whichever limit the account's type has, as for create_account_with_limit.
+    pub fn set_account_limit(&mut self, account_number: String, limit: Money) -> Result<(), BankError> {
+        self.execute(EventKind::LimitSet(account_number, limit))
+    }
+
+    fn apply_account_limit(&mut self, account_number: String, limit: Money) -> Result<(), BankError> {
+        let account = self.accounts.get(&account_number).ok_or(BankError::AccountNotFound)?;
+        if account.status == AccountStatus::Closed {
+            return Err(BankError::AccountClosed);
+        }
+        match account.account_type {
+            AccountType::Checking => self.set_overdraft_limit(account_number, limit),
+            AccountType::Savings => self.set_minimum_balance(account_number, limit),
+            AccountType::Credit => self.set_credit_limit(account_number, limit),
+        }
+    }
+
+    // Imported entries are history the other bank has already settled, so only the account's
+    // balance floor applies, not limits, fees or review rules.
+    fn apply_import(&mut self, account_number: String, transaction: import::ImportedTransaction) -> Result<(), BankError> {
+        let account = self.accounts.get_mut(&account_number).ok_or(BankError::AccountNotFound)?;
+        account.check_open()?;
+        let balance = account.balance.checked_add(transaction.amount)?;
+        if transaction.amount < Money::ZERO {
+            account.check_balance_floor(balance, SystemTime::now())?;
+        }
+        account.balance = balance;
+        let kind = if transaction.amount < Money::ZERO {
+            TransactionKind::Withdrawal(-transaction.amount)
+        } else {
+            TransactionKind::Deposit(transaction.amount)
+        };
+        let currency = account.currency.clone();
+        let memo = Some(transaction.description).filter(|description| !description.is_empty());
+        account.record_at(kind, currency, memo, transaction.date);
+        account.imported_ids.insert(transaction.external_id);
+        Ok(())
+    }
+
+    pub fn open_account(&mut self, account_number: String, account_type: AccountType, opening_deposit: Money) -> Result<(), BankError> {
+        if self.accounts.contains_key(&account_number) {
+            return Err(BankError::AccountExists);
+        }
+        if opening_deposit < self.minimum_opening_deposit(&account_type) {
+            return Err(BankError::BelowMinimumDeposit);
+        }
+        // In a journal, so a refused deposit doesn't leave an empty account behind.
+        let mut journal = self.begin_journal();
+        journal.create_account(account_number.clone(), account_type)?;
+        if opening_deposit > Money::ZERO {
+            journal.deposit(account_number, opening_deposit)?;
+        }
+        journal.commit();
+        Ok(())
+    }
+
+    pub fn minimum_opening_deposit(&self, account_type: &AccountType) -> Money {
+        self.minimum_opening_deposits.get(account_type).copied().unwrap_or_default()
+    }
+
+    pub fn set_minimum_opening_deposit(&mut self, account_type: AccountType, amount: Money) {
+        self.minimum_opening_deposits.insert(account_type, amount);
+    }
+
+    pub fn set_transaction_limits(&mut self, currency: Currency, limits: TransactionLimits) {
+        self.transaction_limits.insert(currency, limits);
+    }
+
+    // Applies to existing accounts of the type as well as ones opened later.
+    pub fn set_velocity_limits(&mut self, account_type: AccountType, limits: VelocityLimits) {
+        for account in self.accounts.values_mut().filter(|account| account.account_type == account_type) {
+            account.velocity_limits = limits;
+        }
+        self.velocity_limits.insert(account_type, limits);
+    }
+
+    pub fn remaining_daily_allowance(&self, account_number: &str) -> Result<DailyAllowance, BankError> {
+        let account = self.accounts.get(account_number).ok_or(BankError::AccountNotFound)?;
+        Ok(account.remaining_daily_allowance(SystemTime::now()))
+    }
+
+    // Amounts are checked against the limits for the account's own currency.
+    pub fn check_transaction_limit(&self, account_number: &str, operation: Operation, amount: Money) -> Result<(), BankError> {
+        let account = self.accounts.get(account_number).ok_or(BankError::AccountNotFound)?;
+        self.check_limit_in(&account.currency, operation, amount)
+    }
+
+    fn check_limit_in(&self, currency: &Currency, operation: Operation, amount: Money) -> Result<(), BankError> {
+        let limit = self.transaction_limits.get(currency).and_then(|limits| limits.limit_for(operation));
+        match limit {
+            Some(limit) if amount > limit => Err(BankError::TransactionLimitExceeded),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn set_new_account_hold(&mut self, hold: Duration) {
+        self.new_account_hold = hold;
+    }
+
+    pub fn deposit(&mut self, account_number: String, amount: Money) -> Result<(), BankError> {
+        self.execute(EventKind::Deposited(account_number, amount))
+    }
+
+    fn apply_deposit(&mut self, account_number: String, amount: Money) -> Result<(), BankError> {
+        if let Some(error) = self.injected_failure(Operation::Deposit) {
+            return Err(error);
+        }
+        if amount <= Money::ZERO {
+            return Err(BankError::InvalidAmount);
+        }
+        self.check_transaction_limit(&account_number, Operation::Deposit, amount)?;
+        let account = self.accounts.get_mut(&account_number).ok_or(BankError::AccountNotFound)?;
+        account.deposit(amount)?;
+        self.screen_for_review(&account_number, Operation::Deposit, amount);
+        Ok(())
+    }
+
+    pub fn withdraw(&mut self, account_number: String, amount: Money) -> Result<(), BankError> {
+        self.withdraw_with_memo(account_number, amount, None)
+    }
+
+    pub fn withdraw_with_memo(&mut self, account_number: String, amount: Money, memo: Option<String>) -> Result<(), BankError> {
+        self.execute(EventKind::Withdrawn(account_number, amount, memo))
+    }
+
+    fn apply_withdrawal(&mut self, account_number: String, amount: Money, memo: Option<String>) -> Result<(), BankError> {
+        if let Some(error) = self.injected_failure(Operation::Withdraw) {
+            return Err(error);
+        }
+        if amount <= Money::ZERO {
+            return Err(BankError::InvalidAmount);
+        }
+        self.check_transaction_limit(&account_number, Operation::Withdraw, amount)?;
+        let account = self.accounts.get_mut(&account_number).ok_or(BankError::AccountNotFound)?;
+        account.withdraw_with_memo(amount, memo)?;
+        self.screen_for_review(&account_number, Operation::Withdraw, amount);
+        Ok(())
+    }
+
+    // Withdraws `fraction` of the current balance, rounded by the bank's withdrawal rounding.
+    pub fn withdraw_percentage(&mut self, account_number: String, fraction: f64) -> Result<(), BankError> {
+        let balance = self.accounts.get(&account_number).ok_or(BankError::AccountNotFound)?.balance;
+        let amount = self.round_withdrawal(balance.to_f64() * fraction);
+        self.withdraw(account_number, amount)
+    }
+
+    pub fn set_review_rules(&mut self, rules: ReviewRules) {
+        self.review_rules = rules;
+    }
+
+    pub fn screen_for_review(&mut self, account_number: &str, operation: Operation, amount: Money) {
+        let reason = match (self.review_rules.amount_threshold, self.review_rules.round_multiple) {
+            (Some(threshold), _) if amount > threshold => format!("amount over {}", threshold),
+            (_, Some(multiple)) if multiple > Money::ZERO && amount >= multiple && amount.cents() % multiple.cents() == 0 => {
+                format!("round amount (multiple of {})", multiple)
+            }
+            _ => return,
+        };
+        let id = self.next_review_id;
+        self.next_review_id += 1;
+        self.review_queue.insert(
+            id,
+            ReviewItem {
+                id,
+                account_number: account_number.to_string(),
+                operation,
+                amount,
+                reason,
+                flagged_at: SystemTime::now(),
+            },
+        );
+    }
+
+    pub fn review_queue(&self) -> Vec<&ReviewItem> {
+        self.review_queue.values().collect()
+    }
+
+    pub fn clear_review(&mut self, id: u64) -> bool {
+        self.review_queue.remove(&id).is_some()
+    }
+
+    pub fn balance(&self, account_number: String) -> Option<Money> {
+        self.accounts.get(&account_number).map(Account::balance)
+    }
+
+    pub fn available_balance(&self, account_number: String) -> Option<Money> {
+        self.accounts.get(&account_number).map(Account::available_balance)
+    }
+
+    pub fn deposit_currency(&mut self, account_number: String, amount: Money, currency: Currency) -> Result<(), BankError> {
+        self.execute(EventKind::DepositedIn(account_number, amount, currency))
+    }
+
+    pub fn withdraw_currency(&mut self, account_number: String, amount: Money, currency: Currency) -> Result<(), BankError> {
+        self.execute(EventKind::WithdrawnIn(account_number, amount, currency))
+    }
+
+    pub fn balance_in(&self, account_number: String, currency: Currency) -> Option<Money> {
+        self.accounts.get(&account_number).map(|account| account.balance_in(&currency))
+    }
+
+    pub fn transfer(&mut self, from_account: String, to_account: String, amount: Money) -> Result<(), BankError> {
+        self.execute(EventKind::Transferred(from_account, to_account, amount))
+    }
+
+    fn apply_transfer(&mut self, from_account: String, to_account: String, amount: Money) -> Result<(), BankError> {
+        if let Some(error) = self.injected_failure(Operation::Transfer) {
+            return Err(error);
+        }
+        if amount <= Money::ZERO {
+            return Err(BankError::InvalidAmount);
+        }
+        let from = self.accounts.get(&from_account).ok_or(BankError::AccountNotFound)?;
+        let to = self.accounts.get(&to_account).ok_or(BankError::AccountNotFound)?;
+        if from_account == to_account {
+            return Err(BankError::SameAccount);
+        }
+        self.check_transaction_limit(&from_account, Operation::Transfer, amount)?;
+        from.check_open()?;
+        to.check_open()?;
+        from.check_withdrawal_hold(SystemTime::now())?;
+        from.check_transfer_velocity(SystemTime::now())?;
+        let (from_currency, to_currency) = (from.currency.clone(), to.currency.clone());
+        let (credited, rate, retained) = match self.convert(amount, &from_currency, &to_currency) {
+            Some((converted, rate)) if from_currency != to_currency => {
+                let (rounded, retained) = self.round_remainder(converted, Flow::Payout);
+                (rounded, rate, retained)
+            }
+            Some(_) => (amount, 1.0, 0.0),
+            None => return Err(BankError::ExchangeRateUnavailable),
+        };
+
+        if credited <= Money::ZERO {
+            return Err(BankError::InvalidAmount);
+        }
+        to.balance.checked_add(credited)?;
+
+        // Each side writes one leg, both dated alike. Should the credit still fail, the debit
+        // and its fee are taken back out of the source ledger.
+        let now = SystemTime::now();
+        let from = self.accounts.get_mut(&from_account).ok_or(BankError::AccountNotFound)?;
+        let (ledger_len, balance) = (from.transactions.len(), from.balance);
+        from.debit(TransactionKind::TransferOut(amount, to_account.clone()), None, now)?;
+        let to = self.accounts.get_mut(&to_account).ok_or(BankError::AccountNotFound)?;
+        if let Err(error) = to.receive_transfer(credited, from_account.clone(), now) {
+            let from = self.accounts.get_mut(&from_account).ok_or(BankError::AccountNotFound)?;
+            from.transactions.truncate(ledger_len);
+            from.balance = balance;
+            return Err(error);
+        }
+        if from_currency != to_currency {
+            to.record_at(TransactionKind::Conversion(amount, from_currency, rate), to_currency, None, now);
+            self.retained_remainders += retained;
+        }
+        self.screen_for_review(&from_account, Operation::Transfer, amount);
+        Ok(())
+    }
+
+    // Reverses the most recent deposit, withdrawal or transfer on the account that hasn't been
+    // reversed yet, through reverse_transaction, so the entries stay and Reversal entries undo them.
+    // Fails with NotReversible when the latest remaining entry isn't such an operation.
+    pub fn undo_last(&mut self, account_number: &str) -> Result<(), BankError> {
+        let account = self.accounts.get(account_number).ok_or(BankError::AccountNotFound)?;
+        let undone = |t: &Transaction| t.reversed_by.is_some() || matches!(t.kind, TransactionKind::Reversal(..));
+        let end = account.transactions.iter().rposition(|t| !undone(t)).ok_or(BankError::TransactionNotFound)? + 1;
+        let start = account.operation_start(end).ok_or(BankError::NotReversible)?;
+        let id = account.transactions[start].id;
+        self.reverse_transaction(account_number.to_string(), id)
+    }
+
+    // Undoes the deposit, withdrawal or transfer that wrote transaction `id` by posting a
+    // Reversal entry, on both accounts for a transfer, and marks the original entries reversed.
+    // A withdrawal's fee is refunded with it.
+    pub fn reverse_transaction(&mut self, account_number: String, id: u64) -> Result<(), BankError> {
+        self.execute(EventKind::Reversed(account_number, id))
+    }
+
+    fn apply_reversal(&mut self, account_number: String, id: u64) -> Result<(), BankError> {
+        let account = self.accounts.get(&account_number).ok_or(BankError::AccountNotFound)?;
+        let index = account.transactions.iter().position(|t| t.id == id).ok_or(BankError::TransactionNotFound)?;
+        let range = account.operation_containing(index).ok_or(BankError::NotReversible)?;
+        let mut reversals = vec![(account_number.clone(), range.clone())];
+
+        let is_leg = |kind: &TransactionKind| matches!(kind, TransactionKind::TransferOut(..) | TransactionKind::TransferIn(..));
+        let leg = range.clone().find(|i| is_leg(&account.transactions[*i].kind));
+        if let Some(leg) = leg {
+            let (counterparty, other_leg) = self.counterpart_leg(&account_number, leg)?;
+            let other = &self.accounts[&counterparty];
+            reversals.push((counterparty, other.operation_containing(other_leg).ok_or(BankError::NotReversible)?));
+        }
+
+        self.post_reversals(reversals)
+    }
+
+    // Posts a Reversal entry undoing each range of entries and marks them reversed. Every account
+    // is checked before any is changed, so both legs of a transfer are reversed or neither.
+    fn post_reversals(&mut self, reversals: Vec<(String, Range<usize>)>) -> Result<(), BankError> {
+        let now = SystemTime::now();
+        for (number, range) in &reversals {
+            let account = &self.accounts[number];
+            let entries = &account.transactions[range.clone()];
+            if entries.iter().any(|t| t.reversed_by.is_some()) {
+                return Err(BankError::AlreadyReversed);
+            }
+            if account.status == AccountStatus::Closed {
+                return Err(BankError::AccountClosed);
+            }
+            if entries.iter().any(|t| t.currency != account.currency) {
+                return Err(BankError::NotReversible);
+            }
+            let effect = entries.iter().try_fold(Money::ZERO, |effect, t| effect.checked_add(t.kind.balance_effect()))?;
+            let remaining = account.balance.checked_sub(effect)?;
+            if effect > Money::ZERO {
+                account.check_balance_floor(remaining, now)?;
+            }
+        }
+        for (number, range) in reversals {
+            let account = self.accounts.get_mut(&number).ok_or(BankError::AccountNotFound)?;
+            let effect: Money = account.transactions[range.clone()].iter().map(|t| t.kind.balance_effect()).sum();
+            let original = account.transactions[range.start].id;
+            let reversal = account.next_transaction_id;
+            account.balance = account.balance.checked_sub(effect)?;
+            account.record(TransactionKind::Reversal(-effect, original));
+            for transaction in &mut account.transactions[range] {
+                transaction.reversed_by = Some(reversal);
+            }
+        }
+        Ok(())
+    }
+
+    // The other side of the transfer leg at `index`: the counterparty and the position of its leg.
+    fn counterpart_leg(&self, account_number: &str, index: usize) -> Result<(String, usize), BankError> {
+        let account = self.accounts.get(account_number).ok_or(BankError::AccountNotFound)?;
+        let (sent, counterparty) = match &account.transactions.get(index).ok_or(BankError::TransactionNotFound)?.kind {
+            TransactionKind::TransferOut(_, to) => (true, to.clone()),
+            TransactionKind::TransferIn(_, from) => (false, from.clone()),
+            _ => return Err(BankError::TransactionNotFound),
+        };
+        let other_leg = if sent {
+            self.transfer_legs(account_number, &counterparty).into_iter().find(|(out, _)| *out == index).and_then(|(_, leg)| leg)
+        } else {
+            self.transfer_legs(&counterparty, account_number).into_iter().find(|(_, leg)| *leg == Some(index)).map(|(out, _)| out)
+        };
+        Ok((counterparty, other_leg.ok_or(BankError::TransactionNotFound)?))
+    }
+
+    // Pairs each TransferOut from `from` to `to` with the TransferIn it wrote in `to`'s ledger, by
+    // amount sent. Both legs carry the transfer's timestamp, so a TransferIn dated exactly with
+    // the TransferOut is preferred; failing that, any unpaired one dated after it. Unpaired legs
+    // were never credited.
+    fn transfer_legs(&self, from: &str, to: &str) -> Vec<(usize, Option<usize>)> {
+        let sender = match self.accounts.get(from) {
+            Some(sender) => sender,
+            None => return Vec::new(),
+        };
+        // A recipient that no longer exists received nothing.
+        let received: &[Transaction] = self.accounts.get(to).map_or(&[], |recipient| &recipient.transactions);
+        let outgoing: Vec<(usize, Money, SystemTime)> = sender
+            .transactions
+            .iter()
+            .enumerate()
+            .filter_map(|(i, t)| match &t.kind {
+                TransactionKind::TransferOut(amount, other) if other == to => Some((i, *amount, t.timestamp)),
+                _ => None,
+            })
+            .collect();
+        let mut unpaired: Vec<(usize, Money, SystemTime)> = received
+            .iter()
+            .enumerate()
+            .filter_map(|(i, t)| match &t.kind {
+                TransactionKind::TransferIn(credited, other) if other == from => {
+                    // A converted credit is followed by the amount that was sent.
+                    let sent = match received.get(i + 1).map(|next| &next.kind) {
+                        Some(TransactionKind::Conversion(amount, ..)) => *amount,
+                        _ => *credited,
+                    };
+                    Some((i, sent, t.timestamp))
+                }
+                _ => None,
+            })
+            .collect();
+        let mut legs: Vec<Option<usize>> = vec![None; outgoing.len()];
+        for same_time in [true, false] {
+            for (leg, (_, amount, sent_at)) in legs.iter_mut().zip(&outgoing) {
+                if leg.is_some() {
+                    continue;
+                }
+                let found = unpaired.iter().position(|(_, sent, at)| sent == amount && at >= sent_at && (!same_time || at == sent_at));
+                *leg = found.map(|position| unpaired.remove(position).0);
+            }
+        }
+        outgoing.iter().map(|(i, ..)| *i).zip(legs).collect()
+    }
+
+    // Nets the transfers between each pair of accounts so only the difference moves, as one
+    // transfer per pair. Transfer fees apply to the net movement, not to each gross transfer.
+    // The batch runs in a journal: if any movement fails, the whole batch is rolled back, leaving
+    // no events behind, and the error is returned.
+    pub fn net_settle(&mut self, transfers: &[(String, String, Money)]) -> Result<(), BankError> {
+        let mut net: BTreeMap<(String, String), Money> = BTreeMap::new();
+        for (from, to, amount) in transfers {
+            if from == to {
+                return Err(BankError::SameAccount);
+            }
+            if from < to {
+                *net.entry((from.clone(), to.clone())).or_default() += *amount;
+            } else {
+                *net.entry((to.clone(), from.clone())).or_default() -= *amount;
+            }
+        }
+
+        let mut journal = self.begin_journal();
+        for ((first, second), amount) in net {
+            let (from, to, amount) = if amount > Money::ZERO {
+                (first, second, amount)
+            } else if amount < Money::ZERO {
+                (second, first, -amount)
+            } else {
+                continue;
+            };
+            journal.transfer(from, to, amount)?;
+        }
+        journal.commit();
+        Ok(())
+    }
+
+    // Finds transfers debited from the sender that never reached the recipient and repairs each
+    // through repair_transfer. Returns a line per transfer repaired.
+    pub fn repair_incomplete_transfers(&mut self) -> Vec<String> {
+        let mut incomplete = Vec::new();
+        for (number, account) in &self.accounts {
+            let recipients: BTreeSet<&String> = account
+                .transactions
+                .iter()
+                .filter_map(|t| match &t.kind {
+                    TransactionKind::TransferOut(_, to) => Some(to),
+                    _ => None,
+                })
+                .collect();
+            for to in recipients {
+                for (out, _) in self.transfer_legs(number, to).into_iter().filter(|(_, leg)| leg.is_none()) {
+                    let transaction = &account.transactions[out];
+                    if transaction.reversed_by.is_none() {
+                        incomplete.push((number.clone(), transaction.id, transaction.kind.amount(), to.clone()));
+                    }
+                }
+            }
+        }
+
+        let mut report = Vec::new();
+        for (from, id, amount, to) in incomplete {
+            if self.repair_transfer(from.clone(), id).is_err() {
+                continue;
+            }
+            let rolled_back = self.transaction(from.clone(), id).is_ok_and(|t| t.reversed_by.is_some());
+            let action = if rolled_back { "Rolled back" } else { "Completed" };
+            report.push(format!("{} transfer of {} from {} to {}", action, amount, from, to));
+        }
+        report
+    }
+
+    // Repairs the transfer whose TransferOut is transaction `id` in the sender's ledger but that
+    // has no credit in the recipient's. It is completed if the recipient can still take it,
+    // converted and rounded as the transfer would have been and dated with the original. If not,
+    // the sender's operation is reversed, refunding the principal and its fee.
+    pub fn repair_transfer(&mut self, from_account: String, id: u64) -> Result<(), BankError> {
+        self.execute(EventKind::TransferRepaired(from_account, id))
+    }
+
+    fn apply_transfer_repair(&mut self, from_account: String, id: u64) -> Result<(), BankError> {
+        let from = self.accounts.get(&from_account).ok_or(BankError::AccountNotFound)?;
+        let index = from.transactions.iter().position(|t| t.id == id).ok_or(BankError::TransactionNotFound)?;
+        let transaction = &from.transactions[index];
+        let (amount, to_account) = match &transaction.kind {
+            TransactionKind::TransferOut(amount, to) => (*amount, to.clone()),
+            _ => return Err(BankError::TransactionNotFound),
+        };
+        if transaction.reversed_by.is_some() {
+            return Err(BankError::AlreadyReversed);
+        }
+        if self.transfer_legs(&from_account, &to_account).iter().any(|(out, leg)| *out == index && leg.is_some()) {
+            return Err(BankError::TransferComplete);
+        }
+        let sent_at = transaction.timestamp;
+        let from_currency = from.currency.clone();
+
+        let credit = self.accounts.get(&to_account).filter(|to| to.check_open().is_ok()).and_then(|to| {
+            let (converted, rate) = self.convert(amount, &from_currency, &to.currency)?;
+            let (credited, retained) = if from_currency == to.currency {
+                (amount, 0.0)
+            } else {
+                self.round_remainder(converted, Flow::Payout)
+            };
+            to.balance.checked_add(credited).ok().map(|_| (credited, rate, retained))
+        });
+        let (credited, rate, retained) = match credit {
+            Some(credit) => credit,
+            None => {
+                let range = from.operation_containing(index).ok_or(BankError::NotReversible)?;
+                return self.post_reversals(vec![(from_account, range)]);
+            }
+        };
+        let to = self.accounts.get_mut(&to_account).ok_or(BankError::AccountNotFound)?;
+        let to_currency = to.currency.clone();
+        to.receive_transfer(credited, from_account, sent_at)?;
+        if from_currency != to_currency {
+            to.record_at(TransactionKind::Conversion(amount, from_currency, rate), to_currency, None, sent_at);
+            self.retained_remainders += retained;
+        }
+        Ok(())
+    }
+
+    // Pulls the transfer's amount back from the recipient, who may be left overdrawn by it. Both
+    // legs are marked reversed, so a transfer is only charged back once and can't then be reversed.
+    pub fn chargeback_transfer(&mut self, transfer: TransferRef) -> Result<(), BankError> {
+        self.execute(EventKind::ChargedBack(transfer.account_number, transfer.index))
+    }
+
+    fn apply_chargeback(&mut self, account_number: String, index: usize) -> Result<(), BankError> {
+        let sender = self.accounts.get(&account_number).ok_or(BankError::AccountNotFound)?;
+        let amount = match sender.transactions.get(index).map(|t| &t.kind) {
+            Some(TransactionKind::TransferOut(amount, _)) => *amount,
+            _ => return Err(BankError::TransactionNotFound),
+        };
+        let (recipient_number, leg) = self.counterpart_leg(&account_number, index)?;
+        let recipient = &self.accounts[&recipient_number];
+        if sender.transactions[index].reversed_by.is_some() || recipient.transactions[leg].reversed_by.is_some() {
+            return Err(BankError::AlreadyReversed);
+        }
+        sender.check_open()?;
+        recipient.check_open()?;
+        let recipient_balance = recipient.balance.checked_sub(amount)?;
+        let sender_balance = sender.balance.checked_add(amount)?;
+
+        let recipient = self.accounts.get_mut(&recipient_number).ok_or(BankError::AccountNotFound)?;
+        recipient.transactions[leg].reversed_by = Some(recipient.next_transaction_id);
+        recipient.balance = recipient_balance;
+        recipient.record(TransactionKind::Chargeback(-amount, account_number.clone()));
+
+        let sender = self.accounts.get_mut(&account_number).ok_or(BankError::AccountNotFound)?;
+        sender.transactions[index].reversed_by = Some(sender.next_transaction_id);
+        sender.balance = sender_balance;
+        sender.record(TransactionKind::Chargeback(amount, recipient_number));
+        Ok(())
+    }
+
+    pub fn export_account(&self, account_number: String, path: &Path) -> io::Result<()> {
+        let account = self
+            .accounts
+            .get(&account_number)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "account not found"))?;
+        let document = json::object(vec![
+            ("account_number", json::string(&account_number)),
+            ("account", account.to_json()),
+        ]);
+        fs::write(path, document.to_string())
+    }
+
+    pub fn import_account(&mut self, path: &Path) -> Result<String, BankError> {
+        let contents = fs::read_to_string(path).map_err(|e| BankError::Io(e.to_string()))?;
+        let document = json::parse(&contents).map_err(BankError::InvalidData)?;
+        let account_number = document
+            .get("account_number")
+            .and_then(json::Value::as_str)
+            .ok_or_else(|| BankError::InvalidData("missing account_number".to_string()))?
+            .to_string();
+        let state = document.get("account").ok_or_else(|| BankError::InvalidData("missing account".to_string()))?;
+        self.execute(EventKind::AccountImported(account_number.clone(), state.to_string()))?;
+        Ok(account_number)
+    }
+
+    fn apply_account_import(&mut self, account_number: String, state: &str) -> Result<(), BankError> {
+        if self.accounts.contains_key(&account_number) {
+            return Err(BankError::AccountExists);
+        }
+        let account = json::parse(state).and_then(|value| Account::from_json(&value)).map_err(BankError::InvalidData)?;
+        self.accounts.insert(account_number, account);
+        Ok(())
+    }
+
+    pub fn save_to_file(&self, path: &Path) -> Result<(), BankError> {
+        let accounts = self.accounts.iter().map(|(number, account)| (number.clone(), account.to_json())).collect();
+        let recurring = self.recurring_payments.iter().map(|(id, payment)| payment.to_json(*id)).collect();
+        let mut rates: Vec<(&(Currency, Currency), &ExchangeRate)> = self.exchange_rates.iter().collect();
+        rates.sort_by(|a, b| a.0.cmp(b.0));
+        let rates = rates
+            .into_iter()
+            .map(|((base, quote), rate)| {
+                json::object(vec![
+                    ("base", json::string(base.code())),
+                    ("quote", json::string(quote.code())),
+                    ("bid", json::Value::Number(rate.bid)),
+                    ("ask", json::Value::Number(rate.ask)),
+                ])
+            })
+            .collect();
+        let customers = self.customers.iter().map(|(id, customer)| (id.clone(), customer.to_json())).collect();
+        let mandates = self.mandates.iter().map(|(id, mandate)| mandate.to_json(*id)).collect();
+        let audit_log = self.audit_log.iter().map(audit::AuditRecord::to_json).collect();
+        let scheduled_transfers = self.scheduled_transfers.iter().map(ScheduledTransfer::to_json).collect();
+        let scheduled_deposits = self.scheduled_deposits.iter().map(ScheduledDeposit::to_json).collect();
+        let review_queue = self.review_queue.values().map(ReviewItem::to_json).collect();
+        let document = json::object(vec![
+            ("settings", self.settings_to_json()),
+            ("accounts", json::Value::Object(accounts)),
+            ("customers", json::Value::Object(customers)),
+            ("recurring_payments", json::Value::Array(recurring)),
+            ("mandates", json::Value::Array(mandates)),
+            ("scheduled_transfers", json::Value::Array(scheduled_transfers)),
+            ("scheduled_deposits", json::Value::Array(scheduled_deposits)),
+            ("review_queue", json::Value::Array(review_queue)),
+            ("next_review_id", json::Value::Number(self.next_review_id as f64)),
+            ("retained_remainders", json::Value::Number(self.retained_remainders)),
+            ("exchange_rates", json::Value::Array(rates)),
+            ("audit_log", json::Value::Array(audit_log)),
+        ]);
+        fs::write(path, document.to_string()).map_err(|e| BankError::Io(e.to_string()))
+    }
+
+    pub fn load_from_file(path: &Path) -> Result<Bank, BankError> {
+        let contents = fs::read_to_string(path).map_err(|e| BankError::Io(e.to_string()))?;
+        let document = json::parse(&contents).map_err(BankError::InvalidData)?;
+        let accounts = match document.get("accounts") {
+            Some(json::Value::Object(accounts)) => accounts,
+            _ => return Err(BankError::InvalidData("missing accounts".to_string())),
+        };
+        let mut bank = Bank::new();
+        for (number, value) in accounts {
+            let account = Account::from_json(value).map_err(|e| BankError::InvalidData(format!("account {}: {}", number, e)))?;
+            bank.accounts.insert(number.clone(), account);
+        }
+        if let Some(json::Value::Object(customers)) = document.get("customers") {
+            for (id, value) in customers {
+                let customer = Customer::from_json(value).map_err(|e| BankError::InvalidData(format!("customer {}: {}", id, e)))?;
+                bank.customers.insert(id.clone(), customer);
+            }
+        }
+        let last_auth_id = bank.accounts.values().flat_map(|account| account.auth_holds.keys()).max().copied();
+        bank.next_auth_id = last_auth_id.map_or(1, |id| id + 1);
+        for value in document.get("recurring_payments").and_then(json::Value::as_array).into_iter().flatten() {
+            let (id, payment) = RecurringPayment::from_json(value).map_err(BankError::InvalidData)?;
+            bank.recurring_payments.insert(id, payment);
+        }
+        bank.next_recurring_id = bank.recurring_payments.keys().max().map_or(1, |id| id + 1);
+        for value in document.get("mandates").and_then(json::Value::as_array).into_iter().flatten() {
+            let (id, mandate) = Mandate::from_json(value).map_err(BankError::InvalidData)?;
+            bank.mandates.insert(id, mandate);
+        }
+        bank.next_mandate_id = bank.mandates.keys().max().map_or(1, |id| id + 1);
+        for value in document.get("exchange_rates").and_then(json::Value::as_array).into_iter().flatten() {
+            let code = |field: &str| value.get(field).and_then(json::Value::as_str).map(Currency::new);
+            let rate = |field: &str| value.get(field).and_then(json::Value::as_f64);
+            match (code("base"), code("quote"), rate("bid"), rate("ask")) {
+                (Some(base), Some(quote), Some(bid), Some(ask)) => bank.set_exchange_rate(base, quote, bid, ask),
+                _ => return Err(BankError::InvalidData("invalid exchange rate".to_string())),
+            }
+        }
+        for value in document.get("scheduled_transfers").and_then(json::Value::as_array).into_iter().flatten() {
+            bank.scheduled_transfers.push(ScheduledTransfer::from_json(value).map_err(BankError::InvalidData)?);
+        }
+        for value in document.get("scheduled_deposits").and_then(json::Value::as_array).into_iter().flatten() {
+            bank.scheduled_deposits.push(ScheduledDeposit::from_json(value).map_err(BankError::InvalidData)?);
+        }
+        for value in document.get("review_queue").and_then(json::Value::as_array).into_iter().flatten() {
+            let item = ReviewItem::from_json(value).map_err(BankError::InvalidData)?;
+            bank.review_queue.insert(item.id, item);
+        }
+        // Cleared items leave gaps, so the next id is saved rather than worked out from the queue.
+        let next_review_id = document.get("next_review_id").and_then(json::Value::as_f64).map(|id| id as u64);
+        bank.next_review_id = next_review_id.unwrap_or_else(|| bank.review_queue.keys().max().map_or(1, |id| id + 1));
+        bank.retained_remainders = document.get("retained_remainders").and_then(json::Value::as_f64).unwrap_or(0.0);
+        for value in document.get("audit_log").and_then(json::Value::as_array).into_iter().flatten() {
+            bank.audit_log.push(audit::AuditRecord::from_json(value).map_err(BankError::InvalidData)?);
+        }
+        if let Some(settings) = document.get("settings") {
+            bank.load_settings(settings).map_err(BankError::InvalidData)?;
+        }
+        Ok(bank)
+    }
+
+    // The bank's own configuration: its name, rates, limits and policies. Files from before
+    // settings were saved load with the defaults.
+    fn settings_to_json(&self) -> json::Value {
+        let by_type = |entries: Vec<(&AccountType, json::Value)>| {
+            let mut entries: Vec<(String, json::Value)> =
+                entries.into_iter().map(|(account_type, value)| (account_type.name().to_string(), value)).collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            json::Value::Object(entries)
+        };
+        let interest_rates = by_type(self.interest_rates.iter().map(|(t, rate)| (t, json::Value::Number(*rate))).collect());
+        let opening_deposits = by_type(self.minimum_opening_deposits.iter().map(|(t, amount)| (t, amount.to_json())).collect());
+        let velocity_limits = by_type(self.velocity_limits.iter().map(|(t, limits)| (t, limits.to_json())).collect());
+        let mut transaction_limits: Vec<(String, json::Value)> =
+            self.transaction_limits.iter().map(|(currency, limits)| (currency.code().to_string(), limits.to_json())).collect();
+        transaction_limits.sort_by(|a, b| a.0.cmp(&b.0));
+        json::object(vec![
+            ("name", json::string(&self.name)),
+            ("remainder_policy", json::string(self.remainder_policy.name())),
+            ("withdrawal_rounding", json::string(self.withdrawal_rounding.name())),
+            ("new_account_hold_ms", json::Value::Number(self.new_account_hold.as_millis() as f64)),
+            ("overdraft_fee", self.overdraft_fee.to_json()),
+            ("overdraft_grace_ms", json::Value::Number(self.overdraft_grace.as_millis() as f64)),
+            ("minimum_opening_deposits", opening_deposits),
+            ("interest_rates", interest_rates),
+            ("transaction_limits", json::Value::Object(transaction_limits)),
+            ("velocity_limits", velocity_limits),
+            ("max_pin_attempts", json::Value::Number(self.max_pin_attempts as f64)),
+            ("review_rules", self.review_rules.to_json()),
+        ])
+    }
+
+    fn load_settings(&mut self, settings: &json::Value) -> Result<(), String> {
+        let text = |field: &str| settings.get(field).and_then(json::Value::as_str);
+        let millis = |field: &str| settings.get(field).and_then(json::Value::as_f64).map(|ms| Duration::from_millis(ms as u64));
+        let by_type = |field: &str| -> Result<Vec<(AccountType, &json::Value)>, String> {
+            match settings.get(field) {
+                Some(json::Value::Object(entries)) => entries
+                    .iter()
+                    .map(|(name, value)| AccountType::from_name(name).map(|t| (t, value)).ok_or(format!("unknown account type {}", name)))
+                    .collect(),
+                _ => Ok(Vec::new()),
+            }
+        };
+        if let Some(name) = text("name") {
+            self.name = name.to_string();
+        }
+        if let Some(name) = text("remainder_policy") {
+            self.remainder_policy = RemainderPolicy::from_name(name).ok_or(format!("unknown remainder policy {}", name))?;
+        }
+        if let Some(name) = text("withdrawal_rounding") {
+            self.withdrawal_rounding = WithdrawalRounding::from_name(name).ok_or(format!("unknown withdrawal rounding {}", name))?;
+        }
+        self.new_account_hold = millis("new_account_hold_ms").unwrap_or_default();
+        self.overdraft_fee = settings.get("overdraft_fee").and_then(Money::from_json).unwrap_or_default();
+        self.overdraft_grace = millis("overdraft_grace_ms").unwrap_or_default();
+        for (account_type, value) in by_type("minimum_opening_deposits")? {
+            let amount = Money::from_json(value).ok_or("invalid minimum opening deposit")?;
+            self.minimum_opening_deposits.insert(account_type, amount);
+        }
+        for (account_type, value) in by_type("interest_rates")? {
+            self.interest_rates.insert(account_type, value.as_f64().ok_or("invalid interest rate")?);
+        }
+        if let Some(json::Value::Object(entries)) = settings.get("transaction_limits") {
+            for (code, value) in entries {
+                self.transaction_limits.insert(Currency::new(code), TransactionLimits::from_json(value));
+            }
+        }
+        // Through the setter, which also puts the limits back on the loaded accounts.
+        for (account_type, value) in by_type("velocity_limits")? {
+            self.set_velocity_limits(account_type, VelocityLimits::from_json(value));
+        }
+        if let Some(attempts) = settings.get("max_pin_attempts").and_then(json::Value::as_f64) {
+            self.set_max_pin_attempts(attempts as u32);
+        }
+        if let Some(rules) = settings.get("review_rules") {
+            self.review_rules = ReviewRules::from_json(rules);
+        }
+        Ok(())
+    }
+
+    // Dry run of loading an older file: lists each account field the current format writes
+    // that the file lacks, with how many accounts would get the default for it. Nothing is
+    // written, and the file must still parse.
+    pub fn migration_report(path: &Path) -> Result<MigrationReport, BankError> {
+        let contents = fs::read_to_string(path).map_err(|e| BankError::Io(e.to_string()))?;
+        let document = json::parse(&contents).map_err(BankError::InvalidData)?;
+        let accounts = match document.get("accounts") {
+            Some(json::Value::Object(accounts)) => accounts,
+            _ => return Err(BankError::InvalidData("missing accounts".to_string())),
+        };
+        let mut report = MigrationReport { total_accounts: accounts.len(), ..MigrationReport::default() };
+        for (number, value) in accounts {
+            let account = Account::from_json(value).map_err(|e| BankError::InvalidData(format!("account {}: {}", number, e)))?;
+            let current = match account.to_json() {
+                json::Value::Object(fields) => fields,
+                _ => Vec::new(),
+            };
+            let missing: Vec<String> = current.into_iter().map(|(field, _)| field).filter(|field| value.get(field).is_none()).collect();
+            if !missing.is_empty() {
+                report.accounts_affected += 1;
+            }
+            for field in missing {
+                *report.defaulted_fields.entry(field).or_insert(0) += 1;
+            }
+        }
+        Ok(report)
+    }
+
+    pub fn auto_sweep(&mut self, checking: String, savings: String, target: Money) -> Result<(), BankError> {
+        let checking_balance = self.balance(checking.clone()).ok_or(BankError::AccountNotFound)?;
+        let savings_balance = self.balance(savings.clone()).ok_or(BankError::AccountNotFound)?;
+
+        if checking_balance > target {
+            self.transfer(checking, savings, checking_balance - target)
+        } else if checking_balance < target && savings_balance > Money::ZERO {
+            self.transfer(savings, checking, (target - checking_balance).min(savings_balance))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn schedule_transfer(&mut self, from_account: String, to_account: String, amount: Money, due: SystemTime) -> Result<(), BankError> {
+        if !self.accounts.contains_key(&from_account) || !self.accounts.contains_key(&to_account) {
+            return Err(BankError::AccountNotFound);
+        }
+        if amount <= Money::ZERO {
+            return Err(BankError::InvalidAmount);
+        }
+        self.scheduled_transfers.push(ScheduledTransfer {
+            from_account,
+            to_account,
+            amount,
+            due,
+        });
+        Ok(())
+    }
+
+    pub fn scheduled_transfers(&self) -> &Vec<ScheduledTransfer> {
+        &self.scheduled_transfers
+    }
+
+    pub fn schedule_deposit(&mut self, account_number: String, amount: Money, available_at: SystemTime) -> Result<(), BankError> {
+        if !self.accounts.contains_key(&account_number) {
+            return Err(BankError::AccountNotFound);
+        }
+        if amount <= Money::ZERO {
+            return Err(BankError::InvalidAmount);
+        }
+        self.scheduled_deposits.push(ScheduledDeposit {
+            account_number,
+            amount,
+            available_at,
+        });
+        Ok(())
+    }
+
+    pub fn scheduled_deposits(&self) -> &Vec<ScheduledDeposit> {
+        &self.scheduled_deposits
+    }
+
+    // Runs the transfers and deposits that have come due. Returns how many succeeded; failed
+    // ones are dropped rather than retried.
+    pub fn process_due(&mut self, now: SystemTime) -> usize {
+        let (due, pending): (Vec<ScheduledTransfer>, Vec<ScheduledTransfer>) =
+            self.scheduled_transfers.drain(..).partition(|scheduled| scheduled.due <= now);
+        self.scheduled_transfers = pending;
+        let transferred = due
+            .into_iter()
+            .filter(|scheduled| {
+                self.transfer(scheduled.from_account.clone(), scheduled.to_account.clone(), scheduled.amount)
+                    .is_ok()
+            })
+            .count();
+
+        let (due, pending): (Vec<ScheduledDeposit>, Vec<ScheduledDeposit>) =
+            self.scheduled_deposits.drain(..).partition(|scheduled| scheduled.available_at <= now);
+        self.scheduled_deposits = pending;
+        let deposited = due
+            .into_iter()
+            .filter(|scheduled| self.deposit(scheduled.account_number.clone(), scheduled.amount).is_ok())
+            .count();
+        transferred + deposited
+    }
+
+    // First paid on `day_of_month` on or after `start`. Without `from_account` the payment is a
+    // deposit into `to_account`.
+    pub fn schedule_recurring(
+        &mut self,
+        from_account: Option<String>,
+        to_account: String,
+        amount: Money,
+        day_of_month: u32,
+        start: SystemTime,
+    ) -> Result<u64, BankError> {
+        if from_account.iter().chain([&to_account]).any(|number| !self.accounts.contains_key(number)) {
+            return Err(BankError::AccountNotFound);
+        }
+        if from_account.as_ref() == Some(&to_account) {
+            return Err(BankError::SameAccount);
+        }
+        if amount <= Money::ZERO {
+            return Err(BankError::InvalidAmount);
+        }
+        if !(1..=31).contains(&day_of_month) {
+            return Err(BankError::InvalidSchedule);
+        }
+        let id = self.next_recurring_id;
+        self.next_recurring_id += 1;
+        self.recurring_payments.insert(
+            id,
+            RecurringPayment {
+                from_account,
+                to_account,
+                amount,
+                day_of_month,
+                next_due: calendar::monthly_on_or_after(start, day_of_month),
+            },
+        );
+        Ok(id)
+    }
+
+    pub fn recurring_payments(&self) -> &BTreeMap<u64, RecurringPayment> {
+        &self.recurring_payments
+    }
+
+    pub fn cancel_recurring(&mut self, id: u64) -> Result<(), BankError> {
+        self.recurring_payments.remove(&id).map(|_| ()).ok_or(BankError::ScheduleNotFound)
+    }
+
+    // Makes every recurring payment that has come due, once for each month missed since the
+    // last run. A payment moves on to its next date whether or not it went through.
+    pub fn process_due_payments(&mut self, now: SystemTime) -> Vec<(u64, Result<(), BankError>)> {
+        let mut outcomes = Vec::new();
+        let ids: Vec<u64> = self.recurring_payments.keys().copied().collect();
+        for id in ids {
+            while let Some(payment) = self.recurring_payments.get(&id).filter(|payment| payment.next_due <= now).cloned() {
+                let result = match payment.from_account {
+                    Some(from_account) => self.transfer(from_account, payment.to_account, payment.amount),
+                    None => self.deposit(payment.to_account, payment.amount),
+                };
+                outcomes.push((id, result));
+                if let Some(scheduled) = self.recurring_payments.get_mut(&id) {
+                    let day_after = payment.next_due + Duration::from_secs(86_400);
+                    scheduled.next_due = calendar::monthly_on_or_after(day_after, payment.day_of_month);
+                }
+            }
+        }
+        outcomes
+    }
+
+    pub fn projected_balance(&self, account_number: String, as_of: SystemTime) -> Option<Money> {
+        let account = self.accounts.get(&account_number)?;
+        let mut projected = account.balance;
+        for scheduled in self.scheduled_transfers.iter().filter(|scheduled| scheduled.due <= as_of) {
+            if scheduled.from_account == account_number {
+                projected -= scheduled.amount;
+            }
+            if scheduled.to_account == account_number {
+                let sender_currency = self.accounts.get(&scheduled.from_account).map(|from| &from.currency);
+                projected += sender_currency
+                    .and_then(|currency| self.convert(scheduled.amount, currency, &account.currency))
+                    .map(|(converted, _)| Money::from_f64(converted))
+                    .unwrap_or_default();
+            }
+        }
+        for scheduled in self.scheduled_deposits.iter().filter(|scheduled| scheduled.available_at <= as_of) {
+            if scheduled.account_number == account_number {
+                projected += scheduled.amount;
+            }
+        }
+        Some(projected)
+    }
+
+    pub fn authorize_mandate(&mut self, debtor: String, creditor: String, max_per_charge: Money, total_cap: Money) -> Result<u64, BankError> {
+        if !self.accounts.contains_key(&debtor) || !self.accounts.contains_key(&creditor) {
+            return Err(BankError::AccountNotFound);
+        }
+        let id = self.next_mandate_id;
+        self.next_mandate_id += 1;
+        self.mandates.insert(
+            id,
+            Mandate {
+                debtor,
+                creditor,
+                max_per_charge,
+                total_cap,
+                charged: Money::ZERO,
+            },
+        );
+        Ok(id)
+    }
+
+    pub fn revoke_mandate(&mut self, mandate_id: u64) -> Result<(), BankError> {
+        self.mandates.remove(&mandate_id).map(|_| ()).ok_or(BankError::MandateNotFound)
+    }
+
+    pub fn mandate(&self, mandate_id: u64) -> Option<&Mandate> {
+        self.mandates.get(&mandate_id)
+    }
+
+    pub fn charge_mandate(&mut self, mandate_id: u64, amount: Money) -> Result<(), BankError> {
+        let mandate = self.mandates.get(&mandate_id).ok_or(BankError::MandateNotFound)?;
+        if amount <= Money::ZERO {
+            return Err(BankError::InvalidAmount);
+        }
+        let charged = mandate.charged.checked_add(amount)?;
+        if amount > mandate.max_per_charge || charged > mandate.total_cap {
+            return Err(BankError::MandateLimitExceeded);
+        }
+        let (debtor, creditor) = (mandate.debtor.clone(), mandate.creditor.clone());
+        self.transfer(debtor, creditor, amount)?;
+        if let Some(mandate) = self.mandates.get_mut(&mandate_id) {
+            mandate.charged = charged;
+        }
+        Ok(())
+    }
+
+    // Holds `amount` against the available balance until `expires` without moving any money.
+    pub fn authorize(&mut self, account_number: String, amount: Money, expires: SystemTime) -> Result<u64, BankError> {
+        let id = self.next_auth_id;
+        self.execute(EventKind::Authorized(account_number, id, amount, expires))?;
+        Ok(id)
+    }
+
+    // Replayed holds keep their original ids, so the next id is kept past the highest seen.
+    fn apply_authorization(&mut self, account_number: String, id: u64, amount: Money, expires: SystemTime) -> Result<(), BankError> {
+        let account = self.accounts.get_mut(&account_number).ok_or(BankError::AccountNotFound)?;
+        account.check_debit(amount, SystemTime::now())?;
+        account.auth_holds.insert(id, AuthHold { amount, expires });
+        account.record(TransactionKind::Authorization(amount));
+        self.next_auth_id = self.next_auth_id.max(id + 1);
+        Ok(())
+    }
+
+    fn holder_of_authorization(&self, auth_id: u64) -> Result<String, BankError> {
+        self.accounts
+            .iter()
+            .find(|(_, account)| account.auth_holds.contains_key(&auth_id))
+            .map(|(number, _)| number.clone())
+            .ok_or(BankError::TransactionNotFound)
+    }
+
+    // Releases the hold and withdraws the authorized amount.
+    pub fn settle_authorization(&mut self, auth_id: u64) -> Result<(), BankError> {
+        let account_number = self.holder_of_authorization(auth_id)?;
+        self.execute(EventKind::AuthorizationSettled(account_number, auth_id))
+    }
+
+    fn apply_settlement(&mut self, account_number: String, auth_id: u64) -> Result<(), BankError> {
+        let account = self.accounts.get_mut(&account_number).ok_or(BankError::AccountNotFound)?;
+        let hold = account.auth_holds.remove(&auth_id).ok_or(BankError::TransactionNotFound)?;
+        if let Err(error) = account.withdraw(hold.amount) {
+            account.auth_holds.insert(auth_id, hold);
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    // A pending transaction, e.g. a card authorization: reserves `amount` from the available
+    // balance without touching the ledger balance until it is captured or released.
+    pub fn place_hold(&mut self, account_number: String, amount: Money) -> Result<u64, BankError> {
+        if amount <= Money::ZERO {
+            return Err(BankError::InvalidAmount);
+        }
+        self.authorize(account_number, amount, SystemTime::now() + HOLD_DURATION)
+    }
+
+    pub fn capture_hold(&mut self, hold_id: u64) -> Result<(), BankError> {
+        self.settle_authorization(hold_id)
+    }
+
+    pub fn release_hold(&mut self, hold_id: u64) -> Result<(), BankError> {
+        let account_number = self.holder_of_authorization(hold_id)?;
+        self.execute(EventKind::AuthorizationReleased(account_number, hold_id))
+    }
+
+    fn apply_release(&mut self, account_number: String, hold_id: u64) -> Result<(), BankError> {
+        let account = self.accounts.get_mut(&account_number).ok_or(BankError::AccountNotFound)?;
+        let hold = account.auth_holds.remove(&hold_id).ok_or(BankError::TransactionNotFound)?;
+        account.record(TransactionKind::AuthReversed(hold.amount));
+        Ok(())
+    }
+
+    // Releases every authorization that expired unsettled, recording an AuthReversed entry for
+    // each so an automatic reversal can be told apart from a settlement. Returns their ids.
+    pub fn expire_authorizations(&mut self, now: SystemTime) -> Vec<u64> {
+        let expired: Vec<(String, u64)> = self
+            .accounts
+            .iter()
+            .flat_map(|(number, account)| {
+                account.auth_holds.iter().filter(|(_, hold)| hold.expires <= now).map(move |(id, _)| (number.clone(), *id))
+            })
+            .collect();
+        let mut reversed = Vec::new();
+        for (number, id) in expired {
+            if self.execute(EventKind::AuthorizationReleased(number, id)).is_ok() {
+                reversed.push(id);
+            }
+        }
+        reversed.sort_unstable();
+        reversed
+    }
+
+    // Nothing is written if any value is wider than its field.
+    pub fn export_statement_fixed_width(&self, account_number: String, path: &Path, spec: &FixedWidthSpec) -> io::Result<()> {
+        let account = self
+            .accounts
+            .get(&account_number)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "account not found"))?;
+        let mut output = String::new();
+        for (i, transaction) in account.transactions.iter().enumerate() {
+            output.push_str(&spec.format(i + 1, transaction)?);
+            output.push('\n');
+        }
+        fs::write(path, output)
+    }
+
+    // Amounts use the same two-decimal format as displayed balances, and timestamps are UTC.
+    pub fn export_csv(&self, account_number: String, path: &Path) -> Result<(), BankError> {
+        let account = self.accounts.get(&account_number).ok_or(BankError::AccountNotFound)?;
+        let mut csv = String::from("index,type,amount,counterparty,timestamp\n");
+        for (i, transaction) in account.transactions.iter().enumerate() {
+            csv.push_str(&format!(
+                "{},{},{},{},{}\n",
+                i + 1,
+                transaction.kind.name(),
+                transaction.kind.amount(),
+                csv_field(transaction.kind.counterparty().unwrap_or("")),
+                calendar::format_datetime(transaction.timestamp),
+            ));
+        }
+        fs::write(path, csv).map_err(|e| BankError::Io(e.to_string()))
+    }
+
+    // The account's entries within `period` for bookkeeping, each with the balance it left in
+    // its currency. In CSV the opening and closing balances are the first and last rows.
+    pub fn export_statement(
+        &self,
+        account_number: String,
+        period: &Period,
+        format: StatementFormat,
+        writer: &mut dyn io::Write,
+    ) -> Result<(), BankError> {
+        let account = self.accounts.get(&account_number).ok_or(BankError::AccountNotFound)?;
+        let statement = account.statement(&account_number, period);
+        let currency = account.currency.code();
+        let output = match format {
+            StatementFormat::Csv => {
+                let mut csv = String::from("id,date,type,amount,currency,counterparty,memo,balance\n");
+                csv.push_str(&format!(
+                    ",{},Opening balance,,{},,,{}\n",
+                    calendar::format_datetime(period.from),
+                    currency,
+                    statement.opening_balance
+                ));
+                for transaction in &statement.transactions {
+                    csv.push_str(&format!(
+                        "{},{},{},{},{},{},{},{}\n",
+                        transaction.id,
+                        calendar::format_datetime(transaction.timestamp),
+                        transaction.kind.name(),
+                        transaction.kind.amount(),
+                        transaction.currency.code(),
+                        csv_field(transaction.kind.counterparty().unwrap_or("")),
+                        csv_field(transaction.memo.as_deref().unwrap_or("")),
+                        transaction.balance_after,
+                    ));
+                }
+                csv.push_str(&format!(
+                    ",{},Closing balance,,{},,,{}\n",
+                    calendar::format_datetime(period.to),
+                    currency,
+                    statement.closing_balance
+                ));
+                csv
+            }
+            StatementFormat::Json => json::object(vec![
+                ("account_number", json::string(&account_number)),
+                ("currency", json::string(currency)),
+                ("from", json::Value::Number(calendar::to_millis(period.from) as f64)),
+                ("to", json::Value::Number(calendar::to_millis(period.to) as f64)),
+                ("opening_balance", statement.opening_balance.to_json()),
+                ("closing_balance", statement.closing_balance.to_json()),
+                ("transactions", json::Value::Array(statement.transactions.iter().map(Transaction::to_json).collect())),
+            ])
+            .to_string(),
+        };
+        writer.write_all(output.as_bytes()).map_err(|e| BankError::Io(e.to_string()))
+    }
+
+    // Writes a SQL script, not a database file: the schema for an `accounts` and a
+    // `transactions` table, one INSERT per account and per ledger entry, all in one
+    // transaction. Feed it to SQLite to build the database, e.g. `sqlite3 bank.db < bank.sql`.
+    // Running it again replaces both tables. Writing the database file itself would need an
+    // SQLite library such as rusqlite, which this crate doesn't depend on.
+    pub fn export_sql_script(&self, path: &Path) -> Result<(), BankError> {
+        fn quote(text: &str) -> String {
+            format!("'{}'", text.replace('\'', "''"))
+        }
+
+        let mut sql = String::from(
+            "PRAGMA foreign_keys = ON;\n\
+             BEGIN TRANSACTION;\n\
+             DROP TABLE IF EXISTS transactions;\n\
+             DROP TABLE IF EXISTS accounts;\n\
+             CREATE TABLE accounts (\n    \
+                 account_number TEXT PRIMARY KEY,\n    \
+                 account_type TEXT NOT NULL,\n    \
+                 balance REAL NOT NULL,\n    \
+                 currency TEXT NOT NULL,\n    \
+                 status TEXT NOT NULL,\n    \
+                 created_at INTEGER NOT NULL\n\
+             );\n\
+             CREATE TABLE transactions (\n    \
+                 id INTEGER PRIMARY KEY,\n    \
+                 account_number TEXT NOT NULL REFERENCES accounts(account_number),\n    \
+                 seq INTEGER NOT NULL,\n    \
+                 type TEXT NOT NULL,\n    \
+                 amount REAL NOT NULL,\n    \
+                 currency TEXT NOT NULL,\n    \
+                 counterparty TEXT,\n    \
+                 timestamp INTEGER NOT NULL\n\
+             );\n",
+        );
+        for (number, account) in &self.accounts {
+            sql.push_str(&format!(
+                "INSERT INTO accounts VALUES ({}, {}, {}, {}, {}, {});\n",
+                quote(number),
+                quote(account.account_type.name()),
+                account.balance,
+                quote(account.currency.code()),
+                quote(account.status.name()),
+                calendar::to_millis(account.created_at),
+            ));
+        }
+        for (number, account) in &self.accounts {
+            for (seq, transaction) in account.transactions.iter().enumerate() {
+                sql.push_str(&format!(
+                    "INSERT INTO transactions (account_number, seq, type, amount, currency, counterparty, timestamp) \
+                     VALUES ({}, {}, {}, {}, {}, {}, {});\n",
+                    quote(number),
+                    seq + 1,
+                    quote(transaction.kind.name()),
+                    transaction.kind.amount(),
+                    quote(transaction.currency.code()),
+                    transaction.kind.counterparty().map(quote).unwrap_or_else(|| "NULL".to_string()),
+                    calendar::to_millis(transaction.timestamp),
+                ));
+            }
+        }
+        sql.push_str("COMMIT;\n");
+        fs::write(path, sql).map_err(|e| BankError::Io(e.to_string()))
+    }
+
+    pub fn get_account_type(&self, account_number: String) -> Option<AccountType> {
+        self.accounts.get(&account_number).map(|a| a.account_type.clone())
+    }
+
+    pub fn account(&self, account_number: &str) -> Option<&Account> {
+        self.accounts.get(account_number)
+    }
+
+    // By account number.
+    pub fn accounts(&self) -> impl Iterator<Item = (&String, &Account)> {
+        self.accounts.iter()
+    }
+
+    pub fn get_transactions(&self, account_number: String) -> Option<&Vec<Transaction>> {
+        if let Some(account) = self.accounts.get(&account_number) {
+            Some(account.transactions())
+        } else {
+            None
+        }
+    }
+
+    pub fn transaction(&self, account_number: String, id: u64) -> Result<&Transaction, BankError> {
+        let account = self.accounts.get(&account_number).ok_or(BankError::AccountNotFound)?;
+        account.transaction(id).ok_or(BankError::TransactionNotFound)
+    }
+
+    pub fn ledger(&self, account_number: String, period: Option<&Period>, kind: Option<&str>) -> Result<Vec<&Transaction>, BankError> {
+        let account = self.accounts.get(&account_number).ok_or(BankError::AccountNotFound)?;
+        Ok(account.ledger(period, kind))
+    }
+
+    // Transactions recorded between `from` and `to` inclusive, oldest first.
+    pub fn statement(&self, account_number: String, from: SystemTime, to: SystemTime) -> Option<Vec<&Transaction>> {
+        let period = Period { from, to };
+        let account = self.accounts.get(&account_number)?;
+        let mut transactions: Vec<&Transaction> = account.transactions.iter().filter(|t| period.contains(t.timestamp)).collect();
+        transactions.sort_by_key(|t| t.timestamp);
+        Some(transactions)
+    }
+
+    pub fn account_status(&self, account_number: &str) -> Result<AccountStatus, BankError> {
+        self.accounts.get(account_number).map(Account::status).ok_or(BankError::AccountNotFound)
+    }
+
+    // A frozen account keeps its money but refuses deposits, withdrawals and transfers until
+    // it is unfrozen.
+    pub fn freeze_account(&mut self, account_number: String) -> Result<(), BankError> {
+        self.execute(EventKind::Frozen(account_number))
+    }
+
+    pub fn unfreeze_account(&mut self, account_number: String) -> Result<(), BankError> {
+        self.execute(EventKind::Unfrozen(account_number))
+    }
+
+    // The names these had before accounts could be closed for good.
+    pub fn activate_account(&mut self, account_number: String) -> Result<(), BankError> {
+        self.unfreeze_account(account_number)
+    }
+
+    pub fn deactivate_account(&mut self, account_number: String) -> Result<(), BankError> {
+        self.freeze_account(account_number)
+    }
+
+    // A closed account keeps its history but refuses everything from then on. A Credit account
+    // in debt has a negative balance, which blocks closure just like funds do.
+    pub fn close_account(&mut self, account_number: &str) -> Result<(), BankError> {
+        self.execute(EventKind::Closed(account_number.to_string(), None))
+    }
+
+    // Transfers whatever the account holds to `sweep_to` and closes it, or does neither.
+    pub fn close_account_sweeping(&mut self, account_number: &str, sweep_to: String) -> Result<(), BankError> {
+        self.execute(EventKind::Closed(account_number.to_string(), Some(sweep_to)))
+    }
+
+    fn apply_close(&mut self, account_number: &str, sweep_to: Option<String>) -> Result<(), BankError> {
+        let account = self.accounts.get(account_number).ok_or(BankError::AccountNotFound)?;
+        if account.status == AccountStatus::Closed {
+            return Err(BankError::AccountClosed);
+        }
+        let balance = account.balance;
+        let Some(sweep_to) = sweep_to.filter(|_| balance > Money::ZERO) else {
+            return self.accounts.get_mut(account_number).ok_or(BankError::AccountNotFound)?.close();
+        };
+
+        // A withdrawal fee on the sweep leaves the account overdrawn and unable to close. Only
+        // the two accounts and the bank's remainder and review bookkeeping change, so only they
+        // are put back.
+        let before = (account.clone(), self.accounts.get(&sweep_to).cloned(), self.retained_remainders, self.next_review_id);
+        self.apply_transfer(account_number.to_string(), sweep_to.clone(), balance)?;
+        let closed = self.accounts.get_mut(account_number).ok_or(BankError::AccountNotFound)?.close();
+        if closed.is_err() {
+            let (account, recipient, retained_remainders, next_review_id) = before;
+            self.accounts.insert(account_number.to_string(), account);
+            if let Some(recipient) = recipient {
+                self.accounts.insert(sweep_to, recipient);
+            }
+            self.retained_remainders = retained_remainders;
+            self.review_queue.retain(|id, _| *id < next_review_id);
+            self.next_review_id = next_review_id;
+        }
+        closed
+    }
+
+    pub fn set_fee_schedule(&mut self, account_number: String, fee_schedule: Option<FeeSchedule>) -> Result<(), BankError> {
+        let account = self.accounts.get_mut(&account_number).ok_or(BankError::AccountNotFound)?;
+        account.fee_schedule = fee_schedule;
+        Ok(())
+    }
+
+    pub fn set_credit_limit(&mut self, account_number: String, limit: Money) -> Result<(), BankError> {
+        let account = self.accounts.get_mut(&account_number).ok_or(BankError::AccountNotFound)?;
+        if !matches!(account.account_type, AccountType::Credit) {
+            return Err(BankError::UnsupportedAccountType);
+        }
+        account.credit_limit = limit;
+        Ok(())
+    }
+
+    // Lets a Checking account overdraw by up to `limit`. Overdrafts are charged the bank's
+    // overdraft fee by process_overdraft_fees once they outlast the grace period.
+    pub fn set_overdraft_limit(&mut self, account_number: String, limit: Money) -> Result<(), BankError> {
+        let account = self.accounts.get_mut(&account_number).ok_or(BankError::AccountNotFound)?;
+        if !matches!(account.account_type, AccountType::Checking) {
+            return Err(BankError::UnsupportedAccountType);
+        }
+        account.overdraft_limit = limit;
+        Ok(())
+    }
+
+    pub fn set_minimum_balance(&mut self, account_number: String, floor: Money) -> Result<(), BankError> {
+        let account = self.accounts.get_mut(&account_number).ok_or(BankError::AccountNotFound)?;
+        if !matches!(account.account_type, AccountType::Savings) {
+            return Err(BankError::UnsupportedAccountType);
+        }
+        account.minimum_balance = floor;
+        Ok(())
+    }
+
+    pub fn set_maturity(&mut self, account_number: String, maturity: SystemTime) -> Result<(), BankError> {
+        let account = self.accounts.get_mut(&account_number).ok_or(BankError::AccountNotFound)?;
+        if !matches!(account.account_type, AccountType::Savings) {
+            return Err(BankError::UnsupportedAccountType);
+        }
+        account.maturity = Some(maturity);
+        Ok(())
+    }
+
+    pub fn grant_temp_credit_limit(&mut self, account_number: String, extra: Money, until: SystemTime) -> Result<(), BankError> {
+        let account = self.accounts.get_mut(&account_number).ok_or(BankError::AccountNotFound)?;
+        if !matches!(account.account_type, AccountType::Credit) {
+            return Err(BankError::UnsupportedAccountType);
+        }
+        account.temporary_credit = Some(TemporaryCredit { extra, until });
+        Ok(())
+    }
+
+    pub fn set_interest_rate(&mut self, account_number: String, rate: f64) -> Result<(), BankError> {
+        let account = self.accounts.get_mut(&account_number).ok_or(BankError::AccountNotFound)?;
+        account.interest_rate = rate;
+        Ok(())
+    }
+
+    pub fn set_bonus_rate(&mut self, account_number: String, rate: f64, cap: Option<Money>) -> Result<(), BankError> {
+        let account = self.accounts.get_mut(&account_number).ok_or(BankError::AccountNotFound)?;
+        account.bonus_rate = rate;
+        account.bonus_rate_cap = cap;
+        Ok(())
+    }
+
+    pub fn set_interest_basis(&mut self, account_number: String, basis: InterestBasis) -> Result<(), BankError> {
+        let account = self.accounts.get_mut(&account_number).ok_or(BankError::AccountNotFound)?;
+        account.interest_basis = basis;
+        Ok(())
+    }
+
+    pub fn set_compound_frequency(&mut self, account_number: String, frequency: CompoundFrequency) -> Result<(), BankError> {
+        let account = self.accounts.get_mut(&account_number).ok_or(BankError::AccountNotFound)?;
+        account.compound_frequency = frequency;
+        Ok(())
+    }
+
+    pub fn forecast_interest(&self, months: u32) -> Money {
+        self.accounts.values().map(|account| account.projected_interest_at(months, self.effective_rate(account))).sum()
+    }
+
+    // Interest paid over `months` at current rates and with each account type's rates moved by
+    // its delta; types without a delta keep their rates. Nothing is changed.
+    pub fn simulate_rate_scenario(&self, rate_deltas: &HashMap<AccountType, f64>, months: u32) -> ScenarioResult {
+        ScenarioResult {
+            current_interest: self.forecast_interest(months),
+            projected_interest: self
+                .accounts
+                .values()
+                .map(|account| {
+                    let delta = rate_deltas.get(&account.account_type).copied().unwrap_or(0.0);
+                    account.projected_interest_shifted_at(months, self.effective_rate(account), delta)
+                })
+                .sum(),
+        }
+    }
+
+    // Extra interest paid over `months` if every deposit account of the type earned
+    // `promo_rate` instead of its current rate. Credit accounts never earn interest.
+    pub fn promo_cost(&self, account_type: AccountType, promo_rate: f64, months: u32) -> Money {
+        if matches!(account_type, AccountType::Credit) {
+            return Money::ZERO;
+        }
+        let years = months as f64 / 12.0;
+        self.accounts
+            .values()
+            .filter(|account| account.account_type == account_type && account.balance > Money::ZERO)
+            .map(|account| {
+                let current = account.projected_interest_at(months, self.effective_rate(account));
+                account.balance.scale(account.compound_frequency.growth(promo_rate, years)) - current
+            })
+            .sum()
+    }
+
+    // Every balance held in `currency`, with what is owed on credit accounts netted off.
+    pub fn total_holdings(&self, currency: &Currency) -> Money {
+        self.accounts.values().map(|account| account.balance_in(currency)).sum()
+    }
+
+    pub fn total_deposits(&self) -> Money {
+        self.accounts.values().map(|account| account.balance).filter(|balance| *balance > Money::ZERO).sum()
+    }
+
+    // Liquid assets are the positive deposit balances; with no stressed outflow the bank is
+    // fully covered and the ratio is infinite.
+    pub fn liquidity_coverage_ratio(&self, stressed_outflow: Money) -> f64 {
+        if stressed_outflow <= Money::ZERO {
+            return f64::INFINITY;
+        }
+        self.total_deposits().cents() as f64 / stressed_outflow.cents() as f64
+    }
+
+    // Prometheus text exposition format. Assets are the balances owed on Credit accounts, as
+    // in net_interest_margin; deposits are the positive balances.
+    pub fn prometheus_metrics(&self) -> String {
+        let mut out = String::new();
+        out.push_str("# HELP bank_account_balance Current balance of each account.\n");
+        out.push_str("# TYPE bank_account_balance gauge\n");
+        for (number, account) in &self.accounts {
+            out.push_str(&format!(
+                "bank_account_balance{{account=\"{}\",type=\"{}\"}} {}\n",
+                prometheus_label(number),
+                account.account_type.name().to_lowercase(),
+                account.balance
+            ));
+        }
+
+        let assets: Money = self
+            .accounts
+            .values()
+            .filter(|account| matches!(account.account_type, AccountType::Credit) && account.balance < Money::ZERO)
+            .map(|account| -account.balance)
+            .sum();
+        out.push_str("# HELP bank_total_assets Balances owed to the bank on Credit accounts.\n");
+        out.push_str("# TYPE bank_total_assets gauge\n");
+        out.push_str(&format!("bank_total_assets {}\n", assets));
+        out.push_str("# HELP bank_total_deposits Sum of positive account balances.\n");
+        out.push_str("# TYPE bank_total_deposits gauge\n");
+        out.push_str(&format!("bank_total_deposits {}\n", self.total_deposits()));
+
+        out.push_str("# HELP bank_accounts Number of accounts by type.\n");
+        out.push_str("# TYPE bank_accounts gauge\n");
+        for account_type in [AccountType::Checking, AccountType::Savings, AccountType::Credit] {
+            let count = self.accounts.values().filter(|account| account.account_type == account_type).count();
+            out.push_str(&format!("bank_accounts{{type=\"{}\"}} {}\n", account_type.name().to_lowercase(), count));
+        }
+        out
+    }
+
+    // Positive when reserves fall short of `reserve_ratio` of total deposits, negative when
+    // the bank holds a surplus.
+    pub fn reserve_shortfall(&self, reserve_ratio: f64, reserves_held: Money) -> Money {
+        self.total_deposits().scale(reserve_ratio) - reserves_held
+    }
+
+    // Balances owed on Credit accounts over total deposits. With no deposits the ratio is
+    // infinite if anything is lent and zero otherwise.
+    pub fn loan_to_deposit_ratio(&self) -> f64 {
+        let loans: Money = self
+            .accounts
+            .values()
+            .filter(|account| matches!(account.account_type, AccountType::Credit) && account.balance < Money::ZERO)
+            .map(|account| -account.balance)
+            .sum();
+        let deposits = self.total_deposits();
+        if deposits == Money::ZERO {
+            return if loans > Money::ZERO { f64::INFINITY } else { 0.0 };
+        }
+        loans.cents() as f64 / deposits.cents() as f64
+    }
+
+    pub fn deposit_shares(&self) -> Vec<(String, f64)> {
+        let total = self.total_deposits();
+        if total == Money::ZERO {
+            return Vec::new();
+        }
+        self.accounts
+            .iter()
+            .filter(|(_, account)| account.balance > Money::ZERO)
+            .map(|(number, account)| (number.clone(), account.balance.cents() as f64 / total.cents() as f64))
+            .collect()
+    }
+
+    pub fn set_owner(&mut self, account_number: String, owner: Option<String>) -> Result<(), BankError> {
+        let account = self.accounts.get_mut(&account_number).ok_or(BankError::AccountNotFound)?;
+        account.owner = owner;
+        Ok(())
+    }
+
+    pub fn add_customer(&mut self, customer_id: String, customer: Customer) -> Result<(), BankError> {
+        if self.customers.contains_key(&customer_id) {
+            return Err(BankError::CustomerExists);
+        }
+        self.customers.insert(customer_id, customer);
+        Ok(())
+    }
+
+    pub fn customer(&self, customer_id: &str) -> Option<&Customer> {
+        self.customers.get(customer_id)
+    }
+
+    pub fn customers(&self) -> &BTreeMap<String, Customer> {
+        &self.customers
+    }
+
+    // Makes the customer the account's owner; the customer must already be known.
+    pub fn link_account(&mut self, account_number: String, customer_id: String) -> Result<(), BankError> {
+        if !self.customers.contains_key(&customer_id) {
+            return Err(BankError::CustomerNotFound);
+        }
+        self.set_owner(account_number, Some(customer_id))
+    }
+
+    pub fn customer_accounts(&self, customer_id: &str) -> Result<Vec<String>, BankError> {
+        if !self.customers.contains_key(customer_id) {
+            return Err(BankError::CustomerNotFound);
+        }
+        Ok(self
+            .accounts
+            .iter()
+            .filter(|(_, account)| account.owner() == Some(customer_id))
+            .map(|(number, _)| number.clone())
+            .collect())
+    }
+
+    // Every balance the customer holds, foreign-currency ones included, converted to `currency`
+    // at the mid rate. Credit balances are negative and so reduce it.
+    pub fn customer_net_worth(&self, customer_id: &str, currency: &Currency) -> Result<Money, BankError> {
+        let mut total = Money::ZERO;
+        for number in self.customer_accounts(customer_id)? {
+            let account = &self.accounts[&number];
+            let balances = account.foreign_balances.iter().chain([(&account.currency, &account.balance)]);
+            for (from, balance) in balances {
+                let rate = self.mid_rate(from, currency).ok_or(BankError::ExchangeRateUnavailable)?;
+                total = total.checked_add(Money::from_f64(balance.to_f64() * rate))?;
+            }
+        }
+        Ok(total)
+    }
+
+    // Closes all of the customer's open or frozen accounts, or none of them if any still holds
+    // a balance. Returns the account numbers closed.
+    pub fn close_customer_accounts(&mut self, customer_id: &str) -> Result<Vec<String>, BankError> {
+        let mut numbers = self.customer_accounts(customer_id)?;
+        numbers.retain(|number| self.accounts[number].status != AccountStatus::Closed);
+        for number in &numbers {
+            let balance = self.accounts[number].balance;
+            if balance != Money::ZERO {
+                return Err(BankError::BalanceRemaining(balance));
+            }
+        }
+        for number in &numbers {
+            self.close_account(number)?;
+        }
+        Ok(numbers)
+    }
+
+    // Balance-years across the owner's accounts: each account's age in years times its average
+    // daily balance since opening. Balances below zero count as zero.
+    pub fn customer_tenure_score(&self, owner: &str, now: SystemTime) -> f64 {
+        self.accounts
+            .values()
+            .filter(|account| account.owner() == Some(owner))
+            .map(|account| {
+                let years = now.duration_since(account.created_at).unwrap_or_default().as_secs_f64() / (365.0 * 86_400.0);
+                account.average_daily_balance(account.created_at, now).max(Money::ZERO).to_f64() * years
+            })
+            .sum()
+    }
+
+    pub fn set_statement_delivery(&mut self, account_number: String, method: DeliveryMethod) -> Result<(), BankError> {
+        let account = self.accounts.get_mut(&account_number).ok_or(BankError::AccountNotFound)?;
+        account.set_statement_delivery(method);
+        Ok(())
+    }
+
+    pub fn generate_statements(&self, period: &Period) -> Vec<Statement> {
+        self.accounts
+            .iter()
+            .filter(|(_, account)| account.statement_delivery != DeliveryMethod::None)
+            .map(|(number, account)| account.statement(number, period))
+            .collect()
+    }
+
+    pub fn formal_statement(&self, account_number: String, period: &Period) -> Result<FormalStatement, BankError> {
+        let account = self.accounts.get(&account_number).ok_or(BankError::AccountNotFound)?;
+        let statement = account.statement(&account_number, period);
+        let mut formal = FormalStatement {
+            bank_name: self.name.clone(),
+            account_number,
+            account_holder: account.owner.clone(),
+            currency: account.currency.clone(),
+            period: period.clone(),
+            opening_balance: statement.opening_balance,
+            closing_balance: statement.closing_balance,
+            transactions: statement.transactions,
+            generated_at: SystemTime::now(),
+            content_hash: String::new(),
+        };
+        formal.content_hash = sha256::hex_digest(formal.body().as_bytes());
+        Ok(formal)
+    }
+
+    // Account numbers that do not exist are listed in `missing` rather than skipped silently.
+    pub fn household_statement(&self, account_numbers: &[String], period: &Period) -> HouseholdStatement {
+        let mut sections = Vec::new();
+        let mut missing = Vec::new();
+        for number in account_numbers {
+            match self.accounts.get(number) {
+                Some(account) => sections.push(account.statement(number, period)),
+                None => missing.push(number.clone()),
+            }
+        }
+        HouseholdStatement {
+            period: period.clone(),
+            opening_balance: sections.iter().map(|section| section.opening_balance).sum(),
+            closing_balance: sections.iter().map(|section| section.closing_balance).sum(),
+            sections,
+            missing,
+        }
+    }
+
+    // Unlike generate_statements, every account is archived regardless of its delivery method.
+    pub fn generate_and_retain_statements(&mut self, period: &Period, keep_last: usize) {
+        for (number, account) in self.accounts.iter_mut() {
+            let statement = account.statement(number, period);
+            account.retain_statement(statement, keep_last);
+        }
+    }
+
+    // Closes the month for every account that isn't closed: charges the maintenance fee its fee
+    // schedule asks for, unless the average daily balance over the period reaches the waiver
+    // balance, then archives the period's statement like generate_and_retain_statements. The fee
+    // is dated at the end of the period, or now if the period hasn't ended yet.
+    pub fn run_month_end(&mut self, period: &Period, keep_last: usize) -> Vec<MonthEndSummary> {
+        let charged_at = period.to.min(SystemTime::now());
+        let mut maintenance_fees = BTreeMap::new();
+        for (number, account) in &self.accounts {
+            if account.status == AccountStatus::Closed {
+                continue;
+            }
+            let maintenance_fee = match &account.fee_schedule {
+                Some(schedule) => match schedule.maintenance_waiver_balance {
+                    Some(threshold) if account.average_daily_balance(period.from, period.to) >= threshold => Money::ZERO,
+                    _ => schedule.monthly_maintenance,
+                },
+                None => Money::ZERO,
+            };
+            maintenance_fees.insert(number.clone(), maintenance_fee);
+        }
+        for (number, fee) in &mut maintenance_fees {
+            if *fee > Money::ZERO && self.execute(EventKind::FeeCharged(number.clone(), *fee, FeeType::Maintenance, charged_at)).is_err() {
+                *fee = Money::ZERO;
+            }
+        }
+        let mut summaries = Vec::new();
+        for (number, account) in self.accounts.iter_mut() {
+            let maintenance_fee = match maintenance_fees.get(number) {
+                Some(fee) => *fee,
+                None => continue,
+            };
+            let statement = account.statement(number, period);
+            let total = |matches: fn(&TransactionKind) -> bool| -> Money {
+                statement
+                    .transactions
+                    .iter()
+                    .filter(|t| t.currency == account.currency && matches(&t.kind))
+                    .map(|t| t.kind.amount())
+                    .sum()
+            };
+            summaries.push(MonthEndSummary {
+                account_number: number.clone(),
+                period: period.clone(),
+                currency: account.currency.clone(),
+                opening_balance: statement.opening_balance,
+                closing_balance: statement.closing_balance,
+                deposits: total(|kind| matches!(kind, TransactionKind::Deposit(_))),
+                withdrawals: total(|kind| matches!(kind, TransactionKind::Withdrawal(_))),
+                fees: total(|kind| matches!(kind, TransactionKind::Fee(..))),
+                interest: total(|kind| matches!(kind, TransactionKind::Interest(_))),
+                maintenance_fee,
+            });
+            account.retain_statement(statement, keep_last);
+        }
+        let parameters = vec![("from", calendar::format_date(period.from)), ("to", calendar::format_date(period.to))];
+        self.audit("run_month_end", parameters, &Ok::<_, BankError>(()));
+        summaries
+    }
+
+    // True when the balance exceeds what replaying the log from zero explains by more than
+    // `tolerance`, which points at a corrupted file or a balance changed without a record.
+    // Only entries in the account's own currency count toward its balance.
+    pub fn unexplained_growth(&self, account_number: String, tolerance: Money) -> bool {
+        match self.accounts.get(&account_number) {
+            Some(account) => account.balance - account.balance_effect_where(|_| true) > tolerance,
+            None => false,
+        }
+    }
+
+    pub fn detect_structuring(&self, account_number: String, threshold: Money, window: Duration) -> bool {
+        let account = match self.accounts.get(&account_number) {
+            Some(account) => account,
+            None => return false,
+        };
+        let near_floor = threshold.scale(1.0 - STRUCTURING_MARGIN);
+        let mut near_threshold: Vec<(SystemTime, Money)> = account
+            .transactions
+            .iter()
+            .filter_map(|t| match t.kind {
+                TransactionKind::Deposit(amount) | TransactionKind::Withdrawal(amount) => Some((t.timestamp, amount)),
+                _ => None,
+            })
+            .filter(|(_, amount)| *amount < threshold && *amount >= near_floor)
+            .collect();
+        near_threshold.sort_by_key(|(timestamp, _)| *timestamp);
+
+        near_threshold.iter().enumerate().any(|(i, (start, _))| {
+            let in_window: Vec<Money> = near_threshold[i..]
+                .iter()
+                .take_while(|(timestamp, _)| *timestamp <= *start + window)
+                .map(|(_, amount)| *amount)
+                .collect();
+            in_window.len() > 1 && in_window.iter().copied().sum::<Money>() > threshold
+        })
+    }
+
+    // Fraction of the account's deposits and withdrawals that are exact multiples of
+    // ROUND_AMOUNT; 0 when the account is unknown or has none.
+    pub fn round_number_ratio(&self, account_number: String) -> f64 {
+        let amounts: Vec<Money> = match self.accounts.get(&account_number) {
+            Some(account) => account
+                .transactions
+                .iter()
+                .filter_map(|t| match t.kind {
+                    TransactionKind::Deposit(amount) | TransactionKind::Withdrawal(amount) => Some(amount),
+                    _ => None,
+                })
+                .collect(),
+            None => return 0.0,
+        };
+        if amounts.is_empty() {
+            return 0.0;
+        }
+        let round = amounts
+            .iter()
+            .filter(|amount| **amount != Money::ZERO && amount.cents() % ROUND_AMOUNT.cents() == 0)
+            .count();
+        round as f64 / amounts.len() as f64
+    }
+
+    // Averages are rounded to the nearest cent.
+    pub fn average_transaction_size_by_type(&self) -> HashMap<AccountType, Money> {
+        let mut totals: HashMap<AccountType, (Money, usize)> = HashMap::new();
+        for account in self.accounts.values() {
+            for transaction in &account.transactions {
+                let effect = transaction.kind.balance_effect();
+                if effect != Money::ZERO {
+                    let entry = totals.entry(account.account_type.clone()).or_default();
+                    entry.0 += effect.abs();
+                    entry.1 += 1;
+                }
+            }
+        }
+        totals
+            .into_iter()
+            .map(|(account_type, (total, count))| (account_type, total.scale(1.0 / count as f64)))
+            .collect()
+    }
+
+    pub fn fee_report(&self, account_number: String, from: SystemTime, to: SystemTime) -> HashMap<FeeType, Money> {
+        let period = Period { from, to };
+        let mut report = HashMap::new();
+        if let Some(account) = self.accounts.get(&account_number) {
+            for transaction in account.transactions.iter().filter(|t| period.contains(t.timestamp)) {
+                if let TransactionKind::Fee(amount, fee_type) = transaction.kind {
+                    *report.entry(fee_type).or_default() += amount;
+                }
+            }
+        }
+        report
+    }
+
+    // Interest paid to deposit accounts between `from` and `to` inclusive.
+    pub fn interest_expense(&self, from: SystemTime, to: SystemTime) -> Money {
+        let period = Period { from, to };
+        self.accounts
+            .values()
+            .filter(|account| !matches!(account.account_type, AccountType::Credit))
+            .flat_map(|account| &account.transactions)
+            .filter(|t| period.contains(t.timestamp))
+            .map(|t| match t.kind {
+                TransactionKind::Interest(amount) => amount,
+                _ => Money::ZERO,
+            })
+            .sum()
+    }
+
+    pub fn accounts_created_between(&self, from: SystemTime, to: SystemTime) -> Vec<&String> {
+        let period = Period { from, to };
+        self.accounts
+            .iter()
+            .filter(|(_, account)| period.contains(account.created_at))
+            .map(|(number, _)| number)
+            .collect()
+    }
+
+    // Transactions across all accounts counted per bucket of the given width, with buckets
+    // aligned to the Unix epoch. Every bucket from the first transaction to the last is
+    // listed, so quiet periods show as zero.
+    pub fn throughput(&self, bucket: Duration) -> Vec<(SystemTime, usize)> {
+        let width = bucket.as_millis() as i64;
+        if width == 0 {
+            return Vec::new();
+        }
+        let mut counts: BTreeMap<i64, usize> = BTreeMap::new();
+        for transaction in self.accounts.values().flat_map(|account| &account.transactions) {
+            let start = calendar::to_millis(transaction.timestamp).div_euclid(width) * width;
+            *counts.entry(start).or_insert(0) += 1;
+        }
+        let (first, last) = match (counts.keys().next(), counts.keys().next_back()) {
+            (Some(first), Some(last)) => (*first, *last),
+            _ => return Vec::new(),
+        };
+        (0..=(last - first) / width)
+            .map(|n| {
+                let start = first + n * width;
+                (calendar::from_millis(start), counts.get(&start).copied().unwrap_or(0))
+            })
+            .collect()
+    }
+
+    // Every bucket is listed, including empty ones; accounts dated after `now` count as new.
+    pub fn account_age_distribution(&self, now: SystemTime) -> Vec<(AgeBucket, usize)> {
+        let mut counts = [0; AgeBucket::ALL.len()];
+        for account in self.accounts.values() {
+            let age = now.duration_since(account.created_at).unwrap_or_default();
+            counts[AgeBucket::for_age(age) as usize] += 1;
+        }
+        AgeBucket::ALL.iter().copied().zip(counts).collect()
+    }
+
+    // Every account is a node, mapped to the accounts it sent transfers to and the total sent
+    // to each, ordered by account number.
+    pub fn transfer_graph(&self) -> BTreeMap<String, Vec<(String, Money)>> {
+        self.accounts
+            .iter()
+            .map(|(number, account)| {
+                let mut totals: BTreeMap<String, Money> = BTreeMap::new();
+                for transaction in &account.transactions {
+                    if let TransactionKind::TransferOut(amount, to) = &transaction.kind {
+                        *totals.entry(to.clone()).or_default() += *amount;
+                    }
+                }
+                (number.clone(), totals.into_iter().collect())
+            })
+            .collect()
+    }
+
+    pub fn weighted_average_deposit_rate(&self) -> f64 {
+        let (weighted, total) = self
+            .accounts
+            .values()
+            .filter(|account| account.is_interest_bearing_at(self.effective_rate(account)))
+            .fold((0.0, 0.0), |(weighted, total), account| {
+                let balance = account.balance.to_f64();
+                (weighted + balance * self.effective_rate(account), total + balance)
+            });
+        if total == 0.0 {
+            0.0
+        } else {
+            weighted / total
+        }
+    }
+
+    // Annual net interest income over earning assets, i.e. the balances owed on Credit
+    // accounts. A Credit account's interest rate is what it is charged on a negative balance.
+    pub fn net_interest_margin(&self) -> f64 {
+        let (earned, assets) = self
+            .accounts
+            .values()
+            .filter(|account| matches!(account.account_type, AccountType::Credit) && account.balance < Money::ZERO)
+            .fold((0.0, 0.0), |(earned, assets), account| {
+                let owed = -account.balance.to_f64();
+                (earned + owed * self.effective_rate(account), assets + owed)
+            });
+        if assets == 0.0 {
+            return 0.0;
+        }
+        let paid: f64 = self
+            .accounts
+            .values()
+            .filter(|account| account.is_interest_bearing_at(self.effective_rate(account)))
+            .map(|account| account.balance.to_f64() * self.effective_rate(account))
+            .sum();
+        (earned - paid) / assets
+    }
+
+    // Estimated yearly contribution of each account: non-interest fees charged over the past
+    // year plus the interest a Credit balance earns at its rate, minus the interest a deposit
+    // account is projected to be paid. Finance charges are left out of the fees since the
+    // interest estimate already covers them.
+    pub fn account_profitability(&self) -> Vec<(String, Money)> {
+        let year_ago = SystemTime::now() - Duration::from_secs(365 * 86_400);
+        self.accounts
+            .iter()
+            .map(|(number, account)| {
+                let fees: Money = account
+                    .transactions
+                    .iter()
+                    .filter(|t| t.timestamp >= year_ago)
+                    .map(|t| match t.kind {
+                        TransactionKind::Fee(amount, fee_type) if fee_type != FeeType::FinanceCharge => amount,
+                        _ => Money::ZERO,
+                    })
+                    .sum();
+                let interest = match account.account_type {
+                    AccountType::Credit if account.balance < Money::ZERO => (-account.balance).scale(self.effective_rate(account)),
+                    AccountType::Credit => Money::ZERO,
+                    _ => -account.projected_interest_at(12, self.effective_rate(account)),
+                };
+                (number.clone(), fees + interest)
+            })
+            .collect()
+    }
+
+    // `rates` gives the value of one unit of each currency in the base currency.
+    pub fn list_accounts_with_base(&self, base: &Currency, rates: &HashMap<Currency, f64>) -> Vec<(String, Money, Currency, Option<Money>)> {
+        self.accounts
+            .iter()
+            .map(|(number, account)| {
+                let converted = if account.currency == *base {
+                    Some(account.balance)
+                } else {
+                    rates.get(&account.currency).map(|rate| account.balance.scale(*rate))
+                };
+                (number.clone(), account.balance, account.currency.clone(), converted)
+            })
+            .collect()
+    }
+
+    // Closes accounts holding nothing in any currency whose last transaction (or opening, if
+    // they never had one) is at least `inactive_for` before `now`. They stay on the books as
+    // Closed, each through close_account. Returns the accounts closed.
+    pub fn close_abandoned(&mut self, inactive_for: Duration, now: SystemTime) -> Vec<String> {
+        let abandoned: Vec<String> = self
+            .accounts
+            .iter()
+            .filter(|(_, account)| account.status != AccountStatus::Closed)
+            .filter(|(_, account)| {
+                account.balance == Money::ZERO && account.foreign_balances.values().all(|balance| *balance == Money::ZERO)
+            })
+            .filter(|(_, account)| account.idle_for(now).is_some_and(|idle| idle >= inactive_for))
+            .map(|(number, _)| number.clone())
+            .collect();
+        abandoned.into_iter().filter(|number| self.close_account(number).is_ok()).collect()
+    }
+
+    pub fn recently_reactivated(&self, dormant_threshold: Duration, active_window: Duration, now: SystemTime) -> Vec<&String> {
+        let window_start = now.checked_sub(active_window).unwrap_or(SystemTime::UNIX_EPOCH);
+        self.accounts
+            .iter()
+            .filter(|(_, account)| {
+                let first_recent = match account.transactions.iter().map(|t| t.timestamp).filter(|t| *t >= window_start).min() {
+                    Some(first_recent) => first_recent,
+                    None => return false,
+                };
+                let last_before = account
+                    .transactions
+                    .iter()
+                    .map(|t| t.timestamp)
+                    .filter(|t| *t < first_recent)
+                    .max()
+                    .unwrap_or(account.created_at);
+                first_recent.duration_since(last_before).is_ok_and(|gap| gap >= dormant_threshold)
+            })
+            .map(|(number, _)| number)
+            .collect()
+    }
+
+    pub fn find_accounts(&self, filter: &AccountFilter) -> Vec<&String> {
+        self.accounts.iter().filter(|(_, account)| filter.matches(account)).map(|(number, _)| number).collect()
+    }
+
+    // Accounts that aren't closed and have had no transaction (or, if they never had one, were
+    // opened) at least `inactive_for` before `now`, whatever their balance.
+    pub fn dormant_accounts(&self, inactive_for: Duration, now: SystemTime) -> Vec<&String> {
+        self.accounts
+            .iter()
+            .filter(|(_, account)| account.status != AccountStatus::Closed)
+            .filter(|(_, account)| account.idle_for(now).is_some_and(|idle| idle >= inactive_for))
+            .map(|(number, _)| number)
+            .collect()
+    }
+
+    // What total_deposits adds up, split by account type. Types with no money held are left out.
+    pub fn deposits_by_type(&self) -> HashMap<AccountType, Money> {
+        let mut totals: HashMap<AccountType, Money> = HashMap::new();
+        for account in self.accounts.values().filter(|account| account.balance > Money::ZERO) {
+            *totals.entry(account.account_type.clone()).or_default() += account.balance;
+        }
+        totals
+    }
+
+    pub fn transaction_heatmap(&self) -> [usize; 24] {
+        let mut heatmap = [0; 24];
+        for transaction in self.accounts.values().flat_map(|account| &account.transactions) {
+            heatmap[calendar::hour_of_day(transaction.timestamp)] += 1;
+        }
+        heatmap
+    }
+
+    pub fn tag_where(&mut self, pred: impl Fn(&Account) -> bool, tag: &str) -> usize {
+        let mut tagged = 0;
+        for account in self.accounts.values_mut().filter(|account| pred(account)) {
+            account.tags.insert(tag.to_string());
+            tagged += 1;
+        }
+        tagged
+    }
+
+    pub fn balance_histogram(&self, buckets: &[Money]) -> Vec<(Range<Money>, usize)> {
+        let mut bounds = vec![Money::from_cents(i64::MIN)];
+        bounds.extend_from_slice(buckets);
+        bounds.push(Money::from_cents(i64::MAX));
+        bounds.sort();
+        bounds.dedup();
+
+        let mut histogram: Vec<(Range<Money>, usize)> = bounds.windows(2).map(|w| (w[0]..w[1], 0)).collect();
+        for account in self.accounts.values() {
+            if let Some(bucket) = histogram.iter_mut().find(|(range, _)| range.contains(&account.balance)) {
+                bucket.1 += 1;
+            }
+        }
+        histogram
+    }
+}
+
+impl Default for Bank {
+    fn default() -> Bank {
+        Bank::new()
+    }
+}
+
+// An all-or-nothing scope over the bank. The state at begin_journal is kept aside, so
+// rollback (or dropping the journal without committing) restores everything changed
+// through it, configuration included. The audit log is the exception: what was attempted
+// stays on record.
+pub struct Journal<'a> {
+    bank: &'a mut Bank,
+    before: Option<Box<Bank>>,
+}
+
+impl Journal<'_> {
+    pub fn commit(mut self) {
+        self.before = None;
+        self.bank.open_journals -= 1;
+        if self.bank.open_journals == 0 {
+            let _ = self.bank.flush_event_log();
+        }
+    }
+
+    pub fn rollback(mut self) {
+        self.restore();
+    }
+
+    fn restore(&mut self) {
+        if let Some(before) = self.before.take() {
+            let audit_log = std::mem::take(&mut self.bank.audit_log);
+            *self.bank = *before;
+            self.bank.audit_log = audit_log;
+        }
+    }
+}
+
+impl Deref for Journal<'_> {
+    type Target = Bank;
+
+    fn deref(&self) -> &Bank {
+        self.bank
+    }
+}
+
+impl DerefMut for Journal<'_> {
+    fn deref_mut(&mut self) -> &mut Bank {
+        self.bank
+    }
+}
+
+impl Drop for Journal<'_> {
+    fn drop(&mut self) {
+        self.restore();
+    }
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+// Label values escape backslashes, double quotes and newlines.
+fn prometheus_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+// The public API grouped by what it is about. Everything is also reachable from the crate root.
+pub mod bank {
+    pub use super::{
+        AccountFilter, Bank, BankError, Customer, DailyAllowance, Event, EventKind, MonthEndSummary, Money, Operation, Period,
+        RecurringPayment, VelocityLimit, VelocityLimits,
+    };
+}
+
+pub mod account {
+    pub use super::{
+        Account, AccountStatus, AccountType, AuthHold, CompoundFrequency, Currency, DeliveryMethod, FeeSchedule, InterestBasis, Statement,
+        StatementFormat,
+    };
+}
+
+pub mod transaction {
+    pub use super::{FeeType, Transaction, TransactionKind, TransferRef};
+}
+
+pub mod auth {
+    use super::{AccountStatus, Bank, BankError, EventKind, Money, Transaction};
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum PinStatus {
+        NotSet,
+        Unlocked { failed_attempts: u32 },
+        // Too many failures; the account stays locked until an operator reactivates it.
+        Locked,
+    }
+
+    // PINs are stored salted and hashed. The *_with_pin operations verify the PIN first and
+    // count a wrong one towards the lockout like verify_pin does.
+    impl Bank {
+        pub fn set_max_pin_attempts(&mut self, attempts: u32) {
+            self.max_pin_attempts = attempts.max(1);
+        }
+
+        pub fn set_pin(&mut self, account_number: String, pin: &str) -> Result<(), BankError> {
+            let result = self.accounts.get_mut(&account_number).map(|account| account.set_pin(pin)).ok_or(BankError::AccountNotFound);
+            self.audit("set_pin", vec![("account", account_number)], &result);
+            result
+        }
+
+        // A PIN that is already set can only be replaced by someone who knows it.
+        pub fn change_pin(&mut self, account_number: String, current: &str, new: &str) -> Result<(), BankError> {
+            self.verify_pin(account_number.clone(), current)?;
+            self.set_pin(account_number, new)
+        }
+
+        // Every attempt is audited, but never the PIN that was tried. A lockout freezes an open
+        // account through freeze_account, so the freeze is audited and replayed.
+        pub fn verify_pin(&mut self, account_number: String, pin: &str) -> Result<(), BankError> {
+            let max_attempts = self.max_pin_attempts;
+            let result = match self.accounts.get_mut(&account_number) {
+                Some(account) => account.verify_pin(pin, max_attempts),
+                None => Err(BankError::AccountNotFound),
+            };
+            self.audit("verify_pin", vec![("account", account_number.clone())], &result);
+            let open = self.accounts.get(&account_number).is_some_and(|account| account.status == AccountStatus::Open);
+            if result == Err(BankError::AccountLocked) && open {
+                self.freeze_account(account_number)?;
+            }
+            result
+        }
+
+        pub fn pin_status(&self, account_number: &str) -> Result<PinStatus, BankError> {
+            let account = self.accounts.get(account_number).ok_or(BankError::AccountNotFound)?;
+            Ok(match account.pin_hash {
+                None => PinStatus::NotSet,
+                Some(_) if account.failed_pin_attempts >= self.max_pin_attempts => PinStatus::Locked,
+                Some(_) => PinStatus::Unlocked { failed_attempts: account.failed_pin_attempts },
+            })
+        }
+
+        pub fn withdraw_with_pin(&mut self, account_number: String, pin: &str, amount: Money) -> Result<(), BankError> {
+            self.verify_pin(account_number.clone(), pin)?;
+            self.withdraw(account_number, amount)
+        }
+
+        pub fn transfer_with_pin(&mut self, from_account: String, pin: &str, to_account: String, amount: Money) -> Result<(), BankError> {
+            self.verify_pin(from_account.clone(), pin)?;
+            self.transfer(from_account, to_account, amount)
+        }
+
+        pub fn deactivate_with_pin(&mut self, account_number: String, pin: &str) -> Result<(), BankError> {
+            self.verify_pin(account_number.clone(), pin)?;
+            self.deactivate_account(account_number)
+        }
+    }
+
+    // How a customer holds an account. The account's owner, as set by link_account, is always
+    // an Owner; anyone else needs a role from add_holder. Joint accounts have several Owners.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Role {
+        Owner,
+        // May move money, but not change who holds the account or close it.
+        Signer,
+        // May see balances and transactions only.
+        Viewer,
+    }
+
+    impl Role {
+        pub fn name(&self) -> &'static str {
+            match self {
+                Role::Owner => "Owner",
+                Role::Signer => "Signer",
+                Role::Viewer => "Viewer",
+            }
+        }
+
+        pub fn from_name(name: &str) -> Option<Role> {
+            match name {
+                "Owner" => Some(Role::Owner),
+                "Signer" => Some(Role::Signer),
+                "Viewer" => Some(Role::Viewer),
+                _ => None,
+            }
+        }
+
+        pub fn allows(&self, permission: Permission) -> bool {
+            match permission {
+                Permission::View => true,
+                Permission::Transact => *self != Role::Viewer,
+                Permission::Manage => *self == Role::Owner,
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Permission {
+        View,
+        // Deposits, withdrawals and transfers out.
+        Transact,
+        // Adding and removing holders, freezing and closing.
+        Manage,
+    }
+
+    // The *_as operations act on behalf of a customer, who must hold the account in a role that
+    // allows the operation. Refusals are audited like a failed operation.
+    impl Bank {
+        // Customers are configuration rather than events, so the customer is checked here and
+        // not when the event is replayed.
+        pub fn add_holder(&mut self, account_number: String, customer_id: String, role: Role) -> Result<(), BankError> {
+            if !self.customers.contains_key(&customer_id) {
+                let result = Err(BankError::CustomerNotFound);
+                self.audit("add_holder", vec![("account", account_number), ("customer", customer_id)], &result);
+                return result;
+            }
+            self.execute(EventKind::HolderAdded(account_number, customer_id, role))
+        }
+
+        // The owner can't be removed this way; link the account to someone else instead.
+        pub fn remove_holder(&mut self, account_number: String, customer_id: &str) -> Result<(), BankError> {
+            self.execute(EventKind::HolderRemoved(account_number, customer_id.to_string()))
+        }
+
+        // The owner first, then the other holders by customer id.
+        pub fn holders(&self, account_number: &str) -> Result<Vec<(String, Role)>, BankError> {
+            let account = self.accounts.get(account_number).ok_or(BankError::AccountNotFound)?;
+            let owner = account.owner.iter().map(|owner| (owner.clone(), Role::Owner));
+            let others = account.holders.iter().filter(|(id, _)| account.owner.as_ref() != Some(*id));
+            Ok(owner.chain(others.map(|(id, role)| (id.clone(), *role))).collect())
+        }
+
+        // None when the customer doesn't hold the account at all.
+        pub fn role_of(&self, account_number: &str, customer_id: &str) -> Result<Option<Role>, BankError> {
+            let account = self.accounts.get(account_number).ok_or(BankError::AccountNotFound)?;
+            if account.owner.as_deref() == Some(customer_id) {
+                return Ok(Some(Role::Owner));
+            }
+            Ok(account.holders.get(customer_id).copied())
+        }
+
+        pub fn check_permission(&self, account_number: &str, customer_id: &str, permission: Permission) -> Result<(), BankError> {
+            match self.role_of(account_number, customer_id)? {
+                Some(role) if role.allows(permission) => Ok(()),
+                _ => Err(BankError::NotPermitted),
+            }
+        }
+
+        fn permit(&mut self, operation: &str, account_number: &str, customer_id: &str, permission: Permission) -> Result<(), BankError> {
+            let result = self.check_permission(account_number, customer_id, permission);
+            if result.is_err() {
+                let parameters = vec![("account", account_number.to_string()), ("customer", customer_id.to_string())];
+                self.audit(operation, parameters, &result);
+            }
+            result
+        }
+
+        pub fn balance_as(&self, account_number: &str, customer_id: &str) -> Result<Money, BankError> {
+            self.check_permission(account_number, customer_id, Permission::View)?;
+            Ok(self.accounts[account_number].balance)
+        }
+
+        pub fn transactions_as(&self, account_number: &str, customer_id: &str) -> Result<&[Transaction], BankError> {
+            self.check_permission(account_number, customer_id, Permission::View)?;
+            Ok(&self.accounts[account_number].transactions)
+        }
+
+        pub fn deposit_as(&mut self, account_number: String, customer_id: &str, amount: Money) -> Result<(), BankError> {
+            self.permit("deposit", &account_number, customer_id, Permission::Transact)?;
+            self.deposit(account_number, amount)
+        }
+
+        pub fn withdraw_as(&mut self, account_number: String, customer_id: &str, amount: Money) -> Result<(), BankError> {
+            self.permit("withdraw", &account_number, customer_id, Permission::Transact)?;
+            self.withdraw(account_number, amount)
+        }
+
+        // Only the source account is checked; anyone may be paid.
+        pub fn transfer_as(&mut self, from_account: String, customer_id: &str, to_account: String, amount: Money) -> Result<(), BankError> {
+            self.permit("transfer", &from_account, customer_id, Permission::Transact)?;
+            self.transfer(from_account, to_account, amount)
+        }
+
+        pub fn add_holder_as(&mut self, account_number: String, customer_id: &str, holder: String, role: Role) -> Result<(), BankError> {
+            self.permit("add_holder", &account_number, customer_id, Permission::Manage)?;
+            self.add_holder(account_number, holder, role)
+        }
+
+        pub fn freeze_account_as(&mut self, account_number: String, customer_id: &str) -> Result<(), BankError> {
+            self.permit("freeze_account", &account_number, customer_id, Permission::Manage)?;
+            self.freeze_account(account_number)
+        }
+
+        pub fn close_account_as(&mut self, account_number: &str, customer_id: &str) -> Result<(), BankError> {
+            self.permit("close_account", account_number, customer_id, Permission::Manage)?;
+            self.close_account(account_number)
+        }
+    }
+}
+
+pub mod audit {
+    use super::{calendar, csv_field, json, Bank, BankError, Period};
+    use std::io;
+    use std::time::SystemTime;
+
+    // One operation the bank was asked to perform, whether or not it succeeded. Parameters
+    // are (name, value) pairs such as ("account", "1001") or ("amount", "25.00").
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct AuditRecord {
+        pub sequence: u64,
+        pub timestamp: SystemTime,
+        pub actor: String,
+        pub operation: String,
+        pub parameters: Vec<(String, String)>,
+        // Why the bank refused the operation; None when it succeeded.
+        pub error: Option<String>,
+    }
+
+    impl AuditRecord {
+        pub fn succeeded(&self) -> bool {
+            self.error.is_none()
+        }
+
+        pub fn parameter(&self, name: &str) -> Option<&str> {
+            self.parameters.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+        }
+
+        // Either side of a transfer, or the account a closing balance was swept to, counts as
+        // involved.
+        pub fn involves(&self, account_number: &str) -> bool {
+            ["account", "from", "to", "sweep_to"].iter().any(|name| self.parameter(name) == Some(account_number))
+        }
+
+        pub fn outcome(&self) -> &str {
+            self.error.as_deref().unwrap_or("ok")
+        }
+
+        pub fn to_json(&self) -> json::Value {
+            let parameters = self.parameters.iter().map(|(name, value)| (name.clone(), json::string(value))).collect();
+            json::object(vec![
+                ("sequence", json::Value::Number(self.sequence as f64)),
+                ("timestamp", json::Value::Number(calendar::to_millis(self.timestamp) as f64)),
+                ("actor", json::string(&self.actor)),
+                ("operation", json::string(&self.operation)),
+                ("parameters", json::Value::Object(parameters)),
+                ("error", self.error.as_deref().map_or(json::Value::Null, json::string)),
+            ])
+        }
+
+        pub fn from_json(value: &json::Value) -> Result<AuditRecord, String> {
+            let number = |field: &str| value.get(field).and_then(json::Value::as_f64).ok_or(format!("audit record missing {}", field));
+            let text = |field: &str| value.get(field).and_then(json::Value::as_str).map(str::to_string);
+            let parameters = match value.get("parameters") {
+                Some(json::Value::Object(fields)) => fields
+                    .iter()
+                    .map(|(name, value)| value.as_str().map(|value| (name.clone(), value.to_string())))
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or("audit record has a non-text parameter")?,
+                _ => Vec::new(),
+            };
+            Ok(AuditRecord {
+                sequence: number("sequence")? as u64,
+                timestamp: calendar::from_millis(number("timestamp")? as i64),
+                actor: text("actor").ok_or("audit record missing actor")?,
+                operation: text("operation").ok_or("audit record missing operation")?,
+                parameters,
+                error: text("error"),
+            })
+        }
+    }
+
+    impl Bank {
+        // Operations are attributed to the actor until it is changed; a new bank starts with "system".
+        pub fn set_actor(&mut self, actor: &str) {
+            self.actor = actor.to_string();
+        }
+
+        pub fn actor(&self) -> &str {
+            &self.actor
+        }
+
+        pub fn audit_log(&self) -> &[AuditRecord] {
+            &self.audit_log
+        }
+
+        // Oldest first, narrowed to one account and/or a period when given.
+        pub fn audit_records(&self, account_number: Option<&str>, period: Option<&Period>) -> Vec<&AuditRecord> {
+            self.audit_log
+                .iter()
+                .filter(|record| account_number.is_none_or(|number| record.involves(number)))
+                .filter(|record| period.is_none_or(|period| period.contains(record.timestamp)))
+                .collect()
+        }
+
+        // Parameters share one column as `name=value` pairs separated by semicolons.
+        pub fn export_audit_log(&self, records: &[&AuditRecord], writer: &mut dyn io::Write) -> Result<(), BankError> {
+            let mut csv = String::from("sequence,timestamp,actor,operation,parameters,outcome\n");
+            for record in records {
+                let parameters: Vec<String> = record.parameters.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+                csv.push_str(&format!(
+                    "{},{},{},{},{},{}\n",
+                    record.sequence,
+                    calendar::format_datetime(record.timestamp),
+                    csv_field(&record.actor),
+                    csv_field(&record.operation),
+                    csv_field(&parameters.join("; ")),
+                    csv_field(record.outcome()),
+                ));
+            }
+            writer.write_all(csv.as_bytes()).map_err(|e| BankError::Io(e.to_string()))
+        }
+    }
+}
+
+pub mod import {
+    use super::{calendar, Bank, BankError, EventKind, Money};
+    use std::fs;
+    use std::path::Path;
+    use std::time::SystemTime;
+
+    // One row of another bank's export. Credits are positive and debits negative.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct ImportedTransaction {
+        // The exporting bank's id for the transaction, e.g. an OFX FITID.
+        pub external_id: String,
+        pub date: SystemTime,
+        pub amount: Money,
+        pub description: String,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum ImportFormat {
+        Csv,
+        Ofx,
+    }
+
+    impl ImportFormat {
+        pub fn name(&self) -> &'static str {
+            match self {
+                ImportFormat::Csv => "CSV",
+                ImportFormat::Ofx => "OFX",
+            }
+        }
+
+        pub fn from_name(name: &str) -> Option<ImportFormat> {
+            match name.to_ascii_uppercase().as_str() {
+                "CSV" => Some(ImportFormat::Csv),
+                "OFX" | "QFX" => Some(ImportFormat::Ofx),
+                _ => None,
+            }
+        }
+
+        // By the file's extension, falling back to CSV.
+        pub fn for_path(path: &Path) -> ImportFormat {
+            path.extension().and_then(|extension| extension.to_str()).and_then(ImportFormat::from_name).unwrap_or(ImportFormat::Csv)
+        }
+    }
+
+    #[derive(Debug, Clone, Default, PartialEq)]
+    pub struct ImportReport {
+        pub imported: usize,
+        // Rows whose external id had already been imported into the account.
+        pub skipped: usize,
+        // Row number (from 1, not counting a CSV header) and why the row was refused.
+        pub rejected: Vec<(usize, String)>,
+    }
+
+    // Each row parsed, or why it couldn't be.
+    pub type ParsedRows = Vec<Result<ImportedTransaction, String>>;
+
+    pub fn parse(format: ImportFormat, text: &str) -> Result<ParsedRows, BankError> {
+        match format {
+            ImportFormat::Csv => parse_csv(text),
+            ImportFormat::Ofx => Ok(parse_ofx(text)),
+        }
+    }
+
+    // The first line is a header naming the columns, in any order: id, date (YYYY-MM-DD),
+    // amount and, optionally, description. Blank lines are ignored.
+    pub fn parse_csv(text: &str) -> Result<ParsedRows, BankError> {
+        let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+        let header = split_csv_line(lines.next().unwrap_or(""));
+        let header: Vec<String> = header.iter().map(|name| name.trim().to_ascii_lowercase()).collect();
+        let column = |names: &[&str]| header.iter().position(|name| names.contains(&name.as_str()));
+        let missing = |name: &str| BankError::InvalidData(format!("CSV header has no {} column", name));
+        let id = column(&["id", "transaction id", "fitid"]).ok_or_else(|| missing("id"))?;
+        let date = column(&["date", "posted"]).ok_or_else(|| missing("date"))?;
+        let amount = column(&["amount"]).ok_or_else(|| missing("amount"))?;
+        let description = column(&["description", "memo", "payee"]);
+        Ok(lines
+            .map(|line| {
+                let fields = split_csv_line(line);
+                let field = |index: usize| fields.get(index).map_or("", |field| field.trim());
+                row(field(id), parse_date(field(date)), field(amount), description.map_or("", field))
+            })
+            .collect())
+    }
+
+    // Each <STMTTRN> aggregate, taking FITID, DTPOSTED, TRNAMT and NAME or MEMO. Works for
+    // both SGML files, where elements needn't be closed, and XML ones.
+    pub fn parse_ofx(text: &str) -> ParsedRows {
+        text.split("<STMTTRN>")
+            .skip(1)
+            .map(|block| {
+                let block = block.split("</STMTTRN>").next().unwrap_or(block);
+                let element = |name: &str| {
+                    let start = block.find(&format!("<{}>", name))? + name.len() + 2;
+                    Some(block[start..].split('<').next().unwrap_or("").trim())
+                };
+                // DTPOSTED is YYYYMMDD, optionally followed by a time that is ignored.
+                let date = element("DTPOSTED").and_then(|posted| match (posted.get(..4), posted.get(4..6), posted.get(6..8)) {
+                    (Some(year), Some(month), Some(day)) => calendar::parse_date(&format!("{}-{}-{}", year, month, day)),
+                    _ => None,
+                });
+                let description = element("NAME").or_else(|| element("MEMO")).unwrap_or("");
+                row(element("FITID").unwrap_or(""), date, element("TRNAMT").unwrap_or(""), description)
+            })
+            .collect()
+    }
+
+    fn row(id: &str, date: Option<SystemTime>, amount: &str, description: &str) -> Result<ImportedTransaction, String> {
+        if id.is_empty() {
+            return Err("missing transaction id".to_string());
+        }
+        let date = date.ok_or("invalid date")?;
+        let unsigned = amount.strip_prefix('+').unwrap_or(amount);
+        let (negative, unsigned) = unsigned.strip_prefix('-').map_or((false, unsigned), |rest| (true, rest));
+        let magnitude: Money = unsigned.replace(',', "").parse().map_err(|_| format!("invalid amount {}", amount))?;
+        let amount = if negative { -magnitude } else { magnitude };
+        if amount == Money::ZERO {
+            return Err("zero amount".to_string());
+        }
+        Ok(ImportedTransaction { external_id: id.to_string(), date, amount, description: description.to_string() })
+    }
+
+    // YYYY-MM-DD, or MM/DD/YYYY as most US banks export.
+    fn parse_date(text: &str) -> Option<SystemTime> {
+        match text.split('/').collect::<Vec<_>>()[..] {
+            [month, day, year] => calendar::parse_date(&format!("{}-{}-{}", year, month, day)),
+            _ => calendar::parse_date(text),
+        }
+    }
+
+    // Splits on commas outside double quotes; "" inside quotes is a literal quote.
+    fn split_csv_line(line: &str) -> Vec<String> {
+        let mut fields = vec![String::new()];
+        let mut quoted = false;
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' if quoted && chars.peek() == Some(&'"') => {
+                    chars.next();
+                    fields.last_mut().unwrap().push('"');
+                }
+                '"' => quoted = !quoted,
+                ',' if !quoted => fields.push(String::new()),
+                _ => fields.last_mut().unwrap().push(c),
+            }
+        }
+        fields
+    }
+
+    impl Bank {
+        // Applies the rows in order. A row already imported into the account, or repeated in
+        // the same file, is skipped; one that can't be parsed or applied is rejected without
+        // stopping the rest.
+        pub fn import_transactions(&mut self, account_number: &str, rows: ParsedRows) -> Result<ImportReport, BankError> {
+            self.accounts.get(account_number).ok_or(BankError::AccountNotFound)?;
+            let mut report = ImportReport::default();
+            for (index, row) in rows.into_iter().enumerate() {
+                let transaction = match row {
+                    Ok(transaction) => transaction,
+                    Err(reason) => {
+                        report.rejected.push((index + 1, reason));
+                        continue;
+                    }
+                };
+                if self.accounts[account_number].imported_ids.contains(&transaction.external_id) {
+                    report.skipped += 1;
+                    continue;
+                }
+                match self.execute(EventKind::Imported(account_number.to_string(), transaction)) {
+                    Ok(()) => report.imported += 1,
+                    Err(error) => report.rejected.push((index + 1, error.to_string())),
+                }
+            }
+            Ok(report)
+        }
+
+        pub fn import_file(&mut self, account_number: &str, path: &Path) -> Result<ImportReport, BankError> {
+            let text = fs::read_to_string(path).map_err(|e| BankError::Io(e.to_string()))?;
+            let rows = parse(ImportFormat::for_path(path), &text)?;
+            self.import_transactions(account_number, rows)
+        }
+    }
+}
+
+pub mod simulation {
+    use super::{AccountType, Bank, BankError, Currency, Money};
+    use std::fmt;
+
+    #[derive(Debug, Clone)]
+    pub struct SimulationConfig {
+        // The same seed always produces the same operations.
+        pub seed: u64,
+        pub steps: usize,
+        pub accounts: usize,
+        // Limit given to each Credit account; Checking and Savings accounts keep a zero floor.
+        pub credit_limit: Money,
+    }
+
+    impl Default for SimulationConfig {
+        fn default() -> SimulationConfig {
+            SimulationConfig { seed: 1, steps: 1000, accounts: 5, credit_limit: Money::from_cents(50_000) }
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum Step {
+        Deposit(String, Money),
+        Withdraw(String, Money),
+        Transfer(String, String, Money),
+        // The account and the id of a transaction made earlier in the run.
+        Reverse(String, u64),
+        Freeze(String),
+        Unfreeze(String),
+    }
+
+    impl fmt::Display for Step {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                Step::Deposit(account, amount) => write!(f, "deposit {} into {}", amount, account),
+                Step::Withdraw(account, amount) => write!(f, "withdraw {} from {}", amount, account),
+                Step::Transfer(from, to, amount) => write!(f, "transfer {} from {} to {}", amount, from, to),
+                Step::Reverse(account, id) => write!(f, "reverse transaction {} on {}", id, account),
+                Step::Freeze(account) => write!(f, "freeze {}", account),
+                Step::Unfreeze(account) => write!(f, "unfreeze {}", account),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum Invariant {
+        // Holdings change only by money deposited or withdrawn; transfers move it, never make it.
+        MoneyConserved,
+        // Only Credit accounts may go below zero.
+        NoNegativeBalance,
+        // Each account's ledger adds up to its balance.
+        LedgerMatchesBalance,
+        // Zero and negative amounts are refused, never applied.
+        AmountsPositive,
+    }
+
+    impl Invariant {
+        pub fn name(&self) -> &'static str {
+            match self {
+                Invariant::MoneyConserved => "MoneyConserved",
+                Invariant::NoNegativeBalance => "NoNegativeBalance",
+                Invariant::LedgerMatchesBalance => "LedgerMatchesBalance",
+                Invariant::AmountsPositive => "AmountsPositive",
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Violation {
+        // Counted from 1.
+        pub step: usize,
+        pub operation: Step,
+        pub invariant: Invariant,
+        pub detail: String,
+    }
+
+    #[derive(Debug, Clone, Default, PartialEq)]
+    pub struct SimulationReport {
+        pub steps: usize,
+        pub succeeded: usize,
+        // Operations the bank refused, e.g. for insufficient funds; expected and not a failure.
+        pub refused: usize,
+        // Of those refused, the ones refused for a zero or negative amount.
+        pub invalid_amounts: usize,
+        // The run stops after the first step that breaks an invariant, so these are all from it.
+        pub violations: Vec<Violation>,
+    }
+
+    // Invariants broken by the bank as it stands, given the holdings it should have.
+    pub fn check_invariants(bank: &Bank, expected_holdings: Money) -> Vec<(Invariant, String)> {
+        let mut broken = Vec::new();
+        let holdings = bank.total_holdings(&Currency::default());
+        if holdings != expected_holdings {
+            broken.push((Invariant::MoneyConserved, format!("holdings are {}, expected {}", holdings, expected_holdings)));
+        }
+        for (number, account) in bank.accounts() {
+            if account.account_type != AccountType::Credit && account.balance < Money::ZERO {
+                broken.push((Invariant::NoNegativeBalance, format!("{} has balance {}", number, account.balance)));
+            }
+            if let Err((computed, balance)) = account.verify_balance() {
+                let detail = format!("{} ledger sums to {} but balance is {}", number, computed, balance);
+                broken.push((Invariant::LedgerMatchesBalance, detail));
+            }
+        }
+        broken
+    }
+
+    // Opens the accounts in a new bank, then makes random operations against them, checking the
+    // invariants after each one.
+    pub fn run(config: &SimulationConfig) -> SimulationReport {
+        let mut bank = Bank::new();
+        let mut rng = Rng::new(config.seed);
+        let types = [AccountType::Checking, AccountType::Savings, AccountType::Credit];
+        let accounts: Vec<String> = (1..=config.accounts).map(|n| format!("sim-{}", n)).collect();
+        for (number, account_type) in accounts.iter().zip(types.iter().cycle()) {
+            bank.create_account(number.clone(), account_type.clone()).expect("simulated accounts are new");
+            if matches!(account_type, AccountType::Credit) {
+                bank.set_account_limit(number.clone(), config.credit_limit).expect("simulated account exists");
+            }
+        }
+
+        let mut report = SimulationReport::default();
+        // Transactions that may be reversed, with how much each brought into the bank.
+        let mut made: Vec<(String, u64, Money)> = Vec::new();
+        let mut expected = Money::ZERO;
+        let last_id = |bank: &Bank, account: &str| bank.account(account).and_then(|a| a.transactions().last()).map(|t| t.id);
+        for step in 1..=config.steps {
+            let operation = rng.step(&accounts, &made);
+            let non_positive = match &operation {
+                Step::Deposit(_, amount) | Step::Withdraw(_, amount) | Step::Transfer(_, _, amount) => *amount <= Money::ZERO,
+                Step::Reverse(..) | Step::Freeze(_) | Step::Unfreeze(_) => false,
+            };
+            let result = match &operation {
+                Step::Deposit(account, amount) => bank.deposit(account.clone(), *amount).map(|_| (account, *amount)),
+                Step::Withdraw(account, amount) => bank.withdraw(account.clone(), *amount).map(|_| (account, -*amount)),
+                Step::Transfer(from, to, amount) => bank.transfer(from.clone(), to.clone(), *amount).map(|_| (from, Money::ZERO)),
+                Step::Reverse(account, id) => bank.reverse_transaction(account.clone(), *id).map(|_| (account, Money::ZERO)),
+                Step::Freeze(account) => bank.freeze_account(account.clone()).map(|_| (account, Money::ZERO)),
+                Step::Unfreeze(account) => bank.unfreeze_account(account.clone()).map(|_| (account, Money::ZERO)),
+            };
+            let accepted = result.is_ok();
+            match result {
+                Ok((account, inflow)) => {
+                    report.succeeded += 1;
+                    expected += inflow;
+                    match &operation {
+                        Step::Reverse(_, id) => {
+                            let position = made.iter().position(|(number, made_id, _)| number == account && made_id == id);
+                            if let Some(position) = position {
+                                expected -= made.remove(position).2;
+                            }
+                        }
+                        Step::Deposit(..) | Step::Withdraw(..) | Step::Transfer(..) => {
+                            if let Some(id) = last_id(&bank, account) {
+                                made.push((account.clone(), id, inflow));
+                            }
+                        }
+                        Step::Freeze(_) | Step::Unfreeze(_) => {}
+                    }
+                }
+                Err(error) => {
+                    report.refused += 1;
+                    if error == BankError::InvalidAmount {
+                        report.invalid_amounts += 1;
+                    }
+                }
+            }
+            report.steps = step;
+            let mut broken = check_invariants(&bank, expected);
+            if non_positive && accepted {
+                broken.push((Invariant::AmountsPositive, format!("{} was applied", operation)));
+            }
+            if !broken.is_empty() {
+                report.violations = broken
+                    .into_iter()
+                    .map(|(invariant, detail)| Violation { step, operation: operation.clone(), invariant, detail })
+                    .collect();
+                break;
+            }
+        }
+        report
+    }
+
+    // xorshift64*: fast, reproducible and plenty for choosing operations.
+    struct Rng(u64);
+
+    impl Rng {
+        fn new(seed: u64) -> Rng {
+            // Zero is the one state xorshift never leaves.
+            Rng(seed.max(1))
+        }
+
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 >> 12;
+            self.0 ^= self.0 << 25;
+            self.0 ^= self.0 >> 27;
+            self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+        }
+
+        fn below(&mut self, bound: usize) -> usize {
+            (self.next() % bound as u64) as usize
+        }
+
+        fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+            &items[self.below(items.len())]
+        }
+
+        // Up to $500, in whole cents. About one in twenty is zero or negative, which the bank
+        // must refuse.
+        fn amount(&mut self) -> Money {
+            let cents = 1 + self.below(50_000) as i64;
+            match self.below(40) {
+                0 => Money::ZERO,
+                1 => Money::from_cents(-cents),
+                _ => Money::from_cents(cents),
+            }
+        }
+
+        fn step(&mut self, accounts: &[String], made: &[(String, u64, Money)]) -> Step {
+            let account = self.pick(accounts).clone();
+            match self.below(100) {
+                0..=29 => Step::Deposit(account, self.amount()),
+                30..=49 => Step::Withdraw(account, self.amount()),
+                50..=84 => Step::Transfer(account, self.pick(accounts).clone(), self.amount()),
+                85..=92 if !made.is_empty() => {
+                    let (account, id, _) = self.pick(made);
+                    Step::Reverse(account.clone(), *id)
+                }
+                93..=96 => Step::Freeze(account),
+                _ => Step::Unfreeze(account),
+            }
+        }
+    }
+}
+
+pub mod concurrent {
+    use super::{Account, AccountType, Bank, BankError, Currency, Money, Operation, TransactionKind};
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, PoisonError, RwLock, RwLockWriteGuard};
+    use std::time::SystemTime;
+
+    type SharedAccount = Arc<RwLock<Account>>;
+
+    // A bank that threads can share. Each account has its own lock, so operations on different
+    // accounts run in parallel, and a transfer takes both of its accounts' locks in account
+    // number order so opposing transfers cannot deadlock. The bank's configuration is fixed
+    // while shared. Transaction limits apply; review screening and injected failures do not,
+    // and transfers between currencies are left to Bank.
+    pub struct ConcurrentBank {
+        bank: Bank,
+        accounts: RwLock<BTreeMap<String, SharedAccount>>,
+    }
+
+    fn write(account: &SharedAccount) -> RwLockWriteGuard<'_, Account> {
+        account.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    impl ConcurrentBank {
+        pub fn new(mut bank: Bank) -> ConcurrentBank {
+            let accounts = std::mem::take(&mut bank.accounts)
+                .into_iter()
+                .map(|(number, account)| (number, Arc::new(RwLock::new(account))))
+                .collect();
+            ConcurrentBank {
+                bank,
+                accounts: RwLock::new(accounts),
+            }
+        }
+
+        pub fn into_bank(self) -> Bank {
+            let mut bank = self.bank;
+            let accounts = self.accounts.into_inner().unwrap_or_else(PoisonError::into_inner);
+            for (number, account) in accounts {
+                // Operations only hold their clones while they borrow the bank.
+                let account = Arc::try_unwrap(account).expect("no operation outlives the bank");
+                bank.accounts.insert(number, account.into_inner().unwrap_or_else(PoisonError::into_inner));
+            }
+            bank
+        }
+
+        fn account(&self, account_number: &str) -> Result<SharedAccount, BankError> {
+            let accounts = self.accounts.read().unwrap_or_else(PoisonError::into_inner);
+            accounts.get(account_number).cloned().ok_or(BankError::AccountNotFound)
+        }
+
+        pub fn create_account(&self, account_number: String, account_type: AccountType) -> Result<(), BankError> {
+            let mut accounts = self.accounts.write().unwrap_or_else(PoisonError::into_inner);
+            if accounts.contains_key(&account_number) {
+                return Err(BankError::AccountExists);
+            }
+            let account = self.bank.new_account(account_type, Currency::default());
+            accounts.insert(account_number, Arc::new(RwLock::new(account)));
+            Ok(())
+        }
+
+        pub fn balance(&self, account_number: &str) -> Option<Money> {
+            let account = self.account(account_number).ok()?;
+            let balance = account.read().unwrap_or_else(PoisonError::into_inner).balance;
+            Some(balance)
+        }
+
+        pub fn deposit(&self, account_number: String, amount: Money) -> Result<(), BankError> {
+            let account = self.account(&account_number)?;
+            let mut account = write(&account);
+            self.bank.check_limit_in(&account.currency, Operation::Deposit, amount)?;
+            account.deposit(amount)
+        }
+
+        pub fn withdraw(&self, account_number: String, amount: Money) -> Result<(), BankError> {
+            let account = self.account(&account_number)?;
+            let mut account = write(&account);
+            self.bank.check_limit_in(&account.currency, Operation::Withdraw, amount)?;
+            account.withdraw(amount)
+        }
+
+        pub fn transfer(&self, from_account: String, to_account: String, amount: Money) -> Result<(), BankError> {
+            if from_account == to_account {
+                return Err(BankError::SameAccount);
+            }
+            if amount <= Money::ZERO {
+                return Err(BankError::InvalidAmount);
+            }
+            let (from, to) = (self.account(&from_account)?, self.account(&to_account)?);
+            let (mut from, mut to) = if from_account < to_account {
+                let from = write(&from);
+                (from, write(&to))
+            } else {
+                let to = write(&to);
+                (write(&from), to)
+            };
+            self.bank.check_limit_in(&from.currency, Operation::Transfer, amount)?;
+            from.check_open()?;
+            to.check_open()?;
+            if from.currency != to.currency {
+                return Err(BankError::ExchangeRateUnavailable);
+            }
+            from.check_transfer_velocity(SystemTime::now())?;
+            to.balance.checked_add(amount)?;
+
+            // With both accounts locked and checked, the credit cannot fail once the debit has
+            // gone through.
+            let now = SystemTime::now();
+            from.debit(TransactionKind::TransferOut(amount, to_account), None, now)?;
+            to.receive_transfer(amount, from_account, now)
+        }
+    }
+}
+
+pub mod server {
+    use super::{json, AccountType, Bank, BankError, Currency, Money, Transaction};
+    use std::io::{self, BufRead, BufReader, Read, Write};
+    use std::net::TcpStream;
+    use std::time::Duration;
+
+    // Larger bodies are refused with 413 before any of them is read.
+    pub const MAX_BODY_BYTES: usize = 64 * 1024;
+    // A client that stops sending mid-request gives up its connection after this long.
+    const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+    // Serves the bank over HTTP with JSON bodies:
+    //   POST /accounts                       {"account_number", "account_type", "pin", "currency"?}
+    //   POST /accounts/{number}/deposit      {"amount"}
+    //   POST /accounts/{number}/withdraw     {"amount", "pin"}
+    //   POST /transfers                      {"from", "to", "amount", "pin"}
+    //   GET  /accounts/{number}/balance        ledger and available balance
+    //   GET  /accounts/{number}/transactions
+    // Amounts may be given as strings ("50.00") or numbers. Failures answer {"error": message}.
+    pub fn handle(bank: &mut Bank, method: &str, path: &str, body: &str) -> (u16, String) {
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+        let request = match method {
+            "POST" => match json::parse(if body.trim().is_empty() { "{}" } else { body }) {
+                Ok(request) => request,
+                Err(error) => return failure(400, &format!("Invalid JSON: {}", error)),
+            },
+            _ => json::Value::Null,
+        };
+        let result = match (method, segments.as_slice()) {
+            ("POST", ["accounts"]) => create_account(bank, &request),
+            ("POST", ["accounts", number, "deposit"]) => {
+                amount(&request).and_then(|amount| bank.deposit(number.to_string(), amount)).map(|()| ok())
+            }
+            ("POST", ["accounts", number, "withdraw"]) => amount(&request)
+                .and_then(|amount| bank.withdraw_with_pin(number.to_string(), &field(&request, "pin")?, amount))
+                .map(|()| ok()),
+            ("POST", ["transfers"]) => transfer(bank, &request),
+            ("GET", ["accounts", number, "balance"]) => match bank.account(number) {
+                Some(account) => Ok(json::object(vec![
+                    ("account_number", json::string(number)),
+                    ("balance", json::string(&account.balance().to_string())),
+                    ("available_balance", json::string(&account.available_balance().to_string())),
+                    ("currency", json::string(account.currency().code())),
+                ])),
+                None => Err(BankError::AccountNotFound),
+            },
+            ("GET", ["accounts", number, "transactions"]) => match bank.account(number) {
+                Some(account) => Ok(json::object(vec![(
+                    "transactions",
+                    json::Value::Array(account.transactions().iter().map(Transaction::to_json).collect()),
+                )])),
+                None => Err(BankError::AccountNotFound),
+            },
+            (_, ["accounts"] | ["transfers"] | ["accounts", _, _]) => return failure(405, "Method not allowed"),
+            _ => return failure(404, "Not found"),
+        };
+        match result {
+            Ok(response) => (if method == "POST" { 201 } else { 200 }, response.to_string()),
+            Err(error) => failure(status_for(&error), &error.to_string()),
+        }
+    }
+
+    // Reads one request from the connection, answers it and closes. Returns whether the bank
+    // may have changed, so the caller knows when to save; a refused POST can still have counted
+    // a wrong PIN.
+    pub fn handle_connection(bank: &mut Bank, stream: &mut TcpStream) -> io::Result<bool> {
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        let mut reader = BufReader::new(&*stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        let mut parts = request_line.split_whitespace();
+        let (method, path) = (parts.next().unwrap_or("").to_string(), parts.next().unwrap_or("").to_string());
+        let mut content_length = 0;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.trim().eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().unwrap_or(0);
+                }
+            }
+        }
+        let (status, response) = if content_length > MAX_BODY_BYTES {
+            failure(413, &format!("Request body is over {} bytes", MAX_BODY_BYTES))
+        } else {
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body)?;
+            handle(bank, &method, &path, &String::from_utf8_lossy(&body))
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            reason(status),
+            response.len(),
+            response
+        )?;
+        stream.flush()?;
+        Ok(method == "POST" && status != 413)
+    }
+
+    fn create_account(bank: &mut Bank, request: &json::Value) -> Result<json::Value, BankError> {
+        let account_number = field(request, "account_number")?;
+        let account_type = AccountType::from_name(&field(request, "account_type")?).ok_or(BankError::UnsupportedAccountType)?;
+        let pin = field(request, "pin")?;
+        let currency = request.get("currency").and_then(json::Value::as_str).map(Currency::new).unwrap_or_default();
+        bank.create_account_in_currency(account_number.clone(), account_type, currency)?;
+        bank.set_pin(account_number, &pin)?;
+        Ok(ok())
+    }
+
+    fn transfer(bank: &mut Bank, request: &json::Value) -> Result<json::Value, BankError> {
+        let amount = amount(request)?;
+        bank.transfer_with_pin(field(request, "from")?, &field(request, "pin")?, field(request, "to")?, amount)?;
+        Ok(ok())
+    }
+
+    fn field(request: &json::Value, name: &str) -> Result<String, BankError> {
+        match request.get(name).and_then(json::Value::as_str) {
+            Some(value) => Ok(value.to_string()),
+            None => Err(BankError::InvalidData(format!("missing {}", name))),
+        }
+    }
+
+    fn amount(request: &json::Value) -> Result<Money, BankError> {
+        let amount = match request.get("amount") {
+            Some(json::Value::String(text)) => text.parse()?,
+            Some(value) => Money::from_json(value).ok_or(BankError::InvalidAmount)?,
+            None => return Err(BankError::InvalidData("missing amount".to_string())),
+        };
+        if amount <= Money::ZERO {
+            return Err(BankError::InvalidAmount);
+        }
+        Ok(amount)
+    }
+
+    fn ok() -> json::Value {
+        json::object(vec![("status", json::string("ok"))])
+    }
+
+    fn failure(status: u16, message: &str) -> (u16, String) {
+        (status, json::object(vec![("error", json::string(message))]).to_string())
+    }
+
+    fn status_for(error: &BankError) -> u16 {
+        match error {
+            BankError::AccountNotFound | BankError::TransactionNotFound => 404,
+            BankError::AccountExists => 409,
+            BankError::PinNotSet | BankError::IncorrectPin | BankError::AccountLocked => 403,
+            BankError::InvalidData(_) | BankError::InvalidAmount | BankError::Overflow => 400,
+            BankError::Io(_) => 500,
+            _ => 422,
+        }
+    }
+
+    fn reason(status: u16) -> &'static str {
+        match status {
+            200 => "OK",
+            201 => "Created",
+            400 => "Bad Request",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            409 => "Conflict",
+            413 => "Payload Too Large",
+            422 => "Unprocessable Entity",
+            _ => "Internal Server Error",
+        }
+    }
+}
+
+pub mod storage {
+    use super::{Bank, BankError};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    // Somewhere a bank's state outlives the process.
+    pub trait Storage {
+        // None when nothing has been saved yet.
+        fn load(&self) -> Result<Option<Bank>, BankError>;
+        fn save(&self, bank: &Bank) -> Result<(), BankError>;
+    }
+
+    // The JSON document written by Bank::save_to_file, kept at one path.
+    #[derive(Debug, Clone)]
+    pub struct JsonFile {
+        path: PathBuf,
+    }
+
+    impl JsonFile {
+        pub fn new(path: impl Into<PathBuf>) -> JsonFile {
+            JsonFile { path: path.into() }
+        }
+
+        pub fn path(&self) -> &Path {
+            &self.path
+        }
+    }
+
+    impl Storage for JsonFile {
+        fn load(&self) -> Result<Option<Bank>, BankError> {
+            if !self.path.exists() {
+                return Ok(None);
+            }
+            Bank::load_from_file(&self.path).map(Some)
+        }
+
+        // Written to a sibling file first and renamed over the old one, so a failed save
+        // leaves the previous state intact.
+        fn save(&self, bank: &Bank) -> Result<(), BankError> {
+            let mut staging = self.path.clone().into_os_string();
+            staging.push(".tmp");
+            let staging = PathBuf::from(staging);
+            bank.save_to_file(&staging)?;
+            fs::rename(&staging, &self.path).map_err(|e| BankError::Io(e.to_string()))
+        }
+    }
+}
+
+pub mod calendar {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    const SECONDS_PER_DAY: i64 = 86_400;
+
+    pub fn seconds_since_epoch(time: SystemTime) -> i64 {
+        match time.duration_since(UNIX_EPOCH) {
+            Ok(elapsed) => elapsed.as_secs() as i64,
+            Err(before) => -(before.duration().as_secs() as i64),
+        }
+    }
+
+    pub fn day_number(time: SystemTime) -> i64 {
+        seconds_since_epoch(time).div_euclid(SECONDS_PER_DAY)
+    }
+
+    // Whole days from `from` to `to` inclusive, as a fraction of a 365-day year.
+    pub fn years_between(from: SystemTime, to: SystemTime) -> f64 {
+        (day_number(to) - day_number(from) + 1).max(0) as f64 / 365.0
+    }
+
+    // Howard Hinnant's days-to-civil algorithm, proleptic Gregorian calendar.
+    pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+        (year, month, day)
+    }
+
+    // Inverse of civil_from_days.
+    pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+        let year = if month <= 2 { year - 1 } else { year };
+        let era = year.div_euclid(400);
+        let yoe = year.rem_euclid(400);
+        let mp = (month as i64 + 9) % 12;
+        let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        era * 146_097 + doe - 719_468
+    }
+
+    // Parses YYYY-MM-DD as midnight UTC, rejecting dates that do not exist.
+    pub fn parse_date(input: &str) -> Option<SystemTime> {
+        let mut parts = input.trim().splitn(3, '-');
+        let year: i64 = parts.next()?.parse().ok()?;
+        let month: u32 = parts.next()?.parse().ok()?;
+        let day: u32 = parts.next()?.parse().ok()?;
+        if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+            return None;
+        }
+        let days = days_from_civil(year, month, day);
+        if civil_from_days(days) != (year, month, day) {
+            return None;
+        }
+        Some(from_millis(days * SECONDS_PER_DAY * 1000))
+    }
+
+    pub fn hour_of_day(time: SystemTime) -> usize {
+        (seconds_since_epoch(time).rem_euclid(SECONDS_PER_DAY) / 3600) as usize
+    }
+
+    pub fn days_in_month(year: i64, month: u32) -> u32 {
+        let (next_year, next_month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+        (days_from_civil(next_year, next_month, 1) - days_from_civil(year, month, 1)) as u32
+    }
+
+    // Midnight UTC on `day` of the month, or on its last day when the month is shorter; the
+    // first such date at or after `from`.
+    pub fn monthly_on_or_after(from: SystemTime, day: u32) -> SystemTime {
+        let (mut year, mut month) = year_month(from);
+        loop {
+            let days = days_from_civil(year, month, day.min(days_in_month(year, month)));
+            let date = from_millis(days * SECONDS_PER_DAY * 1000);
+            if date >= from {
+                return date;
+            }
+            (year, month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+        }
+    }
+
+    // Midnight UTC on the first of the month.
+    pub fn month_start(year: i64, month: u32) -> SystemTime {
+        from_millis(days_from_civil(year, month, 1) * SECONDS_PER_DAY * 1000)
+    }
+
+    pub fn year_month(time: SystemTime) -> (i64, u32) {
+        let (year, month, _) = civil_from_days(day_number(time));
+        (year, month)
+    }
+
+    pub fn format_date(time: SystemTime) -> String {
+        let (year, month, day) = civil_from_days(day_number(time));
+        format!("{:04}-{:02}-{:02}", year, month, day)
+    }
+
+    pub fn format_datetime(time: SystemTime) -> String {
+        let seconds = seconds_since_epoch(time).rem_euclid(SECONDS_PER_DAY);
+        format!("{} {:02}:{:02}:{:02}", format_date(time), seconds / 3600, seconds / 60 % 60, seconds % 60)
+    }
+
+    pub fn to_millis(time: SystemTime) -> i64 {
+        match time.duration_since(UNIX_EPOCH) {
+            Ok(elapsed) => elapsed.as_millis() as i64,
+            Err(before) => -(before.duration().as_millis() as i64),
+        }
+    }
+
+    pub fn from_millis(millis: i64) -> SystemTime {
+        if millis >= 0 {
+            UNIX_EPOCH + Duration::from_millis(millis as u64)
+        } else {
+            UNIX_EPOCH - Duration::from_millis(millis.unsigned_abs())
+        }
+    }
+}
+
+mod sha256 {
+    const K: [u32; 64] = [
+        0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5, 0xd807aa98, 0x12835b01,
+        0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc,
+        0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da, 0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147,
+        0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+        0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070, 0x19a4c116, 0x1e376c08,
+        0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3, 0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208,
+        0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+    ];
+
+    pub fn digest(data: &[u8]) -> [u8; 32] {
+        let mut state: [u32; 8] = [
+            0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+        ];
+
+        let mut message = data.to_vec();
+        message.push(0x80);
+        while message.len() % 64 != 56 {
+            message.push(0);
+        }
+        message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+        for block in message.chunks(64) {
+            let mut w = [0u32; 64];
+            for (i, word) in block.chunks(4).enumerate() {
+                w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+            }
+            for i in 16..64 {
+                let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+                let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+                w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+            }
+
+            let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+            for i in 0..64 {
+                let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+                let ch = (e & f) ^ (!e & g);
+                let t1 = h.wrapping_add(s1).wrapping_add(ch).wrapping_add(K[i]).wrapping_add(w[i]);
+                let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+                let maj = (a & b) ^ (a & c) ^ (b & c);
+                let t2 = s0.wrapping_add(maj);
+                h = g;
+                g = f;
+                f = e;
+                e = d.wrapping_add(t1);
+                d = c;
+                c = b;
+                b = a;
+                a = t1.wrapping_add(t2);
+            }
+            for (word, value) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+                *word = word.wrapping_add(value);
+            }
+        }
+
+        let mut out = [0u8; 32];
+        for (chunk, word) in out.chunks_mut(4).zip(state) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+        out
+    }
+
+    pub fn hex_digest(data: &[u8]) -> String {
+        digest(data).iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+}
+
+mod json {
+    use std::fmt;
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum Value {
+        Null,
+        Bool(bool),
+        Number(f64),
+        String(String),
+        Array(Vec<Value>),
+        Object(Vec<(String, Value)>),
+    }
+
+    impl Value {
+        pub fn get(&self, key: &str) -> Option<&Value> {
+            match self {
+                Value::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+                _ => None,
+            }
+        }
+
+        pub fn as_f64(&self) -> Option<f64> {
+            match self {
+                Value::Number(n) => Some(*n),
+                _ => None,
+            }
+        }
+
+        pub fn as_str(&self) -> Option<&str> {
+            match self {
+                Value::String(s) => Some(s),
+                _ => None,
+            }
+        }
+
+        pub fn as_bool(&self) -> Option<bool> {
+            match self {
+                Value::Bool(b) => Some(*b),
+                _ => None,
+            }
+        }
+
+        pub fn as_array(&self) -> Option<&Vec<Value>> {
+            match self {
+                Value::Array(items) => Some(items),
+                _ => None,
+            }
+        }
+    }
+
+    pub fn object(fields: Vec<(&str, Value)>) -> Value {
+        Value::Object(fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+    }
+
+    pub fn string(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+
+    fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+        write!(f, "\"")?;
+        for c in s.chars() {
+            match c {
+                '"' => write!(f, "\\\"")?,
+                '\\' => write!(f, "\\\\")?,
+                '\n' => write!(f, "\\n")?,
+                '\r' => write!(f, "\\r")?,
+                '\t' => write!(f, "\\t")?,
+                c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+                c => write!(f, "{}", c)?,
+            }
+        }
+        write!(f, "\"")
+    }
+
+    impl fmt::Display for Value {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                Value::Null => write!(f, "null"),
+                Value::Bool(b) => write!(f, "{}", b),
+                Value::Number(n) if n.is_finite() => write!(f, "{}", n),
+                Value::Number(_) => write!(f, "null"),
+                Value::String(s) => write_string(f, s),
+                Value::Array(items) => {
+                    write!(f, "[")?;
+                    for (i, item) in items.iter().enumerate() {
+                        if i > 0 {
+                            write!(f, ",")?;
+                        }
+                        write!(f, "{}", item)?;
+                    }
+                    write!(f, "]")
+                }
+                Value::Object(fields) => {
+                    write!(f, "{{")?;
+                    for (i, (key, value)) in fields.iter().enumerate() {
+                        if i > 0 {
+                            write!(f, ",")?;
+                        }
+                        write_string(f, key)?;
+                        write!(f, ":{}", value)?;
+                    }
+                    write!(f, "}}")
+                }
+            }
+        }
+    }
+
+    pub fn parse(input: &str) -> Result<Value, String> {
+        let mut parser = Parser {
+            chars: input.chars().collect(),
+            pos: 0,
+            depth: 0,
+        };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.pos != parser.chars.len() {
+            return Err(format!("unexpected trailing data at offset {}", parser.pos));
+        }
+        Ok(value)
+    }
+
+    // Arrays and objects are parsed recursively, so nesting is capped well short of the stack.
+    const MAX_DEPTH: usize = 128;
+
+    struct Parser {
+        chars: Vec<char>,
+        pos: usize,
+        depth: usize,
+    }
+
+    impl Parser {
+        fn skip_whitespace(&mut self) {
+            while self.pos < self.chars.len() && self.chars[self.pos].is_whitespace() {
+                self.pos += 1;
+            }
+        }
+
+        fn peek(&self) -> Option<char> {
+            self.chars.get(self.pos).copied()
+        }
+
+        fn expect(&mut self, expected: char) -> Result<(), String> {
+            if self.peek() == Some(expected) {
+                self.pos += 1;
+                Ok(())
+            } else {
+                Err(format!("expected '{}' at offset {}", expected, self.pos))
+            }
+        }
+
+        fn literal(&mut self, word: &str, value: Value) -> Result<Value, String> {
+            let end = self.pos + word.len();
+            if end <= self.chars.len() && self.chars[self.pos..end].iter().copied().eq(word.chars()) {
+                self.pos = end;
+                Ok(value)
+            } else {
+                Err(format!("invalid literal at offset {}", self.pos))
+            }
+        }
+
+        fn value(&mut self) -> Result<Value, String> {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('n') => self.literal("null", Value::Null),
+                Some('t') => self.literal("true", Value::Bool(true)),
+                Some('f') => self.literal("false", Value::Bool(false)),
+                Some('"') => self.string().map(Value::String),
+                Some(c @ ('[' | '{')) => {
+                    if self.depth == MAX_DEPTH {
+                        return Err(format!("nested deeper than {} at offset {}", MAX_DEPTH, self.pos));
+                    }
+                    self.depth += 1;
+                    let value = if c == '[' { self.array() } else { self.object() };
+                    self.depth -= 1;
+                    value
+                }
+                Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+                Some(c) => Err(format!("unexpected '{}' at offset {}", c, self.pos)),
+                None => Err("unexpected end of input".to_string()),
+            }
+        }
+
+        fn number(&mut self) -> Result<Value, String> {
+            let start = self.pos;
+            while let Some(c) = self.peek() {
+                if c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E') {
+                    self.pos += 1;
+                } else {
+                    break;
+                }
+            }
+            let text: String = self.chars[start..self.pos].iter().collect();
+            text.parse().map(Value::Number).map_err(|_| format!("invalid number at offset {}", start))
+        }
+
+        fn hex4(&mut self) -> Result<u32, String> {
+            let end = self.pos + 4;
+            if end > self.chars.len() {
+                return Err("truncated unicode escape".to_string());
+            }
+            let text: String = self.chars[self.pos..end].iter().collect();
+            self.pos = end;
+            u32::from_str_radix(&text, 16).map_err(|_| format!("invalid unicode escape at offset {}", self.pos - 4))
+        }
+
+        fn string(&mut self) -> Result<String, String> {
+            self.expect('"')?;
+            let mut out = String::new();
+            loop {
+                let c = self.peek().ok_or("unterminated string")?;
+                self.pos += 1;
+                match c {
+                    '"' => return Ok(out),
+                    '\\' => {
+                        let escape = self.peek().ok_or("unterminated escape")?;
+                        self.pos += 1;
+                        match escape {
+                            '"' => out.push('"'),
+                            '\\' => out.push('\\'),
+                            '/' => out.push('/'),
+                            'b' => out.push('\u{8}'),
+                            'f' => out.push('\u{c}'),
+                            'n' => out.push('\n'),
+                            'r' => out.push('\r'),
+                            't' => out.push('\t'),
+                            'u' => {
+                                let mut code = self.hex4()?;
+                                if (0xD800..0xDC00).contains(&code) {
+                                    self.expect('\\')?;
+                                    self.expect('u')?;
+                                    let low = self.hex4()?;
+                                    code = 0x10000 + ((code - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF);
+                                }
+                                out.push(char::from_u32(code).ok_or("invalid unicode escape")?);
+                            }
+                            other => return Err(format!("invalid escape '\\{}'", other)),
+                        }
+                    }
+                    c => out.push(c),
+                }
+            }
+        }
+
+        fn array(&mut self) -> Result<Value, String> {
+            self.expect('[')?;
+            let mut items = Vec::new();
+            self.skip_whitespace();
+            if self.peek() == Some(']') {
+                self.pos += 1;
+                return Ok(Value::Array(items));
+            }
+            loop {
+                items.push(self.value()?);
+                self.skip_whitespace();
+                match self.peek() {
+                    Some(',') => self.pos += 1,
+                    Some(']') => {
+                        self.pos += 1;
+                        return Ok(Value::Array(items));
+                    }
+                    _ => return Err(format!("expected ',' or ']' at offset {}", self.pos)),
+                }
+            }
+        }
+
+        fn object(&mut self) -> Result<Value, String> {
+            self.expect('{')?;
+            let mut fields = Vec::new();
+            self.skip_whitespace();
+            if self.peek() == Some('}') {
+                self.pos += 1;
+                return Ok(Value::Object(fields));
+            }
+            loop {
+                self.skip_whitespace();
+                let key = self.string()?;
+                self.skip_whitespace();
+                self.expect(':')?;
+                fields.push((key, self.value()?));
+                self.skip_whitespace();
+                match self.peek() {
+                    Some(',') => self.pos += 1,
+                    Some('}') => {
+                        self.pos += 1;
+                        return Ok(Value::Object(fields));
+                    }
+                    _ => return Err(format!("expected ',' or '}}' at offset {}", self.pos)),
+                }
+            }
+        }
+    }
+}
@@ -1,333 +1,1190 @@
-use std::collections::HashMap;
-use std::io;
+use bank::account::{AccountStatus, AccountType, Currency, StatementFormat};
+use bank::auth::PinStatus;
+use bank::bank::{AccountFilter, Bank, Money, Period};
+use bank::calendar;
+use bank::server;
+use bank::storage::{JsonFile, Storage};
+use std::fs::File;
+use std::net::TcpListener;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
 
-#[derive(Debug, Clone)]
-enum AccountType {
-    Checking,
-    Savings,
-    Credit,
-}
-
-struct Account {
-    balance: f64,
-    account_type: AccountType,
-    transactions: Vec<Transaction>,
-    is_active: bool,
-}
+const DATA_FILE: &str = "bank.json";
+// Every change the bank makes, one JSON event per line, for rebuilding it with Bank::replay.
+const EVENT_LOG: &str = "bank-events.jsonl";
 
-impl Account {
-    fn new(account_type: AccountType) -> Account {
-        Account {
-            balance: 0.0,
-            account_type,
-            transactions: Vec::new(),
-            is_active: true,
+fn main() {
+    let storage = JsonFile::new(DATA_FILE);
+    let mut bank = match storage.load() {
+        Ok(bank) => bank.unwrap_or_default(),
+        Err(error) => {
+            // Starting empty here would let the next save overwrite the broken file.
+            eprintln!("Could not load {}: {}", DATA_FILE, error);
+            std::process::exit(1);
+        }
+    };
+    if let Err(error) = bank.set_event_log(Path::new(EVENT_LOG)) {
+        eprintln!("Could not open {}: {}", EVENT_LOG, error);
+        std::process::exit(1);
+    }
+    if std::env::var("BANK_LOCALE").is_ok_and(|locale| locale.eq_ignore_ascii_case("eu")) {
+        menu::set_money_format(menu::MoneyFormat::EUROPEAN);
+    }
+    // On stderr, so they stay out of the output of scripted commands.
+    for (id, result) in bank.process_due_payments(SystemTime::now()) {
+        match result {
+            Ok(()) => eprintln!("Scheduled payment {} made.", id),
+            Err(error) => eprintln!("Scheduled payment {} failed: {}!", id, error),
         }
     }
 
-    fn deposit(&mut self, amount: f64) {
-        if self.is_active {
-            self.balance += amount;
-            self.transactions.push(Transaction::Deposit(amount));
-        } else {
-            println!("Account is inactive!");
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        None => bank.set_actor(&actor("menu")),
+        Some("serve") => {
+            bank.set_actor(&actor("http"));
+            serve(&mut bank, &storage, &args[1..]);
+            return;
+        }
+        Some(_) => {
+            bank.set_actor(&actor("cli"));
+            std::process::exit(cli::run(&mut bank, &storage, &args));
         }
     }
 
-    fn withdraw(&mut self, amount: f64) -> bool {
-        if self.is_active {
-            if amount > self.balance {
-                false
-            } else {
-                self.balance -= amount;
-                self.transactions.push(Transaction::Withdrawal(amount));
-                true
+    loop {
+        println!("1. Create Account");
+        println!("2. Deposit");
+        println!("3. Withdraw");
+        println!("4. Check Balance");
+        println!("5. Transfer");
+        println!("6. Get Account Type");
+        println!("7. Get Transactions");
+        println!("8. Unfreeze Account");
+        println!("9. Freeze Account");
+        println!("10. Statement");
+        println!("11. Export CSV");
+        println!("12. Save");
+        println!("13. Close Account");
+        println!("14. Accrue Interest");
+        println!("15. Export Statement");
+        println!("16. Schedule Payment");
+        println!("17. List Scheduled Payments");
+        println!("18. Cancel Scheduled Payment");
+        println!("19. Set Exchange Rate");
+        println!("20. Set PIN");
+        println!("21. View Audit Log");
+        println!("22. Reports");
+        println!("23. Reverse Transaction");
+        println!("24. Save and Exit");
+
+        let choice = menu::select("Enter your choice: ");
+        if menu::at_eof() {
+            save(&bank, &storage);
+            break;
+        }
+        match choice {
+            Some(1) => create_account(&mut bank),
+            Some(2) => deposit(&mut bank),
+            Some(3) => withdraw(&mut bank),
+            Some(4) => check_balance(&bank),
+            Some(5) => transfer(&mut bank),
+            Some(6) => get_account_type(&bank),
+            Some(7) => get_transactions(&bank),
+            Some(8) => unfreeze_account(&mut bank),
+            Some(9) => freeze_account(&mut bank),
+            Some(10) => statement(&bank),
+            Some(11) => export_csv(&bank),
+            Some(12) => save(&bank, &storage),
+            Some(13) => close_account(&mut bank),
+            Some(14) => accrue_interest(&mut bank),
+            Some(15) => export_statement(&bank),
+            Some(16) => schedule_payment(&mut bank),
+            Some(17) => list_scheduled_payments(&bank),
+            Some(18) => cancel_scheduled_payment(&mut bank),
+            Some(19) => set_exchange_rate(&mut bank),
+            Some(20) => set_pin(&mut bank),
+            Some(21) => view_audit_log(&bank),
+            Some(22) => reports(&bank),
+            Some(23) => reverse_transaction(&mut bank),
+            Some(24) => {
+                save(&bank, &storage);
+                break;
             }
-        } else {
-            println!("Account is inactive!");
-            false
+            _ => println!("Invalid choice!"),
         }
     }
+}
 
-    fn balance(&self) -> f64 {
-        self.balance
+// Audit records name the local user as well as the interface the operation came through.
+fn actor(channel: &str) -> String {
+    match std::env::var("USER") {
+        Ok(user) if !user.is_empty() => format!("{}@{}", user, channel),
+        _ => channel.to_string(),
     }
+}
 
-    fn transactions(&self) -> &Vec<Transaction> {
-        &self.transactions
+// `serve [--port N]` answers HTTP requests one at a time on localhost until killed, saving
+// after every request that changed the bank.
+fn serve(bank: &mut Bank, storage: &JsonFile, args: &[String]) {
+    let port: u16 = match args {
+        [] => 8080,
+        [flag, port] if flag == "--port" => match port.parse() {
+            Ok(port) => port,
+            Err(_) => {
+                eprintln!("Invalid port: {}", port);
+                std::process::exit(2);
+            }
+        },
+        _ => {
+            eprintln!("Usage: bank serve [--port N]");
+            std::process::exit(2);
+        }
+    };
+    let listener = match TcpListener::bind(("127.0.0.1", port)) {
+        Ok(listener) => listener,
+        Err(error) => {
+            eprintln!("Could not listen on port {}: {}", port, error);
+            std::process::exit(1);
+        }
+    };
+    println!("Listening on http://127.0.0.1:{}", port);
+    for stream in listener.incoming() {
+        let changed = stream.and_then(|mut stream| server::handle_connection(bank, &mut stream));
+        match changed {
+            Ok(true) => save(bank, storage),
+            Ok(false) => {}
+            Err(error) => eprintln!("Connection failed: {}", error),
+        }
     }
+}
 
-    fn activate(&mut self) {
-        self.is_active = true;
-    }
+fn create_account(bank: &mut Bank) {
+    let account_number = menu::input("Enter account number: ");
+    let account_type = match menu::select("Enter account type (1. Checking, 2. Savings, 3. Credit): ") {
+        Some(1) => AccountType::Checking,
+        Some(2) => AccountType::Savings,
+        Some(3) => AccountType::Credit,
+        _ => {
+            println!("Invalid account type!");
+            return;
+        }
+    };
 
-    fn deactivate(&mut self) {
-        self.is_active = false;
+    let limit_prompt = match account_type {
+        AccountType::Checking => "Enter overdraft allowance (0 for none): ",
+        AccountType::Savings => "Enter minimum balance: ",
+        AccountType::Credit => "Enter credit limit: ",
+    };
+    let limit = match menu::amount(limit_prompt) {
+        Some(limit) => limit,
+        None => return,
+    };
+    let currency = match menu::input("Enter currency code (leave empty for USD): ").as_str() {
+        "" => Currency::default(),
+        code => Currency::new(code),
+    };
+    let pin = match read_new_pin() {
+        Some(pin) => pin,
+        None => return,
+    };
+    let created = bank
+        .create_account_in_currency(account_number.clone(), account_type, currency)
+        .and_then(|()| bank.set_account_limit(account_number.clone(), limit))
+        .and_then(|()| bank.set_pin(account_number, &pin));
+    match created {
+        Ok(()) => println!("Account created successfully!"),
+        Err(error) => println!("{}!", error),
     }
 }
 
-#[derive(Debug, Clone)]
-enum Transaction {
-    Deposit(f64),
-    Withdrawal(f64),
-    Transfer(f64, String),
+fn deposit(bank: &mut Bank) {
+    let account_number = menu::input("Enter account number: ");
+    let amount = match menu::amount("Enter amount to deposit: ") {
+        Some(amount) => amount,
+        None => return,
+    };
+
+    match bank.deposit(account_number, amount) {
+        Ok(()) => println!("Deposit successful!"),
+        Err(error) => println!("{}!", error),
+    }
 }
 
-struct Bank {
-    accounts: HashMap<String, Account>,
+fn withdraw(bank: &mut Bank) {
+    let account_number = menu::input("Enter account number: ");
+    let pin = menu::input("Enter PIN: ");
+    let amount = match menu::amount("Enter amount to withdraw: ") {
+        Some(amount) => amount,
+        None => return,
+    };
+
+    match bank.withdraw_with_pin(account_number, &pin, amount) {
+        Ok(()) => println!("Withdrawal successful!"),
+        Err(error) => println!("{}!", error),
+    }
 }
 
-impl Bank {
-    fn new() -> Bank {
-        Bank {
-            accounts: HashMap::new(),
+fn check_balance(bank: &Bank) {
+    let account_number = menu::input("Enter account number: ");
+    match bank.account(&account_number) {
+        Some(account) => {
+            println!("Balance: {}", account.balance().format_in(account.currency()));
+            if account.available_balance() != account.balance() {
+                println!("Available: {}", account.available_balance().format_in(account.currency()));
+            }
+            if account.status() != AccountStatus::Open {
+                println!("Status: {}", account.status().name());
+            }
         }
+        None => println!("Account not found!"),
     }
+}
 
-    fn create_account(&mut self, account_number: String, account_type: AccountType) {
-        self.accounts.insert(account_number, Account::new(account_type));
+fn transfer(bank: &mut Bank) {
+    let from_account = menu::input("Enter account number to transfer from: ");
+    let pin = menu::input("Enter PIN: ");
+    let to_account = menu::input("Enter account number to transfer to: ");
+    let amount = match menu::amount("Enter amount to transfer: ") {
+        Some(amount) => amount,
+        None => return,
+    };
+
+    match bank.transfer_with_pin(from_account, &pin, to_account, amount) {
+        Ok(()) => println!("Transfer successful!"),
+        Err(error) => println!("{}!", error),
     }
+}
 
-    fn deposit(&mut self, account_number: String, amount: f64) -> bool {
-        if let Some(account) = self.accounts.get_mut(&account_number) {
-            account.deposit(amount);
-            true
-        } else {
-            false
-        }
+fn get_account_type(bank: &Bank) {
+    let account_number = menu::input("Enter account number: ");
+    if let Some(account_type) = bank.get_account_type(account_number) {
+        println!("Account Type: {:?}", account_type);
+    } else {
+        println!("Account not found!");
     }
+}
 
-    fn withdraw(&mut self, account_number: String, amount: f64) -> bool {
-        if let Some(account) = self.accounts.get_mut(&account_number) {
-            account.withdraw(amount)
-        } else {
-            false
+fn get_transactions(bank: &Bank) {
+    let account_number = menu::input("Enter account number: ");
+    if let Some(transactions) = bank.get_transactions(account_number) {
+        println!("Transactions:");
+        for (i, transaction) in transactions.iter().enumerate() {
+            println!("{}: {:?}", i + 1, transaction);
         }
+    } else {
+        println!("Account not found!");
     }
+}
 
-    fn balance(&self, account_number: String) -> Option<f64> {
-        if let Some(account) = self.accounts.get(&account_number) {
-            Some(account.balance())
-        } else {
-            None
-        }
+fn unfreeze_account(bank: &mut Bank) {
+    let account_number = menu::input("Enter account number: ");
+    match bank.unfreeze_account(account_number) {
+        Ok(()) => println!("Account unfrozen successfully!"),
+        Err(error) => println!("{}!", error),
     }
+}
 
-    fn transfer(&mut self, from_account: String, to_account: String, amount: f64) -> bool {
-        if let (Some(from), Some(to)) = (self.accounts.get_mut(&from_account), self.accounts.get_mut(&to_account)) {
-            if from.withdraw(amount) {
-                to.deposit(amount);
-                from.transactions.push(Transaction::Transfer(amount, to_account.clone()));
-                to.transactions.push(Transaction::Transfer(amount, from_account.clone()));
-                true
-            } else {
-                false
-            }
-        } else {
-            false
-        }
+fn freeze_account(bank: &mut Bank) {
+    let account_number = menu::input("Enter account number: ");
+    let pin = menu::input("Enter PIN: ");
+    match bank.deactivate_with_pin(account_number, &pin) {
+        Ok(()) => println!("Account frozen successfully!"),
+        Err(error) => println!("{}!", error),
     }
+}
 
-    fn get_account_type(&self, account_number: String) -> Option<AccountType> {
-        if let Some(account) = self.accounts.get(&account_number) {
-            Some(account.account_type.clone())
-        } else {
-            None
+fn close_account(bank: &mut Bank) {
+    let account_number = menu::input("Enter account number: ");
+    let sweep_to = menu::input("Enter account to move the remaining balance to (leave empty for none): ");
+    let closed = if sweep_to.is_empty() {
+        bank.close_account(&account_number)
+    } else {
+        bank.close_account_sweeping(&account_number, sweep_to)
+    };
+    match closed {
+        Ok(()) => println!("Account closed successfully!"),
+        Err(error) => println!("{}!", error),
+    }
+}
+
+fn reverse_transaction(bank: &mut Bank) {
+    let account_number = menu::input("Enter account number: ");
+    let id = match menu::input("Enter transaction ID: ").parse() {
+        Ok(id) => id,
+        Err(_) => {
+            println!("Invalid transaction ID!");
+            return;
         }
+    };
+    match bank.reverse_transaction(account_number, id) {
+        Ok(()) => println!("Transaction reversed successfully!"),
+        Err(error) => println!("{}!", error),
     }
+}
 
-    fn get_transactions(&self, account_number: String) -> Option<&Vec<Transaction>> {
-        if let Some(account) = self.accounts.get(&account_number) {
-            Some(account.transactions())
-        } else {
+fn read_period() -> Option<Period> {
+    let dates = (
+        calendar::parse_date(&menu::input("Enter start date (YYYY-MM-DD): ")),
+        calendar::parse_date(&menu::input("Enter end date (YYYY-MM-DD): ")),
+    );
+    match dates {
+        // The end date is inclusive, so the range runs to the last instant of that day.
+        (Some(from), Some(to)) => Some(Period {
+            from,
+            to: to + Duration::from_secs(86_400) - Duration::from_nanos(1),
+        }),
+        _ => {
+            println!("Invalid date!");
             None
         }
     }
+}
 
-    fn activate_account(&mut self, account_number: String) -> bool {
-        if let Some(account) = self.accounts.get_mut(&account_number) {
-            account.activate();
-            true
-        } else {
-            false
+fn statement(bank: &Bank) {
+    let account_number = menu::input("Enter account number: ");
+    let Period { from, to } = match read_period() {
+        Some(period) => period,
+        None => return,
+    };
+
+    if let Some(transactions) = bank.statement(account_number, from, to) {
+        println!("Statement:");
+        for transaction in transactions {
+            match transaction.kind.counterparty() {
+                Some(other) => println!(
+                    "{} {} {} ({})",
+                    calendar::format_datetime(transaction.timestamp),
+                    transaction.kind.name(),
+                    transaction.kind.amount(),
+                    other
+                ),
+                None => println!(
+                    "{} {} {}",
+                    calendar::format_datetime(transaction.timestamp),
+                    transaction.kind.name(),
+                    transaction.kind.amount()
+                ),
+            }
         }
+    } else {
+        println!("Account not found!");
     }
+}
 
-    fn deactivate_account(&mut self, account_number: String) -> bool {
-        if let Some(account) = self.accounts.get_mut(&account_number) {
-            account.deactivate();
-            true
+fn accrue_interest(bank: &mut Bank) {
+    for account_type in [AccountType::Checking, AccountType::Savings, AccountType::Credit] {
+        let prompt = format!("Enter annual rate for {} accounts (%): ", account_type.name());
+        match menu::percentage(&prompt) {
+            Some(rate) => bank.set_type_interest_rate(account_type, rate),
+            None => return,
+        }
+    }
+    let period = match read_period() {
+        Some(period) => period,
+        None => return,
+    };
+    let posted = bank.accrue_interest(&period);
+    if posted.is_empty() {
+        println!("No interest to post.");
+    }
+    for (number, amount) in posted {
+        if amount < Money::ZERO {
+            println!("{}: charged {}", number, -amount);
         } else {
-            false
+            println!("{}: paid {}", number, amount);
         }
     }
 }
 
-fn main() {
-    let mut bank = Bank::new();
-
-    loop {
-        println!("1. Create Account");
-        println!("2. Deposit");
-        println!("3. Withdraw");
-        println!("4. Check Balance");
-        println!("5. Transfer");
-        println!("6. Get Account Type");
-        println!("7. Get Transactions");
-        println!("8. Activate Account");
-        println!("9. Deactivate Account");
-        println!("10. Exit");
-
-        match menu::select("Enter your choice: ") {
-            1 => create_account(&mut bank),
-            2 => deposit(&mut bank),
-            3 => withdraw(&mut bank),
-            4 => check_balance(&bank),
-            5 => transfer(&mut bank),
-            6 => get_account_type(&bank),
-            7 => get_transactions(&bank),
-            8 => activate_account(&mut bank),
-            9 => deactivate_account(&mut bank),
-            10 => break,
-            _ => println!("Invalid choice!"),
-        }
+fn export_csv(bank: &Bank) {
+    let account_number = menu::input("Enter account number: ");
+    let path = menu::input("Enter file to write: ");
+    match bank.export_csv(account_number, Path::new(&path)) {
+        Ok(()) => println!("Transactions exported to {}!", path),
+        Err(error) => println!("Failed to export transactions: {}!", error),
     }
 }
 
-fn create_account(bank: &mut Bank) {
+fn export_statement(bank: &Bank) {
     let account_number = menu::input("Enter account number: ");
-    let account_type = match menu::select("Enter account type (1. Checking, 2. Savings, 3. Credit): ") {
-        1 => AccountType::Checking,
-        2 => AccountType::Savings,
-        3 => AccountType::Credit,
+    let period = match read_period() {
+        Some(period) => period,
+        None => return,
+    };
+    let format = match menu::select("Enter format (1. CSV, 2. JSON): ") {
+        Some(1) => StatementFormat::Csv,
+        Some(2) => StatementFormat::Json,
         _ => {
-            println!("Invalid account type!");
+            println!("Invalid format!");
+            return;
+        }
+    };
+    let path = menu::input("Enter file to write: ");
+    let mut file = match File::create(&path) {
+        Ok(file) => file,
+        Err(error) => {
+            println!("Failed to create {}: {}!", path, error);
             return;
         }
     };
+    match bank.export_statement(account_number, &period, format, &mut file) {
+        Ok(()) => println!("{} statement written to {}!", format.name(), path),
+        Err(error) => println!("Failed to export statement: {}!", error),
+    }
+}
 
-    bank.create_account(account_number, account_type);
-    println!("Account created successfully!");
+fn schedule_payment(bank: &mut Bank) {
+    let from_account = menu::input("Enter account to pay from (leave empty for a deposit): ");
+    let to_account = menu::input("Enter account to pay into: ");
+    let amount = match menu::amount("Enter amount: ") {
+        Some(amount) => amount,
+        None => return,
+    };
+    let day = match menu::select("Enter day of the month (1-31): ") {
+        Some(day) => day as u32,
+        None => {
+            println!("Invalid day!");
+            return;
+        }
+    };
+    let from_account = Some(from_account).filter(|number| !number.is_empty());
+    match bank.schedule_recurring(from_account, to_account, amount, day, SystemTime::now()) {
+        Ok(id) => println!("Scheduled payment {} created!", id),
+        Err(error) => println!("{}!", error),
+    }
 }
 
-fn deposit(bank: &mut Bank) {
-    let account_number = menu::input("Enter account number: ");
-    let amount = match menu::float("Enter amount to deposit: ") {
-        Ok(amount) => amount,
+fn list_scheduled_payments(bank: &Bank) {
+    if bank.recurring_payments().is_empty() {
+        println!("No scheduled payments.");
+    }
+    for (id, payment) in bank.recurring_payments() {
+        let what = match &payment.from_account {
+            Some(from_account) => format!("Transfer of {} from {} to {}", payment.amount, from_account, payment.to_account),
+            None => format!("Deposit of {} into {}", payment.amount, payment.to_account),
+        };
+        println!(
+            "{}. {} on day {} of each month, next on {}",
+            id,
+            what,
+            payment.day_of_month,
+            calendar::format_date(payment.next_due)
+        );
+    }
+}
+
+fn cancel_scheduled_payment(bank: &mut Bank) {
+    let id = match menu::input("Enter scheduled payment number: ").parse() {
+        Ok(id) => id,
         Err(_) => {
-            println!("Invalid amount!");
+            println!("Invalid number!");
             return;
         }
     };
+    match bank.cancel_recurring(id) {
+        Ok(()) => println!("Scheduled payment cancelled!"),
+        Err(error) => println!("{}!", error),
+    }
+}
 
-    if bank.deposit(account_number, amount) {
-        println!("Deposit successful!");
-    } else {
-        println!("Account not found!");
+fn set_exchange_rate(bank: &mut Bank) {
+    let base = Currency::new(&menu::input("Enter currency to convert from: "));
+    let quote = Currency::new(&menu::input("Enter currency to convert to: "));
+    if base.code().is_empty() || quote.code().is_empty() || base == quote {
+        println!("Invalid currency pair!");
+        return;
     }
+    let prompt = format!("Enter {} per 1 {}: ", quote.code(), base.code());
+    let rate = match menu::rate(&prompt) {
+        Some(rate) => rate,
+        None => return,
+    };
+    bank.set_exchange_rate(base, quote, rate, rate);
+    println!("Exchange rate set!");
 }
 
-fn withdraw(bank: &mut Bank) {
+// Accounts created before PINs existed get their first one here without a check; changing a
+// PIN needs the current one.
+fn set_pin(bank: &mut Bank) {
     let account_number = menu::input("Enter account number: ");
-    let amount = match menu::float("Enter amount to withdraw: ") {
-        Ok(amount) => amount,
-        Err(_) => {
-            println!("Invalid amount!");
+    let status = match bank.pin_status(&account_number) {
+        Ok(status) => status,
+        Err(error) => {
+            println!("{}!", error);
             return;
         }
     };
+    let current = match status {
+        PinStatus::NotSet => None,
+        PinStatus::Unlocked { .. } | PinStatus::Locked => Some(menu::input("Enter current PIN: ")),
+    };
+    let pin = match read_new_pin() {
+        Some(pin) => pin,
+        None => return,
+    };
+    let changed = match current {
+        Some(current) => bank.change_pin(account_number, &current, &pin),
+        None => bank.set_pin(account_number, &pin),
+    };
+    match changed {
+        Ok(()) => println!("PIN set!"),
+        Err(error) => println!("{}!", error),
+    }
+}
 
-    if bank.withdraw(account_number, amount) {
-        println!("Withdrawal successful!");
-    } else {
-        println!("Insufficient balance or account not found!");
+fn view_audit_log(bank: &Bank) {
+    let account_number = menu::input("Enter account number (leave empty for all): ");
+    let records = bank.audit_records(Some(account_number.as_str()).filter(|number| !number.is_empty()), None);
+    if records.is_empty() {
+        println!("No audit records!");
+        return;
+    }
+    for record in &records {
+        println!("{}", cli::format_audit_record(record));
+    }
+    let path = menu::input("Enter file to export to (leave empty to skip): ");
+    if path.is_empty() {
+        return;
+    }
+    let exported = File::create(&path)
+        .map_err(|error| error.to_string())
+        .and_then(|mut file| bank.export_audit_log(&records, &mut file).map_err(|error| error.to_string()));
+    match exported {
+        Ok(()) => println!("Audit log exported to {}!", path),
+        Err(error) => println!("Failed to export audit log: {}!", error),
     }
 }
 
-fn check_balance(bank: &Bank) {
-    let account_number = menu::input("Enter account number: ");
-    if let Some(balance) = bank.balance(account_number) {
-        println!("Balance: {}", balance);
-    } else {
-        println!("Account not found!");
+fn reports(bank: &Bank) {
+    println!("1. Find Accounts");
+    println!("2. Dormant Accounts");
+    println!("3. Deposits by Account Type");
+    match menu::select("Enter report: ") {
+        Some(1) => find_accounts(bank),
+        Some(2) => dormant_accounts(bank),
+        Some(3) => deposits_by_type(bank),
+        _ => println!("Invalid choice!"),
     }
 }
 
-fn transfer(bank: &mut Bank) {
-    let from_account = menu::input("Enter account number to transfer from: ");
-    let to_account = menu::input("Enter account number to transfer to: ");
-    let amount = match menu::float("Enter amount to transfer: ") {
-        Ok(amount) => amount,
-        Err(_) => {
-            println!("Invalid amount!");
+fn find_accounts(bank: &Bank) {
+    let account_type = match menu::input("Enter account type (1. Checking, 2. Savings, 3. Credit, empty for any): ").as_str() {
+        "" => None,
+        "1" => Some(AccountType::Checking),
+        "2" => Some(AccountType::Savings),
+        "3" => Some(AccountType::Credit),
+        _ => {
+            println!("Invalid account type!");
             return;
         }
     };
+    let status = match menu::input("Enter status (1. Open, 2. Frozen, 3. Closed, empty for any): ").as_str() {
+        "" => None,
+        "1" => Some(AccountStatus::Open),
+        "2" => Some(AccountStatus::Frozen),
+        "3" => Some(AccountStatus::Closed),
+        _ => {
+            println!("Invalid status!");
+            return;
+        }
+    };
+    let (min_balance, max_balance) = match (
+        menu::optional_amount("Enter minimum balance (empty for none): "),
+        menu::optional_amount("Enter maximum balance (empty for none): "),
+    ) {
+        (Some(min), Some(max)) => (min, max),
+        _ => return,
+    };
+    let filter = AccountFilter { account_type, status, min_balance, max_balance };
+    let numbers = bank.find_accounts(&filter);
+    if numbers.is_empty() {
+        println!("No matching accounts.");
+    }
+    for number in numbers {
+        if let Some(account) = bank.account(number) {
+            println!(
+                "{}: {} {}, {}",
+                number,
+                account.status().name(),
+                account.account_type().name(),
+                account.balance().format_in(account.currency())
+            );
+        }
+    }
+}
 
-    if bank.transfer(from_account, to_account, amount) {
-        println!("Transfer successful!");
-    } else {
-        println!("Insufficient balance or account not found!");
+fn dormant_accounts(bank: &Bank) {
+    let days: u64 = match menu::input("Enter days without transactions: ").parse() {
+        Ok(days) => days,
+        Err(_) => {
+            println!("Invalid number of days!");
+            return;
+        }
+    };
+    let numbers = bank.dormant_accounts(Duration::from_secs(days * 86_400), SystemTime::now());
+    if numbers.is_empty() {
+        println!("No dormant accounts.");
+    }
+    for number in numbers {
+        let idle = bank.account(number).and_then(|account| account.idle_for(SystemTime::now())).unwrap_or_default();
+        println!("{}: idle for {} days", number, idle.as_secs() / 86_400);
     }
 }
 
-fn get_account_type(bank: &Bank) {
-    let account_number = menu::input("Enter account number: ");
-    if let Some(account_type) = bank.get_account_type(account_number) {
-        println!("Account Type: {:?}", account_type);
-    } else {
-        println!("Account not found!");
+fn deposits_by_type(bank: &Bank) {
+    let totals = bank.deposits_by_type();
+    for account_type in [AccountType::Checking, AccountType::Savings, AccountType::Credit] {
+        println!("{}: {}", account_type.name(), totals.get(&account_type).copied().unwrap_or_default());
     }
+    println!("Total: {}", bank.total_deposits());
 }
 
-fn get_transactions(bank: &Bank) {
-    let account_number = menu::input("Enter account number: ");
-    if let Some(transactions) = bank.get_transactions(account_number) {
-        println!("Transactions:");
-        for (i, transaction) in transactions.iter().enumerate() {
-            println!("{}: {:?}", i + 1, transaction);
-        }
-    } else {
-        println!("Account not found!");
+fn read_new_pin() -> Option<String> {
+    let pin = menu::input("Choose a PIN (4 to 12 digits): ");
+    if !(4..=12).contains(&pin.len()) || !pin.chars().all(|c| c.is_ascii_digit()) {
+        println!("Invalid PIN!");
+        return None;
     }
+    if menu::input("Repeat the PIN: ") != pin {
+        println!("PINs do not match!");
+        return None;
+    }
+    Some(pin)
 }
 
-fn activate_account(bank: &mut Bank) {
-    let account_number = menu::input("Enter account number: ");
-    if bank.activate_account(account_number) {
-        println!("Account activated successfully!");
-    } else {
-        println!("Account not found!");
+fn save(bank: &Bank, storage: &JsonFile) {
+    match storage.save(bank) {
+        Ok(()) => println!("Bank saved to {}!", storage.path().display()),
+        Err(error) => println!("Failed to save bank: {}!", error),
     }
 }
 
-fn deactivate_account(bank: &mut Bank) {
-    let account_number = menu::input("Enter account number: ");
-    if bank.deactivate_account(account_number) {
-        println!("Account deactivated successfully!");
-    } else {
-        println!("Account not found!");
+// Commands given on the command line instead of through the menu, for use from scripts:
+//   bank create --account N --type Checking|Savings|Credit --pin P [--currency C]
+//   bank deposit --account N --amount X
+//   bank withdraw --account N --amount X --pin P
+//   bank transfer --from A --to B --amount X --pin P
+//   bank balance --account N
+//   bank transactions --account N
+// Any command takes --json for machine-readable output. Amounts use a decimal point.
+mod cli {
+    use bank::account::{AccountType, Currency};
+    use bank::audit::AuditRecord;
+    use bank::bank::{Bank, BankError, Money, MonthEndSummary, Period};
+    use bank::calendar;
+    use bank::import::ImportReport;
+    use bank::simulation::{self, SimulationConfig, SimulationReport};
+    use bank::storage::{JsonFile, Storage};
+    use bank::transaction::Transaction;
+    use std::collections::BTreeMap;
+    use std::path::Path;
+    use std::time::SystemTime;
+
+    const EXIT_FAILED: i32 = 1;
+    const EXIT_USAGE: i32 = 2;
+    // Month-end statements archived per account.
+    const STATEMENTS_KEPT: usize = 12;
+
+    enum Output {
+        Done(String),
+        // Ledger and available balance.
+        Balance(String, Money, Money, Currency),
+        Transactions(Vec<Transaction>),
+        Audit(Vec<AuditRecord>),
+        MonthEnd(Vec<MonthEndSummary>),
+        Import(ImportReport),
+        Simulation(SimulationReport),
+    }
+
+    enum Failure {
+        Usage(String),
+        Bank(BankError),
+    }
+
+    impl From<BankError> for Failure {
+        fn from(error: BankError) -> Failure {
+            Failure::Bank(error)
+        }
+    }
+
+    // Returns the exit code: 0 on success, 1 when the bank refused the command and 2 when
+    // the command line itself was wrong.
+    pub fn run(bank: &mut Bank, storage: &JsonFile, args: &[String]) -> i32 {
+        let json = args.iter().any(|arg| arg == "--json");
+        let result = parse_options(&args[1..]).and_then(|options| execute(bank, &args[0], &options));
+        // A refused command may still have changed the bank, e.g. by counting a wrong PIN.
+        let changes_bank = matches!(args[0].as_str(), "create" | "deposit" | "withdraw" | "transfer" | "month-end" | "import" | "reverse");
+        if changes_bank && !matches!(result, Err(Failure::Usage(_))) {
+            if let Err(error) = storage.save(bank) {
+                report_error(json, &format!("Failed to save bank: {}", error));
+                return EXIT_FAILED;
+            }
+        }
+        match result {
+            Ok(output) => {
+                println!("{}", if json { to_json(&output) } else { to_text(&output) });
+                match output {
+                    Output::Simulation(report) if !report.violations.is_empty() => EXIT_FAILED,
+                    _ => 0,
+                }
+            }
+            Err(Failure::Usage(message)) => {
+                report_error(json, &message);
+                EXIT_USAGE
+            }
+            Err(Failure::Bank(error)) => {
+                report_error(json, &error.to_string());
+                EXIT_FAILED
+            }
+        }
+    }
+
+    fn parse_options(args: &[String]) -> Result<BTreeMap<String, String>, Failure> {
+        let mut options = BTreeMap::new();
+        let mut args = args.iter().filter(|arg| *arg != "--json");
+        while let Some(arg) = args.next() {
+            let name = arg.strip_prefix("--").ok_or_else(|| Failure::Usage(format!("Unexpected argument {}", arg)))?;
+            let value = args.next().ok_or_else(|| Failure::Usage(format!("Missing value for --{}", name)))?;
+            options.insert(name.to_string(), value.clone());
+        }
+        Ok(options)
+    }
+
+    fn execute(bank: &mut Bank, command: &str, options: &BTreeMap<String, String>) -> Result<Output, Failure> {
+        let allowed: &[&str] = match command {
+            "create" => &["account", "type", "pin", "currency"],
+            "deposit" => &["account", "amount"],
+            "withdraw" => &["account", "amount", "pin"],
+            "transfer" => &["from", "to", "amount", "pin"],
+            "balance" | "transactions" => &["account"],
+            "audit" => &["account"],
+            "month-end" => &["month"],
+            "import" => &["account", "file"],
+            "reverse" => &["account", "id"],
+            "sim" => &["seed", "steps", "accounts"],
+            _ => return Err(Failure::Usage(format!("Unknown command {}", command))),
+        };
+        if let Some(name) = options.keys().find(|name| !allowed.contains(&name.as_str())) {
+            return Err(Failure::Usage(format!("Unknown option --{} for {}", name, command)));
+        }
+        let option = |name: &str| options.get(name).cloned().ok_or_else(|| Failure::Usage(format!("Missing --{}", name)));
+        // Every command's amount moves money, so zero is as malformed as a sign.
+        let amount = || -> Result<Money, Failure> {
+            let amount = option("amount")?;
+            amount.parse().ok().filter(|amount| *amount > Money::ZERO).ok_or_else(|| Failure::Usage(format!("Invalid amount {}", amount)))
+        };
+        Ok(match command {
+            "create" => {
+                let account_number = option("account")?;
+                let kind = option("type")?;
+                let account_type = AccountType::from_name(&kind).ok_or_else(|| Failure::Usage(format!("Invalid account type {}", kind)))?;
+                let currency = options.get("currency").map_or_else(Currency::default, |code| Currency::new(code));
+                bank.create_account_in_currency(account_number.clone(), account_type, currency)?;
+                bank.set_pin(account_number.clone(), &option("pin")?)?;
+                Output::Done(format!("Account {} created", account_number))
+            }
+            "deposit" => {
+                bank.deposit(option("account")?, amount()?)?;
+                Output::Done("Deposit successful".to_string())
+            }
+            "withdraw" => {
+                bank.withdraw_with_pin(option("account")?, &option("pin")?, amount()?)?;
+                Output::Done("Withdrawal successful".to_string())
+            }
+            "transfer" => {
+                bank.transfer_with_pin(option("from")?, &option("pin")?, option("to")?, amount()?)?;
+                Output::Done("Transfer successful".to_string())
+            }
+            "balance" => {
+                let account_number = option("account")?;
+                let account = bank.account(&account_number).ok_or(BankError::AccountNotFound)?;
+                Output::Balance(account_number, account.balance(), account.available_balance(), account.currency().clone())
+            }
+            "transactions" => {
+                let account = bank.account(&option("account")?).ok_or(BankError::AccountNotFound)?;
+                Output::Transactions(account.transactions().clone())
+            }
+            "month-end" => {
+                // Without --month, the month that ended last.
+                let period = match options.get("month") {
+                    Some(month) => parse_month(month).ok_or_else(|| Failure::Usage(format!("Invalid month {}", month)))?,
+                    None => match calendar::year_month(SystemTime::now()) {
+                        (year, 1) => Period::month(year - 1, 12),
+                        (year, month) => Period::month(year, month - 1),
+                    },
+                };
+                Output::MonthEnd(bank.run_month_end(&period, STATEMENTS_KEPT))
+            }
+            "reverse" => {
+                let id = option("id")?;
+                let id = id.parse().map_err(|_| Failure::Usage(format!("Invalid transaction id {}", id)))?;
+                bank.reverse_transaction(option("account")?, id)?;
+                Output::Done("Transaction reversed".to_string())
+            }
+            // Runs against a bank of its own; the saved bank is left alone.
+            "sim" => {
+                let number = |name: &str, default: u64| -> Result<u64, Failure> {
+                    match options.get(name) {
+                        Some(value) => value.parse().map_err(|_| Failure::Usage(format!("Invalid --{} {}", name, value))),
+                        None => Ok(default),
+                    }
+                };
+                let defaults = SimulationConfig::default();
+                let config = SimulationConfig {
+                    seed: number("seed", defaults.seed)?,
+                    steps: number("steps", defaults.steps as u64)? as usize,
+                    accounts: number("accounts", defaults.accounts as u64)?.max(1) as usize,
+                    ..defaults
+                };
+                Output::Simulation(simulation::run(&config))
+            }
+            // CSV, or OFX when the file ends in .ofx or .qfx.
+            "import" => Output::Import(bank.import_file(&option("account")?, Path::new(&option("file")?))?),
+            _ => Output::Audit(bank.audit_records(options.get("account").map(String::as_str), None).into_iter().cloned().collect()),
+        })
+    }
+
+    // The calendar month named by YYYY-MM.
+    fn parse_month(month: &str) -> Option<Period> {
+        let first = calendar::parse_date(&format!("{}-01", month))?;
+        let (year, month) = calendar::year_month(first);
+        Some(Period::month(year, month))
+    }
+
+    fn to_text(output: &Output) -> String {
+        match output {
+            Output::Done(message) => message.clone(),
+            Output::Balance(_, balance, available, currency) if available != balance => {
+                format!("{} ({} available)", balance.format_in(currency), available.format_in(currency))
+            }
+            Output::Balance(_, balance, _, currency) => balance.format_in(currency),
+            Output::Transactions(transactions) => transactions
+                .iter()
+                .map(|t| {
+                    let counterparty = t.kind.counterparty().map(|other| format!(" ({})", other)).unwrap_or_default();
+                    let when = calendar::format_datetime(t.timestamp);
+                    format!("{} {} {} {}{} -> {}", t.id, when, t.kind.name(), t.kind.amount(), counterparty, t.balance_after)
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Output::Audit(records) => records.iter().map(format_audit_record).collect::<Vec<_>>().join("\n"),
+            Output::MonthEnd(summaries) => summaries
+                .iter()
+                .map(|s| {
+                    format!(
+                        "{} {}: opening {}, deposits {}, withdrawals {}, fees {}, interest {}, closing {}",
+                        s.account_number,
+                        &calendar::format_date(s.period.from)[..7],
+                        s.opening_balance.format_in(&s.currency),
+                        s.deposits,
+                        s.withdrawals,
+                        s.fees,
+                        s.interest,
+                        s.closing_balance.format_in(&s.currency)
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Output::Simulation(report) => {
+                let mut lines = vec![format!(
+                    "{} steps: {} succeeded, {} refused ({} for an invalid amount)",
+                    report.steps, report.succeeded, report.refused, report.invalid_amounts
+                )];
+                lines.extend(
+                    report.violations.iter().map(|v| format!("step {} ({}) broke {}: {}", v.step, v.operation, v.invariant.name(), v.detail)),
+                );
+                lines.join("\n")
+            }
+            Output::Import(report) => {
+                let mut lines = vec![format!("{} imported, {} skipped, {} rejected", report.imported, report.skipped, report.rejected.len())];
+                lines.extend(report.rejected.iter().map(|(row, reason)| format!("row {}: {}", row, reason)));
+                lines.join("\n")
+            }
+        }
+    }
+
+    // Shared with the menu's audit log view.
+    pub fn format_audit_record(record: &AuditRecord) -> String {
+        let parameters: Vec<String> = record.parameters.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+        format!(
+            "{} {} {} {} {} -> {}",
+            record.sequence,
+            calendar::format_datetime(record.timestamp),
+            record.actor,
+            record.operation,
+            parameters.join(" "),
+            record.outcome()
+        )
+    }
+
+    fn to_json(output: &Output) -> String {
+        match output {
+            Output::Done(message) => format!(r#"{{"status":"ok","message":{}}}"#, quote(message)),
+            Output::Balance(account_number, balance, available, currency) => format!(
+                r#"{{"account":{},"balance":"{}","available_balance":"{}","currency":{}}}"#,
+                quote(account_number),
+                balance,
+                available,
+                quote(currency.code())
+            ),
+            Output::Transactions(transactions) => {
+                let entries: Vec<String> = transactions
+                    .iter()
+                    .map(|t| {
+                        let counterparty = t.kind.counterparty().map_or("null".to_string(), quote);
+                        format!(
+                            r#"{{"id":{},"timestamp":{},"type":"{}","amount":"{}","currency":{},"counterparty":{},"balance_after":"{}"}}"#,
+                            t.id,
+                            quote(&calendar::format_datetime(t.timestamp)),
+                            t.kind.name(),
+                            t.kind.amount(),
+                            quote(t.currency.code()),
+                            counterparty,
+                            t.balance_after
+                        )
+                    })
+                    .collect();
+                format!("[{}]", entries.join(","))
+            }
+            Output::Audit(records) => {
+                let entries: Vec<String> = records
+                    .iter()
+                    .map(|record| {
+                        let parameters: Vec<String> =
+                            record.parameters.iter().map(|(name, value)| format!("{}:{}", quote(name), quote(value))).collect();
+                        format!(
+                            r#"{{"sequence":{},"timestamp":{},"actor":{},"operation":{},"parameters":{{{}}},"error":{}}}"#,
+                            record.sequence,
+                            quote(&calendar::format_datetime(record.timestamp)),
+                            quote(&record.actor),
+                            quote(&record.operation),
+                            parameters.join(","),
+                            record.error.as_deref().map_or("null".to_string(), quote)
+                        )
+                    })
+                    .collect();
+                format!("[{}]", entries.join(","))
+            }
+            Output::MonthEnd(summaries) => {
+                let entries: Vec<String> = summaries
+                    .iter()
+                    .map(|s| {
+                        format!(
+                            concat!(
+                                r#"{{"account":{},"from":{},"to":{},"currency":{},"opening_balance":"{}","deposits":"{}","#,
+                                r#""withdrawals":"{}","fees":"{}","maintenance_fee":"{}","interest":"{}","closing_balance":"{}"}}"#
+                            ),
+                            quote(&s.account_number),
+                            quote(&calendar::format_date(s.period.from)),
+                            quote(&calendar::format_date(s.period.to)),
+                            quote(s.currency.code()),
+                            s.opening_balance,
+                            s.deposits,
+                            s.withdrawals,
+                            s.fees,
+                            s.maintenance_fee,
+                            s.interest,
+                            s.closing_balance
+                        )
+                    })
+                    .collect();
+                format!("[{}]", entries.join(","))
+            }
+            Output::Simulation(report) => {
+                let violations: Vec<String> = report
+                    .violations
+                    .iter()
+                    .map(|v| {
+                        format!(
+                            r#"{{"step":{},"operation":{},"invariant":"{}","detail":{}}}"#,
+                            v.step,
+                            quote(&v.operation.to_string()),
+                            v.invariant.name(),
+                            quote(&v.detail)
+                        )
+                    })
+                    .collect();
+                format!(
+                    r#"{{"steps":{},"succeeded":{},"refused":{},"invalid_amounts":{},"violations":[{}]}}"#,
+                    report.steps,
+                    report.succeeded,
+                    report.refused,
+                    report.invalid_amounts,
+                    violations.join(",")
+                )
+            }
+            Output::Import(report) => {
+                let rejected: Vec<String> =
+                    report.rejected.iter().map(|(row, reason)| format!(r#"{{"row":{},"reason":{}}}"#, row, quote(reason))).collect();
+                format!(r#"{{"imported":{},"skipped":{},"rejected":[{}]}}"#, report.imported, report.skipped, rejected.join(","))
+            }
+        }
+    }
+
+    fn report_error(json: bool, message: &str) {
+        if json {
+            println!(r#"{{"error":{}}}"#, quote(message));
+        } else {
+            eprintln!("{}", message);
+        }
+    }
+
+    fn quote(text: &str) -> String {
+        let mut quoted = String::from("\"");
+        for c in text.chars() {
+            match c {
+                '"' => quoted.push_str("\\\""),
+                '\\' => quoted.push_str("\\\\"),
+                c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+                c => quoted.push(c),
+            }
+        }
+        quoted.push('"');
+        quoted
     }
 }
 
 mod menu {
+    use bank::Money;
     use std::io;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::OnceLock;
+
+    static EOF: AtomicBool = AtomicBool::new(false);
+
+    // True once stdin has been closed (e.g. Ctrl-D) or can no longer be read.
+    pub fn at_eof() -> bool {
+        EOF.load(Ordering::Relaxed)
+    }
 
-    pub fn select(prompt: &str) -> u8 {
+    fn read_line(prompt: &str) -> Option<String> {
         println!("{}", prompt);
         let mut input = String::new();
-        io::stdin().read_line(&mut input).expect("Failed to read input");
-        input.trim().parse().expect("Invalid input")
+        match io::stdin().read_line(&mut input) {
+            Ok(0) | Err(_) => {
+                EOF.store(true, Ordering::Relaxed);
+                None
+            }
+            Ok(_) => Some(input),
+        }
+    }
+
+    // None for anything that is not a number, or at end of input.
+    pub fn select(prompt: &str) -> Option<u8> {
+        read_line(prompt)?.trim().parse().ok()
     }
 
     pub fn input(prompt: &str) -> String {
-        println!("{}", prompt);
-        let mut input = String::new();
-        io::stdin().read_line(&mut input).expect("Failed to read input");
-        input.trim().to_string()
+        read_line(prompt).map(|input| input.trim().to_string()).unwrap_or_default()
     }
 
-    pub fn float(prompt: &str) -> Result<f64, ()> {
-        println!("{}", prompt);
-        let mut input = String::new();
-        io::stdin().read_line(&mut input).expect("Failed to read
+    // Re-prompts until a valid amount is entered; None only at end of input.
+    pub fn amount(prompt: &str) -> Option<Money> {
+        loop {
+            match parse_amount(&read_line(prompt)?, money_format()) {
+                Ok(amount) => return Some(amount),
+                Err(()) => println!("Invalid amount!"),
+            }
+        }
+    }
+
+    // Like amount, but an empty line means no amount. None only at end of input.
+    pub fn optional_amount(prompt: &str) -> Option<Option<Money>> {
+        loop {
+            let line = read_line(prompt)?;
+            if line.trim().is_empty() {
+                return Some(None);
+            }
+            match parse_amount(&line, money_format()) {
+                Ok(amount) => return Some(Some(amount)),
+                Err(()) => println!("Invalid amount!"),
+            }
+        }
+    }
+
+    // Reads a percentage such as "4.5" and returns it as a fraction (0.045), re-prompting
+    // until it parses; None only at end of input.
+    pub fn percentage(prompt: &str) -> Option<f64> {
+        loop {
+            match read_line(prompt)?.trim().trim_end_matches('%').parse::<f64>() {
+                Ok(percent) if percent.is_finite() => return Some(percent / 100.0),
+                _ => println!("Invalid rate!"),
+            }
+        }
+    }
+
+    // Re-prompts until a positive number is entered; None only at end of input.
+    pub fn rate(prompt: &str) -> Option<f64> {
+        loop {
+            match read_line(prompt)?.trim().parse::<f64>() {
+                Ok(rate) if rate.is_finite() && rate > 0.0 => return Some(rate),
+                _ => println!("Invalid rate!"),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct MoneyFormat {
+        pub decimal_separator: char,
+        pub thousands_separator: char,
+    }
+
+    impl MoneyFormat {
+        pub const US: MoneyFormat = MoneyFormat {
+            decimal_separator: '.',
+            thousands_separator: ',',
+        };
+        pub const EUROPEAN: MoneyFormat = MoneyFormat {
+            decimal_separator: ',',
+            thousands_separator: '.',
+        };
+    }
+
+    static MONEY_FORMAT: OnceLock<MoneyFormat> = OnceLock::new();
+
+    pub fn set_money_format(format: MoneyFormat) {
+        let _ = MONEY_FORMAT.set(format);
+    }
+
+    fn money_format() -> &'static MoneyFormat {
+        MONEY_FORMAT.get_or_init(|| MoneyFormat::US)
+    }
+
+    // Accepts digits with at most one decimal separator followed by no more than two
+    // digits and, optionally, thousands separators in correctly sized groups. Anything
+    // else, a sign included, is ambiguous and rejected.
+    pub fn parse_amount(input: &str, format: &MoneyFormat) -> Result<Money, ()> {
+        let input = input.trim();
+        let (integer, fraction) = match input.split_once(format.decimal_separator) {
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (input, None),
+        };
+        if integer.is_empty() && fraction.is_none_or(str::is_empty) {
+            return Err(());
+        }
+        if let Some(fraction) = fraction {
+            if fraction.len() > 2 || !fraction.chars().all(|c| c.is_ascii_digit()) {
+                return Err(());
+            }
+        }
+
+        let groups: Vec<&str> = integer.split(format.thousands_separator).collect();
+        if groups.len() > 1 {
+            let first_ok = (1..=3).contains(&groups[0].len());
+            if !first_ok || groups[1..].iter().any(|group| group.len() != 3) {
+                return Err(());
+            }
+        }
+        if !groups.iter().all(|group| group.chars().all(|c| c.is_ascii_digit())) {
+            return Err(());
+        }
+
+        let whole: i64 = match groups.concat().as_str() {
+            "" => 0,
+            digits => digits.parse().map_err(|_| ())?,
+        };
+        let cents: i64 = format!("{:0<2}", fraction.unwrap_or("")).parse().map_err(|_| ())?;
+        whole.checked_mul(100).and_then(|whole| whole.checked_add(cents)).map(Money::from_cents).ok_or(())
+    }
+}
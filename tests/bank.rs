@@ -0,0 +1,3231 @@
+use bank::auth::{PinStatus, Role};
+use bank::concurrent::ConcurrentBank;
+use bank::import;
+use bank::server;
+use bank::simulation::{self, Invariant, SimulationConfig};
+use bank::storage::{JsonFile, Storage};
+use bank::{
+    calendar, classify_transaction, AccountFilter, AccountStatus, AccountType, AgeBucket, Alignment, Bank, BankError,
+    Category, CategoryMatch, CategoryRule, Column, CompoundFrequency, Currency, Customer, DailyAllowance, DeliveryMethod,
+    Event, EventKind, FeeSchedule, FeeType, FixedWidthField, FixedWidthSpec, InterestBasis, Money, Operation, Period,
+    RemainderPolicy, ReviewRules, StatementFormat, Transaction, TransactionKind, TransactionLimits, TransferCost, TransferRef,
+    UsageProfile, VelocityLimit, VelocityLimits, WithdrawalRounding,
+};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{Read, Write};
+use std::time::{Duration, SystemTime};
+
+fn dollars(amount: i64) -> Money {
+    Money::from_cents(amount * 100)
+}
+
+fn bank_with_accounts() -> Bank {
+    let mut bank = Bank::new();
+    bank.create_account("alice".to_string(), AccountType::Checking).unwrap();
+    bank.create_account("bob".to_string(), AccountType::Savings).unwrap();
+    bank
+}
+
+#[test]
+fn deposit_transfer_and_query() {
+    let mut bank = bank_with_accounts();
+    bank.deposit("alice".to_string(), dollars(100)).unwrap();
+    bank.transfer("alice".to_string(), "bob".to_string(), dollars(40)).unwrap();
+
+    assert_eq!(bank.balance("alice".to_string()), Some(dollars(60)));
+    assert_eq!(bank.balance("bob".to_string()), Some(dollars(40)));
+    assert_eq!(bank.get_account_type("bob".to_string()), Some(AccountType::Savings));
+
+    let received = bank.get_transactions("bob".to_string()).unwrap();
+    assert!(received
+        .iter()
+        .any(|t| matches!(&t.kind, TransactionKind::TransferIn(amount, from) if *amount == dollars(40) && from == "alice")));
+}
+
+#[test]
+fn many_small_deposits_sum_to_the_exact_cent() {
+    let mut bank = bank_with_accounts();
+    let dime: Money = "0.10".parse().unwrap();
+    for _ in 0..1_000 {
+        bank.deposit("alice".to_string(), dime).unwrap();
+    }
+    assert_eq!(bank.balance("alice".to_string()).unwrap().cents(), 10_000);
+    for _ in 0..3 {
+        bank.transfer("alice".to_string(), "bob".to_string(), "33.33".parse().unwrap()).unwrap();
+    }
+    assert_eq!(bank.balance("alice".to_string()).unwrap().cents(), 1);
+    assert_eq!(bank.balance("bob".to_string()).unwrap().to_string(), "99.99");
+
+    assert_eq!("12.34".parse::<Money>().map(Money::cents), Ok(1_234));
+    assert_eq!(".5".parse::<Money>().map(Money::cents), Ok(50));
+    for input in ["12.345", "", ".", "1.2.3", "12a", "1e3", "-5", "+5"] {
+        assert_eq!(input.parse::<Money>(), Err(BankError::InvalidAmount), "{:?}", input);
+    }
+}
+
+#[test]
+fn duplicate_account_is_rejected() {
+    let mut bank = bank_with_accounts();
+    bank.deposit("alice".to_string(), dollars(10)).unwrap();
+    assert_eq!(bank.create_account("alice".to_string(), AccountType::Credit), Err(BankError::AccountExists));
+    assert_eq!(bank.balance("alice".to_string()), Some(dollars(10)));
+    assert_eq!(
+        bank.create_account_in_currency("alice".to_string(), AccountType::Checking, Currency::new("EUR")),
+        Err(BankError::AccountExists)
+    );
+    // The first account is untouched: same type, currency and history.
+    assert_eq!(bank.get_account_type("alice".to_string()), Some(AccountType::Checking));
+    assert_eq!(bank.account("alice").unwrap().currency(), &Currency::new("USD"));
+    assert_eq!(bank.get_transactions("alice".to_string()).unwrap().len(), 1);
+    assert_eq!(BankError::AccountExists.to_string(), "Account already exists");
+    assert_eq!(bank.events().len(), 3);
+}
+
+#[test]
+fn percentage_withdrawals_truncate_or_round_to_the_cent() {
+    let withdrawn = |rounding, fraction| {
+        let mut bank = bank_with_accounts();
+        bank.set_withdrawal_rounding(rounding);
+        bank.deposit("alice".to_string(), dollars(100)).unwrap();
+        bank.withdraw_percentage("alice".to_string(), fraction).unwrap();
+        dollars(100) - bank.balance("alice".to_string()).unwrap()
+    };
+    // 33.333% of 100.00 is 33.333: both policies drop the 0.3 of a cent.
+    assert_eq!(withdrawn(WithdrawalRounding::Truncate, 0.33333), Money::from_cents(3_333));
+    assert_eq!(withdrawn(WithdrawalRounding::Round, 0.33333), Money::from_cents(3_333));
+    // Two thirds is 66.666...: truncation keeps 66.66, rounding takes 66.67.
+    assert_eq!(withdrawn(WithdrawalRounding::Truncate, 2.0 / 3.0), Money::from_cents(6_666));
+    assert_eq!(withdrawn(WithdrawalRounding::Round, 2.0 / 3.0), Money::from_cents(6_667));
+    // An exact amount is left alone despite float error: 0.29 * 100.00 is 28.999999...
+    assert_eq!(withdrawn(WithdrawalRounding::Truncate, 0.29), dollars(29));
+}
+
+#[test]
+fn transfer_without_funds_leaves_balances_unchanged() {
+    let mut bank = bank_with_accounts();
+    bank.deposit("alice".to_string(), dollars(5)).unwrap();
+    assert_eq!(
+        bank.transfer("alice".to_string(), "bob".to_string(), dollars(50)),
+        Err(BankError::InsufficientFunds)
+    );
+    assert_eq!(bank.balance("alice".to_string()), Some(dollars(5)));
+    assert_eq!(bank.balance("bob".to_string()), Some(Money::ZERO));
+}
+
+#[test]
+fn unknown_accounts_are_reported() {
+    let mut bank = bank_with_accounts();
+    assert_eq!(bank.balance("carol".to_string()), None);
+    assert_eq!(bank.deposit("carol".to_string(), dollars(1)), Err(BankError::AccountNotFound));
+    assert_eq!(
+        bank.transfer("alice".to_string(), "carol".to_string(), dollars(1)),
+        Err(BankError::AccountNotFound)
+    );
+}
+
+// Sends `amount` from alice to bob, lets bob spend it and then charges the transfer back,
+// leaving bob overdrawn by `amount`.
+fn overdraw_bob(bank: &mut Bank, amount: Money) {
+    bank.deposit("alice".to_string(), amount).unwrap();
+    bank.transfer("alice".to_string(), "bob".to_string(), amount).unwrap();
+    bank.withdraw("bob".to_string(), amount).unwrap();
+    let index = bank.get_transactions("alice".to_string()).unwrap().len() - 1;
+    bank.chargeback_transfer(TransferRef { account_number: "alice".to_string(), index }).unwrap();
+}
+
+#[test]
+fn transfer_is_charged_back_once() {
+    let mut bank = bank_with_accounts();
+    bank.create_account("carol".to_string(), AccountType::Checking).unwrap();
+    overdraw_bob(&mut bank, dollars(30));
+    assert_eq!(bank.balance("alice".to_string()), Some(dollars(30)));
+    assert_eq!(bank.balance("bob".to_string()), Some(dollars(-30)));
+
+    let index = bank.get_transactions("alice".to_string()).unwrap().len() - 2;
+    let charged_back = TransferRef { account_number: "alice".to_string(), index };
+    assert_eq!(bank.chargeback_transfer(charged_back.clone()), Err(BankError::AlreadyReversed));
+    assert_eq!(bank.reverse_transaction("bob".to_string(), 1), Err(BankError::AlreadyReversed));
+    let deposit = TransferRef { account_number: "alice".to_string(), index: 0 };
+    assert_eq!(bank.chargeback_transfer(deposit), Err(BankError::TransactionNotFound));
+    assert_eq!(bank.balance("bob".to_string()), Some(dollars(-30)));
+
+    // Neither side may be closed.
+    bank.deposit("alice".to_string(), dollars(10)).unwrap();
+    bank.transfer("alice".to_string(), "carol".to_string(), dollars(10)).unwrap();
+    bank.withdraw("carol".to_string(), dollars(10)).unwrap();
+    bank.close_account("carol").unwrap();
+    let index = bank.get_transactions("alice".to_string()).unwrap().len() - 1;
+    let to_carol = TransferRef { account_number: "alice".to_string(), index };
+    assert_eq!(bank.chargeback_transfer(to_carol), Err(BankError::AccountClosed));
+
+    let chargebacks: Vec<bool> = bank
+        .audit_log()
+        .iter()
+        .filter(|record| record.operation == "chargeback_transfer")
+        .map(|record| record.succeeded())
+        .collect();
+    assert_eq!(chargebacks, vec![true, false, false, false]);
+    let replayed = Bank::from_events(bank.events()).unwrap();
+    assert_eq!(replayed.balance("bob".to_string()), Some(dollars(-30)));
+    assert_eq!(replayed.balance("alice".to_string()), Some(dollars(30)));
+    assert_eq!(bank.get_transactions("alice".to_string()).unwrap()[charged_back.index].reversed_by, Some(3));
+}
+
+#[test]
+fn overdraft_cured_within_grace_period_is_not_charged() {
+    let mut bank = bank_with_accounts();
+    bank.set_overdraft_fee(dollars(25), Duration::from_secs(86_400));
+    overdraw_bob(&mut bank, dollars(30));
+    assert_eq!(bank.process_overdraft_fees(SystemTime::now()), Vec::<String>::new());
+
+    bank.deposit("bob".to_string(), dollars(30)).unwrap();
+    let later = SystemTime::now() + Duration::from_secs(2 * 86_400);
+    assert_eq!(bank.process_overdraft_fees(later), Vec::<String>::new());
+    assert_eq!(bank.balance("bob".to_string()), Some(Money::ZERO));
+}
+
+#[test]
+fn overdraft_past_grace_period_is_charged_once() {
+    let mut bank = bank_with_accounts();
+    bank.set_overdraft_fee(dollars(25), Duration::from_secs(86_400));
+    overdraw_bob(&mut bank, dollars(30));
+
+    let later = SystemTime::now() + Duration::from_secs(2 * 86_400);
+    assert_eq!(bank.process_overdraft_fees(later), vec!["bob".to_string()]);
+    assert_eq!(bank.process_overdraft_fees(later), Vec::<String>::new());
+    assert_eq!(bank.balance("bob".to_string()), Some(-dollars(55)));
+}
+
+#[test]
+fn withdraw_reports_why_it_failed() {
+    let mut bank = bank_with_accounts();
+    bank.deposit("alice".to_string(), dollars(20)).unwrap();
+    assert_eq!(bank.withdraw("carol".to_string(), dollars(1)), Err(BankError::AccountNotFound));
+    assert_eq!(bank.withdraw("alice".to_string(), dollars(21)), Err(BankError::InsufficientFunds));
+
+    bank.deactivate_account("alice".to_string()).unwrap();
+    assert_eq!(bank.withdraw("alice".to_string(), dollars(1)), Err(BankError::AccountInactive));
+    assert_eq!(bank.deposit("alice".to_string(), dollars(1)), Err(BankError::AccountInactive));
+    assert_eq!(bank.balance("alice".to_string()), Some(dollars(20)));
+}
+
+#[test]
+fn reserve_shortfall_counts_only_positive_deposits() {
+    let mut bank = bank_with_accounts();
+    bank.create_account_with_limit("carol".to_string(), AccountType::Credit, dollars(500)).unwrap();
+    bank.deposit("alice".to_string(), dollars(600)).unwrap();
+    bank.deposit("bob".to_string(), dollars(400)).unwrap();
+    bank.withdraw("carol".to_string(), dollars(300)).unwrap();
+
+    // 10% of 1000.00 in deposits is 100.00 required.
+    assert_eq!(bank.reserve_shortfall(0.1, dollars(60)), dollars(40));
+    assert_eq!(bank.reserve_shortfall(0.1, dollars(150)), -dollars(50));
+}
+
+fn yearly_interest_on(balance: Money, cap: Option<Money>) -> Money {
+    let mut bank = Bank::new();
+    bank.create_account("saver".to_string(), AccountType::Savings).unwrap();
+    bank.deposit("saver".to_string(), balance).unwrap();
+    bank.set_compound_frequency("saver".to_string(), CompoundFrequency::Annually).unwrap();
+    bank.set_interest_rate("saver".to_string(), 0.01).unwrap();
+    bank.set_bonus_rate("saver".to_string(), 0.05, cap).unwrap();
+    bank.forecast_interest(12)
+}
+
+#[test]
+fn bonus_rate_applies_up_to_the_cap() {
+    // Entirely under the cap: 5% of 8000.00.
+    assert_eq!(yearly_interest_on(dollars(8_000), Some(dollars(10_000))), dollars(400));
+    // Blended: 5% of the first 10000.00 plus 1% of the remaining 5000.00.
+    assert_eq!(yearly_interest_on(dollars(15_000), Some(dollars(10_000))), dollars(550));
+    // No cap: the base rate on the whole balance.
+    assert_eq!(yearly_interest_on(dollars(15_000), None), dollars(150));
+}
+
+#[test]
+fn balance_histogram_puts_boundary_balances_in_the_upper_bucket() {
+    let mut bank = bank_with_accounts();
+    bank.create_account_with_limit("card".to_string(), AccountType::Credit, dollars(100)).unwrap();
+    bank.create_account("carol".to_string(), AccountType::Checking).unwrap();
+    bank.create_account("dave".to_string(), AccountType::Checking).unwrap();
+    bank.withdraw("card".to_string(), dollars(5)).unwrap();
+    bank.deposit("bob".to_string(), Money::from_cents(9_999)).unwrap();
+    bank.deposit("carol".to_string(), dollars(100)).unwrap();
+    bank.deposit("dave".to_string(), dollars(250)).unwrap();
+
+    // Unsorted, repeated boundaries are tidied; alice's zero sits on the first boundary.
+    let histogram = bank.balance_histogram(&[dollars(100), Money::ZERO, dollars(200), dollars(100)]);
+    let counts: Vec<(Money, Money, usize)> = histogram.iter().map(|(range, count)| (range.start, range.end, *count)).collect();
+    assert_eq!(
+        counts,
+        vec![
+            (Money::from_cents(i64::MIN), Money::ZERO, 1),
+            (Money::ZERO, dollars(100), 2),
+            (dollars(100), dollars(200), 1),
+            (dollars(200), Money::from_cents(i64::MAX), 1),
+        ]
+    );
+    assert_eq!(bank.balance_histogram(&[]), vec![(Money::from_cents(i64::MIN)..Money::from_cents(i64::MAX), 5)]);
+}
+
+#[test]
+fn withdrawals_past_the_free_allowance_pay_the_fee_until_the_month_turns() {
+    let mut bank = bank_with_accounts();
+    let schedule = FeeSchedule { free_withdrawals: 3, fee: dollars(1), monthly_maintenance: Money::ZERO, maintenance_waiver_balance: None };
+    bank.set_fee_schedule("alice".to_string(), Some(schedule)).unwrap();
+    bank.deposit("alice".to_string(), dollars(100)).unwrap();
+    for _ in 0..3 {
+        bank.withdraw("alice".to_string(), dollars(10)).unwrap();
+    }
+    assert_eq!(bank.balance("alice".to_string()), Some(dollars(70)));
+
+    // The fourth withdrawal this month is charged, and so is every one after it.
+    bank.withdraw("alice".to_string(), dollars(10)).unwrap();
+    bank.withdraw("alice".to_string(), dollars(10)).unwrap();
+    assert_eq!(bank.balance("alice".to_string()), Some(dollars(48)));
+    let fees: Vec<usize> = bank
+        .get_transactions("alice".to_string())
+        .unwrap()
+        .iter()
+        .enumerate()
+        .filter(|(_, t)| matches!(t.kind, TransactionKind::Fee(fee, FeeType::Transaction) if fee == dollars(1)))
+        .map(|(i, _)| i)
+        .collect();
+    assert_eq!(fees, vec![5, 7]);
+    // A withdrawal the fee would overdraw is refused whole.
+    assert_eq!(bank.withdraw("alice".to_string(), dollars(48)), Err(BankError::InsufficientFunds));
+
+    let alice = bank.account("alice").unwrap();
+    assert_eq!(alice.withdrawal_fee(SystemTime::now()), dollars(1));
+    assert_eq!(alice.withdrawal_fee(SystemTime::now() + Duration::from_secs(32 * 86_400)), Money::ZERO);
+}
+
+#[test]
+fn amortization_schedule_pays_the_loan_off_in_level_payments() {
+    let mut bank = Bank::new();
+    bank.create_account_with_limit("card".to_string(), AccountType::Credit, dollars(20_000)).unwrap();
+    bank.create_account("saver".to_string(), AccountType::Savings).unwrap();
+    let card = bank.account("card").unwrap();
+
+    // 10000.00 over a year at 12%: 10000 * 0.01 / (1 - 1.01^-12) = 888.49 a month.
+    let schedule = card.amortization_schedule(dollars(10_000), 0.12, 12);
+    assert_eq!(schedule.len(), 12);
+    let first = &schedule[0];
+    assert_eq!(
+        (first.period, first.amount, first.interest, first.principal),
+        (1, Money::from_cents(88_849), dollars(100), Money::from_cents(78_849))
+    );
+    assert_eq!(first.remaining, Money::from_cents(921_151));
+    assert!(schedule[..11].iter().all(|payment| payment.amount == Money::from_cents(88_849)));
+    assert!((schedule[11].amount - Money::from_cents(88_849)).abs() <= Money::from_cents(5));
+    assert_eq!(schedule[11].remaining, Money::ZERO);
+    assert_eq!(schedule.iter().map(|payment| payment.principal).sum::<Money>(), dollars(10_000));
+    assert!(schedule.iter().all(|payment| payment.amount == payment.principal + payment.interest));
+
+    // Interest-free, the principal is split evenly; only Credit accounts have a schedule.
+    let flat = card.amortization_schedule(dollars(1_200), 0.0, 12);
+    assert!(flat.iter().all(|payment| payment.amount == dollars(100) && payment.interest == Money::ZERO));
+    assert!(bank.account("saver").unwrap().amortization_schedule(dollars(1_200), 0.1, 12).is_empty());
+    assert!(card.amortization_schedule(dollars(1_200), 0.1, 0).is_empty());
+    assert_eq!(bank.balance("card".to_string()), Some(Money::ZERO));
+}
+
+#[test]
+fn finds_the_first_transaction_matching_a_predicate() {
+    let mut bank = bank_with_accounts();
+    bank.deposit("alice".to_string(), dollars(1_000)).unwrap();
+    bank.withdraw("alice".to_string(), dollars(50)).unwrap();
+    bank.withdraw("alice".to_string(), dollars(150)).unwrap();
+    bank.withdraw("alice".to_string(), dollars(300)).unwrap();
+    let alice = bank.account("alice").unwrap();
+
+    let over_100 = |t: &Transaction| matches!(t.kind, TransactionKind::Withdrawal(amount) if amount > dollars(100));
+    let (index, found) = alice.find_transaction_where(over_100).unwrap();
+    assert_eq!(index, 2);
+    assert!(matches!(found.kind, TransactionKind::Withdrawal(amount) if amount == dollars(150)));
+    assert!(alice.find_transaction_where(|t| matches!(t.kind, TransactionKind::TransferOut(..))).is_none());
+    assert!(bank.account("bob").unwrap().find_transaction_where(|_| true).is_none());
+}
+
+#[test]
+fn exports_list_accounts_by_number_and_repeat_byte_for_byte() {
+    let mut bank = Bank::new();
+    for number in ["zed", "alice", "mike"] {
+        bank.create_account(number.to_string(), AccountType::Checking).unwrap();
+        bank.deposit(number.to_string(), dollars(10)).unwrap();
+    }
+    let dir = std::env::temp_dir();
+    let pid = std::process::id();
+    let export = |name: &str, write: &dyn Fn(&std::path::Path)| {
+        let path = dir.join(format!("bank-ordered-{}-{}", pid, name));
+        write(&path);
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        contents
+    };
+    let saved = export("save", &|path| bank.save_to_file(path).unwrap());
+    assert_eq!(saved, export("save", &|path| bank.save_to_file(path).unwrap()));
+    let script = export("sql", &|path| bank.export_sql_script(path).unwrap());
+    assert_eq!(script, export("sql", &|path| bank.export_sql_script(path).unwrap()));
+
+    let position = |text: &str, number: &str| text.find(&format!("'{}'", number)).unwrap();
+    assert!(position(&script, "alice") < position(&script, "mike") && position(&script, "mike") < position(&script, "zed"));
+    let position = |text: &str, number: &str| text.find(&format!("\"{}\"", number)).unwrap();
+    assert!(position(&saved, "alice") < position(&saved, "mike") && position(&saved, "mike") < position(&saved, "zed"));
+    let numbers: Vec<&String> = bank.accounts().map(|(number, _)| number).collect();
+    assert_eq!(numbers, vec!["alice", "mike", "zed"]);
+}
+
+#[test]
+fn interest_forecast_sums_each_accounts_projection() {
+    let mut bank = bank_with_accounts();
+    bank.create_account("saver".to_string(), AccountType::Savings).unwrap();
+    bank.create_account_with_limit("card".to_string(), AccountType::Credit, dollars(1_000)).unwrap();
+    for (number, balance, rate) in [("bob", 10_000, 0.02), ("saver", 5_000, 0.04)] {
+        bank.deposit(number.to_string(), dollars(balance)).unwrap();
+        bank.set_compound_frequency(number.to_string(), CompoundFrequency::Annually).unwrap();
+        bank.set_interest_rate(number.to_string(), rate).unwrap();
+    }
+    bank.withdraw("card".to_string(), dollars(500)).unwrap();
+    bank.set_interest_rate("card".to_string(), 0.2).unwrap();
+
+    let projection = |number: &str| bank.account(number).unwrap().projected_interest(12);
+    assert_eq!((projection("bob"), projection("saver"), projection("card")), (dollars(200), dollars(200), Money::ZERO));
+    assert_eq!(bank.forecast_interest(12), projection("bob") + projection("saver"));
+    // Part of a compounding year grows at the fractional power: 99.50 and 99.02.
+    assert_eq!(bank.forecast_interest(6), Money::from_cents(9_950 + 9_902));
+    assert_eq!(bank.forecast_interest(0), Money::ZERO);
+    // A forecast changes nothing.
+    assert_eq!(bank.balance("bob".to_string()), Some(dollars(10_000)));
+}
+
+#[test]
+fn household_statement_combines_accounts_and_reports_missing_ones() {
+    let mut bank = bank_with_accounts();
+    bank.deposit("alice".to_string(), dollars(120)).unwrap();
+    bank.deposit("bob".to_string(), dollars(30)).unwrap();
+    let period = Period {
+        from: SystemTime::now() - Duration::from_secs(86_400),
+        to: SystemTime::now() + Duration::from_secs(86_400),
+    };
+
+    let numbers = ["alice".to_string(), "carol".to_string(), "bob".to_string()];
+    let household = bank.household_statement(&numbers, &period);
+    let sections: Vec<&str> = household.sections.iter().map(|section| section.account_number.as_str()).collect();
+    assert_eq!(sections, vec!["alice", "bob"]);
+    assert_eq!(household.missing, vec!["carol".to_string()]);
+    assert_eq!(household.opening_balance, Money::ZERO);
+    assert_eq!(household.closing_balance, dollars(150));
+}
+
+#[test]
+fn rate_scenario_projects_without_changing_rates() {
+    let mut bank = bank_with_accounts();
+    bank.deposit("bob".to_string(), dollars(10_000)).unwrap();
+    bank.set_compound_frequency("bob".to_string(), CompoundFrequency::Annually).unwrap();
+    bank.set_interest_rate("bob".to_string(), 0.02).unwrap();
+
+    let unchanged = bank.simulate_rate_scenario(&HashMap::new(), 12);
+    assert_eq!(unchanged.current_interest, bank.forecast_interest(12));
+    assert_eq!(unchanged.projected_interest, unchanged.current_interest);
+
+    let raised = bank.simulate_rate_scenario(&HashMap::from([(AccountType::Savings, 0.01)]), 12);
+    assert_eq!(raised.current_interest, dollars(200));
+    assert_eq!(raised.projected_interest, dollars(300));
+    assert_eq!(bank.forecast_interest(12), dollars(200));
+}
+
+#[test]
+fn accounts_without_their_own_rate_are_projected_at_their_types_rate() {
+    let mut bank = bank_with_accounts();
+    bank.deposit("bob".to_string(), dollars(10_000)).unwrap();
+    bank.set_compound_frequency("bob".to_string(), CompoundFrequency::Annually).unwrap();
+    bank.set_type_interest_rate(AccountType::Savings, 0.03);
+    let bob = bank.account("bob").unwrap();
+    assert_eq!(bank.effective_rate(bob), 0.03);
+
+    assert_eq!(bank.forecast_interest(12), dollars(300));
+    assert_eq!(bank.weighted_average_deposit_rate(), 0.03);
+    let raised = bank.simulate_rate_scenario(&HashMap::from([(AccountType::Savings, 0.01)]), 12);
+    assert_eq!((raised.current_interest, raised.projected_interest), (dollars(300), dollars(400)));
+    let bob_profit = bank.account_profitability().into_iter().find(|(number, _)| number == "bob").map(|(_, profit)| profit);
+    assert_eq!(bob_profit, Some(-dollars(300)));
+
+    // An account's own rate still wins over its type's.
+    bank.set_interest_rate("bob".to_string(), 0.02).unwrap();
+    assert_eq!(bank.effective_rate(bank.account("bob").unwrap()), 0.02);
+    assert_eq!(bank.forecast_interest(12), dollars(200));
+}
+
+#[test]
+fn credit_accounts_earn_and_savings_accounts_cost() {
+    let mut bank = Bank::new();
+    bank.create_account_with_limit("card".to_string(), AccountType::Credit, dollars(5_000)).unwrap();
+    bank.create_account("saver".to_string(), AccountType::Savings).unwrap();
+    bank.withdraw("card".to_string(), dollars(1_000)).unwrap();
+    bank.set_interest_rate("card".to_string(), 0.2).unwrap();
+    bank.deposit("saver".to_string(), dollars(20_000)).unwrap();
+    bank.set_compound_frequency("saver".to_string(), CompoundFrequency::Annually).unwrap();
+    bank.set_interest_rate("saver".to_string(), 0.03).unwrap();
+
+    assert_eq!(
+        bank.account_profitability(),
+        vec![("card".to_string(), dollars(200)), ("saver".to_string(), -dollars(600))]
+    );
+}
+
+#[test]
+fn expired_authorization_is_reversed_and_released() {
+    let mut bank = bank_with_accounts();
+    bank.deposit("alice".to_string(), dollars(100)).unwrap();
+    let expires = SystemTime::now() + Duration::from_secs(3_600);
+    let id = bank.authorize("alice".to_string(), dollars(70), expires).unwrap();
+    assert_eq!(bank.available_balance("alice".to_string()), Some(dollars(30)));
+    assert_eq!(bank.withdraw("alice".to_string(), dollars(40)), Err(BankError::InsufficientFunds));
+
+    assert_eq!(bank.expire_authorizations(SystemTime::now()), Vec::<u64>::new());
+    assert_eq!(bank.expire_authorizations(expires), vec![id]);
+    assert_eq!(bank.available_balance("alice".to_string()), Some(dollars(100)));
+    assert_eq!(bank.balance("alice".to_string()), Some(dollars(100)));
+    let last = bank.get_transactions("alice".to_string()).unwrap().last().unwrap();
+    assert!(matches!(last.kind, TransactionKind::AuthReversed(amount) if amount == dollars(70)));
+    assert_eq!(bank.settle_authorization(id), Err(BankError::TransactionNotFound));
+}
+
+#[test]
+fn settled_authorization_withdraws_the_held_amount() {
+    let mut bank = bank_with_accounts();
+    bank.deposit("alice".to_string(), dollars(100)).unwrap();
+    let id = bank.authorize("alice".to_string(), dollars(70), SystemTime::now() + Duration::from_secs(3_600)).unwrap();
+    bank.settle_authorization(id).unwrap();
+    assert_eq!(bank.balance("alice".to_string()), Some(dollars(30)));
+    assert_eq!(bank.available_balance("alice".to_string()), Some(dollars(30)));
+}
+
+#[test]
+fn prometheus_metrics_lists_balances_and_totals() {
+    let mut bank = bank_with_accounts();
+    bank.create_account("we\"ird".to_string(), AccountType::Checking).unwrap();
+    bank.create_account_with_limit("card".to_string(), AccountType::Credit, dollars(500)).unwrap();
+    bank.deposit("alice".to_string(), Money::from_cents(12_345)).unwrap();
+    bank.withdraw("card".to_string(), dollars(20)).unwrap();
+
+    let metrics = bank.prometheus_metrics();
+    let lines: Vec<&str> = metrics.lines().collect();
+    for expected in [
+        "bank_account_balance{account=\"alice\",type=\"checking\"} 123.45",
+        "bank_account_balance{account=\"bob\",type=\"savings\"} 0.00",
+        "bank_account_balance{account=\"card\",type=\"credit\"} -20.00",
+        "bank_account_balance{account=\"we\\\"ird\",type=\"checking\"} 0.00",
+        "bank_total_assets 20.00",
+        "bank_total_deposits 123.45",
+        "bank_accounts{type=\"checking\"} 2",
+        "bank_accounts{type=\"credit\"} 1",
+    ] {
+        assert!(lines.contains(&expected), "missing {}", expected);
+    }
+}
+
+#[test]
+fn transfer_cost_breakdown_separates_fee_and_fx_cost() {
+    let mut bank = bank_with_accounts();
+    bank.create_account_in_currency("euro".to_string(), AccountType::Checking, Currency::new("EUR")).unwrap();
+    bank.set_exchange_rate(Currency::new("USD"), Currency::new("EUR"), 0.90, 0.92);
+    let schedule = FeeSchedule {
+        free_withdrawals: 0,
+        fee: Money::from_cents(150),
+        monthly_maintenance: Money::ZERO,
+        maintenance_waiver_balance: None,
+    };
+    bank.set_fee_schedule("alice".to_string(), Some(schedule)).unwrap();
+
+    let same = bank.transfer_cost_breakdown("alice", "bob", dollars(100)).unwrap();
+    assert_eq!(
+        same,
+        TransferCost {
+            principal: dollars(100),
+            fee: Money::from_cents(150),
+            fx_cost: Money::ZERO,
+            total_debited: Money::from_cents(10_150),
+            credited: dollars(100),
+        }
+    );
+
+    // 100.00 USD buys 90.00 EUR at the bid; at the 0.91 mid rate 90.00 EUR is worth 98.90 USD.
+    let cross = bank.transfer_cost_breakdown("alice", "euro", dollars(100)).unwrap();
+    assert_eq!(cross.credited, dollars(90));
+    assert_eq!(cross.fx_cost, Money::from_cents(110));
+    assert_eq!(cross.total_debited, Money::from_cents(10_150));
+    assert_eq!(bank.balance("alice".to_string()), Some(Money::ZERO));
+}
+
+#[test]
+fn loaded_exchange_rates_price_cross_currency_transfers() {
+    let mut bank = bank_with_accounts();
+    bank.create_account_in_currency("euro".to_string(), AccountType::Checking, Currency::new("EUR")).unwrap();
+    bank.set_exchange_rate(Currency::new("USD"), Currency::new("EUR"), 0.5, 0.5);
+    bank.deposit("alice".to_string(), dollars(300)).unwrap();
+
+    let path = std::env::temp_dir().join(format!("bank-rates-{}.csv", std::process::id()));
+    fs::write(&path, "base,quote,rate\nusd,eur,0.92\n\nGBP,USD,1.25\n").unwrap();
+    assert_eq!(bank.load_exchange_rates(&path), Ok(2));
+    // The reloaded pair replaces the rate set before.
+    bank.transfer("alice".to_string(), "euro".to_string(), dollars(100)).unwrap();
+    assert_eq!(bank.balance("euro".to_string()), Some(dollars(92)));
+
+    // A malformed row is reported by line and nothing from the file is loaded.
+    fs::write(&path, "USD,EUR,0.80\nUSD,JPY\n").unwrap();
+    let error = bank.load_exchange_rates(&path).unwrap_err();
+    assert_eq!(error, BankError::InvalidData("line 2: expected base,quote,rate but found \"USD,JPY\"".to_string()));
+    fs::write(&path, "USD,EUR,-1\n").unwrap();
+    assert!(matches!(bank.load_exchange_rates(&path), Err(BankError::InvalidData(_))));
+    fs::remove_file(&path).unwrap();
+    bank.transfer("alice".to_string(), "euro".to_string(), dollars(100)).unwrap();
+    assert_eq!(bank.balance("euro".to_string()), Some(dollars(184)));
+}
+
+#[test]
+fn transaction_size_percentiles_use_nearest_rank() {
+    let mut bank = bank_with_accounts();
+    assert_eq!(bank.account("alice").unwrap().transaction_size_percentiles(&[0.5, 0.9]), vec![Money::ZERO; 2]);
+    for amount in [10, 20, 30, 40, 50, 60, 70, 80, 90, 100] {
+        bank.deposit("alice".to_string(), dollars(amount)).unwrap();
+    }
+    bank.withdraw("alice".to_string(), dollars(5)).unwrap();
+
+    // Eleven sizes: the median is the 6th smallest and p90 the 10th.
+    let percentiles = bank.account("alice").unwrap().transaction_size_percentiles(&[0.5, 0.9, 1.0]);
+    assert_eq!(percentiles, vec![dollars(50), dollars(90), dollars(100)]);
+}
+
+#[test]
+fn net_settle_moves_only_the_difference() {
+    let mut bank = bank_with_accounts();
+    bank.deposit("alice".to_string(), dollars(100)).unwrap();
+    bank.deposit("bob".to_string(), dollars(50)).unwrap();
+    let batch = [
+        ("alice".to_string(), "bob".to_string(), dollars(100)),
+        ("bob".to_string(), "alice".to_string(), dollars(30)),
+    ];
+    bank.net_settle(&batch).unwrap();
+
+    assert_eq!(bank.balance("alice".to_string()), Some(dollars(30)));
+    assert_eq!(bank.balance("bob".to_string()), Some(dollars(120)));
+    let sent: Vec<&TransactionKind> = bank
+        .get_transactions("alice".to_string())
+        .unwrap()
+        .iter()
+        .map(|t| &t.kind)
+        .filter(|kind| matches!(kind, TransactionKind::TransferOut(..) | TransactionKind::TransferIn(..)))
+        .collect();
+    assert!(matches!(sent.as_slice(), [TransactionKind::TransferOut(amount, to)] if *amount == dollars(70) && to == "bob"));
+}
+
+#[test]
+fn failed_net_settlement_is_undone() {
+    let mut bank = bank_with_accounts();
+    bank.create_account("carol".to_string(), AccountType::Checking).unwrap();
+    bank.deposit("alice".to_string(), dollars(100)).unwrap();
+    let batch = [
+        ("alice".to_string(), "bob".to_string(), dollars(60)),
+        ("bob".to_string(), "carol".to_string(), dollars(500)),
+    ];
+    let events = bank.events().len();
+    assert_eq!(bank.net_settle(&batch), Err(BankError::InsufficientFunds));
+    assert_eq!(bank.balance("alice".to_string()), Some(dollars(100)));
+    assert_eq!(bank.balance("bob".to_string()), Some(Money::ZERO));
+    // The transfer that went through leaves no trace in the ledgers or the events.
+    assert_eq!(bank.get_transactions("alice".to_string()).unwrap().len(), 1);
+    assert_eq!(bank.events().len(), events);
+}
+
+#[test]
+fn abandoned_empty_accounts_are_closed_not_removed() {
+    let mut bank = bank_with_accounts();
+    bank.create_account("carol".to_string(), AccountType::Checking).unwrap();
+    bank.deposit("bob".to_string(), dollars(5)).unwrap();
+    bank.deposit("carol".to_string(), dollars(5)).unwrap();
+    bank.withdraw("carol".to_string(), dollars(5)).unwrap();
+
+    assert_eq!(bank.close_abandoned(Duration::from_secs(3_600), SystemTime::now()), Vec::<String>::new());
+    let later = SystemTime::now() + Duration::from_secs(2 * 3_600);
+    assert_eq!(bank.close_abandoned(Duration::from_secs(3_600), later), vec!["alice".to_string(), "carol".to_string()]);
+    assert_eq!(bank.account_status("carol"), Ok(AccountStatus::Closed));
+    assert_eq!(bank.account_status("bob"), Ok(AccountStatus::Open));
+    assert_eq!(bank.get_transactions("carol".to_string()).unwrap().len(), 2);
+    assert_eq!(bank.close_abandoned(Duration::from_secs(3_600), later), Vec::<String>::new());
+
+    let replayed = Bank::from_events(bank.events()).unwrap();
+    assert_eq!(replayed.account_status("alice"), Ok(AccountStatus::Closed));
+}
+
+#[test]
+fn half_applied_transfers_are_completed_or_refunded_with_their_fee() {
+    let schedule = FeeSchedule {
+        free_withdrawals: 0,
+        fee: Money::from_cents(150),
+        monthly_maintenance: Money::ZERO,
+        maintenance_waiver_balance: None,
+    };
+    let mut sending = bank_with_accounts();
+    sending.create_account("carol".to_string(), AccountType::Checking).unwrap();
+    sending.set_fee_schedule("alice".to_string(), Some(schedule)).unwrap();
+    sending.deposit("alice".to_string(), dollars(200)).unwrap();
+    sending.transfer("alice".to_string(), "bob".to_string(), dollars(50)).unwrap();
+    sending.transfer("alice".to_string(), "carol".to_string(), dollars(30)).unwrap();
+    sending.transfer("alice".to_string(), "bob".to_string(), dollars(20)).unwrap();
+    let path = std::env::temp_dir().join(format!("bank-half-applied-{}.json", std::process::id()));
+    sending.export_account("alice".to_string(), &path).unwrap();
+
+    // A bank holding alice's debits but none of the credits, with carol since closed.
+    let mut bank = Bank::new();
+    bank.create_account("bob".to_string(), AccountType::Savings).unwrap();
+    bank.create_account("carol".to_string(), AccountType::Checking).unwrap();
+    bank.close_account("carol").unwrap();
+    bank.import_account(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(bank.balance("alice".to_string()), Some(Money::from_cents(9_550)));
+
+    assert_eq!(
+        bank.repair_incomplete_transfers(),
+        vec![
+            "Completed transfer of 50.00 from alice to bob".to_string(),
+            "Completed transfer of 20.00 from alice to bob".to_string(),
+            "Rolled back transfer of 30.00 from alice to carol".to_string(),
+        ]
+    );
+    assert_eq!(bank.balance("alice".to_string()), Some(dollars(127)));
+    assert_eq!(bank.balance("bob".to_string()), Some(dollars(70)));
+    assert_eq!(bank.balance("carol".to_string()), Some(Money::ZERO));
+    assert_eq!(bank.repair_incomplete_transfers(), Vec::<String>::new());
+    assert_eq!(bank.repair_transfer("alice".to_string(), 2), Err(BankError::TransferComplete));
+    let repairs = bank.audit_log().iter().filter(|record| record.operation == "repair_transfer" && record.succeeded()).count();
+    assert_eq!(repairs, 3);
+
+    // The credit is dated with the debit and pairs with it like any other.
+    let alice = bank.get_transactions("alice".to_string()).unwrap();
+    let bob = bank.get_transactions("bob".to_string()).unwrap();
+    assert_eq!(bob[0].timestamp, alice[1].timestamp);
+    bank.reverse_transaction("bob".to_string(), 1).unwrap();
+    assert_eq!(bank.balance("alice".to_string()), Some(Money::from_cents(17_850)));
+    assert_eq!(bank.balance("bob".to_string()), Some(dollars(20)));
+}
+
+#[test]
+fn exported_account_moves_to_another_bank_and_replays_from_its_events() {
+    let mut source = bank_with_accounts();
+    source.deposit("alice".to_string(), dollars(100)).unwrap();
+    source.transfer("alice".to_string(), "bob".to_string(), dollars(30)).unwrap();
+    source.set_overdraft_limit("alice".to_string(), dollars(50)).unwrap();
+    let path = std::env::temp_dir().join(format!("bank-moved-account-{}.json", std::process::id()));
+    source.export_account("alice".to_string(), &path).unwrap();
+    assert_eq!(source.import_account(&path), Err(BankError::AccountExists));
+
+    let mut target = Bank::new();
+    target.create_account("bob".to_string(), AccountType::Savings).unwrap();
+    assert_eq!(target.import_account(&path), Ok("alice".to_string()));
+    assert_eq!(target.import_account(&path), Err(BankError::AccountExists));
+    fs::remove_file(&path).unwrap();
+    let (moved, original) = (target.account("alice").unwrap(), source.account("alice").unwrap());
+    assert_eq!(moved.balance(), dollars(70));
+    assert_eq!(moved.account_type(), &AccountType::Checking);
+    assert_eq!(moved.transactions().len(), original.transactions().len());
+    assert!(moved.verify_chain());
+    target.withdraw("alice".to_string(), dollars(110)).unwrap();
+
+    let imports: Vec<bool> = target.audit_log().iter().filter(|r| r.operation == "import_account").map(|r| r.succeeded()).collect();
+    assert_eq!(imports, vec![true, false]);
+    let replayed = Bank::from_events(target.events()).unwrap();
+    assert_eq!(replayed.balance("alice".to_string()), Some(-dollars(40)));
+    assert_eq!(replayed.get_transactions("alice".to_string()).unwrap().len(), 3);
+}
+
+#[test]
+fn sql_script_inserts_every_account_and_entry() {
+    let mut bank = bank_with_accounts();
+    bank.create_account("o'neil".to_string(), AccountType::Checking).unwrap();
+    bank.deposit("alice".to_string(), dollars(100)).unwrap();
+    bank.transfer("alice".to_string(), "o'neil".to_string(), dollars(25)).unwrap();
+
+    let path = std::env::temp_dir().join(format!("bank-script-{}.sql", std::process::id()));
+    bank.export_sql_script(&path).unwrap();
+    let script = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    let rows = |table: &str| script.lines().filter(|line| line.starts_with(&format!("INSERT INTO {} ", table))).count();
+    assert_eq!(rows("accounts"), 3);
+    assert_eq!(rows("transactions"), 3);
+    assert!(script.contains("INSERT INTO accounts VALUES ('o''neil', 'Checking', 25.00, 'USD', 'Open', "));
+    assert!(script.starts_with("PRAGMA foreign_keys = ON;\nBEGIN TRANSACTION;\n"));
+    assert!(script.ends_with("COMMIT;\n"));
+}
+
+// Needs the sqlite3 shell on the PATH; without it there is nothing to load the script into.
+#[test]
+fn sql_script_loads_into_sqlite_and_keeps_entries_tied_to_accounts() {
+    let mut bank = bank_with_accounts();
+    bank.deposit("alice".to_string(), dollars(100)).unwrap();
+    bank.transfer("alice".to_string(), "bob".to_string(), dollars(25)).unwrap();
+    let path = std::env::temp_dir().join(format!("bank-sqlite-{}.sql", std::process::id()));
+    bank.export_sql_script(&path).unwrap();
+    let script = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let query = |sql: &str| {
+        let input = format!("{}{}\n", script, sql);
+        let mut child = match std::process::Command::new("sqlite3")
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(_) => return None,
+        };
+        child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+        let output = child.wait_with_output().unwrap();
+        Some((String::from_utf8(output.stdout).unwrap(), String::from_utf8(output.stderr).unwrap()))
+    };
+    let Some((counts, _)) = query("SELECT (SELECT COUNT(*) FROM accounts) || ',' || (SELECT COUNT(*) FROM transactions);") else {
+        eprintln!("sqlite3 not found; skipping");
+        return;
+    };
+    assert_eq!(counts.trim(), "2,3");
+    let (per_account, _) = query("SELECT account_number, COUNT(*) FROM transactions GROUP BY account_number ORDER BY 1;").unwrap();
+    assert_eq!(per_account.trim(), "alice|2\nbob|1");
+    let orphan = "INSERT INTO transactions (account_number, seq, type, amount, currency, timestamp) \
+                  VALUES ('nobody', 1, 'Deposit', 1, 'USD', 0);";
+    let (_, error) = query(orphan).unwrap();
+    assert!(error.contains("FOREIGN KEY constraint failed"), "{}", error);
+}
+
+#[test]
+fn fixed_width_statement_places_fields_and_refuses_values_that_overflow() {
+    let mut bank = bank_with_accounts();
+    bank.deposit("alice".to_string(), dollars(1_250)).unwrap();
+    bank.transfer("alice".to_string(), "bob".to_string(), Money::from_cents(5)).unwrap();
+    let field = |column, width, alignment, pad| FixedWidthField { column, width, alignment, pad };
+    let mut spec = FixedWidthSpec {
+        fields: vec![
+            field(Column::Index, 4, Alignment::Right, '0'),
+            field(Column::Type, 12, Alignment::Left, ' '),
+            field(Column::Amount, 10, Alignment::Right, ' '),
+            field(Column::Counterparty, 6, Alignment::Left, '.'),
+        ],
+    };
+    assert_eq!(spec.line_width(), 32);
+
+    let path = std::env::temp_dir().join(format!("bank-fixed-width-{}.txt", std::process::id()));
+    bank.export_statement_fixed_width("alice".to_string(), &path, &spec).unwrap();
+    let output = fs::read_to_string(&path).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines.iter().all(|line| line.chars().count() == 32));
+    assert_eq!(lines[0], "0001Deposit        1250.00......");
+    assert_eq!(lines[1], "0002TransferOut       0.05bob...");
+    // Each field reads back from its own columns.
+    assert_eq!((&lines[1][..4], lines[1][4..16].trim_end(), lines[1][16..26].trim_start()), ("0002", "TransferOut", "0.05"));
+
+    spec.fields[2].width = 6;
+    let error = bank.export_statement_fixed_width("alice".to_string(), &path, &spec).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    assert_eq!(error.to_string(), "Amount value \"1250.00\" is wider than its 6 characters");
+    assert_eq!(fs::read_to_string(&path).unwrap(), output);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn transfer_graph_lists_accounts_and_recipients_in_order() {
+    let mut bank = bank_with_accounts();
+    bank.create_account("carol".to_string(), AccountType::Checking).unwrap();
+    bank.deposit("carol".to_string(), dollars(100)).unwrap();
+    bank.transfer("carol".to_string(), "bob".to_string(), dollars(10)).unwrap();
+    bank.transfer("carol".to_string(), "alice".to_string(), dollars(20)).unwrap();
+    bank.transfer("carol".to_string(), "bob".to_string(), dollars(5)).unwrap();
+
+    let graph: Vec<(String, Vec<(String, Money)>)> = bank.transfer_graph().into_iter().collect();
+    assert_eq!(
+        graph,
+        vec![
+            ("alice".to_string(), vec![]),
+            ("bob".to_string(), vec![]),
+            ("carol".to_string(), vec![("alice".to_string(), dollars(20)), ("bob".to_string(), dollars(15))]),
+        ]
+    );
+}
+
+#[test]
+fn hash_chain_detects_an_altered_transaction() {
+    let mut bank = bank_with_accounts();
+    bank.deposit("alice".to_string(), dollars(100)).unwrap();
+    bank.withdraw("alice".to_string(), dollars(37)).unwrap();
+    bank.deposit("alice".to_string(), dollars(5)).unwrap();
+    bank.deposit("bob".to_string(), dollars(20)).unwrap();
+    assert!(bank.account("alice").unwrap().verify_chain());
+    assert!(bank.account("bob").unwrap().verify_chain());
+
+    let path = std::env::temp_dir().join(format!("bank-chain-{}.json", std::process::id()));
+    bank.save_to_file(&path).unwrap();
+    let saved = fs::read_to_string(&path).unwrap();
+    assert!(Bank::load_from_file(&path).unwrap().account("alice").unwrap().verify_chain());
+
+    // Shrinking the withdrawal, even with the balances left alone, breaks alice's chain only.
+    assert_eq!(saved.matches("\"amount\":37").count(), 1);
+    fs::write(&path, saved.replacen("\"amount\":37", "\"amount\":7", 1)).unwrap();
+    let tampered = Bank::load_from_file(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(!tampered.account("alice").unwrap().verify_chain());
+    assert!(tampered.account("bob").unwrap().verify_chain());
+}
+
+#[test]
+fn unexplained_growth_flags_a_tampered_balance() {
+    let mut bank = bank_with_accounts();
+    bank.deposit("alice".to_string(), dollars(100)).unwrap();
+    bank.transfer("alice".to_string(), "bob".to_string(), dollars(25)).unwrap();
+    assert!(!bank.unexplained_growth("alice".to_string(), Money::ZERO));
+    assert!(!bank.unexplained_growth("bob".to_string(), Money::ZERO));
+
+    let path = std::env::temp_dir().join(format!("bank-tampered-{}.json", std::process::id()));
+    bank.save_to_file(&path).unwrap();
+    let saved = fs::read_to_string(&path).unwrap();
+    fs::write(&path, saved.replacen("\"balance\":25", "\"balance\":2500", 1)).unwrap();
+    let tampered = Bank::load_from_file(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert!(tampered.unexplained_growth("bob".to_string(), dollars(1)));
+    assert!(!tampered.unexplained_growth("alice".to_string(), dollars(1)));
+}
+
+#[test]
+fn spending_is_grouped_by_derived_category() {
+    let mut bank = bank_with_accounts();
+    bank.deposit("alice".to_string(), dollars(500)).unwrap();
+    bank.withdraw_with_memo("alice".to_string(), dollars(40), Some("Corner Grocery".to_string())).unwrap();
+    bank.withdraw_with_memo("alice".to_string(), dollars(15), Some("grocery run".to_string())).unwrap();
+    bank.withdraw_with_memo("alice".to_string(), dollars(12), Some("cinema".to_string())).unwrap();
+    bank.transfer("alice".to_string(), "bob".to_string(), dollars(100)).unwrap();
+    let rules = [
+        CategoryRule { matcher: CategoryMatch::MemoKeyword("grocery".to_string()), category: Category::Food },
+        CategoryRule { matcher: CategoryMatch::Counterparty("bob".to_string()), category: Category::Transfers },
+    ];
+
+    let account = bank.account("alice").unwrap();
+    assert_eq!(classify_transaction(&account.transactions()[1], &rules), Category::Food);
+    let spending = account.spending_by_category(&rules);
+    assert_eq!(spending.get(&Category::Food), Some(&dollars(55)));
+    assert_eq!(spending.get(&Category::Transfers), Some(&dollars(100)));
+    assert_eq!(spending.get(&Category::Other), Some(&dollars(12)));
+    assert_eq!(spending.len(), 3);
+    assert!(account.verify_chain());
+}
+
+#[test]
+fn loan_to_deposit_ratio_divides_credit_owed_by_deposits() {
+    let mut bank = bank_with_accounts();
+    assert_eq!(bank.loan_to_deposit_ratio(), 0.0);
+    bank.create_account_with_limit("card".to_string(), AccountType::Credit, dollars(1_000)).unwrap();
+    bank.withdraw("card".to_string(), dollars(300)).unwrap();
+    assert_eq!(bank.loan_to_deposit_ratio(), f64::INFINITY);
+
+    bank.deposit("alice".to_string(), dollars(800)).unwrap();
+    bank.deposit("bob".to_string(), dollars(400)).unwrap();
+    assert_eq!(bank.loan_to_deposit_ratio(), 0.25);
+}
+
+#[test]
+fn journal_rollback_undoes_and_commit_keeps_changes() {
+    let mut bank = bank_with_accounts();
+    bank.deposit("alice".to_string(), dollars(100)).unwrap();
+
+    let mut journal = bank.begin_journal();
+    journal.transfer("alice".to_string(), "bob".to_string(), dollars(60)).unwrap();
+    journal.create_account("carol".to_string(), AccountType::Checking).unwrap();
+    journal.deposit("carol".to_string(), dollars(5)).unwrap();
+    journal.rollback();
+    assert_eq!(bank.balance("alice".to_string()), Some(dollars(100)));
+    assert_eq!(bank.balance("bob".to_string()), Some(Money::ZERO));
+    assert_eq!(bank.balance("carol".to_string()), None);
+    assert_eq!(bank.get_transactions("alice".to_string()).unwrap().len(), 1);
+
+    let mut journal = bank.begin_journal();
+    journal.transfer("alice".to_string(), "bob".to_string(), dollars(60)).unwrap();
+    journal.commit();
+    assert_eq!(bank.balance("alice".to_string()), Some(dollars(40)));
+    assert_eq!(bank.balance("bob".to_string()), Some(dollars(60)));
+}
+
+#[test]
+fn audit_records_survive_a_journal_rollback() {
+    let mut bank = bank_with_accounts();
+    let mut journal = bank.begin_journal();
+    journal.deposit("alice".to_string(), dollars(5)).unwrap();
+    assert_eq!(journal.withdraw("alice".to_string(), dollars(10)), Err(BankError::InsufficientFunds));
+    journal.rollback();
+    bank.deposit("alice".to_string(), dollars(1)).unwrap();
+    let log: Vec<(u64, &str, bool)> =
+        bank.audit_log().iter().map(|record| (record.sequence, record.operation.as_str(), record.succeeded())).collect();
+    assert_eq!(log[2..], [(3, "deposit", true), (4, "withdraw", false), (5, "deposit", true)]);
+}
+
+#[test]
+fn dropped_journal_rolls_back() {
+    let mut bank = bank_with_accounts();
+    {
+        let mut journal = bank.begin_journal();
+        journal.deposit("alice".to_string(), dollars(10)).unwrap();
+    }
+    assert_eq!(bank.balance("alice".to_string()), Some(Money::ZERO));
+}
+
+// Loads a one-account bank whose history is `(type, amount in dollars, days ago)` entries.
+fn bank_with_history(balance: i64, history: &[(&str, i64, u64)]) -> Bank {
+    let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_millis() as u64;
+    let transactions: Vec<String> = history
+        .iter()
+        .map(|(kind, amount, days_ago)| {
+            format!(r#"{{"type":"{}","amount":{},"timestamp":{}}}"#, kind, amount, now - days_ago * 86_400_000)
+        })
+        .collect();
+    let document = format!(
+        r#"{{"accounts":{{"acct":{{"account_type":"Checking","balance":{},"transactions":[{}]}}}}}}"#,
+        balance,
+        transactions.join(",")
+    );
+    let path = std::env::temp_dir().join(format!("bank-history-{}-{}.json", std::process::id(), balance));
+    fs::write(&path, document).unwrap();
+    let bank = Bank::load_from_file(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    bank
+}
+
+#[test]
+fn draining_account_has_a_depletion_estimate() {
+    // 1000.00 thirty days ago and 700.00 now is a burn of 10.00 a day.
+    let bank = bank_with_history(700, &[("Deposit", 1_000, 60), ("Withdrawal", 300, 10)]);
+    assert_eq!(bank.account("acct").unwrap().days_to_depletion(SystemTime::now()), Some(70));
+}
+
+#[test]
+fn growing_account_never_depletes() {
+    let bank = bank_with_history(300, &[("Deposit", 100, 60), ("Deposit", 200, 5)]);
+    assert_eq!(bank.account("acct").unwrap().days_to_depletion(SystemTime::now()), None);
+}
+
+#[test]
+fn transaction_limits_follow_the_account_currency() {
+    let mut bank = bank_with_accounts();
+    bank.create_account_in_currency("yen".to_string(), AccountType::Checking, Currency::new("JPY")).unwrap();
+    bank.set_transaction_limits(
+        Currency::new("USD"),
+        TransactionLimits { max_deposit: Some(dollars(10_000)), max_withdrawal: None, max_transfer: Some(dollars(500)) },
+    );
+    bank.set_transaction_limits(
+        Currency::new("JPY"),
+        TransactionLimits { max_deposit: Some(dollars(1_000_000)), max_withdrawal: None, max_transfer: None },
+    );
+
+    assert_eq!(bank.deposit("alice".to_string(), dollars(20_000)), Err(BankError::TransactionLimitExceeded));
+    bank.deposit("alice".to_string(), dollars(10_000)).unwrap();
+    bank.deposit("yen".to_string(), dollars(500_000)).unwrap();
+    assert_eq!(bank.deposit("yen".to_string(), dollars(1_500_000)), Err(BankError::TransactionLimitExceeded));
+
+    assert_eq!(
+        bank.transfer("alice".to_string(), "bob".to_string(), dollars(501)),
+        Err(BankError::TransactionLimitExceeded)
+    );
+    bank.transfer("alice".to_string(), "bob".to_string(), dollars(500)).unwrap();
+    bank.withdraw("alice".to_string(), dollars(9_000)).unwrap();
+}
+
+#[test]
+fn throughput_counts_transactions_per_bucket() {
+    let bank = bank_with_history(
+        150,
+        &[("Deposit", 100, 3), ("Deposit", 100, 3), ("Withdrawal", 80, 3), ("Deposit", 30, 1)],
+    );
+    let counts: Vec<usize> = bank.throughput(Duration::from_secs(86_400)).into_iter().map(|(_, count)| count).collect();
+    assert_eq!(counts, vec![3, 0, 1]);
+    assert!(bank.throughput(Duration::ZERO).is_empty());
+}
+
+#[test]
+fn migration_report_lists_defaulted_fields_without_writing() {
+    let legacy = r#"{"accounts":{
+        "old1":{"account_type":"Savings","balance":10,"interest_rate":0.01,"transactions":[]},
+        "old2":{"account_type":"Checking","balance":5,"is_active":true,"transactions":[]}
+    }}"#;
+    let path = std::env::temp_dir().join(format!("bank-legacy-{}.json", std::process::id()));
+    fs::write(&path, legacy).unwrap();
+    let report = Bank::migration_report(&path).unwrap();
+    let unchanged = fs::read_to_string(&path).unwrap() == legacy;
+    fs::remove_file(&path).unwrap();
+
+    assert!(unchanged);
+    assert_eq!(report.total_accounts, 2);
+    assert_eq!(report.accounts_affected, 2);
+    assert_eq!(report.defaulted_fields.get("bonus_rate"), Some(&2));
+    assert_eq!(report.defaulted_fields.get("auth_holds"), Some(&2));
+    assert_eq!(report.defaulted_fields.get("interest_rate"), Some(&1));
+    assert_eq!(report.defaulted_fields.get("balance"), None);
+}
+
+#[test]
+fn tenure_score_weights_balances_by_account_age() {
+    let now = SystemTime::now();
+    let millis_ago = |days: u64| (now - Duration::from_secs(days * 86_400)).duration_since(SystemTime::UNIX_EPOCH).unwrap().as_millis();
+    let account = |owner: &str, balance: i64, days: u64| {
+        format!(
+            r#"{{"account_type":"Savings","owner":"{owner}","balance":{balance},"created_at":{at},
+                "transactions":[{{"type":"Deposit","amount":{balance},"timestamp":{at}}}]}}"#,
+            at = millis_ago(days)
+        )
+    };
+    let document = format!(
+        r#"{{"accounts":{{"old":{},"new":{},"other":{}}}}}"#,
+        account("dana", 1_000, 730),
+        account("dana", 500, 365),
+        account("eli", 9_000, 730)
+    );
+    let path = std::env::temp_dir().join(format!("bank-tenure-{}.json", std::process::id()));
+    fs::write(&path, document).unwrap();
+    let mut bank = Bank::load_from_file(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    // 1000.00 held for two years plus 500.00 for one.
+    assert!((bank.customer_tenure_score("dana", now) - 2_500.0).abs() < 1e-6);
+    bank.set_owner("new".to_string(), None).unwrap();
+    assert!((bank.customer_tenure_score("dana", now) - 2_000.0).abs() < 1e-6);
+    assert_eq!(bank.customer_tenure_score("nobody", now), 0.0);
+}
+
+#[test]
+fn replay_skips_duplicated_events() {
+    let event = |id: u64, kind: EventKind| Event { id, kind };
+    let stream = vec![
+        event(1, EventKind::AccountOpened("alice".to_string(), AccountType::Checking, Currency::default())),
+        event(2, EventKind::AccountOpened("bob".to_string(), AccountType::Savings, Currency::default())),
+        event(3, EventKind::Deposited("alice".to_string(), dollars(100))),
+        event(4, EventKind::Transferred("alice".to_string(), "bob".to_string(), dollars(30))),
+        event(5, EventKind::Withdrawn("bob".to_string(), dollars(10), None)),
+    ];
+    let mut duplicated = stream.clone();
+    duplicated.insert(4, stream[2].clone());
+    duplicated.push(stream[3].clone());
+
+    let mut expected = Bank::new();
+    assert_eq!(expected.replay(&stream), Ok(5));
+    let mut bank = Bank::new();
+    assert_eq!(bank.replay(&duplicated), Ok(5));
+    assert_eq!(bank.replay(&stream), Ok(0));
+
+    for number in ["alice", "bob"] {
+        assert_eq!(bank.balance(number.to_string()), expected.balance(number.to_string()));
+        assert_eq!(
+            bank.get_transactions(number.to_string()).unwrap().len(),
+            expected.get_transactions(number.to_string()).unwrap().len()
+        );
+    }
+    assert_eq!(bank.balance("alice".to_string()), Some(dollars(70)));
+}
+
+#[test]
+fn external_transfer_ratio_weighs_transfers_by_amount() {
+    let mut bank = bank_with_accounts();
+    bank.create_account("outside".to_string(), AccountType::Checking).unwrap();
+    let internal = HashSet::from(["bob".to_string()]);
+    assert_eq!(bank.account("alice").unwrap().external_transfer_ratio(&internal), 0.0);
+
+    bank.deposit("alice".to_string(), dollars(1_000)).unwrap();
+    bank.transfer("alice".to_string(), "bob".to_string(), dollars(300)).unwrap();
+    bank.transfer("alice".to_string(), "outside".to_string(), dollars(100)).unwrap();
+    bank.transfer("bob".to_string(), "outside".to_string(), dollars(50)).unwrap();
+
+    assert_eq!(bank.account("alice").unwrap().external_transfer_ratio(&internal), 0.25);
+    assert_eq!(bank.account("bob").unwrap().external_transfer_ratio(&internal), 1.0);
+}
+
+#[test]
+fn scheduled_deposit_is_invisible_until_available() {
+    let mut bank = bank_with_accounts();
+    let payday = SystemTime::now() + Duration::from_secs(3 * 86_400);
+    bank.schedule_deposit("alice".to_string(), dollars(1_200), payday).unwrap();
+    assert_eq!(bank.schedule_deposit("carol".to_string(), dollars(1), payday), Err(BankError::AccountNotFound));
+
+    assert_eq!(bank.process_due(SystemTime::now()), 0);
+    assert_eq!(bank.balance("alice".to_string()), Some(Money::ZERO));
+    assert_eq!(bank.available_balance("alice".to_string()), Some(Money::ZERO));
+    assert_eq!(bank.withdraw("alice".to_string(), dollars(1)), Err(BankError::InsufficientFunds));
+    assert_eq!(bank.projected_balance("alice".to_string(), payday), Some(dollars(1_200)));
+
+    assert_eq!(bank.process_due(payday), 1);
+    assert_eq!(bank.balance("alice".to_string()), Some(dollars(1_200)));
+    assert!(bank.scheduled_deposits().is_empty());
+    assert_eq!(bank.process_due(payday), 0);
+}
+
+#[test]
+fn interest_expense_sums_only_postings_in_the_period() {
+    let bank = bank_with_history(
+        1_111,
+        &[("Deposit", 1_000, 90), ("Interest", 100, 60), ("Interest", 10, 20), ("Interest", 1, 5)],
+    );
+    let now = SystemTime::now();
+    let from = now - Duration::from_secs(30 * 86_400);
+    assert_eq!(bank.interest_expense(from, now - Duration::from_secs(10 * 86_400)), dollars(10));
+    assert_eq!(bank.interest_expense(from, now), dollars(11));
+    assert_eq!(bank.interest_expense(now, now), Money::ZERO);
+}
+
+#[test]
+fn formal_statement_has_every_section_and_a_stable_hash() {
+    let document = r#"{"accounts":{"acct":{"account_type":"Checking","owner":"Dana Reyes","balance":75,"transactions":[
+        {"type":"Deposit","amount":100,"timestamp":1704110400000},
+        {"type":"Withdrawal","amount":25,"timestamp":1706788800000}
+    ]}}}"#;
+    let path = std::env::temp_dir().join(format!("bank-formal-{}.json", std::process::id()));
+    fs::write(&path, document).unwrap();
+    let mut bank = Bank::load_from_file(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    bank.set_name("First Street Bank".to_string());
+    // 2024-01-15 to 2024-02-15.
+    let period = Period {
+        from: SystemTime::UNIX_EPOCH + Duration::from_secs(1_705_276_800),
+        to: SystemTime::UNIX_EPOCH + Duration::from_secs(1_707_955_200),
+    };
+
+    let statement = bank.formal_statement("acct".to_string(), &period).unwrap();
+    assert_eq!(statement.opening_balance, dollars(100));
+    assert_eq!(statement.closing_balance, dollars(75));
+    let text = statement.to_text();
+    for expected in [
+        "First Street Bank",
+        "Account holder: Dana Reyes",
+        "Period: 2024-01-15 to 2024-02-15",
+        "Opening balance: 100.00",
+        "  2024-02-01 12:00:00 Withdrawal 25.00",
+        "Closing balance: 75.00",
+        "Generated: ",
+    ] {
+        assert!(text.contains(expected), "missing {}", expected);
+    }
+    assert!(!text.contains("Deposit"));
+
+    let again = bank.formal_statement("acct".to_string(), &period).unwrap();
+    assert_eq!(again.content_hash, statement.content_hash);
+    assert_eq!(statement.content_hash, "99a9483ac0760b8119123f5e39161965f17cdfd31fdeabf0103c45c008a23be6");
+    assert!(matches!(bank.formal_statement("carol".to_string(), &period), Err(BankError::AccountNotFound)));
+}
+
+#[test]
+fn json_file_storage_round_trips_the_bank() {
+    let path = std::env::temp_dir().join(format!("bank-storage-{}.json", std::process::id()));
+    let storage = JsonFile::new(&path);
+    assert!(storage.load().unwrap().is_none());
+
+    let mut bank = bank_with_accounts();
+    bank.set_review_rules(ReviewRules { amount_threshold: Some(dollars(50)), round_multiple: None });
+    bank.deposit("alice".to_string(), dollars(100)).unwrap();
+    bank.deposit("alice".to_string(), dollars(60)).unwrap();
+    assert!(bank.clear_review(1));
+    bank.transfer("alice".to_string(), "bob".to_string(), dollars(100)).unwrap();
+    bank.deactivate_account("bob".to_string()).unwrap();
+    let later = SystemTime::now() + Duration::from_secs(86_400);
+    bank.schedule_transfer("alice".to_string(), "bob".to_string(), dollars(5), later).unwrap();
+    bank.schedule_deposit("alice".to_string(), dollars(7), later).unwrap();
+    bank.set_type_interest_rate(AccountType::Savings, 0.03);
+    bank.set_minimum_opening_deposit(AccountType::Checking, dollars(25));
+    let limits = TransactionLimits { max_deposit: Some(dollars(1_000)), ..TransactionLimits::default() };
+    bank.set_transaction_limits(Currency::default(), limits);
+    let velocity = VelocityLimits { daily_transfer_count: Some(3), ..VelocityLimits::default() };
+    bank.set_velocity_limits(AccountType::Checking, velocity);
+    bank.set_max_pin_attempts(1);
+    storage.save(&bank).unwrap();
+    let mut loaded = storage.load().unwrap().unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(loaded.scheduled_transfers().len(), 1);
+    assert_eq!(loaded.scheduled_deposits()[0].amount, dollars(7));
+    let queued: Vec<(u64, Money)> = loaded.review_queue().iter().map(|item| (item.id, item.amount)).collect();
+    assert_eq!(queued, [(2, dollars(60)), (3, dollars(100))]);
+    assert_eq!(loaded.effective_rate(loaded.account("bob").unwrap()), 0.03);
+    assert_eq!(loaded.minimum_opening_deposit(&AccountType::Checking), dollars(25));
+    let over_limit = loaded.check_transaction_limit("alice", Operation::Deposit, dollars(1_001));
+    assert_eq!(over_limit, Err(BankError::TransactionLimitExceeded));
+    assert_eq!(loaded.remaining_daily_allowance("alice").unwrap().transfers, Some(2));
+
+    assert_eq!(loaded.balance("alice".to_string()), Some(dollars(60)));
+    assert_eq!(loaded.get_account_type("bob".to_string()), Some(AccountType::Savings));
+    assert_eq!(loaded.deposit("bob".to_string(), dollars(1)), Err(BankError::AccountInactive));
+    let history = loaded.get_transactions("alice".to_string()).unwrap();
+    assert_eq!(history.len(), bank.get_transactions("alice".to_string()).unwrap().len());
+    assert!(loaded.account("alice").unwrap().verify_chain());
+
+    // New review items carry on after the ones saved, not over them.
+    loaded.deposit("alice".to_string(), dollars(70)).unwrap();
+    assert_eq!(loaded.review_queue().last().map(|item| item.id), Some(4));
+    loaded.set_pin("alice".to_string(), "1234").unwrap();
+    assert_eq!(loaded.verify_pin("alice".to_string(), "0000"), Err(BankError::AccountLocked));
+}
+
+#[test]
+fn opening_an_account_needs_the_minimum_deposit_and_leaves_nothing_behind_when_refused() {
+    let mut bank = Bank::new();
+    bank.set_minimum_opening_deposit(AccountType::Savings, dollars(100));
+    let below = bank.open_account("saver".to_string(), AccountType::Savings, Money::from_cents(9_999));
+    assert_eq!(below, Err(BankError::BelowMinimumDeposit));
+    assert!(bank.account("saver").is_none());
+
+    bank.open_account("saver".to_string(), AccountType::Savings, dollars(100)).unwrap();
+    assert_eq!(bank.balance("saver".to_string()), Some(dollars(100)));
+    assert_eq!(bank.open_account("saver".to_string(), AccountType::Savings, dollars(100)), Err(BankError::AccountExists));
+
+    // A deposit refused after the account was created takes the account with it.
+    let limits = TransactionLimits { max_deposit: Some(dollars(500)), ..TransactionLimits::default() };
+    bank.set_transaction_limits(Currency::default(), limits);
+    let refused = bank.open_account("big".to_string(), AccountType::Savings, dollars(600));
+    assert_eq!(refused, Err(BankError::TransactionLimitExceeded));
+    assert!(bank.account("big").is_none());
+    assert!(Bank::from_events(bank.events()).unwrap().account("big").is_none());
+}
+
+#[test]
+fn money_parses_input_formats_with_a_symbol_and_reports_overflow() {
+    assert_eq!("12.34".parse::<Money>(), Ok(Money::from_cents(1_234)));
+    assert_eq!(" 5 ".parse::<Money>(), Ok(dollars(5)));
+    assert_eq!(".5".parse::<Money>(), Ok(Money::from_cents(50)));
+    for invalid in ["", "-", "-5", "1.234", "1,000", "12a", "1.2.3"] {
+        assert_eq!(invalid.parse::<Money>(), Err(BankError::InvalidAmount), "{:?}", invalid);
+    }
+    assert_eq!("99999999999999999999".parse::<Money>(), Err(BankError::Overflow));
+
+    let mut bank = Bank::new();
+    bank.create_account_with_limit("card".to_string(), AccountType::Credit, dollars(100)).unwrap();
+    bank.withdraw("card".to_string(), dollars(50)).unwrap();
+    assert_eq!(bank.withdraw("card".to_string(), Money::from_cents(i64::MAX)), Err(BankError::Overflow));
+    bank.create_account("vault".to_string(), AccountType::Checking).unwrap();
+    bank.deposit("vault".to_string(), Money::from_cents(i64::MAX)).unwrap();
+    assert_eq!(bank.deposit("vault".to_string(), Money::from_cents(1)), Err(BankError::Overflow));
+    assert_eq!(bank.balance("card".to_string()), Some(-dollars(50)));
+
+    assert_eq!(Money::from_cents(1_234).format_in(&Currency::new("USD")), "$12.34");
+    assert_eq!((-dollars(5)).format_in(&Currency::new("eur")), "-€5.00");
+    assert_eq!(Money::from_cents(750).format_in(&Currency::new("CHF")), "7.50 CHF");
+
+    let mut bank = bank_with_accounts();
+    bank.deposit("alice".to_string(), Money::from_cents(i64::MAX)).unwrap();
+    assert_eq!(bank.deposit("alice".to_string(), Money::from_cents(1)), Err(BankError::Overflow));
+    assert_eq!(bank.balance("alice".to_string()), Some(Money::from_cents(i64::MAX)));
+    assert_eq!(Money::from_cents(i64::MIN).checked_sub(Money::from_cents(1)), Err(BankError::Overflow));
+}
+
+#[test]
+fn setters_report_unknown_accounts_and_errors_explain_themselves() {
+    let mut bank = bank_with_accounts();
+    assert_eq!(bank.set_interest_rate("carol".to_string(), 0.01), Err(BankError::AccountNotFound));
+    assert_eq!(bank.activate_account("carol".to_string()), Err(BankError::AccountNotFound));
+    assert_eq!(bank.revoke_mandate(42), Err(BankError::MandateNotFound));
+    bank.set_interest_rate("bob".to_string(), 0.01).unwrap();
+
+    assert_eq!(BankError::InsufficientFunds.to_string(), "Insufficient balance");
+    assert_eq!(
+        BankError::BalanceRemaining(dollars(12)).to_string(),
+        "Account still has a balance of 12.00; withdraw or transfer it first"
+    );
+}
+
+#[test]
+fn accrual_pays_deposits_and_charges_credit_at_type_rates() {
+    let mut bank = bank_with_accounts();
+    bank.create_account_with_limit("card".to_string(), AccountType::Credit, dollars(5_000)).unwrap();
+    bank.create_account("own".to_string(), AccountType::Savings).unwrap();
+    bank.deposit("bob".to_string(), dollars(10_000)).unwrap();
+    bank.deposit("own".to_string(), dollars(10_000)).unwrap();
+    bank.deposit("alice".to_string(), dollars(500)).unwrap();
+    bank.withdraw("card".to_string(), dollars(1_000)).unwrap();
+    for number in ["bob", "own"] {
+        bank.set_compound_frequency(number.to_string(), CompoundFrequency::Annually).unwrap();
+    }
+    bank.set_interest_rate("own".to_string(), 0.05).unwrap();
+    bank.set_type_interest_rate(AccountType::Savings, 0.02);
+    bank.set_type_interest_rate(AccountType::Credit, 0.2);
+
+    // A 365-day year ending today.
+    let now = SystemTime::now();
+    let period = Period { from: now - Duration::from_secs(364 * 86_400), to: now };
+    let posted = bank.accrue_interest(&period);
+    assert_eq!(
+        posted,
+        vec![
+            ("bob".to_string(), dollars(200)),
+            ("card".to_string(), -dollars(200)),
+            ("own".to_string(), dollars(500)),
+        ]
+    );
+    assert_eq!(bank.balance("bob".to_string()), Some(dollars(10_200)));
+    assert_eq!(bank.balance("card".to_string()), Some(-dollars(1_200)));
+    assert_eq!(bank.balance("alice".to_string()), Some(dollars(500)));
+    assert!(matches!(
+        bank.get_transactions("bob".to_string()).unwrap().last().unwrap().kind,
+        TransactionKind::Interest(amount) if amount == dollars(200)
+    ));
+}
+
+#[test]
+fn credit_goes_negative_to_its_limit_and_savings_keeps_its_floor() {
+    let mut bank = bank_with_accounts();
+    bank.create_account_with_limit("card".to_string(), AccountType::Credit, dollars(500)).unwrap();
+    bank.withdraw("card".to_string(), dollars(300)).unwrap();
+    bank.withdraw("card".to_string(), dollars(200)).unwrap();
+    assert_eq!(bank.balance("card".to_string()), Some(-dollars(500)));
+    assert_eq!(bank.withdraw("card".to_string(), Money::from_cents(1)), Err(BankError::CreditLimitExceeded));
+    bank.deposit("card".to_string(), Money::from_cents(1)).unwrap();
+    bank.withdraw("card".to_string(), Money::from_cents(1)).unwrap();
+    assert_eq!(bank.balance("card".to_string()), Some(-dollars(500)));
+
+    bank.set_minimum_balance("bob".to_string(), dollars(25)).unwrap();
+    bank.deposit("bob".to_string(), dollars(100)).unwrap();
+    bank.withdraw("bob".to_string(), dollars(75)).unwrap();
+    assert_eq!(bank.withdraw("bob".to_string(), Money::from_cents(1)), Err(BankError::BelowMinimumBalance));
+    assert_eq!(bank.balance("bob".to_string()), Some(dollars(25)));
+    assert_eq!(BankError::CreditLimitExceeded.to_string(), "Credit limit exceeded");
+}
+
+#[test]
+fn checking_overdraws_up_to_its_allowance_and_savings_never() {
+    let mut bank = bank_with_accounts();
+    bank.set_overdraft_fee(dollars(25), Duration::ZERO);
+    bank.create_account_with_limit("flex".to_string(), AccountType::Checking, dollars(100)).unwrap();
+    bank.deposit("flex".to_string(), dollars(50)).unwrap();
+    assert_eq!(bank.set_overdraft_limit("bob".to_string(), dollars(100)), Err(BankError::UnsupportedAccountType));
+
+    assert_eq!(bank.withdraw("flex".to_string(), dollars(151)), Err(BankError::InsufficientFunds));
+    bank.withdraw("flex".to_string(), dollars(150)).unwrap();
+    assert_eq!(bank.balance("flex".to_string()), Some(-dollars(100)));
+    assert_eq!(bank.process_overdraft_fees(SystemTime::now() + Duration::from_secs(1)), vec!["flex".to_string()]);
+    assert_eq!(bank.balance("flex".to_string()), Some(-dollars(125)));
+    assert!(matches!(
+        bank.get_transactions("flex".to_string()).unwrap().last().unwrap().kind,
+        TransactionKind::Fee(_, FeeType::Overdraft)
+    ));
+
+    assert_eq!(bank.withdraw("alice".to_string(), dollars(1)), Err(BankError::InsufficientFunds));
+    bank.deposit("bob".to_string(), dollars(10)).unwrap();
+    assert_eq!(bank.withdraw("bob".to_string(), dollars(11)), Err(BankError::InsufficientFunds));
+}
+
+#[test]
+fn ledger_entries_carry_ids_and_running_balances() {
+    let mut bank = bank_with_history(70, &[("Deposit", 100, 20), ("Withdrawal", 30, 5)]);
+    bank.deposit("acct".to_string(), dollars(30)).unwrap();
+
+    let ledger = bank.ledger("acct".to_string(), None, None).unwrap();
+    let ids: Vec<u64> = ledger.iter().map(|t| t.id).collect();
+    let balances: Vec<Money> = ledger.iter().map(|t| t.balance_after).collect();
+    assert_eq!(ids, vec![1, 2, 3]);
+    assert_eq!(balances, vec![dollars(100), dollars(70), dollars(100)]);
+
+    let week = Period { from: SystemTime::now() - Duration::from_secs(7 * 86_400), to: SystemTime::now() };
+    let recent_deposits = bank.ledger("acct".to_string(), Some(&week), Some("Deposit")).unwrap();
+    assert_eq!(recent_deposits.len(), 1);
+    assert_eq!(recent_deposits[0].id, 3);
+    assert_eq!(bank.transaction("acct".to_string(), 2).unwrap().kind.amount(), dollars(30));
+    assert_eq!(bank.transaction("acct".to_string(), 9).unwrap_err(), BankError::TransactionNotFound);
+
+    let path = std::env::temp_dir().join("bank_ledger_ids.json");
+    let storage = JsonFile::new(&path);
+    storage.save(&bank).unwrap();
+    let mut loaded = storage.load().unwrap().unwrap();
+    fs::remove_file(&path).unwrap();
+    loaded.deposit("acct".to_string(), dollars(5)).unwrap();
+    let last = loaded.get_transactions("acct".to_string()).unwrap().last().unwrap();
+    assert_eq!((last.id, last.balance_after), (4, dollars(105)));
+}
+
+#[test]
+fn failed_transfer_credit_leaves_the_source_untouched() {
+    let mut bank = bank_with_accounts();
+    bank.deposit("alice".to_string(), dollars(100)).unwrap();
+    bank.deposit("bob".to_string(), Money::from_cents(i64::MAX - 100)).unwrap();
+    let ledger_len = bank.get_transactions("alice".to_string()).unwrap().len();
+
+    assert_eq!(bank.transfer("alice".to_string(), "bob".to_string(), dollars(5)), Err(BankError::Overflow));
+    bank.deactivate_account("bob".to_string()).unwrap();
+    assert_eq!(bank.transfer("alice".to_string(), "bob".to_string(), dollars(5)), Err(BankError::AccountInactive));
+    assert_eq!(bank.balance("alice".to_string()), Some(dollars(100)));
+    assert_eq!(bank.get_transactions("alice".to_string()).unwrap().len(), ledger_len);
+
+    bank.create_account("carol".to_string(), AccountType::Savings).unwrap();
+    bank.transfer("alice".to_string(), "carol".to_string(), dollars(5)).unwrap();
+    let legs = |number: &str| {
+        bank.get_transactions(number.to_string())
+            .unwrap()
+            .iter()
+            .filter(|t| matches!(t.kind, TransactionKind::TransferOut(..) | TransactionKind::TransferIn(..)))
+            .count()
+    };
+    assert_eq!((legs("alice"), legs("carol")), (1, 1));
+    assert!(bank.account("alice").unwrap().verify_chain());
+}
+
+#[test]
+fn mandate_charges_within_the_caps_and_refuses_a_cumulative_breach_after_a_reload() {
+    let mut bank = bank_with_accounts();
+    bank.deposit("alice".to_string(), dollars(500)).unwrap();
+    let id = bank.authorize_mandate("alice".to_string(), "bob".to_string(), dollars(100), dollars(150)).unwrap();
+    bank.charge_mandate(id, dollars(100)).unwrap();
+    assert_eq!(bank.balance("bob".to_string()), Some(dollars(100)));
+    assert_eq!(bank.charge_mandate(id, dollars(101)), Err(BankError::MandateLimitExceeded));
+    assert_eq!(bank.charge_mandate(id, -dollars(100)), Err(BankError::InvalidAmount));
+    assert_eq!(bank.charge_mandate(id, Money::ZERO), Err(BankError::InvalidAmount));
+
+    // The cumulative cap counts what was charged before the restart.
+    let path = std::env::temp_dir().join(format!("bank-mandates-{}.json", std::process::id()));
+    bank.save_to_file(&path).unwrap();
+    let mut loaded = Bank::load_from_file(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.mandate(id).map(|mandate| mandate.charged), Some(dollars(100)));
+    assert_eq!(loaded.charge_mandate(id, dollars(60)), Err(BankError::MandateLimitExceeded));
+    loaded.charge_mandate(id, dollars(50)).unwrap();
+    assert_eq!(loaded.charge_mandate(id, Money::from_cents(1)), Err(BankError::MandateLimitExceeded));
+    assert_eq!(loaded.balance("alice".to_string()), Some(dollars(350)));
+    assert_eq!(loaded.balance("bob".to_string()), Some(dollars(150)));
+    let next = loaded.authorize_mandate("bob".to_string(), "alice".to_string(), dollars(1), dollars(1)).unwrap();
+    assert_ne!(next, id);
+}
+
+#[test]
+fn transfers_write_one_leg_per_side_and_are_not_cash() {
+    let mut bank = bank_with_accounts();
+    let schedule = FeeSchedule { free_withdrawals: 0, fee: dollars(1), monthly_maintenance: Money::ZERO, maintenance_waiver_balance: None };
+    bank.set_fee_schedule("alice".to_string(), Some(schedule)).unwrap();
+    bank.deposit("alice".to_string(), dollars(20_000)).unwrap();
+    bank.transfer("alice".to_string(), "bob".to_string(), dollars(9_500)).unwrap();
+    bank.transfer("alice".to_string(), "bob".to_string(), dollars(9_500)).unwrap();
+
+    let kinds = |number: &str| -> Vec<String> {
+        bank.get_transactions(number.to_string()).unwrap().iter().map(|t| format!("{:?}", t.kind)).collect()
+    };
+    assert_eq!(kinds("bob"), vec![r#"TransferIn(9500.00, "alice")"#, r#"TransferIn(9500.00, "alice")"#]);
+    assert_eq!(kinds("alice")[1..3], [r#"TransferOut(9500.00, "bob")"#.to_string(), "Fee(1.00, Transfer)".to_string()]);
+    assert_eq!(bank.balance("alice".to_string()), Some(dollars(998)));
+    assert_eq!(bank.account("bob").unwrap().ledger_sum(), dollars(19_000));
+
+    // Money moved between accounts is neither cash deposited nor cash withdrawn.
+    let day = Duration::from_secs(86_400);
+    assert!(!bank.detect_structuring("bob".to_string(), dollars(10_000), day));
+    bank.withdraw("bob".to_string(), dollars(9_500)).unwrap();
+    bank.withdraw("bob".to_string(), dollars(9_500)).unwrap();
+    assert!(bank.detect_structuring("bob".to_string(), dollars(10_000), day));
+}
+
+#[test]
+fn public_api_is_reachable_through_its_modules() {
+    let mut bank = bank::bank::Bank::new();
+    bank.create_account("acct".to_string(), bank::account::AccountType::Checking).unwrap();
+    bank.deposit("acct".to_string(), dollars(10)).unwrap();
+    let account: &bank::account::Account = bank.account("acct").unwrap();
+    let entry: &bank::transaction::Transaction = &account.transactions()[0];
+    assert!(matches!(entry.kind, bank::transaction::TransactionKind::Deposit(amount) if amount == dollars(10)));
+}
+
+#[test]
+fn statement_export_has_opening_closing_and_running_balances() {
+    let bank = bank_with_history(120, &[("Deposit", 100, 40), ("Deposit", 50, 10), ("Withdrawal", 30, 5)]);
+    let period = Period { from: SystemTime::now() - Duration::from_secs(20 * 86_400), to: SystemTime::now() };
+
+    let mut csv = Vec::new();
+    bank.export_statement("acct".to_string(), &period, StatementFormat::Csv, &mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    let rows: Vec<Vec<&str>> = csv.lines().map(|line| line.split(',').collect()).collect();
+    assert_eq!(rows[0], ["id", "date", "type", "amount", "currency", "counterparty", "memo", "balance"]);
+    assert_eq!((rows[1][2], rows[1][7]), ("Opening balance", "100.00"));
+    assert_eq!((rows[2][0], rows[2][2], rows[2][7]), ("2", "Deposit", "150.00"));
+    assert_eq!((rows[3][0], rows[3][2], rows[3][7]), ("3", "Withdrawal", "120.00"));
+    assert_eq!((rows[4][2], rows[4][7]), ("Closing balance", "120.00"));
+
+    let mut json = Vec::new();
+    bank.export_statement("acct".to_string(), &period, StatementFormat::Json, &mut json).unwrap();
+    let json = String::from_utf8(json).unwrap();
+    assert!(json.contains(r#""opening_balance":100"#) && json.contains(r#""closing_balance":120"#));
+    assert!(json.contains(r#""balance_after":150"#));
+
+    assert_eq!(
+        bank.export_statement("nobody".to_string(), &period, StatementFormat::Csv, &mut Vec::new()),
+        Err(BankError::AccountNotFound)
+    );
+}
+
+#[test]
+fn recurring_payments_catch_up_monthly_and_survive_a_reload() {
+    let mut bank = bank_with_accounts();
+    bank.deposit("alice".to_string(), dollars(1_000)).unwrap();
+    let start = calendar::parse_date("2024-01-15").unwrap();
+    let rent = bank.schedule_recurring(Some("alice".to_string()), "bob".to_string(), dollars(300), 31, start).unwrap();
+    let salary = bank.schedule_recurring(None, "alice".to_string(), dollars(100), 1, start).unwrap();
+    assert_eq!(bank.schedule_recurring(None, "bob".to_string(), dollars(1), 0, start), Err(BankError::InvalidSchedule));
+    assert_eq!(bank.recurring_payments()[&rent].next_due, calendar::parse_date("2024-01-31").unwrap());
+
+    // Rent on Jan 31 and Feb 29; salary on Feb 1 and Mar 1 would be after the cut-off.
+    let outcomes = bank.process_due_payments(calendar::parse_date("2024-03-01").unwrap() - Duration::from_secs(1));
+    assert_eq!(outcomes, vec![(rent, Ok(())), (rent, Ok(())), (salary, Ok(()))]);
+    assert_eq!(bank.balance("bob".to_string()), Some(dollars(600)));
+    assert_eq!(bank.balance("alice".to_string()), Some(dollars(500)));
+    assert_eq!(bank.recurring_payments()[&rent].next_due, calendar::parse_date("2024-03-31").unwrap());
+
+    let path = std::env::temp_dir().join("bank_recurring_payments.json");
+    let storage = JsonFile::new(&path);
+    storage.save(&bank).unwrap();
+    let mut loaded = storage.load().unwrap().unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.recurring_payments().len(), 2);
+    loaded.cancel_recurring(rent).unwrap();
+    assert_eq!(loaded.cancel_recurring(rent), Err(BankError::ScheduleNotFound));
+    let next = loaded.schedule_recurring(None, "bob".to_string(), dollars(1), 5, start).unwrap();
+    assert_eq!(next, salary + 1);
+}
+
+#[test]
+fn exchange_rates_are_saved_and_cross_currency_transfers_record_the_conversion() {
+    let mut bank = Bank::new();
+    let (usd, eur) = (Currency::new("USD"), Currency::new("eur"));
+    bank.create_account_in_currency("us".to_string(), AccountType::Checking, usd.clone()).unwrap();
+    bank.create_account_in_currency("eu".to_string(), AccountType::Savings, eur.clone()).unwrap();
+    bank.set_account_limit("eu".to_string(), dollars(5)).unwrap();
+    bank.set_exchange_rate(usd.clone(), eur.clone(), 0.9, 0.9);
+    bank.deposit("us".to_string(), dollars(100)).unwrap();
+
+    let path = std::env::temp_dir().join("bank_exchange_rates.json");
+    let storage = JsonFile::new(&path);
+    storage.save(&bank).unwrap();
+    let mut loaded = storage.load().unwrap().unwrap();
+    fs::remove_file(&path).unwrap();
+
+    loaded.transfer("us".to_string(), "eu".to_string(), dollars(50)).unwrap();
+    assert_eq!(loaded.balance("eu".to_string()), Some(dollars(45)));
+    let entries = loaded.get_transactions("eu".to_string()).unwrap();
+    assert!(matches!(
+        &entries[entries.len() - 2].kind,
+        TransactionKind::TransferIn(credited, from) if *credited == dollars(45) && from == "us"
+    ));
+    assert!(matches!(
+        &entries[entries.len() - 1].kind,
+        TransactionKind::Conversion(original, currency, rate) if *original == dollars(50) && *currency == usd && *rate == 0.9
+    ));
+    assert_eq!(loaded.withdraw("eu".to_string(), dollars(41)), Err(BankError::BelowMinimumBalance));
+}
+
+#[test]
+fn customers_own_accounts_and_can_close_them_together() {
+    let mut bank = bank_with_accounts();
+    bank.create_account_in_currency("euro".to_string(), AccountType::Savings, Currency::new("EUR")).unwrap();
+    bank.create_account_with_limit("card".to_string(), AccountType::Credit, dollars(500)).unwrap();
+    let ada = Customer { name: "Ada".to_string(), email: Some("ada@example.com".to_string()), phone: None };
+    bank.add_customer("c1".to_string(), ada.clone()).unwrap();
+    assert_eq!(bank.add_customer("c1".to_string(), ada.clone()), Err(BankError::CustomerExists));
+    assert_eq!(bank.link_account("alice".to_string(), "c2".to_string()), Err(BankError::CustomerNotFound));
+    for number in ["alice", "euro", "card"] {
+        bank.link_account(number.to_string(), "c1".to_string()).unwrap();
+    }
+    assert_eq!(bank.customer_accounts("c1").unwrap(), vec!["alice", "card", "euro"]);
+
+    bank.deposit("alice".to_string(), dollars(100)).unwrap();
+    bank.deposit("euro".to_string(), dollars(50)).unwrap();
+    bank.withdraw("card".to_string(), dollars(30)).unwrap();
+    let usd = Currency::new("USD");
+    assert_eq!(bank.customer_net_worth("c1", &usd), Err(BankError::ExchangeRateUnavailable));
+    bank.set_exchange_rate(Currency::new("EUR"), usd.clone(), 1.1, 1.3);
+    assert_eq!(bank.customer_net_worth("c1", &usd), Ok(dollars(130)));
+
+    let path = std::env::temp_dir().join("bank_customers.json");
+    let storage = JsonFile::new(&path);
+    storage.save(&bank).unwrap();
+    let mut loaded = storage.load().unwrap().unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.customer("c1"), Some(&ada));
+
+    assert_eq!(loaded.close_customer_accounts("c1"), Err(BankError::BalanceRemaining(dollars(100))));
+    assert_eq!(loaded.customer_accounts("c1").unwrap().len(), 3);
+    loaded.withdraw("alice".to_string(), dollars(100)).unwrap();
+    loaded.withdraw("euro".to_string(), dollars(50)).unwrap();
+    loaded.deposit("card".to_string(), dollars(30)).unwrap();
+    assert_eq!(loaded.close_customer_accounts("c1").unwrap(), vec!["alice", "card", "euro"]);
+    assert_eq!(loaded.account_status("alice"), Ok(AccountStatus::Closed));
+    assert_eq!(loaded.account_status("bob"), Ok(AccountStatus::Open));
+    assert_eq!(loaded.close_customer_accounts("c1").unwrap(), Vec::<String>::new());
+}
+
+#[test]
+fn pin_protected_operations_lock_out_after_repeated_failures() {
+    let mut bank = bank_with_accounts();
+    bank.deposit("alice".to_string(), dollars(100)).unwrap();
+    assert_eq!(bank.pin_status("alice"), Ok(PinStatus::NotSet));
+    assert_eq!(bank.withdraw_with_pin("alice".to_string(), "1234", dollars(10)), Err(BankError::PinNotSet));
+    bank.set_pin("alice".to_string(), "1234").unwrap();
+
+    bank.withdraw_with_pin("alice".to_string(), "1234", dollars(10)).unwrap();
+    assert_eq!(bank.transfer_with_pin("alice".to_string(), "0000", "bob".to_string(), dollars(10)), Err(BankError::IncorrectPin));
+    assert_eq!(bank.pin_status("alice"), Ok(PinStatus::Unlocked { failed_attempts: 1 }));
+    assert_eq!(bank.change_pin("alice".to_string(), "1111", "4321"), Err(BankError::IncorrectPin));
+    assert_eq!(bank.deactivate_with_pin("alice".to_string(), "2222"), Err(BankError::AccountLocked));
+    assert_eq!(bank.pin_status("alice"), Ok(PinStatus::Locked));
+    assert_eq!(bank.withdraw_with_pin("alice".to_string(), "1234", dollars(10)), Err(BankError::AccountLocked));
+    assert_eq!(bank.balance("alice".to_string()), Some(dollars(90)));
+    // The lockout froze the account once, and a bank rebuilt from the events has it frozen too.
+    let freezes = bank.audit_log().iter().filter(|record| record.operation == "freeze_account" && record.succeeded()).count();
+    assert_eq!(freezes, 1);
+    assert_eq!(bank.account("alice").unwrap().status(), AccountStatus::Frozen);
+    assert_eq!(Bank::from_events(bank.events()).unwrap().account("alice").unwrap().status(), AccountStatus::Frozen);
+
+    bank.activate_account("alice".to_string()).unwrap();
+    bank.change_pin("alice".to_string(), "1234", "4321").unwrap();
+    bank.transfer_with_pin("alice".to_string(), "4321", "bob".to_string(), dollars(10)).unwrap();
+    bank.deactivate_with_pin("alice".to_string(), "4321").unwrap();
+    assert_eq!(bank.deposit("alice".to_string(), dollars(1)), Err(BankError::AccountInactive));
+}
+
+#[test]
+fn zero_and_negative_amounts_are_refused_everywhere() {
+    let mut bank = bank_with_accounts();
+    bank.deposit("alice".to_string(), dollars(100)).unwrap();
+    bank.deposit("bob".to_string(), dollars(100)).unwrap();
+    bank.set_pin("alice".to_string(), "1234").unwrap();
+    let later = SystemTime::now() + Duration::from_secs(86_400);
+    let euros = Currency::new("EUR");
+    for amount in [Money::ZERO, -dollars(50)] {
+        assert_eq!(bank.transfer_with_pin("alice".to_string(), "1234", "bob".to_string(), amount), Err(BankError::InvalidAmount));
+        assert_eq!(bank.deposit("alice".to_string(), amount), Err(BankError::InvalidAmount));
+        assert_eq!(bank.withdraw("alice".to_string(), amount), Err(BankError::InvalidAmount));
+        assert_eq!(bank.deposit_currency("alice".to_string(), amount, euros.clone()), Err(BankError::InvalidAmount));
+        assert_eq!(bank.withdraw_currency("alice".to_string(), amount, euros.clone()), Err(BankError::InvalidAmount));
+        assert_eq!(bank.schedule_transfer("alice".to_string(), "bob".to_string(), amount, later), Err(BankError::InvalidAmount));
+        assert_eq!(bank.schedule_deposit("alice".to_string(), amount, later), Err(BankError::InvalidAmount));
+    }
+    assert_eq!(bank.balance("alice".to_string()), Some(dollars(100)));
+    assert_eq!(bank.balance("bob".to_string()), Some(dollars(100)));
+    assert!(bank.scheduled_transfers().is_empty() && bank.scheduled_deposits().is_empty());
+
+    let shared = ConcurrentBank::new(bank);
+    assert_eq!(shared.transfer("alice".to_string(), "bob".to_string(), -dollars(50)), Err(BankError::InvalidAmount));
+    assert_eq!(shared.deposit("bob".to_string(), -dollars(50)), Err(BankError::InvalidAmount));
+    assert_eq!(shared.withdraw("bob".to_string(), Money::ZERO), Err(BankError::InvalidAmount));
+    assert_eq!(shared.balance("bob"), Some(dollars(100)));
+}
+
+#[test]
+fn concurrent_bank_runs_opposing_transfers_without_losing_money() {
+    let mut bank = bank_with_accounts();
+    bank.deposit("alice".to_string(), dollars(1_000)).unwrap();
+    bank.deposit("bob".to_string(), dollars(1_000)).unwrap();
+    let shared = ConcurrentBank::new(bank);
+    shared.create_account("carol".to_string(), AccountType::Checking).unwrap();
+
+    std::thread::scope(|scope| {
+        for _ in 0..4 {
+            scope.spawn(|| {
+                for _ in 0..100 {
+                    shared.transfer("alice".to_string(), "bob".to_string(), dollars(1)).unwrap();
+                    shared.transfer("bob".to_string(), "alice".to_string(), dollars(1)).unwrap();
+                }
+            });
+        }
+        scope.spawn(|| {
+            for _ in 0..100 {
+                shared.deposit("carol".to_string(), dollars(1)).unwrap();
+            }
+        });
+    });
+    assert_eq!(shared.transfer("carol".to_string(), "dave".to_string(), dollars(1)), Err(BankError::AccountNotFound));
+    assert_eq!(shared.withdraw("carol".to_string(), dollars(101)), Err(BankError::InsufficientFunds));
+
+    let bank = shared.into_bank();
+    assert_eq!(bank.balance("alice".to_string()), Some(dollars(1_000)));
+    assert_eq!(bank.balance("bob".to_string()), Some(dollars(1_000)));
+    assert_eq!(bank.balance("carol".to_string()), Some(dollars(100)));
+    assert_eq!(bank.get_transactions("alice".to_string()).unwrap().len(), 1 + 4 * 100 * 2);
+}
+
+#[test]
+fn server_routes_requests_to_the_bank() {
+    let mut bank = Bank::new();
+    let create = r#"{"account_number":"acct","account_type":"Checking","pin":"1234"}"#;
+    assert_eq!(server::handle(&mut bank, "POST", "/accounts", create).0, 201);
+    assert_eq!(server::handle(&mut bank, "POST", "/accounts", create).0, 409);
+    assert_eq!(server::handle(&mut bank, "POST", "/accounts/acct/deposit", r#"{"amount":"50.25"}"#).0, 201);
+    assert_eq!(server::handle(&mut bank, "POST", "/accounts/acct/withdraw", r#"{"amount":10,"pin":"0000"}"#).0, 403);
+    assert_eq!(server::handle(&mut bank, "POST", "/accounts/acct/withdraw", r#"{"amount":"x","pin":"1234"}"#).0, 400);
+    assert_eq!(server::handle(&mut bank, "POST", "/transfers", r#"{"from":"acct","to":"nobody","amount":1,"pin":"1234"}"#).0, 404);
+    assert_eq!(server::handle(&mut bank, "GET", "/accounts/acct/deposit", "").0, 405);
+    assert_eq!(server::handle(&mut bank, "GET", "/elsewhere", "").0, 404);
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let client = std::thread::spawn(move || {
+        let mut stream = std::net::TcpStream::connect(address).unwrap();
+        let body = r#"{"amount":"10.00","pin":"1234"}"#;
+        write!(stream, "POST /accounts/acct/withdraw HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    });
+    let (mut stream, _) = listener.accept().unwrap();
+    assert!(server::handle_connection(&mut bank, &mut stream).unwrap());
+    drop(stream);
+    let response = client.join().unwrap();
+    assert!(response.starts_with("HTTP/1.1 201 Created\r\n"), "{}", response);
+    assert!(response.ends_with(r#"{"status":"ok"}"#));
+
+    // An oversized body is refused without being read.
+    let client = std::thread::spawn(move || {
+        let mut stream = std::net::TcpStream::connect(address).unwrap();
+        write!(stream, "POST /accounts/acct/deposit HTTP/1.1\r\nContent-Length: {}\r\n\r\n", server::MAX_BODY_BYTES + 1).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    });
+    let (mut stream, _) = listener.accept().unwrap();
+    assert!(!server::handle_connection(&mut bank, &mut stream).unwrap());
+    drop(stream);
+    assert!(client.join().unwrap().starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+
+    let (status, body) = server::handle(&mut bank, "GET", "/accounts/acct/balance", "");
+    assert_eq!(status, 200);
+    assert_eq!(body, r#"{"account_number":"acct","balance":"40.25","available_balance":"40.25","currency":"USD"}"#);
+
+    // Nesting is capped, so a body of brackets is refused rather than overflowing the stack.
+    let (status, body) = server::handle(&mut bank, "POST", "/accounts/acct/deposit", &"[".repeat(60_000));
+    assert_eq!(status, 400);
+    assert!(body.contains("nested deeper than 128"), "{}", body);
+    let nested = format!(r#"{{"amount":"1.00","note":{}{}}}"#, "[".repeat(100), "]".repeat(100));
+    assert_eq!(server::handle(&mut bank, "POST", "/accounts/acct/deposit", &nested).0, 201);
+}
+
+#[test]
+fn velocity_limits_cap_withdrawals_and_transfers_per_day() {
+    let mut bank = bank_with_accounts();
+    bank.deposit("alice".to_string(), dollars(1_000)).unwrap();
+    let limits = VelocityLimits {
+        max_withdrawal: Some(dollars(200)),
+        daily_withdrawal_total: Some(dollars(300)),
+        daily_transfer_count: Some(2),
+    };
+    bank.set_velocity_limits(AccountType::Checking, limits);
+
+    let exceeded = |limit| Err(BankError::VelocityLimitExceeded(limit));
+    assert_eq!(bank.withdraw("alice".to_string(), dollars(201)), exceeded(VelocityLimit::MaxWithdrawal));
+    bank.withdraw("alice".to_string(), dollars(200)).unwrap();
+    // Transfers count towards the transfer limit, not the withdrawal total.
+    bank.transfer("alice".to_string(), "bob".to_string(), dollars(250)).unwrap();
+    assert_eq!(
+        bank.remaining_daily_allowance("alice"),
+        Ok(DailyAllowance { withdrawal: Some(dollars(100)), transfers: Some(1) })
+    );
+    assert_eq!(bank.withdraw("alice".to_string(), dollars(101)), exceeded(VelocityLimit::DailyWithdrawalTotal));
+    bank.withdraw("alice".to_string(), dollars(100)).unwrap();
+    bank.transfer("alice".to_string(), "bob".to_string(), dollars(1)).unwrap();
+    assert_eq!(bank.transfer("alice".to_string(), "bob".to_string(), dollars(1)), exceeded(VelocityLimit::DailyTransferCount));
+    assert_eq!(bank.balance("alice".to_string()), Some(dollars(449)));
+
+    // Other account types are unaffected, and new Checking accounts pick the limits up.
+    bank.withdraw("bob".to_string(), dollars(251)).unwrap();
+    bank.create_account("carol".to_string(), AccountType::Checking).unwrap();
+    assert_eq!(bank.remaining_daily_allowance("carol").unwrap().transfers, Some(2));
+}
+
+#[test]
+fn holds_reduce_available_balance_until_captured_or_released() {
+    let mut bank = bank_with_accounts();
+    bank.deposit("alice".to_string(), dollars(100)).unwrap();
+    let groceries = bank.place_hold("alice".to_string(), dollars(30)).unwrap();
+    let fuel = bank.place_hold("alice".to_string(), dollars(50)).unwrap();
+    assert_eq!(bank.place_hold("alice".to_string(), dollars(21)), Err(BankError::InsufficientFunds));
+    assert_eq!(bank.balance("alice".to_string()), Some(dollars(100)));
+    assert_eq!(bank.available_balance("alice".to_string()), Some(dollars(20)));
+    assert_eq!(bank.withdraw("alice".to_string(), dollars(21)), Err(BankError::InsufficientFunds));
+
+    bank.capture_hold(groceries).unwrap();
+    bank.release_hold(fuel).unwrap();
+    assert_eq!(bank.release_hold(fuel), Err(BankError::TransactionNotFound));
+    assert_eq!(bank.capture_hold(groceries), Err(BankError::TransactionNotFound));
+    assert_eq!(bank.balance("alice".to_string()), Some(dollars(70)));
+    assert_eq!(bank.available_balance("alice".to_string()), Some(dollars(70)));
+    assert!(bank.account("alice").unwrap().holds().is_empty());
+    assert!(matches!(
+        bank.get_transactions("alice".to_string()).unwrap().last().unwrap().kind,
+        TransactionKind::AuthReversed(amount) if amount == dollars(50)
+    ));
+}
+
+#[test]
+fn audit_log_records_operations_with_actor_and_outcome() {
+    let mut bank = bank_with_accounts();
+    bank.set_actor("teller");
+    bank.deposit("alice".to_string(), dollars(100)).unwrap();
+    assert_eq!(bank.withdraw("bob".to_string(), dollars(5)), Err(BankError::InsufficientFunds));
+    bank.transfer("alice".to_string(), "bob".to_string(), dollars(40)).unwrap();
+    bank.set_pin("alice".to_string(), "1234").unwrap();
+    assert!(bank.verify_pin("alice".to_string(), "9999").is_err());
+    bank.deactivate_account("bob".to_string()).unwrap();
+
+    let operations: Vec<&str> = bank.audit_log().iter().map(|record| record.operation.as_str()).collect();
+    assert_eq!(
+        operations,
+        ["create_account", "create_account", "deposit", "withdraw", "transfer", "set_pin", "verify_pin", "freeze_account"]
+    );
+    let log = bank.audit_log();
+    assert_eq!(log[0].actor, "system");
+    assert!(log[2..].iter().all(|record| record.actor == "teller"));
+    assert_eq!(log[3].error, Some(BankError::InsufficientFunds.to_string()));
+    assert_eq!(log[4].parameter("amount"), Some("40.00"));
+    assert!(!log[6].succeeded());
+    assert!(log[6].parameters.iter().all(|(_, value)| value != "9999"));
+
+    let bobs: Vec<u64> = bank.audit_records(Some("bob"), None).iter().map(|record| record.sequence).collect();
+    assert_eq!(bobs, [2, 4, 5, 8]);
+    let mut csv = Vec::new();
+    bank.export_audit_log(&bank.audit_records(Some("bob"), None), &mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    assert_eq!(csv.lines().count(), 5);
+    assert!(csv.lines().nth(2).unwrap().ends_with(",teller,withdraw,account=bob; amount=5.00,Insufficient balance"));
+
+    let path = std::env::temp_dir().join(format!("bank-audit-{}.json", std::process::id()));
+    bank.save_to_file(&path).unwrap();
+    let reloaded = Bank::load_from_file(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    // Timestamps are stored to the millisecond, so compare everything else.
+    let without_times = |bank: &Bank| -> Vec<_> {
+        bank.audit_log().iter().map(|r| (r.sequence, r.actor.clone(), r.parameters.clone(), r.error.clone())).collect()
+    };
+    assert_eq!(without_times(&reloaded), without_times(&bank));
+}
+
+#[test]
+fn bank_rebuilt_from_event_log_matches_the_original() {
+    let path = std::env::temp_dir().join(format!("bank-events-{}.jsonl", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let mut bank = Bank::new();
+    bank.set_event_log(&path).unwrap();
+    bank.create_account_in_currency("alice".to_string(), AccountType::Checking, Currency::new("EUR")).unwrap();
+    bank.create_account("bob".to_string(), AccountType::Savings).unwrap();
+    bank.create_account("carol".to_string(), AccountType::Checking).unwrap();
+    bank.set_account_limit("alice".to_string(), dollars(20)).unwrap();
+    bank.deposit("alice".to_string(), dollars(100)).unwrap();
+    bank.withdraw_with_memo("alice".to_string(), dollars(110), Some("rent".to_string())).unwrap();
+    assert!(bank.withdraw("bob".to_string(), dollars(1)).is_err());
+    bank.deposit("bob".to_string(), dollars(50)).unwrap();
+    bank.transfer("bob".to_string(), "carol".to_string(), dollars(50)).unwrap();
+    bank.close_account("bob").unwrap();
+    bank.deactivate_account("carol".to_string()).unwrap();
+
+    // Failed operations leave no event behind.
+    let events = Bank::read_event_log(&path).unwrap();
+    assert_eq!(events, bank.events());
+    assert_eq!(events.len(), 10);
+    assert_eq!(events[5].kind, EventKind::Withdrawn("alice".to_string(), dollars(110), Some("rent".to_string())));
+
+    let rebuilt = Bank::from_events(&events).unwrap();
+    assert_eq!(rebuilt.account_status("bob"), Ok(AccountStatus::Closed));
+    assert_eq!(rebuilt.balance("alice".to_string()), Some(dollars(-10)));
+    assert_eq!(rebuilt.account("alice").unwrap().currency(), &Currency::new("EUR"));
+    assert_eq!(rebuilt.balance("carol".to_string()), Some(dollars(50)));
+    let mut inactive = rebuilt.clone();
+    assert_eq!(inactive.deposit("carol".to_string(), dollars(1)), Err(BankError::AccountInactive));
+    assert_eq!(rebuilt.events(), bank.events());
+
+    // A bank picking the log up again numbers its events after the ones already written.
+    let mut resumed = rebuilt.clone();
+    resumed.set_event_log(&path).unwrap();
+    resumed.deposit("alice".to_string(), dollars(10)).unwrap();
+    let events = Bank::read_event_log(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(events.len(), 11);
+    assert_eq!(events[10].id, 11);
+}
+
+#[test]
+fn event_log_only_receives_committed_journal_events() {
+    let path = std::env::temp_dir().join(format!("bank-journal-events-{}.jsonl", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let mut bank = Bank::new();
+    bank.set_event_log(&path).unwrap();
+    bank.create_account("alice".to_string(), AccountType::Checking).unwrap();
+
+    let mut journal = bank.begin_journal();
+    journal.deposit("alice".to_string(), Money::from_cents(500)).unwrap();
+    assert_eq!(Bank::read_event_log(&path).unwrap().len(), 1);
+    journal.rollback();
+    bank.deposit("alice".to_string(), Money::from_cents(100)).unwrap();
+
+    let mut journal = bank.begin_journal();
+    journal.deposit("alice".to_string(), Money::from_cents(200)).unwrap();
+    journal.commit();
+
+    let events = Bank::read_event_log(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(events.iter().map(|event| event.id).collect::<Vec<_>>(), vec![1, 2, 3]);
+    assert_eq!(events, bank.events());
+    let rebuilt = Bank::from_events(&events).unwrap();
+    assert_eq!(rebuilt.balance("alice".to_string()), Some(Money::from_cents(300)));
+}
+
+#[test]
+fn interest_fees_holds_and_currency_movements_replay_from_the_event_log() {
+    let path = std::env::temp_dir().join(format!("bank-posting-events-{}.jsonl", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let mut bank = Bank::new();
+    bank.set_event_log(&path).unwrap();
+    bank.create_account("alice".to_string(), AccountType::Checking).unwrap();
+    bank.create_account("bob".to_string(), AccountType::Savings).unwrap();
+    bank.create_account_with_limit("card".to_string(), AccountType::Credit, dollars(5_000)).unwrap();
+    bank.deposit("alice".to_string(), dollars(100)).unwrap();
+    bank.deposit("bob".to_string(), dollars(10_000)).unwrap();
+    bank.withdraw("card".to_string(), dollars(1_000)).unwrap();
+    bank.set_compound_frequency("bob".to_string(), CompoundFrequency::Annually).unwrap();
+    bank.set_type_interest_rate(AccountType::Savings, 0.02);
+    bank.set_type_interest_rate(AccountType::Credit, 0.2);
+    let now = SystemTime::now();
+    bank.accrue_interest(&Period { from: now - Duration::from_secs(364 * 86_400), to: now });
+    assert_eq!(bank.charge_percentage_fee("bob".to_string(), 0.001, FeeType::Maintenance), Ok(Money::from_cents(1_020)));
+
+    let settled = bank.authorize("alice".to_string(), dollars(30), now + Duration::from_secs(3_600)).unwrap();
+    let released = bank.authorize("alice".to_string(), dollars(20), now + Duration::from_secs(3_600)).unwrap();
+    bank.settle_authorization(settled).unwrap();
+    bank.release_hold(released).unwrap();
+    bank.deposit_currency("alice".to_string(), dollars(50), Currency::new("EUR")).unwrap();
+    bank.withdraw_currency("alice".to_string(), dollars(20), Currency::new("EUR")).unwrap();
+    assert!(bank.withdraw_currency("alice".to_string(), dollars(40), Currency::new("EUR")).is_err());
+
+    let events = Bank::read_event_log(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    let names: Vec<&str> = events[events.len() - 9..].iter().map(|event| event.kind.name()).collect();
+    assert_eq!(
+        names,
+        vec![
+            "InterestPaid",
+            "FeeCharged",
+            "FeeCharged",
+            "Authorized",
+            "Authorized",
+            "AuthorizationSettled",
+            "AuthorizationReleased",
+            "DepositedIn",
+            "WithdrawnIn",
+        ]
+    );
+    let mut rebuilt = Bank::from_events(&events).unwrap();
+    for number in ["alice", "bob", "card"] {
+        assert_eq!(rebuilt.balance(number.to_string()), bank.balance(number.to_string()));
+    }
+    assert_eq!(rebuilt.balance("alice".to_string()), Some(dollars(70)));
+    assert_eq!(rebuilt.balance_in("alice".to_string(), Currency::new("EUR")), Some(dollars(30)));
+    assert_eq!(rebuilt.available_balance("alice".to_string()), Some(dollars(70)));
+    // Hold ids carry on after the replayed ones.
+    assert_eq!(rebuilt.authorize("alice".to_string(), dollars(5), now + Duration::from_secs(60)), Ok(released + 1));
+}
+
+#[test]
+fn only_empty_accounts_can_be_closed() {
+    let mut bank = bank_with_accounts();
+    bank.create_account_with_limit("card".to_string(), AccountType::Credit, dollars(100)).unwrap();
+    bank.deposit("bob".to_string(), Money::from_cents(1)).unwrap();
+    bank.withdraw("card".to_string(), dollars(40)).unwrap();
+
+    bank.close_account("alice").unwrap();
+    assert_eq!(bank.account_status("alice"), Ok(AccountStatus::Closed));
+    assert_eq!(bank.close_account("bob"), Err(BankError::BalanceRemaining(Money::from_cents(1))));
+    // Debt on a Credit account blocks closure too.
+    assert_eq!(bank.close_account("card"), Err(BankError::BalanceRemaining(-dollars(40))));
+    assert_eq!(bank.close_account("carol"), Err(BankError::AccountNotFound));
+    assert_eq!((bank.account_status("bob"), bank.account_status("card")), (Ok(AccountStatus::Open), Ok(AccountStatus::Open)));
+
+    bank.deposit("card".to_string(), dollars(40)).unwrap();
+    bank.close_account("card").unwrap();
+    let closures: Vec<bool> =
+        bank.audit_log().iter().filter(|record| record.operation == "close_account").map(|record| record.succeeded()).collect();
+    assert_eq!(closures, vec![true, false, false, false, true]);
+}
+
+#[test]
+fn account_lifecycle_moves_from_open_through_frozen_to_closed() {
+    let mut bank = bank_with_accounts();
+    bank.deposit("alice".to_string(), dollars(80)).unwrap();
+    assert_eq!(bank.account_status("alice"), Ok(AccountStatus::Open));
+    assert_eq!(bank.account_status("carol"), Err(BankError::AccountNotFound));
+
+    bank.freeze_account("alice".to_string()).unwrap();
+    assert_eq!(
+        bank.freeze_account("alice".to_string()),
+        Err(BankError::InvalidStatusChange(AccountStatus::Frozen, AccountStatus::Frozen))
+    );
+    assert_eq!(bank.withdraw("alice".to_string(), dollars(1)), Err(BankError::AccountInactive));
+    assert_eq!(bank.close_account_sweeping("alice", "bob".to_string()), Err(BankError::AccountInactive));
+    bank.unfreeze_account("alice".to_string()).unwrap();
+    assert_eq!(bank.close_account("alice"), Err(BankError::BalanceRemaining(dollars(80))));
+
+    // A sweep that can't be made leaves the account open with its money.
+    assert_eq!(bank.close_account_sweeping("alice", "carol".to_string()), Err(BankError::AccountNotFound));
+    assert_eq!(bank.account_status("alice"), Ok(AccountStatus::Open));
+    bank.close_account_sweeping("alice", "bob".to_string()).unwrap();
+    assert_eq!(bank.account_status("alice"), Ok(AccountStatus::Closed));
+    assert_eq!(bank.balance("bob".to_string()), Some(dollars(80)));
+
+    assert_eq!(bank.deposit("alice".to_string(), dollars(1)), Err(BankError::AccountClosed));
+    assert_eq!(bank.transfer("bob".to_string(), "alice".to_string(), dollars(1)), Err(BankError::AccountClosed));
+    assert_eq!(bank.unfreeze_account("alice".to_string()), Err(BankError::AccountClosed));
+    assert_eq!(bank.freeze_account("alice".to_string()), Err(BankError::AccountClosed));
+    assert_eq!(bank.close_account("alice"), Err(BankError::AccountClosed));
+    assert_eq!(bank.set_account_limit("alice".to_string(), dollars(10)), Err(BankError::AccountClosed));
+    assert_eq!(bank.get_transactions("alice".to_string()).unwrap().last().unwrap().balance_after, Money::ZERO);
+    assert_eq!(BankError::AccountClosed.to_string(), "Account is closed");
+}
+
+#[test]
+fn sweep_that_leaves_a_fee_owing_puts_both_accounts_back() {
+    let mut bank = bank_with_accounts();
+    bank.set_account_limit("alice".to_string(), dollars(50)).unwrap();
+    let schedule = FeeSchedule { free_withdrawals: 0, fee: dollars(2), monthly_maintenance: Money::ZERO, maintenance_waiver_balance: None };
+    bank.set_fee_schedule("alice".to_string(), Some(schedule)).unwrap();
+    bank.deposit("alice".to_string(), dollars(30)).unwrap();
+
+    assert_eq!(bank.close_account_sweeping("alice", "bob".to_string()), Err(BankError::BalanceRemaining(dollars(-2))));
+    assert_eq!(bank.account_status("alice"), Ok(AccountStatus::Open));
+    assert_eq!(bank.balance("alice".to_string()), Some(dollars(30)));
+    assert_eq!(bank.balance("bob".to_string()), Some(Money::ZERO));
+    assert_eq!(bank.get_transactions("alice".to_string()).unwrap().len(), 1);
+    assert!(bank.get_transactions("bob".to_string()).unwrap().is_empty());
+}
+
+#[test]
+fn month_end_charges_unwaived_maintenance_and_summarizes_the_month() {
+    let entry = |kind: &str, amount: i64, date: &str| {
+        let timestamp = calendar::to_millis(calendar::parse_date(date).unwrap());
+        format!(r#"{{"type":"{}","amount":{},"timestamp":{}}}"#, kind, amount, timestamp)
+    };
+    let basic = [entry("Deposit", 200, "2024-03-05"), entry("Withdrawal", 50, "2024-03-20"), entry("Deposit", 10, "2024-04-02")];
+    let rich = [entry("Deposit", 5_000, "2024-03-01"), entry("Interest", 2, "2024-03-31")];
+    let document = format!(
+        concat!(
+            r#"{{"accounts":{{"basic":{{"account_type":"Checking","balance":160,"transactions":[{}]}},"#,
+            r#""rich":{{"account_type":"Savings","balance":5002,"transactions":[{}]}}}}}}"#
+        ),
+        basic.join(","),
+        rich.join(",")
+    );
+    let path = std::env::temp_dir().join(format!("bank-month-end-{}.json", std::process::id()));
+    fs::write(&path, document).unwrap();
+    let mut bank = Bank::load_from_file(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    let schedule = FeeSchedule {
+        free_withdrawals: 5,
+        fee: dollars(1),
+        monthly_maintenance: dollars(5),
+        maintenance_waiver_balance: Some(dollars(1_000)),
+    };
+    for number in ["basic", "rich"] {
+        bank.set_fee_schedule(number.to_string(), Some(schedule.clone())).unwrap();
+    }
+
+    let summaries = bank.run_month_end(&Period::month(2024, 3), 12);
+    assert_eq!(summaries.len(), 2);
+    let basic = &summaries[0];
+    assert_eq!((basic.account_number.as_str(), basic.opening_balance, basic.closing_balance), ("basic", Money::ZERO, dollars(145)));
+    assert_eq!((basic.deposits, basic.withdrawals, basic.fees), (dollars(200), dollars(50), dollars(5)));
+    assert_eq!(basic.maintenance_fee, dollars(5));
+    let rich = &summaries[1];
+    assert_eq!((rich.maintenance_fee, rich.fees, rich.interest), (Money::ZERO, Money::ZERO, dollars(2)));
+    assert_eq!(rich.closing_balance, dollars(5_002));
+
+    // The fee is dated inside the month it was charged for.
+    assert_eq!(bank.balance("basic".to_string()), Some(dollars(155)));
+    let fee = bank.get_transactions("basic".to_string()).unwrap().last().unwrap().clone();
+    assert!(matches!(fee.kind, TransactionKind::Fee(amount, FeeType::Maintenance) if amount == dollars(5)));
+    assert!(Period::month(2024, 3).contains(fee.timestamp));
+    let archived = bank.account("basic").unwrap().statements();
+    assert_eq!(archived.len(), 1);
+    assert_eq!(archived[0].closing_balance, dollars(145));
+}
+
+#[test]
+fn reports_find_accounts_by_type_status_balance_and_dormancy() {
+    let mut bank = bank_with_accounts();
+    bank.create_account("carol".to_string(), AccountType::Checking).unwrap();
+    bank.create_account("dave".to_string(), AccountType::Credit).unwrap();
+    bank.deposit("alice".to_string(), dollars(500)).unwrap();
+    bank.deposit("bob".to_string(), dollars(300)).unwrap();
+    bank.deposit("carol".to_string(), dollars(50)).unwrap();
+    bank.set_account_limit("dave".to_string(), dollars(100)).unwrap();
+    bank.withdraw("dave".to_string(), dollars(20)).unwrap();
+    bank.freeze_account("carol".to_string()).unwrap();
+
+    let checking = AccountFilter { account_type: Some(AccountType::Checking), ..AccountFilter::default() };
+    assert_eq!(bank.find_accounts(&checking), ["alice", "carol"]);
+    let open_checking = AccountFilter { status: Some(AccountStatus::Open), ..checking };
+    assert_eq!(bank.find_accounts(&open_checking), ["alice"]);
+    let mid_range = AccountFilter { min_balance: Some(dollars(50)), max_balance: Some(dollars(300)), ..AccountFilter::default() };
+    assert_eq!(bank.find_accounts(&mid_range), ["bob", "carol"]);
+    assert_eq!(bank.find_accounts(&AccountFilter::default()).len(), 4);
+
+    let deposits = bank.deposits_by_type();
+    assert_eq!(deposits.get(&AccountType::Checking), Some(&dollars(550)));
+    assert_eq!(deposits.get(&AccountType::Savings), Some(&dollars(300)));
+    assert_eq!(deposits.get(&AccountType::Credit), None);
+    assert_eq!(deposits.values().copied().sum::<Money>(), bank.total_deposits());
+
+    // Nothing has happened for a day yet; a month from now everything has gone quiet.
+    let day = Duration::from_secs(86_400);
+    assert!(bank.dormant_accounts(day, SystemTime::now()).is_empty());
+    assert_eq!(bank.dormant_accounts(30 * day, SystemTime::now() + 31 * day).len(), 4);
+    bank.withdraw("bob".to_string(), dollars(300)).unwrap();
+    bank.close_account("bob").unwrap();
+    assert_eq!(bank.dormant_accounts(30 * day, SystemTime::now() + 31 * day), ["alice", "carol", "dave"]);
+}
+
+#[test]
+fn holders_act_within_their_roles() {
+    let mut bank = bank_with_accounts();
+    for (id, name) in [("c1", "Ada"), ("c2", "Bert"), ("c3", "Cleo")] {
+        bank.add_customer(id.to_string(), Customer { name: name.to_string(), email: None, phone: None }).unwrap();
+    }
+    bank.link_account("alice".to_string(), "c1".to_string()).unwrap();
+    bank.add_holder("alice".to_string(), "c2".to_string(), Role::Signer).unwrap();
+    bank.add_holder_as("alice".to_string(), "c1", "c3".to_string(), Role::Viewer).unwrap();
+    assert_eq!(
+        bank.holders("alice").unwrap(),
+        vec![("c1".to_string(), Role::Owner), ("c2".to_string(), Role::Signer), ("c3".to_string(), Role::Viewer)]
+    );
+
+    bank.deposit_as("alice".to_string(), "c2", dollars(100)).unwrap();
+    bank.withdraw_as("alice".to_string(), "c2", dollars(10)).unwrap();
+    assert_eq!(bank.balance_as("alice", "c3"), Ok(dollars(90)));
+    assert_eq!(bank.transactions_as("alice", "c3").unwrap().len(), 2);
+    assert_eq!(bank.withdraw_as("alice".to_string(), "c3", dollars(10)), Err(BankError::NotPermitted));
+    assert_eq!(bank.transfer_as("alice".to_string(), "c3", "bob".to_string(), dollars(10)), Err(BankError::NotPermitted));
+    assert_eq!(bank.add_holder_as("alice".to_string(), "c2", "c3".to_string(), Role::Owner), Err(BankError::NotPermitted));
+    assert_eq!(bank.balance_as("bob", "c1"), Err(BankError::NotPermitted));
+    assert_eq!(bank.balance_as("alice", "c3"), Ok(dollars(90)));
+    let denied = bank.audit_records(Some("alice"), None).into_iter().filter(|record| !record.succeeded()).count();
+    assert_eq!(denied, 3);
+
+    let path = std::env::temp_dir().join("bank_holders.json");
+    let storage = JsonFile::new(&path);
+    storage.save(&bank).unwrap();
+    let mut loaded = storage.load().unwrap().unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.role_of("alice", "c2"), Ok(Some(Role::Signer)));
+    loaded.remove_holder("alice".to_string(), "c2").unwrap();
+    assert_eq!(loaded.withdraw_as("alice".to_string(), "c2", dollars(10)), Err(BankError::NotPermitted));
+}
+
+#[test]
+fn holder_changes_are_audited_and_replayed() {
+    let mut bank = bank_with_accounts();
+    bank.add_customer("c2".to_string(), Customer { name: "Bert".to_string(), email: None, phone: None }).unwrap();
+    bank.add_holder("alice".to_string(), "c2".to_string(), Role::Signer).unwrap();
+    bank.add_holder("bob".to_string(), "c2".to_string(), Role::Viewer).unwrap();
+    assert_eq!(bank.add_holder("bob".to_string(), "nobody".to_string(), Role::Viewer), Err(BankError::CustomerNotFound));
+    bank.remove_holder("bob".to_string(), "c2").unwrap();
+    assert_eq!(bank.remove_holder("bob".to_string(), "c2"), Err(BankError::CustomerNotFound));
+
+    let audited: Vec<(&str, bool)> = bank
+        .audit_log()
+        .iter()
+        .filter(|record| record.operation.ends_with("_holder"))
+        .map(|record| (record.operation.as_str(), record.succeeded()))
+        .collect();
+    assert_eq!(
+        audited,
+        [("add_holder", true), ("add_holder", true), ("add_holder", false), ("remove_holder", true), ("remove_holder", false)]
+    );
+
+    let rebuilt = Bank::from_events(bank.events()).unwrap();
+    assert_eq!(rebuilt.role_of("alice", "c2"), Ok(Some(Role::Signer)));
+    assert_eq!(rebuilt.role_of("bob", "c2"), Ok(None));
+}
+
+#[test]
+fn imports_csv_and_ofx_skipping_duplicates() {
+    let mut bank = bank_with_accounts();
+    let csv = "Date,Description,Amount,ID\n\
+               2024-03-01,Payroll,1500.00,T1\n\
+               03/02/2024,\"Grocer, Inc.\",-42.10,T2\n\
+               2024-02-30,Bad date,5.00,T3\n\
+               2024-03-04,No id,5.00,\n\
+               2024-03-05,Payroll again,1500.00,T1\n";
+    let report = bank.import_transactions("alice", import::parse_csv(csv).unwrap()).unwrap();
+    assert_eq!((report.imported, report.skipped), (2, 1));
+    assert_eq!(report.rejected, vec![(3, "invalid date".to_string()), (4, "missing transaction id".to_string())]);
+    assert_eq!(bank.balance("alice".to_string()), Some(Money::from_cents(145_790)));
+    let grocer = &bank.account("alice").unwrap().transactions()[1];
+    assert_eq!(grocer.memo.as_deref(), Some("Grocer, Inc."));
+    assert_eq!(grocer.timestamp, calendar::parse_date("2024-03-02").unwrap());
+    assert_eq!(
+        import::parse_csv("Date,Description,ID\n2024-03-01,Payroll,T1\n").unwrap_err(),
+        BankError::InvalidData("CSV header has no amount column".to_string())
+    );
+    let garbled = import::parse_ofx("<STMTTRN><DTPOSTED>202\u{e9}0101<TRNAMT>1.00<FITID>T9</STMTTRN>");
+    assert_eq!(garbled, vec![Err("invalid date".to_string())]);
+
+    let ofx = "<OFX><BANKTRANLIST>\n\
+               <STMTTRN><TRNTYPE>DEBIT<DTPOSTED>20240306120000<TRNAMT>-10.00<FITID>T4<NAME>Coffee</STMTTRN>\n\
+               <STMTTRN><TRNTYPE>CREDIT<DTPOSTED>20240301<TRNAMT>+1500.00<FITID>T1<NAME>Payroll</STMTTRN>\n\
+               <STMTTRN><TRNTYPE>DEBIT<DTPOSTED>20240307<TRNAMT>-100.00<FITID>T5<NAME>Rent</STMTTRN>\n\
+               </BANKTRANLIST></OFX>";
+    let report = bank.import_transactions("bob", import::parse_ofx(ofx)).unwrap();
+    assert_eq!((report.imported, report.skipped), (2, 0));
+    assert_eq!(report.rejected, vec![(1, "Insufficient balance".to_string())]);
+    let report = bank.import_transactions("alice", import::parse_ofx(ofx)).unwrap();
+    assert_eq!((report.imported, report.skipped, report.rejected.len()), (2, 1, 0));
+    assert_eq!(bank.balance("alice".to_string()), Some(Money::from_cents(134_790)));
+
+    let replayed = Bank::from_events(bank.events()).unwrap();
+    assert_eq!(replayed.balance("alice".to_string()), Some(Money::from_cents(134_790)));
+    let path = std::env::temp_dir().join("bank_imported.json");
+    let storage = JsonFile::new(&path);
+    storage.save(&bank).unwrap();
+    let mut loaded = storage.load().unwrap().unwrap();
+    fs::remove_file(&path).unwrap();
+    let report = loaded.import_transactions("alice", import::parse_csv(csv).unwrap()).unwrap();
+    assert_eq!((report.imported, report.skipped), (0, 3));
+}
+
+#[test]
+fn undo_last_reverses_the_latest_operation_and_keeps_the_history() {
+    let mut bank = bank_with_accounts();
+    bank.deposit("alice".to_string(), dollars(100)).unwrap();
+    bank.transfer("alice".to_string(), "bob".to_string(), dollars(40)).unwrap();
+    bank.deposit("alice".to_string(), dollars(5)).unwrap();
+
+    bank.undo_last("alice").unwrap();
+    assert_eq!(bank.balance("alice".to_string()), Some(dollars(60)));
+    // The transfer is next, and comes off both accounts.
+    bank.undo_last("alice").unwrap();
+    assert_eq!(bank.balance("alice".to_string()), Some(dollars(100)));
+    assert_eq!(bank.balance("bob".to_string()), Some(Money::ZERO));
+    assert_eq!(bank.undo_last("bob"), Err(BankError::TransactionNotFound));
+
+    let alice = bank.get_transactions("alice".to_string()).unwrap();
+    assert_eq!(alice.len(), 5);
+    assert_eq!(alice.iter().map(|t| t.reversed_by).collect::<Vec<_>>(), vec![None, Some(5), Some(4), None, None]);
+    assert!(bank.account("alice").unwrap().verify_chain());
+    let operations: Vec<&str> = bank.audit_log().iter().map(|record| record.operation.as_str()).collect();
+    assert_eq!(operations.iter().filter(|operation| **operation == "reverse_transaction").count(), 2);
+    let replayed = Bank::from_events(bank.events()).unwrap();
+    assert_eq!(replayed.balance("alice".to_string()), Some(dollars(100)));
+}
+
+#[test]
+fn reverses_transactions_with_compensating_entries() {
+    let mut bank = bank_with_accounts();
+    bank.deposit("alice".to_string(), dollars(1000)).unwrap();
+    bank.deposit("alice".to_string(), dollars(100)).unwrap();
+    bank.transfer("alice".to_string(), "bob".to_string(), dollars(300)).unwrap();
+    bank.transfer("alice".to_string(), "bob".to_string(), dollars(300)).unwrap();
+    assert_eq!(bank.reverse_transaction("alice".to_string(), 1), Err(BankError::InsufficientFunds));
+    assert_eq!(bank.reverse_transaction("alice".to_string(), 99), Err(BankError::TransactionNotFound));
+
+    // Named by the sender's TransferOut leg, the second transfer comes off both accounts.
+    bank.reverse_transaction("alice".to_string(), 4).unwrap();
+    assert_eq!(bank.balance("alice".to_string()), Some(dollars(800)));
+    assert_eq!(bank.balance("bob".to_string()), Some(dollars(300)));
+    let bob = bank.account("bob").unwrap().transactions();
+    assert_eq!(bob.iter().map(|t| t.reversed_by).collect::<Vec<_>>(), vec![None, Some(3), None]);
+    assert!(matches!(bob[2].kind, TransactionKind::Reversal(amount, 2) if amount == dollars(-300)));
+    assert_eq!(bank.reverse_transaction("bob".to_string(), 2), Err(BankError::AlreadyReversed));
+    assert_eq!(bank.reverse_transaction("bob".to_string(), 3), Err(BankError::NotReversible));
+
+    // The first transfer, from the recipient's side, and then the mistyped deposit.
+    bank.reverse_transaction("bob".to_string(), 1).unwrap();
+    bank.reverse_transaction("alice".to_string(), 2).unwrap();
+    assert_eq!(bank.balance("alice".to_string()), Some(dollars(1000)));
+    assert_eq!(bank.balance("bob".to_string()), Some(dollars(0)));
+    assert_eq!(bank.account("alice").unwrap().transactions().len(), 7);
+    assert_eq!(bank.reverse_transaction("alice".to_string(), 2), Err(BankError::AlreadyReversed));
+
+    let replayed = Bank::from_events(bank.events()).unwrap();
+    assert_eq!(replayed.balance("alice".to_string()), Some(dollars(1000)));
+    let path = std::env::temp_dir().join("bank_reversals.json");
+    let storage = JsonFile::new(&path);
+    storage.save(&bank).unwrap();
+    let mut loaded = storage.load().unwrap().unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.transaction("alice".to_string(), 2).unwrap().reversed_by, Some(7));
+    assert_eq!(loaded.reverse_transaction("alice".to_string(), 2), Err(BankError::AlreadyReversed));
+}
+
+#[test]
+fn simulated_operations_keep_the_invariants() {
+    for seed in [1, 7, 42] {
+        let config = SimulationConfig { seed, steps: 2000, ..SimulationConfig::default() };
+        let report = simulation::run(&config);
+        assert_eq!(report.violations, vec![], "seed {}", seed);
+        assert_eq!(report.steps, 2000);
+        assert_eq!(report.succeeded + report.refused, 2000);
+        assert!(report.succeeded > 500 && report.refused > 100, "seed {}: {:?}", seed, report);
+        // Some of the refusals are the zero and negative amounts the simulation mixes in.
+        assert!(report.invalid_amounts > 10 && report.invalid_amounts < report.refused, "seed {}: {:?}", seed, report);
+        assert_eq!(simulation::run(&config), report);
+    }
+
+    let mut bank = bank_with_accounts();
+    bank.deposit("alice".to_string(), dollars(100)).unwrap();
+    bank.transfer("alice".to_string(), "bob".to_string(), dollars(30)).unwrap();
+    assert_eq!(bank.total_holdings(&Currency::default()), dollars(100));
+    assert_eq!(bank.account("bob").unwrap().ledger_sum(), dollars(30));
+    assert_eq!(simulation::check_invariants(&bank, dollars(100)), vec![]);
+    let broken = simulation::check_invariants(&bank, dollars(90));
+    assert_eq!(broken, vec![(Invariant::MoneyConserved, "holdings are 100.00, expected 90.00".to_string())]);
+}
+
+#[test]
+fn cli_commands_report_results_and_exit_codes_for_scripts() {
+    let dir = std::env::temp_dir().join(format!("bank-cli-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let bank = |args: &[&str]| {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_bank")).args(args).current_dir(&dir).output().unwrap();
+        (output.status.code(), String::from_utf8_lossy(&output.stdout).trim().to_string())
+    };
+
+    let created = bank(&["create", "--account", "a1", "--type", "Checking", "--pin", "1234"]);
+    assert_eq!(created, (Some(0), "Account a1 created".to_string()));
+    assert_eq!(bank(&["create", "--account", "a2", "--type", "Savings", "--pin", "4321"]).0, Some(0));
+    let deposited = bank(&["deposit", "--account", "a1", "--amount", "50.00", "--json"]);
+    assert_eq!(deposited, (Some(0), r#"{"status":"ok","message":"Deposit successful"}"#.to_string()));
+    assert_eq!(bank(&["transfer", "--from", "a1", "--to", "a2", "--amount", "12.50", "--pin", "1234"]).0, Some(0));
+    let balance = bank(&["balance", "--account", "a1", "--json"]);
+    assert_eq!(balance.1, r#"{"account":"a1","balance":"37.50","available_balance":"37.50","currency":"USD"}"#);
+
+    // Refused by the bank: 1. A malformed command line: 2, before anything is touched.
+    let refused = bank(&["withdraw", "--account", "a1", "--amount", "10", "--pin", "9999", "--json"]);
+    assert_eq!(refused, (Some(1), r#"{"error":"Incorrect PIN"}"#.to_string()));
+    assert_eq!(bank(&["deposit", "--account", "a1", "--amount", "1.234"]).0, Some(2));
+    assert_eq!(bank(&["deposit", "--account", "a2", "--amount", "-500"]).0, Some(2));
+    assert_eq!(bank(&["withdraw", "--account", "a1", "--amount", "0", "--pin", "1234"]).0, Some(2));
+    assert_eq!(bank(&["transfer", "--from", "a1", "--to", "a2", "--amount", "-50", "--pin", "1234"]).0, Some(2));
+    assert_eq!(bank(&["deposit", "--account", "a1"]).0, Some(2));
+    assert_eq!(bank(&["frobnicate"]).0, Some(2));
+    assert!(bank(&["balance", "--account", "a2", "--json"]).1.contains(r#""balance":"12.50""#));
+
+    // Each change was saved and logged for the next invocation.
+    let saved = Bank::load_from_file(&dir.join("bank.json")).unwrap();
+    let events = Bank::read_event_log(&dir.join("bank-events.jsonl")).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(saved.balance("a1".to_string()), Some(Money::from_cents(3_750)));
+    assert_eq!(Bank::from_events(&events).unwrap().balance("a2".to_string()), Some(Money::from_cents(1_250)));
+}
+
+#[test]
+fn deposit_shares_sum_to_one_and_leave_out_credit_balances() {
+    let mut bank = bank_with_accounts();
+    assert!(bank.deposit_shares().is_empty());
+    bank.create_account("carol".to_string(), AccountType::Checking).unwrap();
+    bank.create_account_with_limit("card".to_string(), AccountType::Credit, dollars(1_000)).unwrap();
+    for (number, amount) in [("alice", 100), ("bob", 300), ("carol", 600)] {
+        bank.deposit(number.to_string(), dollars(amount)).unwrap();
+    }
+    bank.withdraw("card".to_string(), dollars(400)).unwrap();
+
+    let shares = bank.deposit_shares();
+    let numbers: Vec<&str> = shares.iter().map(|(number, _)| number.as_str()).collect();
+    assert_eq!(numbers, ["alice", "bob", "carol"]);
+    assert!((shares.iter().map(|(_, share)| share).sum::<f64>() - 1.0).abs() < 1e-9);
+    assert!((shares[2].1 - 0.6).abs() < 1e-9);
+}
+
+#[test]
+fn percentage_fee_remainders_go_where_the_policy_sends_them() {
+    // 1.5% of 10.01 is 0.15015: the bank rounds a charge up, the customer gets it rounded down.
+    for (policy, fee, retained) in [
+        (RemainderPolicy::ToBank, Money::from_cents(16), 0.00985),
+        (RemainderPolicy::ToCustomer, Money::from_cents(15), -0.00015),
+        (RemainderPolicy::Discard, Money::from_cents(15), 0.0),
+    ] {
+        let mut bank = bank_with_accounts();
+        bank.set_remainder_policy(policy);
+        bank.deposit("alice".to_string(), Money::from_cents(1_001)).unwrap();
+        assert_eq!(bank.charge_percentage_fee("alice".to_string(), 0.015, FeeType::Transaction), Ok(fee));
+        assert_eq!(bank.balance("alice".to_string()), Some(Money::from_cents(1_001) - fee));
+        assert!((bank.retained_remainders() - retained).abs() < 1e-9, "{:?}: {}", policy, bank.retained_remainders());
+        // The books balance: what was charged is the exact fee plus what the bank kept.
+        if policy != RemainderPolicy::Discard {
+            assert!((fee.to_f64() - (0.15015 + bank.retained_remainders())).abs() < 1e-9);
+        }
+        assert!(bank.account("alice").unwrap().verify_balance().is_ok());
+    }
+}
+
+#[test]
+fn statements_are_generated_for_paper_and_email_but_not_for_none() {
+    let mut bank = bank_with_accounts();
+    bank.create_account("carol".to_string(), AccountType::Checking).unwrap();
+    bank.set_statement_delivery("alice".to_string(), DeliveryMethod::Email).unwrap();
+    bank.set_statement_delivery("carol".to_string(), DeliveryMethod::None).unwrap();
+    assert_eq!(bank.set_statement_delivery("nobody".to_string(), DeliveryMethod::Email), Err(BankError::AccountNotFound));
+    let period = Period {
+        from: SystemTime::now() - Duration::from_secs(86_400),
+        to: SystemTime::now() + Duration::from_secs(86_400),
+    };
+
+    let batch: Vec<(String, DeliveryMethod)> =
+        bank.generate_statements(&period).into_iter().map(|statement| (statement.account_number, statement.delivery)).collect();
+    assert_eq!(batch, [("alice".to_string(), DeliveryMethod::Email), ("bob".to_string(), DeliveryMethod::Paper)]);
+
+    // The preference survives a save and load.
+    let path = std::env::temp_dir().join(format!("bank-delivery-{}.json", std::process::id()));
+    bank.save_to_file(&path).unwrap();
+    let loaded = Bank::load_from_file(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.generate_statements(&period).len(), 2);
+}
+
+#[test]
+fn several_deposits_just_under_the_threshold_are_flagged_as_structuring() {
+    let day = Duration::from_secs(86_400);
+    let mut bank = bank_with_accounts();
+    bank.deposit("alice".to_string(), dollars(9_900)).unwrap();
+    assert!(!bank.detect_structuring("alice".to_string(), dollars(10_000), day));
+    // Amounts at the threshold or well below it are not structuring however many there are.
+    bank.deposit("alice".to_string(), dollars(10_000)).unwrap();
+    bank.deposit("alice".to_string(), dollars(500)).unwrap();
+    assert!(!bank.detect_structuring("alice".to_string(), dollars(10_000), day));
+
+    bank.deposit("alice".to_string(), dollars(9_900)).unwrap();
+    bank.deposit("alice".to_string(), dollars(9_900)).unwrap();
+    assert!(bank.detect_structuring("alice".to_string(), dollars(10_000), day));
+    assert!(!bank.detect_structuring("nobody".to_string(), dollars(10_000), day));
+}
+
+#[test]
+fn auto_sweep_restores_the_checking_target_in_either_direction() {
+    let mut bank = bank_with_accounts();
+    bank.deposit("alice".to_string(), dollars(1_500)).unwrap();
+    bank.auto_sweep("alice".to_string(), "bob".to_string(), dollars(1_000)).unwrap();
+    assert_eq!((bank.balance("alice".to_string()), bank.balance("bob".to_string())), (Some(dollars(1_000)), Some(dollars(500))));
+    let swept = bank.get_transactions("bob".to_string()).unwrap().last().map(|t| format!("{:?}", t.kind));
+    assert_eq!(swept.as_deref(), Some(r#"TransferIn(500.00, "alice")"#));
+
+    bank.withdraw("alice".to_string(), dollars(300)).unwrap();
+    bank.auto_sweep("alice".to_string(), "bob".to_string(), dollars(1_000)).unwrap();
+    assert_eq!((bank.balance("alice".to_string()), bank.balance("bob".to_string())), (Some(dollars(1_000)), Some(dollars(200))));
+
+    // A shortfall larger than the savings pulls in what there is; at the target nothing moves.
+    bank.withdraw("alice".to_string(), dollars(900)).unwrap();
+    bank.auto_sweep("alice".to_string(), "bob".to_string(), dollars(1_000)).unwrap();
+    assert_eq!((bank.balance("alice".to_string()), bank.balance("bob".to_string())), (Some(dollars(300)), Some(Money::ZERO)));
+    let entries = bank.get_transactions("alice".to_string()).unwrap().len();
+    bank.auto_sweep("alice".to_string(), "bob".to_string(), dollars(300)).unwrap();
+    assert_eq!(bank.get_transactions("alice".to_string()).unwrap().len(), entries);
+    assert_eq!(bank.auto_sweep("alice".to_string(), "nobody".to_string(), dollars(1)), Err(BankError::AccountNotFound));
+}
+
+#[test]
+fn last_balance_change_skips_entries_that_move_no_money() {
+    let mut bank = bank_with_accounts();
+    assert!(bank.account("alice").unwrap().last_balance_change().is_none());
+    bank.deposit("alice".to_string(), dollars(100)).unwrap();
+    bank.withdraw("alice".to_string(), dollars(30)).unwrap();
+    // An authorization is recorded but leaves the balance alone.
+    bank.authorize("alice".to_string(), dollars(20), SystemTime::now() + Duration::from_secs(86_400)).unwrap();
+    let alice = bank.account("alice").unwrap();
+    assert!(matches!(alice.transactions().last().map(|t| &t.kind), Some(TransactionKind::Authorization(_))));
+    let last = alice.last_balance_change().unwrap();
+    assert!(matches!(last.kind, TransactionKind::Withdrawal(amount) if amount == dollars(30)));
+    assert_eq!(last.id, alice.transactions()[1].id);
+}
+
+#[test]
+fn average_transaction_size_is_taken_per_account_type() {
+    let mut bank = bank_with_accounts();
+    bank.create_account("carol".to_string(), AccountType::Checking).unwrap();
+    bank.create_account_with_limit("card".to_string(), AccountType::Credit, dollars(100)).unwrap();
+    bank.deposit("alice".to_string(), dollars(100)).unwrap();
+    bank.withdraw("alice".to_string(), dollars(40)).unwrap();
+    bank.deposit("carol".to_string(), Money::from_cents(1)).unwrap();
+    bank.deposit("bob".to_string(), dollars(1_000)).unwrap();
+    bank.deposit("bob".to_string(), dollars(3_000)).unwrap();
+
+    let averages = bank.average_transaction_size_by_type();
+    // (100.00 + 40.00 + 0.01) / 3, to the nearest cent.
+    assert_eq!(averages.get(&AccountType::Checking), Some(&Money::from_cents(4_667)));
+    assert_eq!(averages.get(&AccountType::Savings), Some(&dollars(2_000)));
+    // A type whose accounts have no transactions has no average.
+    assert_eq!(averages.get(&AccountType::Credit), None);
+}
+
+#[test]
+fn verify_balance_reports_a_balance_that_no_longer_matches_its_ledger() {
+    let mut bank = bank_with_accounts();
+    bank.create_account_with_limit("card".to_string(), AccountType::Credit, dollars(500)).unwrap();
+    bank.deposit("alice".to_string(), dollars(100)).unwrap();
+    bank.transfer("alice".to_string(), "bob".to_string(), dollars(30)).unwrap();
+    bank.withdraw("card".to_string(), dollars(45)).unwrap();
+    bank.charge_percentage_fee("alice".to_string(), 0.02, FeeType::Maintenance).unwrap();
+    for number in ["alice", "bob", "card"] {
+        assert_eq!(bank.account(number).unwrap().verify_balance(), Ok(()), "{}", number);
+    }
+
+    // Edit alice's stored balance behind the ledger's back.
+    let path = std::env::temp_dir().join(format!("bank-tampered-{}.json", std::process::id()));
+    bank.save_to_file(&path).unwrap();
+    let saved = fs::read_to_string(&path).unwrap();
+    let tampered = saved.replacen(r#""balance":68.6,"#, r#""balance":99,"#, 1);
+    assert_ne!(saved, tampered);
+    fs::write(&path, tampered).unwrap();
+    let loaded = Bank::load_from_file(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.account("alice").unwrap().verify_balance(), Err((Money::from_cents(6_860), dollars(99))));
+    assert_eq!(loaded.account("bob").unwrap().verify_balance(), Ok(()));
+}
+
+#[test]
+fn conversions_use_the_bid_one_way_and_the_ask_the_other() {
+    let (usd, eur) = (Currency::new("USD"), Currency::new("EUR"));
+    let mut bank = bank_with_accounts();
+    bank.create_account_in_currency("euro".to_string(), AccountType::Checking, eur.clone()).unwrap();
+    bank.set_exchange_rate(usd.clone(), eur.clone(), 0.90, 0.92);
+    bank.deposit("alice".to_string(), dollars(100)).unwrap();
+
+    // The bank buys dollars at the bid...
+    bank.transfer("alice".to_string(), "euro".to_string(), dollars(100)).unwrap();
+    assert_eq!(bank.balance("euro".to_string()), Some(dollars(90)));
+    let conversion = bank.get_transactions("euro".to_string()).unwrap().last().map(|t| t.kind.clone());
+    assert!(matches!(
+        conversion,
+        Some(TransactionKind::Conversion(original, currency, rate)) if original == dollars(100) && currency == usd && rate == 0.90
+    ));
+
+    // ...and sells them back at the ask: 46 EUR buys 46 / 0.92 = 50 USD.
+    bank.transfer("euro".to_string(), "alice".to_string(), dollars(46)).unwrap();
+    assert_eq!(bank.balance("alice".to_string()), Some(dollars(50)));
+    let conversion = bank.get_transactions("alice".to_string()).unwrap().last().map(|t| t.kind.clone());
+    assert!(matches!(
+        conversion,
+        Some(TransactionKind::Conversion(original, currency, rate))
+            if original == dollars(46) && currency == eur && (rate - 1.0 / 0.92).abs() < 1e-12
+    ));
+
+    // Going round trip through both rates costs the spread.
+    assert_eq!(bank.convert(dollars(100), &usd, &eur), Some((90.0, 0.90)));
+    let (back, _) = bank.convert(dollars(90), &eur, &usd).unwrap();
+    assert!((100.0 - back - 2.173_913).abs() < 1e-6, "{}", back);
+}
+
+#[test]
+fn fee_report_subtotals_each_fee_type_within_the_period() {
+    let date = |text: &str| calendar::parse_date(text).unwrap();
+    let mut bank = bank_with_accounts();
+    bank.deposit("alice".to_string(), dollars(1_000)).unwrap();
+    let fees = [
+        (dollars(5), FeeType::Maintenance, "2024-02-28"),
+        (dollars(10), FeeType::Maintenance, "2024-03-01"),
+        (dollars(2), FeeType::Maintenance, "2024-03-31"),
+        (dollars(25), FeeType::Overdraft, "2024-03-10"),
+        (Money::from_cents(150), FeeType::Transfer, "2024-03-12"),
+        (Money::from_cents(250), FeeType::Transfer, "2024-03-20"),
+        (dollars(7), FeeType::FinanceCharge, "2024-04-01"),
+    ];
+    let events: Vec<Event> = fees
+        .iter()
+        .enumerate()
+        .map(|(i, (amount, fee_type, day))| Event {
+            id: 100 + i as u64,
+            kind: EventKind::FeeCharged("alice".to_string(), *amount, *fee_type, date(day)),
+        })
+        .collect();
+    bank.replay(&events).unwrap();
+
+    let report = bank.fee_report("alice".to_string(), date("2024-03-01"), date("2024-03-31"));
+    let expected =
+        HashMap::from([(FeeType::Maintenance, dollars(12)), (FeeType::Overdraft, dollars(25)), (FeeType::Transfer, dollars(4))]);
+    assert_eq!(report, expected);
+    assert!(bank.fee_report("bob".to_string(), date("2024-03-01"), date("2024-03-31")).is_empty());
+}
+
+#[test]
+fn a_temporary_credit_limit_allows_more_until_it_expires() {
+    let hour = Duration::from_secs(3_600);
+    let mut bank = Bank::new();
+    bank.create_account_with_limit("card".to_string(), AccountType::Credit, dollars(500)).unwrap();
+    bank.create_account_with_limit("other".to_string(), AccountType::Credit, dollars(500)).unwrap();
+    let refused = bank.withdraw("card".to_string(), dollars(700));
+    assert_eq!(refused, Err(BankError::CreditLimitExceeded));
+
+    let until = SystemTime::now() + hour;
+    bank.grant_temp_credit_limit("card".to_string(), dollars(300), until).unwrap();
+    bank.withdraw("card".to_string(), dollars(700)).unwrap();
+    assert_eq!(bank.balance("card".to_string()), Some(-dollars(700)));
+    let card = bank.account("card").unwrap();
+    assert_eq!(card.effective_credit_limit(until - hour / 2), dollars(800));
+    assert_eq!(card.effective_credit_limit(until), dollars(500));
+
+    // Once the grant has lapsed the account is back to its own limit.
+    bank.grant_temp_credit_limit("other".to_string(), dollars(300), SystemTime::now() - hour).unwrap();
+    assert_eq!(bank.withdraw("other".to_string(), dollars(700)), refused);
+    bank.withdraw("other".to_string(), dollars(500)).unwrap();
+    assert_eq!(bank.grant_temp_credit_limit("nobody".to_string(), dollars(1), until), Err(BankError::AccountNotFound));
+    bank.create_account("checking".to_string(), AccountType::Checking).unwrap();
+    assert_eq!(bank.grant_temp_credit_limit("checking".to_string(), dollars(1), until), Err(BankError::UnsupportedAccountType));
+}
+
+#[test]
+fn projected_balance_takes_off_a_scheduled_transfer_only_once_it_is_due() {
+    let day = Duration::from_secs(86_400);
+    let mut bank = bank_with_accounts();
+    bank.deposit("alice".to_string(), dollars(500)).unwrap();
+    let due = SystemTime::now() + 3 * day;
+    bank.schedule_transfer("alice".to_string(), "bob".to_string(), dollars(200), due).unwrap();
+
+    assert_eq!(bank.projected_balance("alice".to_string(), due - day), Some(dollars(500)));
+    assert_eq!(bank.projected_balance("alice".to_string(), due), Some(dollars(300)));
+    assert_eq!(bank.projected_balance("bob".to_string(), due + day), Some(dollars(200)));
+    assert_eq!(bank.projected_balance("nobody".to_string(), due), None);
+    // A projection is read-only.
+    assert_eq!(bank.balance("alice".to_string()), Some(dollars(500)));
+    assert_eq!(bank.scheduled_transfers().len(), 1);
+}
+
+#[test]
+fn one_account_holds_separate_usd_and_eur_sub_balances() {
+    let (usd, eur) = (Currency::new("USD"), Currency::new("EUR"));
+    let mut bank = bank_with_accounts();
+    bank.deposit_currency("alice".to_string(), dollars(100), usd.clone()).unwrap();
+    bank.deposit_currency("alice".to_string(), dollars(80), eur.clone()).unwrap();
+    bank.withdraw_currency("alice".to_string(), dollars(30), eur.clone()).unwrap();
+
+    assert_eq!(bank.balance_in("alice".to_string(), usd.clone()), Some(dollars(100)));
+    assert_eq!(bank.balance_in("alice".to_string(), eur.clone()), Some(dollars(50)));
+    assert_eq!(bank.balance_in("alice".to_string(), Currency::new("GBP")), Some(Money::ZERO));
+    // The account's own balance is its USD one; euros can't be spent as dollars or vice versa.
+    assert_eq!(bank.balance("alice".to_string()), Some(dollars(100)));
+    assert_eq!(bank.withdraw_currency("alice".to_string(), dollars(60), eur.clone()), Err(BankError::InsufficientFunds));
+    bank.withdraw_currency("alice".to_string(), dollars(100), usd.clone()).unwrap();
+    assert_eq!(bank.balance_in("alice".to_string(), eur), Some(dollars(50)));
+    assert!(bank.account("alice").unwrap().verify_balance().is_ok());
+}
+
+#[test]
+fn accounts_created_between_includes_both_boundary_dates() {
+    let date = |text: &str| calendar::parse_date(text).unwrap();
+    let account = |created: &str| {
+        format!(r#"{{"account_type":"Checking","balance":0,"created_at":{},"transactions":[]}}"#, calendar::to_millis(date(created)))
+    };
+    let document = format!(
+        r#"{{"accounts":{{"jan":{},"feb":{},"mar":{}}}}}"#,
+        account("2024-01-10"),
+        account("2024-02-10"),
+        account("2024-03-10")
+    );
+    let path = std::env::temp_dir().join(format!("bank-cohorts-{}.json", std::process::id()));
+    fs::write(&path, document).unwrap();
+    let bank = Bank::load_from_file(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(bank.accounts_created_between(date("2024-02-10"), date("2024-03-10")), ["feb", "mar"]);
+    assert_eq!(bank.accounts_created_between(date("2024-01-01"), date("2024-02-09")), ["jan"]);
+    assert!(bank.accounts_created_between(date("2024-03-11"), date("2024-12-31")).is_empty());
+}
+
+#[test]
+fn new_accounts_take_deposits_but_refuse_withdrawals_until_the_hold_ends() {
+    let hold = Duration::from_millis(50);
+    let mut bank = bank_with_accounts();
+    bank.set_new_account_hold(hold);
+    bank.create_account("new".to_string(), AccountType::Checking).unwrap();
+    bank.deposit("new".to_string(), dollars(100)).unwrap();
+    bank.deposit("alice".to_string(), dollars(100)).unwrap();
+
+    assert_eq!(bank.withdraw("new".to_string(), dollars(10)), Err(BankError::AccountOnHold));
+    assert_eq!(bank.transfer("new".to_string(), "alice".to_string(), dollars(10)), Err(BankError::AccountOnHold));
+    // Money may still come in, and accounts opened before the hold was set aren't held.
+    bank.transfer("alice".to_string(), "new".to_string(), dollars(10)).unwrap();
+    assert_eq!(bank.balance("new".to_string()), Some(dollars(110)));
+
+    std::thread::sleep(hold + Duration::from_millis(10));
+    bank.withdraw("new".to_string(), dollars(10)).unwrap();
+    bank.transfer("new".to_string(), "alice".to_string(), dollars(10)).unwrap();
+    assert_eq!(bank.balance("new".to_string()), Some(dollars(90)));
+}
+
+#[test]
+fn average_daily_balance_weights_a_mid_period_deposit_by_the_days_it_was_held() {
+    let day = Duration::from_secs(86_400);
+    let now = SystemTime::now();
+    let period = Period { from: now - 30 * day, to: now };
+    let interest = |basis: InterestBasis| {
+        let mut bank = bank_with_history(1_000, &[("Deposit", 200, 40), ("Deposit", 800, 10)]);
+        bank.set_interest_basis("acct".to_string(), basis).unwrap();
+        bank.set_interest_rate("acct".to_string(), 0.12).unwrap();
+        bank.accrue_interest(&period)[0].1
+    };
+
+    // 200.00 for twenty of the 31 days and 1000.00 for the last eleven.
+    let bank = bank_with_history(1_000, &[("Deposit", 200, 40), ("Deposit", 800, 10)]);
+    let account = bank.account("acct").unwrap();
+    assert_eq!(account.average_daily_balance(period.from, period.to), Money::from_cents(48_387));
+    assert_eq!(account.interest_basis_for(period.from, period.to), dollars(1_000));
+    let (average, ending) = (interest(InterestBasis::AverageDailyBalance), interest(InterestBasis::EndingBalance));
+    assert!(average > Money::ZERO && average < ending, "{} vs {}", average, ending);
+}
+
+#[test]
+fn weighted_average_deposit_rate_weights_each_rate_by_its_balance() {
+    let mut bank = Bank::new();
+    assert_eq!(bank.weighted_average_deposit_rate(), 0.0);
+    for (number, balance, rate) in [("small", 1_000, 0.01), ("large", 3_000, 0.05), ("empty", 0, 0.20)] {
+        bank.create_account(number.to_string(), AccountType::Savings).unwrap();
+        if balance > 0 {
+            bank.deposit(number.to_string(), dollars(balance)).unwrap();
+        }
+        bank.set_interest_rate(number.to_string(), rate).unwrap();
+    }
+    // (1000 * 1% + 3000 * 5%) / 4000; the empty account carries no weight.
+    assert!((bank.weighted_average_deposit_rate() - 0.04).abs() < 1e-12);
+
+    bank.withdraw("small".to_string(), dollars(1_000)).unwrap();
+    bank.withdraw("large".to_string(), dollars(3_000)).unwrap();
+    assert_eq!(bank.weighted_average_deposit_rate(), 0.0);
+}
+
+#[test]
+fn interest_with_a_fractional_cent_posts_whole_cents_and_old_adjustments_still_count() {
+    let mut bank = bank_with_accounts();
+    bank.deposit("bob".to_string(), Money::from_cents(10_001)).unwrap();
+    bank.set_compound_frequency("bob".to_string(), CompoundFrequency::Annually).unwrap();
+    bank.set_interest_rate("bob".to_string(), 0.0123).unwrap();
+    let now = SystemTime::now();
+    // 1.23% of 100.01 over a year is 1.230123.
+    let posted = bank.accrue_interest(&Period { from: now - Duration::from_secs(365 * 86_400), to: now });
+    assert_eq!(posted, vec![("bob".to_string(), Money::from_cents(123))]);
+    assert_eq!(bank.balance("bob".to_string()), Some(Money::from_cents(10_124)));
+    assert!(bank.account("bob").unwrap().verify_balance().is_ok());
+
+    // Histories kept in floating point carry the drift they snapped away as adjustments, and
+    // those still count towards the balance.
+    let document = r#"{"accounts":{"old":{"account_type":"Savings","balance":10.32,"transactions":[
+        {"type":"Deposit","amount":10,"timestamp":0},{"type":"Interest","amount":0.33,"timestamp":1},
+        {"type":"RoundingAdjustment","amount":-0.01,"timestamp":1}]}}}"#;
+    let path = std::env::temp_dir().join(format!("bank-rounding-{}.json", std::process::id()));
+    fs::write(&path, document).unwrap();
+    let legacy = Bank::load_from_file(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    let account = legacy.account("old").unwrap();
+    assert!(matches!(account.transactions()[2].kind, TransactionKind::RoundingAdjustment(amount) if amount == -Money::from_cents(1)));
+    assert_eq!(account.verify_balance(), Ok(()));
+}
+
+#[test]
+fn days_overdrawn_counts_each_day_spent_below_zero() {
+    let history = [("Deposit", 100, 20), ("Withdrawal", 150, 15), ("Deposit", 100, 12), ("Withdrawal", 100, 3)];
+    let bank = bank_with_history(-50, &history);
+    // Days 15 to 12 ago, then 3 days ago through today, counting the days it went in and out.
+    assert_eq!(bank.account("acct").unwrap().days_overdrawn(SystemTime::now()), 8);
+
+    let never = bank_with_history(50, &[("Deposit", 100, 20), ("Withdrawal", 50, 10)]);
+    assert_eq!(never.account("acct").unwrap().days_overdrawn(SystemTime::now()), 0);
+}
+
+#[test]
+fn balance_volatility_is_zero_for_a_flat_balance_and_positive_when_it_swings() {
+    let day = Duration::from_secs(86_400);
+    let now = SystemTime::now();
+    let flat = bank_with_history(500, &[("Deposit", 500, 40)]);
+    assert_eq!(flat.account("acct").unwrap().balance_volatility(now - 30 * day, now), 0.0);
+
+    let history = [("Deposit", 500, 40), ("Withdrawal", 400, 20), ("Deposit", 900, 10), ("Withdrawal", 500, 5)];
+    let swinging = bank_with_history(500, &history);
+    let volatility = swinging.account("acct").unwrap().balance_volatility(now - 30 * day, now);
+    // Over 31 closings: ten at 500.00, ten at 100.00, five at 1000.00 and six at 500.00.
+    assert!((volatility - 299.322_857).abs() < 1e-5, "{}", volatility);
+}
+
+// Failure injection only exists in debug builds.
+#[cfg(debug_assertions)]
+#[test]
+fn an_injected_failure_fails_the_next_withdrawal_only() {
+    let mut bank = bank_with_accounts();
+    bank.deposit("alice".to_string(), dollars(100)).unwrap();
+    bank.set_failure_mode(bank::FailureMode { operation: Operation::Withdraw, error: BankError::Io("disk full".to_string()) });
+
+    // Other kinds of operation are not affected.
+    bank.deposit("alice".to_string(), dollars(10)).unwrap();
+    assert_eq!(bank.withdraw("alice".to_string(), dollars(20)), Err(BankError::Io("disk full".to_string())));
+    assert_eq!(bank.balance("alice".to_string()), Some(dollars(110)));
+    bank.withdraw("alice".to_string(), dollars(20)).unwrap();
+    assert_eq!(bank.balance("alice".to_string()), Some(dollars(90)));
+}
+
+#[test]
+fn merged_transaction_logs_are_ordered_by_time_and_rebalanced() {
+    let first = bank_with_history(150, &[("Deposit", 100, 30), ("Deposit", 50, 10)]);
+    let second = bank_with_history(-20, &[("Withdrawal", 5, 40), ("Withdrawal", 15, 20)]);
+    let mut account = first.account("acct").unwrap().clone();
+    account.merge_transactions(second.account("acct").unwrap().transactions().clone());
+
+    let kinds: Vec<String> = account.transactions().iter().map(|t| format!("{:?}", t.kind)).collect();
+    assert_eq!(
+        kinds,
+        [
+            format!("{:?}", TransactionKind::Withdrawal(dollars(5))),
+            format!("{:?}", TransactionKind::Deposit(dollars(100))),
+            format!("{:?}", TransactionKind::Withdrawal(dollars(15))),
+            format!("{:?}", TransactionKind::Deposit(dollars(50))),
+        ]
+    );
+    assert!(account.transactions().windows(2).all(|pair| pair[0].timestamp <= pair[1].timestamp));
+    assert_eq!(account.balance(), dollars(130));
+    assert_eq!(account.transactions().last().unwrap().balance_after, dollars(130));
+}
+
+#[test]
+fn listings_convert_balances_to_the_base_currency_where_a_rate_is_known() {
+    let mut bank = Bank::new();
+    bank.create_account_in_currency("dollars".to_string(), AccountType::Checking, Currency::new("USD")).unwrap();
+    bank.create_account_in_currency("euros".to_string(), AccountType::Checking, Currency::new("EUR")).unwrap();
+    bank.create_account_in_currency("yen".to_string(), AccountType::Checking, Currency::new("JPY")).unwrap();
+    bank.deposit("dollars".to_string(), dollars(50)).unwrap();
+    bank.deposit("euros".to_string(), dollars(100)).unwrap();
+    bank.deposit("yen".to_string(), dollars(1_000)).unwrap();
+    let rates = HashMap::from([(Currency::new("EUR"), 1.1)]);
+
+    let mut listing = bank.list_accounts_with_base(&Currency::new("USD"), &rates);
+    listing.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(
+        listing,
+        [
+            ("dollars".to_string(), dollars(50), Currency::new("USD"), Some(dollars(50))),
+            ("euros".to_string(), dollars(100), Currency::new("EUR"), Some(dollars(110))),
+            ("yen".to_string(), dollars(1_000), Currency::new("JPY"), None),
+        ]
+    );
+}
+
+#[test]
+fn accounts_waking_from_a_long_dormancy_are_flagged_for_review() {
+    let day = Duration::from_secs(86_400);
+    let dormant = bank_with_history(150, &[("Deposit", 100, 200), ("Deposit", 50, 0)]);
+    let active = bank_with_history(60, &[("Deposit", 20, 20), ("Deposit", 20, 10), ("Deposit", 20, 0)]);
+
+    let now = SystemTime::now();
+    assert_eq!(dormant.recently_reactivated(day * 90, day * 7, now), ["acct"]);
+    assert!(active.recently_reactivated(day * 90, day * 7, now).is_empty());
+    // Two days on, nothing falls inside a one-day window, so there is nothing to report.
+    assert!(dormant.recently_reactivated(day * 90, day, now + day * 2).is_empty());
+}
+
+#[test]
+fn fee_comparison_finds_where_a_flat_fee_becomes_cheaper() {
+    let bank = bank_with_accounts();
+    let account = bank.account("alice").unwrap();
+
+    // Three free, then 1.00 each: five transactions cost 2.00 and six cost 3.00, past the 2.50 flat fee.
+    assert_eq!(account.fee_comparison(dollars(1), 3, Money::from_cents(250)), 6);
+    // A plan with no per-transaction charge is never beaten by a flat fee.
+    assert_eq!(account.fee_comparison(Money::ZERO, 3, Money::from_cents(250)), u32::MAX);
+}
+
+#[test]
+fn menu_amounts_follow_the_configured_locale() {
+    let deposit_through_menu = |locale: &str, input: &str| {
+        let dir = std::env::temp_dir().join(format!("bank-locale-{}-{}", std::process::id(), locale));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let create = ["create", "--account", "a1", "--type", "Checking", "--pin", "1234"];
+        let created = std::process::Command::new(env!("CARGO_BIN_EXE_bank")).args(create).current_dir(&dir).output().unwrap();
+        assert!(created.status.success());
+
+        let mut menu = std::process::Command::new(env!("CARGO_BIN_EXE_bank"))
+            .env("BANK_LOCALE", locale)
+            .current_dir(&dir)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        menu.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+        let output = menu.wait_with_output().unwrap();
+        let balance = Bank::load_from_file(&dir.join("bank.json")).unwrap().balance("a1".to_string());
+        fs::remove_dir_all(&dir).unwrap();
+        (String::from_utf8_lossy(&output.stdout).to_string(), balance)
+    };
+
+    let (_, us) = deposit_through_menu("us", "2\na1\n1,234.56\n24\n");
+    assert_eq!(us, Some(Money::from_cents(123_456)));
+    // The US form is ambiguous to a European operator and asked for again.
+    let (output, eu) = deposit_through_menu("eu", "2\na1\n1,234.56\n1.234,56\n24\n");
+    assert!(output.contains("Invalid amount!"));
+    assert_eq!(eu, Some(Money::from_cents(123_456)));
+}
+
+#[test]
+fn months_to_goal_counts_contributions_and_gives_up_on_unreachable_goals() {
+    let mut bank = bank_with_accounts();
+    bank.deposit("alice".to_string(), dollars(100)).unwrap();
+    let account = bank.account("alice").unwrap();
+
+    // 100.00 grows to 400.00, 700.00 and then 1,000.00.
+    assert_eq!(account.months_to_goal(dollars(1_000), dollars(300), 0.0), Some(3));
+    // A percent a month on top: 401.00, 706.01, then 1,013.07.
+    assert_eq!(account.months_to_goal(dollars(1_000), dollars(300), 0.12), Some(3));
+    assert_eq!(account.months_to_goal(dollars(50), Money::ZERO, 0.0), Some(0));
+    assert_eq!(account.months_to_goal(dollars(1_000), Money::ZERO, 0.0), None);
+}
+
+#[test]
+fn large_and_round_transactions_are_queued_for_review() {
+    let mut bank = bank_with_accounts();
+    bank.set_review_rules(ReviewRules { amount_threshold: Some(dollars(1_000)), round_multiple: Some(dollars(500)) });
+    bank.deposit("alice".to_string(), Money::from_cents(250_050)).unwrap();
+    bank.deposit("alice".to_string(), Money::from_cents(4_210)).unwrap();
+    bank.withdraw("alice".to_string(), dollars(500)).unwrap();
+
+    // Flagged transactions still go through.
+    assert_eq!(bank.balance("alice".to_string()), Some(Money::from_cents(204_260)));
+    let queued: Vec<(u64, Money, &str)> = bank.review_queue().iter().map(|item| (item.id, item.amount, item.reason.as_str())).collect();
+    assert_eq!(
+        queued,
+        [(1, Money::from_cents(250_050), "amount over 1000.00"), (2, dollars(500), "round amount (multiple of 500.00)")]
+    );
+    assert!(bank.clear_review(1));
+    assert!(!bank.clear_review(1));
+    assert_eq!(bank.review_queue().len(), 1);
+}
+
+#[test]
+fn real_return_is_negative_when_inflation_outpaces_the_rate() {
+    let bank = bank_with_accounts();
+    let account = bank.account("bob").unwrap();
+
+    // 1.05 / 1.02 - 1: a little under the 3% a plain subtraction gives.
+    assert!((account.real_return(0.05, 0.02) - 0.029_411_76).abs() < 1e-6);
+    assert!((account.real_return(0.01, 0.04) + 0.028_846_15).abs() < 1e-6);
+    assert_eq!(account.real_return(0.03, 0.03), 0.0);
+}
+
+#[test]
+fn transaction_heatmap_counts_each_transaction_in_its_hour() {
+    let midnight = calendar::to_millis(calendar::parse_date("2024-03-01").unwrap());
+    let at_hour = |hour: i64, minute: i64| {
+        format!(r#"{{"type":"Deposit","amount":10,"timestamp":{}}}"#, midnight + (hour * 60 + minute) * 60_000)
+    };
+    let account = |balance: i64, transactions: Vec<String>| {
+        format!(r#"{{"account_type":"Checking","balance":{},"transactions":[{}]}}"#, balance, transactions.join(","))
+    };
+    let early = account(30, vec![at_hour(0, 5), at_hour(9, 0), at_hour(9, 59)]);
+    let document = format!(r#"{{"accounts":{{"early":{},"late":{}}}}}"#, early, account(10, vec![at_hour(23, 30)]));
+    let path = std::env::temp_dir().join(format!("bank-heatmap-{}.json", std::process::id()));
+    fs::write(&path, document).unwrap();
+    let bank = Bank::load_from_file(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let heatmap = bank.transaction_heatmap();
+    assert_eq!((heatmap[0], heatmap[9], heatmap[10], heatmap[23]), (1, 2, 0, 1));
+    assert_eq!(heatmap.iter().sum::<usize>(), 4);
+}
+
+#[test]
+fn daily_compounding_earns_a_little_more_than_annual_at_the_same_rate() {
+    let mut bank = bank_with_accounts();
+    bank.deposit("bob".to_string(), dollars(10_000)).unwrap();
+    bank.set_interest_rate("bob".to_string(), 0.05).unwrap();
+    let year_at = |bank: &mut Bank, frequency: CompoundFrequency| {
+        bank.set_compound_frequency("bob".to_string(), frequency).unwrap();
+        bank.forecast_interest(12)
+    };
+
+    let annual = year_at(&mut bank, CompoundFrequency::Annually);
+    let monthly = year_at(&mut bank, CompoundFrequency::Monthly);
+    let daily = year_at(&mut bank, CompoundFrequency::Daily);
+    assert_eq!(annual, dollars(500));
+    assert_eq!(monthly, Money::from_cents(51_162));
+    assert_eq!(daily, Money::from_cents(51_267));
+}
+
+#[test]
+fn top_counterparties_rank_by_total_transferred() {
+    let mut bank = bank_with_accounts();
+    bank.create_account("carol".to_string(), AccountType::Checking).unwrap();
+    bank.create_account("dave".to_string(), AccountType::Checking).unwrap();
+    bank.deposit("alice".to_string(), dollars(1_000)).unwrap();
+    for _ in 0..3 {
+        bank.transfer("alice".to_string(), "bob".to_string(), dollars(10)).unwrap();
+    }
+    bank.transfer("alice".to_string(), "carol".to_string(), dollars(200)).unwrap();
+    bank.transfer("alice".to_string(), "dave".to_string(), dollars(50)).unwrap();
+    bank.transfer("dave".to_string(), "alice".to_string(), dollars(50)).unwrap();
+
+    // Money coming back from dave counts towards dave too.
+    let alice = bank.account("alice").unwrap();
+    assert_eq!(
+        alice.top_counterparties(3),
+        [("carol".to_string(), 1, dollars(200)), ("dave".to_string(), 2, dollars(100)), ("bob".to_string(), 3, dollars(30))]
+    );
+    assert_eq!(alice.top_counterparties(1).len(), 1);
+}
+
+#[test]
+fn net_interest_margin_nets_credit_interest_against_deposit_interest() {
+    let mut bank = bank_with_accounts();
+    assert_eq!(bank.net_interest_margin(), 0.0);
+
+    bank.create_account_with_limit("card".to_string(), AccountType::Credit, dollars(5_000)).unwrap();
+    bank.withdraw("card".to_string(), dollars(1_000)).unwrap();
+    bank.set_interest_rate("card".to_string(), 0.18).unwrap();
+    bank.deposit("bob".to_string(), dollars(2_000)).unwrap();
+    bank.set_interest_rate("bob".to_string(), 0.03).unwrap();
+
+    // 180.00 earned on the card less 60.00 paid to bob, over 1,000.00 lent out.
+    assert!((bank.net_interest_margin() - 0.12).abs() < 1e-9);
+}
+
+#[test]
+fn tag_where_tags_only_the_matching_accounts() {
+    let mut bank = bank_with_accounts();
+    bank.create_account("carol".to_string(), AccountType::Savings).unwrap();
+    bank.deposit("alice".to_string(), dollars(150_000)).unwrap();
+    bank.deposit("bob".to_string(), dollars(100_000)).unwrap();
+    bank.deposit("carol".to_string(), dollars(250_000)).unwrap();
+
+    assert_eq!(bank.tag_where(|account| account.balance() > dollars(100_000), "vip"), 2);
+    let vip: Vec<&str> =
+        ["alice", "bob", "carol"].into_iter().filter(|number| bank.account(number).unwrap().tags().contains("vip")).collect();
+    assert_eq!(vip, ["alice", "carol"]);
+    assert_eq!(bank.tag_where(|_| false, "vip"), 0);
+}
+
+#[test]
+fn expected_monthly_fees_waive_maintenance_and_charge_overage() {
+    let mut bank = bank_with_accounts();
+    let schedule = FeeSchedule {
+        free_withdrawals: 5,
+        fee: Money::from_cents(150),
+        monthly_maintenance: dollars(12),
+        maintenance_waiver_balance: Some(dollars(1_500)),
+    };
+    bank.set_fee_schedule("alice".to_string(), Some(schedule)).unwrap();
+    let alice = bank.account("alice").unwrap();
+
+    let wealthy = UsageProfile { deposits: 2, withdrawals: 4, average_balance: dollars(20_000) };
+    assert_eq!(alice.expected_monthly_fees(wealthy), Money::ZERO);
+    // 12.00 maintenance plus seven withdrawals over the free five at 1.50; deposits are free.
+    let busy = UsageProfile { deposits: 30, withdrawals: 12, average_balance: dollars(200) };
+    assert_eq!(alice.expected_monthly_fees(busy), Money::from_cents(2_250));
+    assert_eq!(bank.account("bob").unwrap().expected_monthly_fees(busy), Money::ZERO);
+}
+
+#[test]
+fn liquidity_coverage_compares_positive_balances_with_the_stressed_outflow() {
+    let mut bank = bank_with_accounts();
+    bank.create_account_with_limit("card".to_string(), AccountType::Credit, dollars(1_000)).unwrap();
+    bank.deposit("alice".to_string(), dollars(3_000)).unwrap();
+    bank.deposit("bob".to_string(), dollars(1_500)).unwrap();
+    bank.withdraw("card".to_string(), dollars(800)).unwrap();
+
+    // The card's debt is not a liquid asset: 4,500.00 against 2,000.00 of outflow.
+    assert_eq!(bank.liquidity_coverage_ratio(dollars(2_000)), 2.25);
+    assert_eq!(bank.liquidity_coverage_ratio(Money::ZERO), f64::INFINITY);
+}
+
+#[test]
+fn statement_retention_keeps_only_the_most_recent() {
+    let mut bank = bank_with_accounts();
+    let day = Duration::from_secs(86_400);
+    let period = Period { from: SystemTime::now() - day, to: SystemTime::now() + day };
+    for _ in 0..4 {
+        bank.deposit("alice".to_string(), dollars(10)).unwrap();
+        bank.generate_and_retain_statements(&period, 2);
+    }
+
+    let kept: Vec<Money> = bank.account("alice").unwrap().statements().iter().map(|s| s.closing_balance).collect();
+    assert_eq!(kept, [dollars(30), dollars(40)]);
+    assert_eq!(bank.account("bob").unwrap().statements().len(), 2);
+}
+
+#[test]
+fn round_number_ratio_counts_whole_hundreds() {
+    let mut bank = bank_with_accounts();
+    assert_eq!(bank.round_number_ratio("alice".to_string()), 0.0);
+    bank.deposit("alice".to_string(), dollars(500)).unwrap();
+    bank.deposit("alice".to_string(), Money::from_cents(12_345)).unwrap();
+    bank.deposit("alice".to_string(), dollars(1_000)).unwrap();
+    bank.withdraw("alice".to_string(), dollars(100)).unwrap();
+    bank.withdraw("alice".to_string(), dollars(150)).unwrap();
+
+    assert_eq!(bank.round_number_ratio("alice".to_string()), 0.6);
+    assert_eq!(bank.round_number_ratio("carol".to_string()), 0.0);
+}
+
+#[test]
+fn promo_cost_is_the_extra_interest_at_the_promotional_rate() {
+    let mut bank = bank_with_accounts();
+    bank.create_account("carol".to_string(), AccountType::Savings).unwrap();
+    bank.deposit("alice".to_string(), dollars(8_000)).unwrap();
+    for (number, amount) in [("bob", 10_000), ("carol", 5_000)] {
+        bank.deposit(number.to_string(), dollars(amount)).unwrap();
+        bank.set_compound_frequency(number.to_string(), CompoundFrequency::Annually).unwrap();
+        bank.set_interest_rate(number.to_string(), 0.02).unwrap();
+    }
+    bank.set_interest_rate("alice".to_string(), 0.02).unwrap();
+
+    let cost = bank.promo_cost(AccountType::Savings, 0.05, 12);
+    assert_eq!(cost, dollars(450));
+    let current = bank.forecast_interest(12);
+    for number in ["bob", "carol"] {
+        bank.set_interest_rate(number.to_string(), 0.05).unwrap();
+    }
+    assert_eq!(bank.forecast_interest(12) - current, cost);
+    assert_eq!(bank.promo_cost(AccountType::Credit, 0.05, 12), Money::ZERO);
+}
+
+#[test]
+fn withdrawing_before_maturity_forfeits_part_of_the_interest() {
+    let entry = |kind: &str, amount: i64, days_ago: u64| {
+        let timestamp = calendar::to_millis(SystemTime::now() - Duration::from_secs(days_ago * 86_400));
+        format!(r#"{{"type":"{}","amount":{},"timestamp":{}}}"#, kind, amount, timestamp)
+    };
+    let document = format!(
+        r#"{{"accounts":{{"term":{{"account_type":"Savings","balance":1100,"transactions":[{},{}]}}}}}}"#,
+        entry("Deposit", 1_000, 60),
+        entry("Interest", 100, 30)
+    );
+    let path = std::env::temp_dir().join(format!("bank-term-{}.json", std::process::id()));
+    fs::write(&path, document).unwrap();
+    let mut bank = Bank::load_from_file(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    let maturity = SystemTime::now() + Duration::from_secs(30 * 86_400);
+    bank.set_maturity("term".to_string(), maturity).unwrap();
+
+    // Half of the 100.00 interest is forfeited and recorded as a fee after the withdrawal.
+    let mut early = bank.account("term").unwrap().clone();
+    assert_eq!(early.withdraw_with_penalty(dollars(200), 0.5, SystemTime::now()), Ok(dollars(50)));
+    assert_eq!(early.balance(), dollars(850));
+    let last = &early.transactions().last().unwrap().kind;
+    assert!(matches!(last, TransactionKind::Fee(amount, FeeType::EarlyWithdrawal) if *amount == dollars(50)));
+
+    let mut matured = bank.account("term").unwrap().clone();
+    assert_eq!(matured.withdraw_with_penalty(dollars(200), 0.5, maturity), Ok(Money::ZERO));
+    assert_eq!(matured.balance(), dollars(900));
+}
+
+#[test]
+fn account_age_distribution_buckets_accounts_by_age() {
+    let now = calendar::parse_date("2024-06-01").unwrap();
+    let opened = |days_ago: u64| {
+        let created_at = calendar::to_millis(now - Duration::from_secs(days_ago * 86_400));
+        format!(r#"{{"account_type":"Checking","balance":0,"created_at":{},"transactions":[]}}"#, created_at)
+    };
+    let accounts: Vec<String> = [5, 30, 100, 200, 364, 400].iter().map(|days| format!(r#""age{}":{}"#, days, opened(*days))).collect();
+    let path = std::env::temp_dir().join(format!("bank-ages-{}.json", std::process::id()));
+    fs::write(&path, format!(r#"{{"accounts":{{{}}}}}"#, accounts.join(","))).unwrap();
+    let bank = Bank::load_from_file(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    // 30 days is a month old; 364 days is not yet a year.
+    assert_eq!(
+        bank.account_age_distribution(now),
+        [
+            (AgeBucket::UnderOneMonth, 1),
+            (AgeBucket::OneToSixMonths, 2),
+            (AgeBucket::SixToTwelveMonths, 2),
+            (AgeBucket::OverOneYear, 1),
+        ]
+    );
+}